@@ -0,0 +1,178 @@
+//! Binary-safe HTTP message body
+
+use bytes::Bytes;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::ops::Deref;
+
+/// An HTTP request or response body.
+///
+/// Bodies are raw bytes, so uploads and binary responses pass through the
+/// gateway unchanged. Text helpers are provided for the common case.
+///
+/// When serialized (for the v1 JSON IPC protocol) a UTF-8 body is written as
+/// a plain JSON string and any other body as `{"base64": "..."}`, so text
+/// bodies keep their existing wire format.
+///
+/// # Example
+/// ```ignore
+/// let body = Body::from("hello");
+/// assert_eq!(body.as_str(), Some("hello"));
+///
+/// let body = Body::from(vec![0xff, 0x00]);
+/// assert_eq!(body.as_bytes(), &[0xff, 0x00]);
+/// assert_eq!(body.as_str(), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Body(Bytes);
+
+impl Body {
+    /// Create an empty body.
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// The body as raw bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The body as UTF-8 text, or None if it is not valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.0).ok()
+    }
+
+    /// The body as text, replacing invalid UTF-8 sequences.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.0)
+    }
+
+    /// Consume the body, returning its bytes.
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+
+    /// Length of the body in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the body is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Deref for Body {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Body {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(Bytes::from(bytes))
+    }
+}
+
+impl From<&[u8]> for Body {
+    fn from(bytes: &[u8]) -> Self {
+        Self(Bytes::copy_from_slice(bytes))
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Self {
+        Self(Bytes::from(text))
+    }
+}
+
+impl From<&str> for Body {
+    fn from(text: &str) -> Self {
+        Self(Bytes::copy_from_slice(text.as_bytes()))
+    }
+}
+
+impl From<Body> for Bytes {
+    fn from(body: Body) -> Self {
+        body.0
+    }
+}
+
+impl PartialEq<str> for Body {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for Body {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+/// Wire format: a plain string for UTF-8 bodies, otherwise base64
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Wire<'a> {
+    Text(Cow<'a, str>),
+    Binary { base64: Cow<'a, str> },
+}
+
+impl Serialize for Body {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use base64::Engine;
+        match self.as_str() {
+            Some(text) => Wire::Text(Cow::Borrowed(text)).serialize(serializer),
+            None => {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(&self.0);
+                Wire::Binary { base64: Cow::Owned(encoded) }.serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Body {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use base64::Engine;
+        match Wire::deserialize(deserializer)? {
+            Wire::Text(text) => Ok(Body::from(text.into_owned())),
+            Wire::Binary { base64 } => base64::engine::general_purpose::STANDARD
+                .decode(base64.as_bytes())
+                .map(Body::from)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_and_binary_round_trip() {
+        let text = Body::from("héllo");
+        assert_eq!(serde_json::to_string(&text).unwrap(), "\"héllo\"");
+        assert_eq!(serde_json::from_str::<Body>("\"héllo\"").unwrap(), text);
+
+        let binary = Body::from(vec![0x89, b'P', b'N', b'G', 0x00, 0xff]);
+        let json = serde_json::to_string(&binary).unwrap();
+        assert_eq!(json, r#"{"base64":"iVBORwD/"}"#);
+        assert_eq!(serde_json::from_str::<Body>(&json).unwrap(), binary);
+        assert_eq!(binary.as_str(), None);
+    }
+}
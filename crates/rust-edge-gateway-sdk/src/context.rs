@@ -20,7 +20,7 @@ pub struct Context {
     /// SQLite database client
     pub sqlite: Option<Arc<dyn SqliteService>>,
     
-    /// Request-scoped metadata
+    /// ID of the request being handled (the same as `Request::request_id`)
     pub request_id: String,
 }
 
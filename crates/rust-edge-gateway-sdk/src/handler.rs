@@ -26,6 +26,27 @@ use std::pin::Pin;
  
 use crate::{Request, Response};
 
+/// Version of the interface between the gateway and compiled handlers
+///
+/// Bumped whenever a type passed across it, such as [`Request`],
+/// [`Response`] or [`crate::SocketEvent`], changes layout. The gateway
+/// refuses to load a handler built against a different version.
+pub const ABI_VERSION: u32 = 2;
+
+/// Export the SDK's [`ABI_VERSION`] from a handler library
+///
+/// The gateway's generated handler wrappers call this once; the gateway
+/// reads the value before calling any other entry point.
+#[macro_export]
+macro_rules! export_abi_version {
+    () => {
+        #[no_mangle]
+        pub extern "C" fn handler_abi_version() -> u32 {
+            $crate::handler::ABI_VERSION
+        }
+    };
+}
+
 /// Type alias for boxed future returned by handlers
 pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
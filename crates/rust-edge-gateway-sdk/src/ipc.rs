@@ -62,7 +62,13 @@ pub fn read_request() -> Result<Request, HandlerError> {
 }
 
 /// Send a response to stdout (received by the gateway)
-pub fn send_response(response: Response) -> Result<(), HandlerError> {
+pub fn send_response(mut response: Response) -> Result<(), HandlerError> {
+    // The IPC protocol has no streaming; buffer a streaming body
+    if let Some(reader) = response.stream.take().and_then(|s| s.take()) {
+        let body: Vec<u8> = reader.flat_map(|chunk| chunk.to_vec()).collect();
+        response.body = Some(body.into());
+    }
+
     let stdout = io::stdout();
     let mut handle = stdout.lock();
 
@@ -22,6 +22,7 @@
 //! });
 //! ```
 
+pub mod body;
 pub mod request;
 pub mod response;
 pub mod services;
@@ -31,6 +32,8 @@ pub mod error;
 pub mod sqlite;
 pub mod handler;
 pub mod context;
+pub mod stream;
+pub mod socket;
 
 pub mod prelude {
     //! Common imports for Rust Edge Gateway handlers
@@ -43,8 +46,11 @@ pub mod prelude {
     //! - Error types
     //! - Serialization helpers
 
+    pub use crate::body::Body;
     pub use crate::request::Request;
     pub use crate::response::Response;
+    pub use crate::stream::{BodyStream, BodyWriter, SseWriter, StreamClosed};
+    pub use crate::socket::{CloseFrame, Message, SocketClosed, SocketEvent, WebSocket};
     pub use crate::context::Context;
     pub use crate::services::{MinioClient, SqliteClient, ObjectInfo, ServiceError, ServiceResult};
     pub use crate::storage::{Storage, StorageType};
@@ -65,11 +71,14 @@ pub mod prelude {
 }
 
 // Re-export key types at crate root
+pub use body::Body;
 pub use request::Request;
 pub use response::Response;
+pub use stream::{BodyStream, BodyWriter, SseWriter, StreamClosed};
+pub use socket::{CloseFrame, Message, SocketClosed, SocketEvent, WebSocket};
 pub use error::HandlerError;
 pub use storage::Storage;
 pub use context::Context;
 pub use services::{MinioClient, SqliteClient, ObjectInfo, ServiceError};
-pub use handler::{BoxFuture, HandlerFn};
+pub use handler::{BoxFuture, HandlerFn, ABI_VERSION};
 
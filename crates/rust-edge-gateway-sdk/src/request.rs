@@ -1,5 +1,6 @@
 //! HTTP Request representation for handlers
 
+use crate::body::Body;
 use crate::error::HandlerError;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -22,9 +23,9 @@ pub struct Request {
     #[serde(default)]
     pub headers: HashMap<String, String>,
 
-    /// Request body (raw bytes as base64 for binary, or string for text)
+    /// Request body (raw bytes)
     #[serde(default)]
-    pub body: Option<String>,
+    pub body: Option<Body>,
 
     /// Path parameters extracted from route (e.g., {id} -> "123")
     #[serde(default)]
@@ -34,9 +35,15 @@ pub struct Request {
     #[serde(default)]
     pub client_ip: Option<String>,
 
-    /// Request ID for tracing
+    /// Request ID for tracing, the same as `Context::request_id` and the
+    /// `X-Request-Id` response header
     #[serde(default)]
     pub request_id: String,
+
+    /// Values attached by the gateway while routing (e.g. "subdomain" for
+    /// wildcard hosts)
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
 }
 
 impl Request {
@@ -51,7 +58,7 @@ impl Request {
     /// ```
     pub fn json<T: for<'de> Deserialize<'de>>(&self) -> Result<T, HandlerError> {
         match &self.body {
-            Some(body) => serde_json::from_str(body)
+            Some(body) => serde_json::from_slice(body)
                 .map_err(|e| HandlerError::BadRequest(format!("Invalid JSON: {}", e))),
             None => serde_json::from_str("null")
                 .map_err(|e| HandlerError::BadRequest(format!("Invalid JSON: {}", e))),
@@ -132,6 +139,17 @@ impl Request {
             .map_err(|_| HandlerError::BadRequest(format!("Invalid value for path parameter: {}", key)))
     }
 
+    /// Get a gateway-provided request attribute.
+    ///
+    /// # Example
+    /// ```ignore
+    /// // Domain host: *.example.com, Request host: acme.example.com
+    /// let tenant = req.attribute("subdomain"); // Some(&"acme".to_string())
+    /// ```
+    pub fn attribute(&self, key: &str) -> Option<&String> {
+        self.attributes.get(key)
+    }
+
     /// Get a header value (case-insensitive lookup).
     ///
     /// # Example
@@ -168,12 +186,26 @@ impl Request {
         self.method.eq_ignore_ascii_case(method)
     }
 
-    /// Get the raw body as bytes (useful for binary data).
-    /// The body is stored as a string, so this converts it to bytes.
-    pub fn body_bytes(&self) -> Vec<u8> {
-        self.body.as_ref()
-            .map(|b| b.as_bytes().to_vec())
-            .unwrap_or_default()
+    /// Get the raw body as bytes (empty if there is no body).
+    ///
+    /// # Example
+    /// ```ignore
+    /// let upload: &[u8] = req.body_bytes();
+    /// storage.put("uploads/photo.png", upload)?;
+    /// ```
+    pub fn body_bytes(&self) -> &[u8] {
+        self.body.as_ref().map(Body::as_bytes).unwrap_or_default()
+    }
+
+    /// Get the body as UTF-8 text.
+    /// Returns None if there is no body or it is not valid UTF-8.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let text = req.body_text().unwrap_or("");
+    /// ```
+    pub fn body_text(&self) -> Option<&str> {
+        self.body.as_ref().and_then(Body::as_str)
     }
 
     /// Get the Content-Type header value.
@@ -216,13 +248,14 @@ impl Request {
         let boundary = content_type
             .split("boundary=")
             .nth(1)
-            .map(|b| b.trim_matches('"').to_string())
+            .and_then(|b| b.split(';').next())
+            .map(|b| b.trim().trim_matches('"').to_string())
             .ok_or_else(|| HandlerError::BadRequest("Missing multipart boundary".into()))?;
 
         let body = self.body.as_ref()
             .ok_or_else(|| HandlerError::BadRequest("Empty body for multipart request".into()))?;
 
-        MultipartData::parse(body, &boundary)
+        MultipartData::parse(body.as_bytes(), &boundary)
     }
 }
 
@@ -247,64 +280,85 @@ pub struct MultipartFile {
 }
 
 impl MultipartData {
-    /// Parse multipart body with the given boundary
-    pub fn parse(body: &str, boundary: &str) -> Result<Self, HandlerError> {
+    /// Parse a multipart body with the given boundary.
+    ///
+    /// Works on raw bytes, so file contents are preserved exactly. Text
+    /// fields are decoded as UTF-8, replacing invalid sequences.
+    pub fn parse(body: &[u8], boundary: &str) -> Result<Self, HandlerError> {
         let mut result = MultipartData::default();
-        let delimiter = format!("--{}", boundary);
-        let _end_delimiter = format!("--{}--", boundary);
+        let delimiter = format!("--{}", boundary).into_bytes();
 
-        // Split by boundary
-        let parts: Vec<&str> = body.split(&delimiter).collect();
+        // Delimiters only count at the start of a line
+        let positions: Vec<usize> = find_all(body, &delimiter)
+            .filter(|&pos| pos == 0 || body[pos - 1] == b'\n')
+            .collect();
 
-        for part in parts {
-            let part = part.trim();
-            if part.is_empty() || part == "--" || part.starts_with("--") {
-                continue;
+        if positions.is_empty() {
+            return Err(HandlerError::BadRequest("Multipart boundary not found in body".into()));
+        }
+
+        for pair in positions.windows(2) {
+            let part = &body[pair[0] + delimiter.len()..pair[1]];
+
+            // Anything after the closing "--boundary--" is epilogue
+            if part.starts_with(b"--") {
+                break;
             }
 
+            // Skip the rest of the delimiter line, and drop the line break
+            // that belongs to the next delimiter
+            let Some(line_end) = part.iter().position(|&b| b == b'\n') else {
+                continue;
+            };
+            let part = &part[line_end + 1..];
+            let part = part.strip_suffix(b"\r\n")
+                .or_else(|| part.strip_suffix(b"\n"))
+                .unwrap_or(part);
+
             // Find the header/body separator (empty line)
-            let header_end = part.find("\r\n\r\n")
-                .or_else(|| part.find("\n\n"));
-
-            if let Some(pos) = header_end {
-                let headers_str = &part[..pos];
-                let body_start = if part[pos..].starts_with("\r\n\r\n") { pos + 4 } else { pos + 2 };
-                let body_content = part[body_start..].trim_end_matches("\r\n").trim_end_matches("--");
-
-                // Parse Content-Disposition header
-                let mut name = None;
-                let mut filename = None;
-                let mut content_type = "text/plain".to_string();
-
-                for line in headers_str.lines() {
-                    let line = line.trim();
-                    if line.to_lowercase().starts_with("content-disposition:") {
-                        // Parse name and filename from Content-Disposition
-                        if let Some(n) = extract_header_param(line, "name") {
-                            name = Some(n);
-                        }
-                        if let Some(f) = extract_header_param(line, "filename") {
-                            filename = Some(f);
-                        }
-                    } else if line.to_lowercase().starts_with("content-type:") {
-                        content_type = line.split(':').nth(1)
-                            .map(|s| s.trim().to_string())
-                            .unwrap_or_else(|| "text/plain".to_string());
+            let Some((header_end, body_start)) = find_all(part, b"\r\n\r\n").next()
+                .map(|pos| (pos, pos + 4))
+                .or_else(|| find_all(part, b"\n\n").next().map(|pos| (pos, pos + 2)))
+            else {
+                continue;
+            };
+
+            let headers_str = String::from_utf8_lossy(&part[..header_end]);
+            let body_content = &part[body_start..];
+
+            // Parse Content-Disposition header
+            let mut name = None;
+            let mut filename = None;
+            let mut content_type = "text/plain".to_string();
+
+            for line in headers_str.lines() {
+                let line = line.trim();
+                if line.to_lowercase().starts_with("content-disposition:") {
+                    // Parse name and filename from Content-Disposition
+                    if let Some(n) = extract_header_param(line, "name") {
+                        name = Some(n);
+                    }
+                    if let Some(f) = extract_header_param(line, "filename") {
+                        filename = Some(f);
                     }
+                } else if line.to_lowercase().starts_with("content-type:") {
+                    content_type = line.split(':').nth(1)
+                        .map(|s| s.trim().to_string())
+                        .unwrap_or_else(|| "text/plain".to_string());
                 }
+            }
 
-                if let Some(field_name) = name {
-                    if let Some(file_name) = filename {
-                        // This is a file
-                        result.files.insert(field_name, MultipartFile {
-                            filename: file_name,
-                            content_type,
-                            data: body_content.as_bytes().to_vec(),
-                        });
-                    } else {
-                        // This is a text field
-                        result.fields.insert(field_name, body_content.to_string());
-                    }
+            if let Some(field_name) = name {
+                if let Some(file_name) = filename {
+                    // This is a file
+                    result.files.insert(field_name, MultipartFile {
+                        filename: file_name,
+                        content_type,
+                        data: body_content.to_vec(),
+                    });
+                } else {
+                    // This is a text field
+                    result.fields.insert(field_name, String::from_utf8_lossy(body_content).into_owned());
                 }
             }
         }
@@ -335,6 +389,14 @@ impl MultipartData {
     }
 }
 
+/// Positions of every occurrence of `needle` in `haystack`
+fn find_all<'a>(haystack: &'a [u8], needle: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    haystack.windows(needle.len())
+        .enumerate()
+        .filter(move |(_, window)| *window == needle)
+        .map(|(pos, _)| pos)
+}
+
 /// Helper to extract a parameter value from a header like Content-Disposition
 fn extract_header_param(header: &str, param: &str) -> Option<String> {
     let search = format!("{}=", param);
@@ -367,7 +429,37 @@ impl Default for Request {
             params: HashMap::new(),
             client_ip: None,
             request_id: String::new(),
+            attributes: HashMap::new(),
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipart_preserves_binary_file() {
+        let png: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', 0x00, 0xff];
+
+        let mut body = Vec::new();
+        body.extend_from_slice(b"--XyZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nCaf\xc3\xa9\r\n");
+        body.extend_from_slice(b"--XyZ\r\nContent-Disposition: form-data; name=\"upload\"; filename=\"a.png\"\r\n");
+        body.extend_from_slice(b"Content-Type: image/png\r\n\r\n");
+        body.extend_from_slice(png);
+        body.extend_from_slice(b"\r\n--XyZ--\r\n");
+
+        let mut req = Request::default();
+        req.headers.insert("Content-Type".into(), "multipart/form-data; boundary=XyZ".into());
+        req.body = Some(Body::from(body));
+
+        let data = req.multipart().unwrap();
+        assert_eq!(data.field("title").map(String::as_str), Some("Café"));
+
+        let file = data.file("upload").unwrap();
+        assert_eq!(file.filename, "a.png");
+        assert_eq!(file.content_type, "image/png");
+        assert_eq!(file.data, png);
+    }
+}
@@ -3,6 +3,9 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::body::Body;
+use crate::stream::BodyStream;
+
 /// Represents an outgoing HTTP response.
 ///
 /// # Quick Reference
@@ -25,6 +28,11 @@ use std::collections::HashMap;
 /// Response::binary(200, image_bytes, "image/png")
 ///     .with_header("Content-Disposition", "inline; filename=\"photo.png\"")
 /// ```
+///
+/// # Streaming Responses
+///
+/// For large downloads or Server-Sent Events, see [`Response::stream`] and
+/// [`Response::sse`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Response {
     /// HTTP status code
@@ -34,9 +42,16 @@ pub struct Response {
     #[serde(default)]
     pub headers: HashMap<String, String>,
 
-    /// Response body
+    /// Response body (raw bytes)
     #[serde(default)]
-    pub body: Option<String>,
+    pub body: Option<Body>,
+
+    /// Streaming body, sent instead of `body` when set.
+    ///
+    /// v1 (IPC) handlers have the stream buffered into `body` before the
+    /// response is sent.
+    #[serde(skip)]
+    pub stream: Option<BodyStream>,
 }
 
 impl Response {
@@ -52,6 +67,7 @@ impl Response {
             status,
             headers: HashMap::new(),
             body: None,
+            stream: None,
         }
     }
 
@@ -80,7 +96,8 @@ impl Response {
         Self {
             status,
             headers,
-            body: serde_json::to_string(&body).ok(),
+            body: serde_json::to_vec(&body).ok().map(Body::from),
+            stream: None,
         }
     }
 
@@ -98,14 +115,14 @@ impl Response {
         Self {
             status,
             headers,
-            body: Some(body.into()),
+            body: Some(Body::from(body.into())),
+            stream: None,
         }
     }
 
     /// Create a binary response (for files, images, etc.).
     ///
-    /// The data is base64-encoded for transport over JSON IPC.
-    /// The gateway will decode it before sending to the client.
+    /// The bytes are sent to the client unchanged.
     ///
     /// # Example
     /// ```ignore
@@ -117,17 +134,14 @@ impl Response {
     ///     .with_header("Content-Disposition", "attachment; filename=\"report.pdf\"")
     /// ```
     pub fn binary(status: u16, data: impl AsRef<[u8]>, content_type: impl Into<String>) -> Self {
-        use base64::Engine;
-        let encoded = base64::engine::general_purpose::STANDARD.encode(data.as_ref());
-
         let mut headers = HashMap::new();
         headers.insert("Content-Type".to_string(), content_type.into());
-        headers.insert("X-Binary-Response".to_string(), "base64".to_string());
 
         Self {
             status,
             headers,
-            body: Some(encoded),
+            body: Some(Body::from(data.as_ref())),
+            stream: None,
         }
     }
 
@@ -144,10 +158,37 @@ impl Response {
         Self {
             status,
             headers,
-            body: Some(body.into()),
+            body: Some(Body::from(body.into())),
+            stream: None,
         }
     }
 
+    /// Create a streaming response.
+    ///
+    /// The body is sent to the client chunk by chunk as the handler produces
+    /// it (see [`BodyStream`]).
+    ///
+    /// # Example
+    /// ```ignore
+    /// let (writer, stream) = BodyStream::channel(8);
+    /// std::thread::spawn(move || copy_object_in_chunks(writer));
+    /// Response::stream(200, stream).with_header("Content-Type", "application/octet-stream")
+    /// ```
+    pub fn stream(status: u16, stream: BodyStream) -> Self {
+        let mut response = Self::new(status);
+        response.stream = Some(stream);
+        response
+    }
+
+    /// Create a Server-Sent Events response (`text/event-stream`).
+    ///
+    /// Write events with an [`SseWriter`](crate::stream::SseWriter).
+    pub fn sse(stream: BodyStream) -> Self {
+        Self::stream(200, stream)
+            .with_header("Content-Type", "text/event-stream")
+            .with_header("Cache-Control", "no-cache")
+    }
+
     /// Create a 404 Not Found response.
     pub fn not_found() -> Self {
         Self::json(404, serde_json::json!({"error": "Not Found"}))
@@ -227,8 +268,8 @@ impl Response {
         self
     }
 
-    /// Set the body (builder pattern).
-    pub fn with_body(mut self, body: impl Into<String>) -> Self {
+    /// Set the body (builder pattern). Accepts text or bytes.
+    pub fn with_body(mut self, body: impl Into<Body>) -> Self {
         self.body = Some(body.into());
         self
     }
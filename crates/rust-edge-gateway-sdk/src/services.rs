@@ -82,6 +82,14 @@ pub trait SqliteClient: Send + Sync {
 
     /// Execute a statement (INSERT, UPDATE, DELETE)
     fn execute<'a>(&'a self, sql: &'a str, params: Vec<String>) -> ServiceFuture<'a, u64>;
+
+    /// Execute a query only if it cannot change the database
+    ///
+    /// Returns `None`, without running the statement, if it could write.
+    /// Clients that cannot tell treat every statement as a write.
+    fn query_read_only<'a>(&'a self, _sql: &'a str, _params: Vec<String>) -> ServiceFuture<'a, Option<Vec<HashMap<String, serde_json::Value>>>> {
+        Box::pin(async { Ok(None) })
+    }
 }
 
 /// Configuration for connecting to services
@@ -0,0 +1,128 @@
+//! WebSocket endpoints
+//!
+//! Endpoints of kind `websocket` are not called once per request. The
+//! gateway performs the upgrade and then calls the handler for each event
+//! on the connection:
+//!
+//! ```ignore
+//! use rust_edge_gateway_sdk::prelude::*;
+//!
+//! pub fn on_connect(ctx: &Context, socket: &WebSocket, req: Request) {
+//!     let _ = socket.send_text(format!("welcome {}", socket.id()));
+//! }
+//!
+//! pub fn on_message(ctx: &Context, socket: &WebSocket, msg: Message) {
+//!     if let Message::Text(text) = msg {
+//!         let _ = socket.send_text(text); // echo
+//!     }
+//! }
+//!
+//! pub fn on_close(ctx: &Context, socket: &WebSocket, frame: Option<CloseFrame>) {}
+//! ```
+//!
+//! Callbacks for one connection run one at a time, in order. The
+//! [`WebSocket`] handle can be cloned and used from other threads to push
+//! frames at any time until the connection closes.
+
+use bytes::Bytes;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::request::Request;
+
+/// Error returned when sending on a closed connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("socket closed")]
+pub struct SocketClosed;
+
+/// A data frame sent or received on a WebSocket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Bytes),
+}
+
+/// Close code and reason of a closing handshake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+/// Event delivered to a WebSocket handler
+#[derive(Debug)]
+pub enum SocketEvent {
+    /// The connection was upgraded. Carries the upgrade request (without a body).
+    Connect(Box<Request>),
+
+    /// A text or binary frame arrived from the client
+    Message(Message),
+
+    /// The connection closed, with the client's close frame if it sent one
+    Close(Option<CloseFrame>),
+}
+
+/// Outgoing side of a connection, implemented by the gateway
+pub trait SocketSink: Send + Sync {
+    /// Queue a frame for the client
+    fn send(&self, message: Message) -> Result<(), SocketClosed>;
+
+    /// Start the closing handshake
+    fn close(&self, frame: CloseFrame);
+
+    /// Whether the connection is closed or closing
+    fn is_closed(&self) -> bool;
+}
+
+/// Handle to a WebSocket connection
+#[derive(Clone)]
+pub struct WebSocket {
+    id: String,
+    sink: Arc<dyn SocketSink>,
+}
+
+impl WebSocket {
+    /// Create a handle (used by the gateway).
+    pub fn new(id: impl Into<String>, sink: Arc<dyn SocketSink>) -> Self {
+        Self { id: id.into(), sink }
+    }
+
+    /// Unique id of this connection, stable across callbacks.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Send a text frame.
+    pub fn send_text(&self, text: impl Into<String>) -> Result<(), SocketClosed> {
+        self.sink.send(Message::Text(text.into()))
+    }
+
+    /// Send a binary frame.
+    pub fn send_binary(&self, data: impl Into<Bytes>) -> Result<(), SocketClosed> {
+        self.sink.send(Message::Binary(data.into()))
+    }
+
+    /// Send a frame.
+    pub fn send(&self, message: Message) -> Result<(), SocketClosed> {
+        self.sink.send(message)
+    }
+
+    /// Close the connection with a status code (e.g. 1000 for normal closure).
+    pub fn close(&self, code: u16, reason: impl Into<String>) {
+        self.sink.close(CloseFrame { code, reason: reason.into() });
+    }
+
+    /// Whether the connection is closed or closing.
+    pub fn is_closed(&self) -> bool {
+        self.sink.is_closed()
+    }
+}
+
+impl fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("id", &self.id)
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
@@ -0,0 +1,237 @@
+//! Streaming response bodies
+//!
+//! A [`BodyStream`] lets a handler return its response before the body is
+//! complete. The gateway sends each chunk to the client as it is produced,
+//! which suits large downloads, chunked output and Server-Sent Events.
+//!
+//! Chunks come either from an iterator or from a [`BodyWriter`] fed by a
+//! thread the handler spawns. The channel is bounded, so a writer blocks
+//! while the client is not keeping up. Once the client disconnects, writes
+//! fail with [`StreamClosed`] and the producer should stop.
+//!
+//! # Example
+//! ```ignore
+//! fn handle(ctx: &Context, req: Request) -> Response {
+//!     let (writer, stream) = BodyStream::channel(8);
+//!     std::thread::spawn(move || {
+//!         for i in 0..10 {
+//!             if writer.write(format!("line {}\n", i)).is_err() {
+//!                 break; // client went away
+//!             }
+//!         }
+//!     });
+//!     Response::stream(200, stream).with_header("Content-Type", "text/plain")
+//! }
+//! ```
+
+use bytes::Bytes;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+
+/// Error returned when writing to a stream whose client has gone away
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("stream closed")]
+pub struct StreamClosed;
+
+/// Where a stream's chunks come from
+enum Source {
+    Channel(Receiver<Bytes>),
+    Iter(Box<dyn Iterator<Item = Bytes> + Send>),
+}
+
+/// A response body produced incrementally by the handler.
+///
+/// The stream can be consumed once. Clones share the same underlying
+/// source, so only the first call to [`BodyStream::take`] gets the chunks.
+#[derive(Clone)]
+pub struct BodyStream {
+    source: Arc<Mutex<Option<Source>>>,
+}
+
+impl BodyStream {
+    fn new(source: Source) -> Self {
+        Self { source: Arc::new(Mutex::new(Some(source))) }
+    }
+
+    /// Create a stream fed by a [`BodyWriter`].
+    ///
+    /// `capacity` is the number of chunks buffered before `write` blocks.
+    pub fn channel(capacity: usize) -> (BodyWriter, BodyStream) {
+        let (tx, rx) = mpsc::sync_channel(capacity.max(1));
+        (BodyWriter { tx }, Self::new(Source::Channel(rx)))
+    }
+
+    /// Create a stream from an iterator of chunks.
+    ///
+    /// The iterator is advanced by the gateway as the client reads.
+    pub fn from_chunks<I, T>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+        T: Into<Bytes> + 'static,
+    {
+        Self::new(Source::Iter(Box::new(iter.into_iter().map(Into::into))))
+    }
+
+    /// Take the chunks out of the stream (used by the gateway).
+    ///
+    /// Returns None if the stream was already taken.
+    pub fn take(&self) -> Option<StreamReader> {
+        let mut source = self.source.lock().unwrap_or_else(|e| e.into_inner());
+        source.take().map(StreamReader)
+    }
+}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyStream").finish_non_exhaustive()
+    }
+}
+
+/// Blocking iterator over the chunks of a [`BodyStream`]
+///
+/// Dropping the reader closes the stream, so pending and future writes fail.
+pub struct StreamReader(Source);
+
+impl Iterator for StreamReader {
+    type Item = Bytes;
+
+    fn next(&mut self) -> Option<Bytes> {
+        match &mut self.0 {
+            Source::Channel(rx) => rx.recv().ok(),
+            Source::Iter(iter) => iter.next(),
+        }
+    }
+}
+
+/// Writing half of a channel-backed [`BodyStream`]
+///
+/// The body ends when every writer has been dropped.
+#[derive(Debug, Clone)]
+pub struct BodyWriter {
+    tx: SyncSender<Bytes>,
+}
+
+impl BodyWriter {
+    /// Send a chunk, blocking while the buffer is full.
+    pub fn write(&self, chunk: impl Into<Bytes>) -> Result<(), StreamClosed> {
+        let chunk = chunk.into();
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        self.tx.send(chunk).map_err(|_| StreamClosed)
+    }
+}
+
+/// Writer for Server-Sent Events
+///
+/// Pair with [`crate::Response::sse`].
+///
+/// # Example
+/// ```ignore
+/// let (writer, stream) = BodyStream::channel(16);
+/// let events = SseWriter::new(writer);
+/// std::thread::spawn(move || {
+///     for pct in (0..=100).step_by(10) {
+///         if events.event("progress", &pct.to_string()).is_err() {
+///             break;
+///         }
+///         std::thread::sleep(std::time::Duration::from_millis(500));
+///     }
+/// });
+/// Response::sse(stream)
+/// ```
+#[derive(Debug, Clone)]
+pub struct SseWriter {
+    writer: BodyWriter,
+}
+
+impl SseWriter {
+    /// Wrap a body writer.
+    pub fn new(writer: BodyWriter) -> Self {
+        Self { writer }
+    }
+
+    /// Send an unnamed event (delivered to `onmessage`).
+    pub fn data(&self, data: &str) -> Result<(), StreamClosed> {
+        self.writer.write(format_event(None, None, data))
+    }
+
+    /// Send a named event.
+    pub fn event(&self, name: &str, data: &str) -> Result<(), StreamClosed> {
+        self.writer.write(format_event(Some(name), None, data))
+    }
+
+    /// Send a named event with an id, which clients resend as `Last-Event-ID`
+    /// when reconnecting.
+    pub fn event_with_id(&self, name: &str, id: &str, data: &str) -> Result<(), StreamClosed> {
+        self.writer.write(format_event(Some(name), Some(id), data))
+    }
+
+    /// Send a comment line, commonly used as a keep-alive.
+    pub fn comment(&self, text: &str) -> Result<(), StreamClosed> {
+        self.writer.write(format!(": {}\n\n", single_line(text)))
+    }
+}
+
+/// Format an event. Multi-line data becomes one `data:` field per line.
+fn format_event(name: Option<&str>, id: Option<&str>, data: &str) -> String {
+    let mut out = String::new();
+    if let Some(name) = name {
+        out.push_str(&format!("event: {}\n", single_line(name)));
+    }
+    if let Some(id) = id {
+        out.push_str(&format!("id: {}\n", single_line(id)));
+    }
+    for line in data.split('\n') {
+        out.push_str("data: ");
+        out.push_str(line.strip_suffix('\r').unwrap_or(line));
+        out.push('\n');
+    }
+    out.push('\n');
+    out
+}
+
+/// Strip line breaks from a field that must stay on one line
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_stream_and_close() {
+        let (writer, stream) = BodyStream::channel(4);
+        writer.write("a").unwrap();
+        writer.write(Bytes::from_static(b"b")).unwrap();
+        drop(writer);
+
+        let copy = stream.clone();
+        let chunks: Vec<Bytes> = stream.take().unwrap().collect();
+        assert_eq!(chunks, vec![Bytes::from("a"), Bytes::from("b")]);
+        assert!(copy.take().is_none());
+
+        let (writer, stream) = BodyStream::channel(1);
+        drop(stream.take());
+        assert_eq!(writer.write("late"), Err(StreamClosed));
+    }
+
+    #[test]
+    fn test_iter_stream() {
+        let stream = BodyStream::from_chunks(vec!["x", "y"]);
+        let body: Vec<u8> = stream.take().unwrap().flat_map(|c| c.to_vec()).collect();
+        assert_eq!(body, b"xy");
+    }
+
+    #[test]
+    fn test_sse_format() {
+        assert_eq!(format_event(None, None, "hi"), "data: hi\n\n");
+        assert_eq!(
+            format_event(Some("progress"), Some("7"), "a\nb"),
+            "event: progress\nid: 7\ndata: a\ndata: b\n\n"
+        );
+    }
+}
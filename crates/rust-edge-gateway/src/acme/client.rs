@@ -0,0 +1,319 @@
+//! ACME protocol client (RFC 8555)
+//!
+//! Covers what HTTP-01 issuance needs: account registration, orders,
+//! authorizations, finalization and certificate download. Requests are JWS
+//! signed with the account's P-256 key (ES256).
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Delay between polls of a pending authorization or order
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Polls before giving up on an authorization or order
+const POLL_ATTEMPTS: usize = 30;
+
+/// Timeout for a single request to the ACME server
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The account's signing key
+pub struct AccountKey {
+    key: EcdsaKeyPair,
+    rng: SystemRandom,
+}
+
+impl AccountKey {
+    /// Generate a new P-256 key, returned as PKCS#8 DER
+    pub fn generate_pkcs8() -> Result<Vec<u8>> {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .map_err(|_| anyhow!("Failed to generate ACME account key"))?;
+        Ok(pkcs8.as_ref().to_vec())
+    }
+
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self> {
+        let rng = SystemRandom::new();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8, &rng)
+            .map_err(|e| anyhow!("Invalid ACME account key: {}", e))?;
+        Ok(Self { key, rng })
+    }
+
+    /// Public key as a JWK, members in the order RFC 7638 thumbprints use
+    fn jwk(&self) -> Value {
+        // Uncompressed point: 0x04 || x || y
+        let point = self.key.public_key().as_ref();
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        })
+    }
+
+    /// RFC 7638 thumbprint of the public key
+    pub fn thumbprint(&self) -> String {
+        let digest = ring::digest::digest(&ring::digest::SHA256, self.jwk().to_string().as_bytes());
+        URL_SAFE_NO_PAD.encode(digest.as_ref())
+    }
+
+    /// Key authorization for a challenge token
+    pub fn key_authorization(&self, token: &str) -> String {
+        format!("{}.{}", token, self.thumbprint())
+    }
+
+    /// Flattened JWS of `payload` (empty for POST-as-GET)
+    fn sign(&self, protected: Value, payload: &str) -> Result<Value> {
+        let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+        let payload = URL_SAFE_NO_PAD.encode(payload);
+        let signature = self.key
+            .sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+            .map_err(|_| anyhow!("Failed to sign ACME request"))?;
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+/// An order for a certificate
+#[derive(Debug, Deserialize)]
+pub struct Order {
+    pub status: String,
+    #[serde(default)]
+    pub authorizations: Vec<String>,
+    pub finalize: String,
+    pub certificate: Option<String>,
+}
+
+/// Proof of control required for one identifier
+#[derive(Debug, Deserialize)]
+pub struct Authorization {
+    pub status: String,
+    pub identifier: Identifier,
+    #[serde(default)]
+    pub challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Identifier {
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Challenge {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: String,
+    #[serde(default)]
+    pub token: String,
+    #[serde(default)]
+    error: Option<Problem>,
+}
+
+/// Error document returned by the ACME server
+#[derive(Debug, Default, Deserialize)]
+struct Problem {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    detail: String,
+}
+
+/// A session with an ACME server for one account
+pub struct AcmeClient {
+    http: reqwest::Client,
+    directory: Directory,
+    key: AccountKey,
+    account_url: Option<String>,
+    nonce: Option<String>,
+}
+
+impl AcmeClient {
+    /// Fetch the server's directory
+    ///
+    /// `ca_cert` is an extra PEM root to trust, for test servers such as
+    /// Pebble whose API runs on a private CA.
+    pub async fn connect(directory_url: &str, ca_cert: Option<&Path>, key: AccountKey) -> Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("rust-edge-gateway/", env!("CARGO_PKG_VERSION")));
+        if let Some(path) = ca_cert {
+            let pem = std::fs::read(path).with_context(|| format!("Failed to read ACME CA certificate {}", path.display()))?;
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+        }
+        let http = builder.build()?;
+
+        let directory = http.get(directory_url).send().await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Failed to fetch ACME directory {}", directory_url))?
+            .json()
+            .await
+            .context("Invalid ACME directory")?;
+
+        Ok(Self { http, directory, key, account_url: None, nonce: None })
+    }
+
+    pub fn key(&self) -> &AccountKey {
+        &self.key
+    }
+
+    /// Register the account, or look up the existing one for this key
+    pub async fn register(&mut self, email: Option<&str>) -> Result<()> {
+        let mut payload = json!({ "termsOfServiceAgreed": true });
+        if let Some(email) = email {
+            payload["contact"] = json!([format!("mailto:{}", email)]);
+        }
+        let url = self.directory.new_account.clone();
+        let response = self.post(&url, Some(&payload)).await?;
+        let location = location(&response).ok_or_else(|| anyhow!("ACME server returned no account URL"))?;
+        self.account_url = Some(location);
+        Ok(())
+    }
+
+    /// Place an order for `names`, returning its URL and contents
+    pub async fn new_order(&mut self, names: &[String]) -> Result<(String, Order)> {
+        let identifiers: Vec<Value> = names.iter().map(|n| json!({ "type": "dns", "value": n })).collect();
+        let url = self.directory.new_order.clone();
+        let response = self.post(&url, Some(&json!({ "identifiers": identifiers }))).await?;
+        let order_url = location(&response).ok_or_else(|| anyhow!("ACME server returned no order URL"))?;
+        Ok((order_url, response.json().await.context("Invalid ACME order")?))
+    }
+
+    pub async fn authorization(&mut self, url: &str) -> Result<Authorization> {
+        self.post(url, None).await?.json().await.context("Invalid ACME authorization")
+    }
+
+    /// Tell the server a challenge is ready and wait for the authorization
+    pub async fn complete_challenge(&mut self, challenge_url: &str, authorization_url: &str) -> Result<()> {
+        self.post(challenge_url, Some(&json!({}))).await?;
+
+        for _ in 0..POLL_ATTEMPTS {
+            let authorization = self.authorization(authorization_url).await?;
+            match authorization.status.as_str() {
+                "valid" => return Ok(()),
+                "pending" | "processing" => tokio::time::sleep(POLL_INTERVAL).await,
+                status => {
+                    let detail = authorization.challenges.iter()
+                        .find_map(|c| c.error.as_ref())
+                        .map(|p| p.detail.as_str())
+                        .unwrap_or_default();
+                    bail!("Authorization for {} is {}: {}", authorization.identifier.value, status, detail);
+                }
+            }
+        }
+        bail!("Timed out waiting for authorization {}", authorization_url)
+    }
+
+    /// Submit the CSR and wait for the certificate to be issued
+    pub async fn finalize(&mut self, order_url: &str, finalize_url: &str, csr: &[u8]) -> Result<Order> {
+        self.post(finalize_url, Some(&json!({ "csr": URL_SAFE_NO_PAD.encode(csr) }))).await?;
+
+        for _ in 0..POLL_ATTEMPTS {
+            let order: Order = self.post(order_url, None).await?.json().await.context("Invalid ACME order")?;
+            match order.status.as_str() {
+                "valid" => return Ok(order),
+                "pending" | "ready" | "processing" => tokio::time::sleep(POLL_INTERVAL).await,
+                status => bail!("Order {} is {}", order_url, status),
+            }
+        }
+        bail!("Timed out waiting for order {}", order_url)
+    }
+
+    /// Download the issued certificate chain as PEM
+    pub async fn certificate(&mut self, url: &str) -> Result<String> {
+        let response = self.post(url, None).await?;
+        Ok(response.text().await?)
+    }
+
+    async fn fresh_nonce(&mut self) -> Result<String> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let response = self.http.head(&self.directory.new_nonce).send().await?;
+        replay_nonce(&response).ok_or_else(|| anyhow!("ACME server returned no nonce"))
+    }
+
+    /// Send a signed request, retrying once on a stale nonce
+    async fn post(&mut self, url: &str, payload: Option<&Value>) -> Result<reqwest::Response> {
+        let payload = payload.map(Value::to_string).unwrap_or_default();
+
+        for attempt in 0..2 {
+            let mut protected = json!({ "alg": "ES256", "nonce": self.fresh_nonce().await?, "url": url });
+            match &self.account_url {
+                Some(kid) => protected["kid"] = json!(kid),
+                None => protected["jwk"] = self.key.jwk(),
+            }
+            let body = self.key.sign(protected, &payload)?;
+
+            let response = self.http.post(url)
+                .header("content-type", "application/jose+json")
+                .body(body.to_string())
+                .send()
+                .await
+                .with_context(|| format!("ACME request to {} failed", url))?;
+            self.nonce = replay_nonce(&response);
+
+            if response.status().is_success() {
+                return Ok(response);
+            }
+            let status = response.status();
+            let problem: Problem = response.json().await.unwrap_or_default();
+            if attempt == 0 && problem.kind == "urn:ietf:params:acme:error:badNonce" {
+                continue;
+            }
+            bail!("ACME server returned {} for {}: {} {}", status, url, problem.kind, problem.detail);
+        }
+        unreachable!("the second attempt always returns")
+    }
+}
+
+fn replay_nonce(response: &reqwest::Response) -> Option<String> {
+    response.headers().get("replay-nonce")?.to_str().ok().map(String::from)
+}
+
+fn location(response: &reqwest::Response) -> Option<String> {
+    response.headers().get("location")?.to_str().ok().map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+
+    #[test]
+    fn test_jws_and_key_authorization() {
+        let key = AccountKey::from_pkcs8(&AccountKey::generate_pkcs8().unwrap()).unwrap();
+        let jws = key.sign(json!({ "alg": "ES256", "url": "https://acme.test/new-order" }), "{}").unwrap();
+
+        let signed = format!("{}.{}", jws["protected"].as_str().unwrap(), jws["payload"].as_str().unwrap());
+        let signature = URL_SAFE_NO_PAD.decode(jws["signature"].as_str().unwrap()).unwrap();
+        assert_eq!(signature.len(), 64);
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, key.key.public_key().as_ref())
+            .verify(signed.as_bytes(), &signature)
+            .unwrap();
+
+        // Thumbprint input is the JWK with members sorted and no whitespace
+        let jwk = key.jwk().to_string();
+        assert!(jwk.starts_with(r#"{"crv":"P-256","kty":"EC","x":""#));
+        let authorization = key.key_authorization("token-1");
+        assert_eq!(authorization, format!("token-1.{}", key.thumbprint()));
+        assert_eq!(key.thumbprint().len(), 43);
+    }
+}
@@ -0,0 +1,179 @@
+//! Just enough DER for ACME
+//!
+//! Builds the PKCS#10 certificate signing request sent to finalize an order
+//! and reads the validity period out of an X.509 certificate.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const OID: u8 = 0x06;
+const UTF8_STRING: u8 = 0x0c;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const CONTEXT_0: u8 = 0xa0;
+const DNS_NAME: u8 = 0x82;
+
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_EXTENSION_REQUEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+
+/// Encode one tag-length-value
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Encode a constructed value from already encoded parts
+fn constructed(tag: u8, parts: &[Vec<u8>]) -> Vec<u8> {
+    tlv(tag, &parts.concat())
+}
+
+/// Build a DER certificate signing request for `names`, signed with a P-256 key
+///
+/// The first name is the subject common name; all names go in the subject
+/// alternative name extension.
+pub fn certificate_request(names: &[String], pkcs8: &[u8]) -> Result<Vec<u8>> {
+    let first = names.first().ok_or_else(|| anyhow!("A certificate request needs at least one name"))?;
+    let rng = SystemRandom::new();
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8, &rng)
+        .map_err(|e| anyhow!("Invalid certificate key: {}", e))?;
+
+    let subject = constructed(SEQUENCE, &[constructed(SET, &[constructed(SEQUENCE, &[
+        tlv(OID, OID_COMMON_NAME),
+        tlv(UTF8_STRING, first.as_bytes()),
+    ])])]);
+
+    let public_key = constructed(SEQUENCE, &[
+        constructed(SEQUENCE, &[tlv(OID, OID_EC_PUBLIC_KEY), tlv(OID, OID_PRIME256V1)]),
+        tlv(BIT_STRING, &[&[0u8][..], key.public_key().as_ref()].concat()),
+    ]);
+
+    let alt_names = constructed(SEQUENCE, &names.iter().map(|n| tlv(DNS_NAME, n.as_bytes())).collect::<Vec<_>>());
+    let attributes = constructed(CONTEXT_0, &[constructed(SEQUENCE, &[
+        tlv(OID, OID_EXTENSION_REQUEST),
+        constructed(SET, &[constructed(SEQUENCE, &[constructed(SEQUENCE, &[
+            tlv(OID, OID_SUBJECT_ALT_NAME),
+            tlv(OCTET_STRING, &alt_names),
+        ])])]),
+    ])]);
+
+    let info = constructed(SEQUENCE, &[tlv(INTEGER, &[0]), subject, public_key, attributes]);
+    let signature = key.sign(&rng, &info).map_err(|_| anyhow!("Failed to sign certificate request"))?;
+
+    Ok(constructed(SEQUENCE, &[
+        info,
+        constructed(SEQUENCE, &[tlv(OID, OID_ECDSA_WITH_SHA256)]),
+        tlv(BIT_STRING, &[&[0u8][..], signature.as_ref()].concat()),
+    ]))
+}
+
+/// Split one tag-length-value off the front of `input`
+///
+/// Returns the tag, the content and the remaining input.
+fn read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, mut rest) = rest.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let (bytes, after) = rest.split_at_checked(count)?;
+        rest = after;
+        bytes.iter().fold(0, |len, &b| (len << 8) | b as usize)
+    };
+    let (content, rest) = rest.split_at_checked(len)?;
+    Some((tag, content, rest))
+}
+
+/// The `notBefore` and `notAfter` dates of a DER X.509 certificate
+pub fn validity(cert: &[u8]) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let (SEQUENCE, cert, _) = read(cert)? else { return None };
+    let (SEQUENCE, tbs, _) = read(cert)? else { return None };
+
+    // Optional version, then serial number, signature algorithm and issuer
+    let (tag, _, mut rest) = read(tbs)?;
+    if tag == CONTEXT_0 {
+        (_, _, rest) = read(rest)?;
+    }
+    let (_, _, rest) = read(rest)?;
+    let (_, _, rest) = read(rest)?;
+
+    let (SEQUENCE, validity, _) = read(rest)? else { return None };
+    let (tag, not_before, rest) = read(validity)?;
+    let not_before = parse_time(tag, not_before)?;
+    let (tag, not_after, _) = read(rest)?;
+    Some((not_before, parse_time(tag, not_after)?))
+}
+
+fn parse_time(tag: u8, time: &[u8]) -> Option<DateTime<Utc>> {
+    let time = std::str::from_utf8(time).ok()?;
+    let time = match tag {
+        UTC_TIME => {
+            // Two-digit years 50-99 are 19xx, 00-49 are 20xx
+            let century = if time.get(..2)? >= "50" { "19" } else { "20" };
+            NaiveDateTime::parse_from_str(&format!("{}{}", century, time), "%Y%m%d%H%M%SZ").ok()?
+        }
+        GENERALIZED_TIME => NaiveDateTime::parse_from_str(time, "%Y%m%d%H%M%SZ").ok()?,
+        _ => return None,
+    };
+    Some(time.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+
+    #[test]
+    fn test_certificate_request_is_signed() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let names = vec!["example.com".to_string(), "www.example.com".to_string()];
+        let csr = certificate_request(&names, pkcs8.as_ref()).unwrap();
+
+        let (SEQUENCE, body, []) = read(&csr).unwrap() else { panic!("not a sequence") };
+        let (_, _, rest) = read(body).unwrap();
+        let info = &body[..body.len() - rest.len()];
+        let (_, _, rest) = read(rest).unwrap();
+        let (BIT_STRING, signature, []) = read(rest).unwrap() else { panic!("no signature") };
+
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, key.public_key().as_ref())
+            .verify(info, &signature[1..])
+            .unwrap();
+        assert!(csr.windows(15).any(|w| w == b"www.example.com"));
+        assert!(certificate_request(&[], pkcs8.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_long_lengths_round_trip() {
+        let content = vec![7u8; 300];
+        let encoded = tlv(OCTET_STRING, &content);
+        assert_eq!(&encoded[..4], &[OCTET_STRING, 0x82, 0x01, 0x2c]);
+        assert_eq!(read(&encoded), Some((OCTET_STRING, &content[..], &[][..])));
+        assert_eq!(read(&encoded[..100]), None);
+    }
+}
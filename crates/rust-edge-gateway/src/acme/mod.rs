@@ -0,0 +1,328 @@
+//! Automatic certificates through ACME
+//!
+//! Domains whose `tls.provider` is `letsencrypt` get their certificate from
+//! an ACME server (Let's Encrypt by default) using the HTTP-01 challenge:
+//! the gateway itself answers `/.well-known/acme-challenge/{token}` on its
+//! plain HTTP port while an order is in progress.
+//!
+//! Issued certificates are written to the domain's directory in the
+//! [`CertStore`] like any other certificate. [`run`] checks the domains every
+//! [`CHECK_INTERVAL`] (and right after domain changes) and orders a new
+//! certificate when none is installed or the installed one expires within
+//! the renewal window. The account key is kept in `<data dir>/acme`.
+//!
+//! The directory URL and an extra trusted CA can be configured, so the whole
+//! flow can be run against a local test server such as Pebble.
+
+pub mod client;
+pub mod der;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context as _, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use dashmap::DashMap;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+use tokio::sync::{Mutex, Notify};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::PrivatePkcs8KeyDer;
+
+use crate::api::Domain;
+use crate::net::tls::{write_private, CertStore};
+use crate::AppState;
+use client::{AccountKey, AcmeClient};
+
+/// Let's Encrypt production directory
+pub const LETS_ENCRYPT_DIRECTORY: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// Path prefix the HTTP-01 challenge is fetched from
+pub const CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+/// How often domains are checked for missing or expiring certificates
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long to wait before retrying a domain whose issuance failed
+const RETRY_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Account key file in the ACME directory
+const ACCOUNT_KEY_FILE: &str = "account.key";
+
+/// ACME settings
+#[derive(Debug, Clone)]
+pub struct AcmeConfig {
+    /// Directory URL of the ACME server
+    pub directory_url: String,
+
+    /// Extra PEM CA certificate trusted for the ACME server's API
+    pub ca_cert: Option<PathBuf>,
+
+    /// Renew certificates that expire within this many days
+    pub renew_before_days: u32,
+}
+
+/// A failed issuance, kept to delay retries and for the certificate status
+#[derive(Debug, Clone)]
+struct Failure {
+    at: Instant,
+    error: String,
+}
+
+/// Issues and renews certificates for `letsencrypt` domains
+pub struct AcmeManager {
+    config: AcmeConfig,
+
+    /// Holds the account key
+    dir: PathBuf,
+
+    /// Pending HTTP-01 challenges: token -> key authorization
+    challenges: DashMap<String, String>,
+
+    /// Last failure by domain host, cleared on success
+    failures: DashMap<String, Failure>,
+
+    /// Wakes [`run`] before the next interval
+    wake: Notify,
+
+    /// Only one issuance talks to the ACME server at a time
+    issuing: Mutex<()>,
+}
+
+impl AcmeManager {
+    pub fn new(config: AcmeConfig, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            config,
+            dir: dir.into(),
+            challenges: DashMap::new(),
+            failures: DashMap::new(),
+            wake: Notify::new(),
+            issuing: Mutex::new(()),
+        }
+    }
+
+    /// Key authorization for a pending challenge token
+    pub fn challenge(&self, token: &str) -> Option<String> {
+        self.challenges.get(token).map(|v| v.clone())
+    }
+
+    /// Check the domains now instead of at the next interval
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Error of the last failed issuance for a domain host
+    pub fn last_error(&self, host: &str) -> Option<String> {
+        self.failures.get(host).map(|f| f.error.clone())
+    }
+
+    /// Issue certificates for the domains that need one
+    ///
+    /// Domains that failed within [`RETRY_DELAY`] are skipped. Returns the
+    /// number of certificates issued.
+    pub async fn renew_due(&self, certs: &CertStore, domains: &[Domain]) -> usize {
+        let mut issued = 0;
+        for domain in domains.iter().filter(|d| wants_certificate(d)) {
+            if !self.is_due(certs, domain) {
+                continue;
+            }
+            if self.failures.get(&domain.host).is_some_and(|f| f.at.elapsed() < RETRY_DELAY) {
+                continue;
+            }
+            if self.issue(certs, domain).await.is_ok() {
+                issued += 1;
+            }
+        }
+        issued
+    }
+
+    /// Whether a domain has no certificate or one inside the renewal window
+    ///
+    /// The window is the configured number of days, but at most a third of
+    /// the certificate's lifetime so short-lived certificates are not
+    /// reordered on every check.
+    fn is_due(&self, certs: &CertStore, domain: &Domain) -> bool {
+        let Some((not_before, not_after)) = certs.validity(&domain.host) else {
+            return true;
+        };
+        let window = chrono::Duration::days(self.config.renew_before_days.into()).min((not_after - not_before) / 3);
+        not_after - chrono::Utc::now() < window
+    }
+
+    /// Order a certificate for a domain and install it
+    ///
+    /// The result is also recorded for [`AcmeManager::last_error`]. The
+    /// certificate store is not reloaded.
+    pub async fn issue(&self, certs: &CertStore, domain: &Domain) -> Result<()> {
+        let _issuing = self.issuing.lock().await;
+        tracing::info!(domain = %domain.name, host = %domain.host, "Requesting certificate from {}", self.config.directory_url);
+
+        match self.order(certs, domain).await {
+            Ok(()) => {
+                tracing::info!(domain = %domain.name, host = %domain.host, "Certificate issued");
+                self.failures.remove(&domain.host);
+                Ok(())
+            }
+            Err(e) => {
+                tracing::warn!(domain = %domain.name, host = %domain.host, "Certificate issuance failed: {:#}", e);
+                self.failures.insert(domain.host.clone(), Failure { at: Instant::now(), error: format!("{:#}", e) });
+                Err(e)
+            }
+        }
+    }
+
+    async fn order(&self, certs: &CertStore, domain: &Domain) -> Result<()> {
+        let names = certificate_names(domain)?;
+        let key = AccountKey::from_pkcs8(&self.account_key()?)?;
+        let mut client = AcmeClient::connect(&self.config.directory_url, self.config.ca_cert.as_deref(), key).await?;
+
+        let email = domain.settings.tls.as_ref().and_then(|t| t.email.as_deref());
+        client.register(email).await?;
+        let (order_url, order) = client.new_order(&names).await?;
+
+        for url in &order.authorizations {
+            let authorization = client.authorization(url).await?;
+            if authorization.status == "valid" {
+                continue;
+            }
+            let challenge = authorization.challenges.iter()
+                .find(|c| c.kind == "http-01")
+                .ok_or_else(|| anyhow!("No HTTP-01 challenge offered for {}", authorization.identifier.value))?;
+
+            self.challenges.insert(challenge.token.clone(), client.key().key_authorization(&challenge.token));
+            let result = client.complete_challenge(&challenge.url, url).await;
+            self.challenges.remove(&challenge.token);
+            result?;
+        }
+
+        let rng = SystemRandom::new();
+        let cert_key = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .map_err(|_| anyhow!("Failed to generate certificate key"))?;
+        let csr = der::certificate_request(&names, cert_key.as_ref())?;
+
+        let order = client.finalize(&order_url, &order.finalize, &csr).await?;
+        let url = order.certificate.ok_or_else(|| anyhow!("Order is valid but has no certificate URL"))?;
+        let chain = client.certificate(&url).await?;
+
+        certs.install(&domain.host, &chain, &pem("PRIVATE KEY", cert_key.as_ref()))?;
+        Ok(())
+    }
+
+    /// Load the account key, creating it on first use
+    fn account_key(&self) -> Result<Vec<u8>> {
+        let path = self.dir.join(ACCOUNT_KEY_FILE);
+        if path.is_file() {
+            let key = PrivatePkcs8KeyDer::from_pem_file(&path)
+                .map_err(|e| anyhow!("Invalid ACME account key {}: {}", path.display(), e))?;
+            return Ok(key.secret_pkcs8_der().to_vec());
+        }
+
+        let key = AccountKey::generate_pkcs8()?;
+        std::fs::create_dir_all(&self.dir)?;
+        write_private(&path, pem("PRIVATE KEY", &key).as_bytes())
+            .with_context(|| format!("Failed to write ACME account key {}", path.display()))?;
+        tracing::info!("Created ACME account key {}", path.display());
+        Ok(key)
+    }
+}
+
+/// Whether a domain gets its certificate from ACME
+pub fn wants_certificate(domain: &Domain) -> bool {
+    domain.enabled
+        && domain.settings.tls.as_ref().is_some_and(|t| t.provider == "letsencrypt" && t.http_challenge)
+}
+
+/// Names to put on a domain's certificate: its host and aliases
+///
+/// HTTP-01 cannot prove control of a wildcard, so wildcard aliases are left
+/// out and a wildcard host is an error.
+fn certificate_names(domain: &Domain) -> Result<Vec<String>> {
+    if domain.host.starts_with("*.") {
+        return Err(anyhow!("Wildcard host {} needs a DNS-01 challenge, which is not supported", domain.host));
+    }
+    let mut names = vec![domain.host.clone()];
+    for alias in &domain.aliases {
+        if !alias.starts_with("*.") && !names.contains(alias) {
+            names.push(alias.clone());
+        }
+    }
+    Ok(names)
+}
+
+/// PEM-encode DER data
+fn pem(label: &str, der: &[u8]) -> String {
+    let encoded = STANDARD.encode(der);
+    let mut out = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        out.push('\n');
+    }
+    out.push_str(&format!("-----END {}-----\n", label));
+    out
+}
+
+/// Keep certificates of `letsencrypt` domains issued and renewed
+///
+/// Runs for the life of the gateway, checking every [`CHECK_INTERVAL`] and
+/// whenever [`AcmeManager::wake`] is called.
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        match state.db.list_domains() {
+            Ok(domains) => {
+                if state.acme.renew_due(&state.certs, &domains).await > 0 {
+                    state.reload_certificates();
+                }
+            }
+            Err(e) => tracing::error!("Failed to list domains for certificate renewal: {}", e),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+            _ = state.acme.wake.notified() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::DomainSettings;
+
+    fn domain(host: &str, aliases: &[&str], tls: &str) -> Domain {
+        Domain {
+            id: host.into(),
+            name: host.into(),
+            host: host.into(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            description: None,
+            enabled: true,
+            settings: DomainSettings { tls: Some(serde_json::from_str(tls).unwrap()), ..Default::default() },
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_domain_selection_and_names() {
+        let acme = domain("example.com", &["www.example.com", "*.example.com", "example.com"], r#"{"provider": "letsencrypt"}"#);
+        assert!(wants_certificate(&acme));
+        assert_eq!(certificate_names(&acme).unwrap(), vec!["example.com", "www.example.com"]);
+
+        assert!(!wants_certificate(&domain("a.test", &[], r#"{"provider": "manual"}"#)));
+        assert!(!wants_certificate(&domain("a.test", &[], r#"{"provider": "letsencrypt", "http_challenge": false}"#)));
+        assert!(certificate_names(&domain("*.example.com", &[], r#"{"provider": "letsencrypt"}"#)).is_err());
+    }
+
+    #[test]
+    fn test_account_key_is_created_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AcmeConfig { directory_url: LETS_ENCRYPT_DIRECTORY.into(), ca_cert: None, renew_before_days: 30 };
+        let manager = AcmeManager::new(config, dir.path().join("acme"));
+
+        let key = manager.account_key().unwrap();
+        assert_eq!(manager.account_key().unwrap(), key);
+        assert!(AccountKey::from_pkcs8(&key).is_ok());
+    }
+}
@@ -2,13 +2,15 @@
 
 use axum::{
     extract::{Request, State},
-    http::{header::AUTHORIZATION, Method, StatusCode},
+    http::{header::AUTHORIZATION, Method},
     middleware::Next,
     response::Response,
 };
 use std::sync::Arc;
 
 use crate::db_admin::{AdminDatabase, ApiKey};
+use crate::net::RequestId;
+use crate::router::problem::{Problem, ProblemKind};
 use crate::AppState;
 
 /// Helper function to validate API key and check permissions
@@ -18,41 +20,37 @@ fn validate_api_key_with_permission_sync(
     auth_header: &str,
     method: &Method,
     resource: &str,
-) -> Result<ApiKey, (StatusCode, String)> {
+) -> Result<ApiKey, Problem> {
     // Extract API key from Bearer auth
     if !auth_header.starts_with("Bearer ") {
-        return Err((StatusCode::UNAUTHORIZED, "API key required".to_string()));
+        return Err(unauthorized("API key required"));
     }
 
     let api_key_str = &auth_header[7..];
 
     // Get API key from database
-    let admin_db = AdminDatabase::new(&state.config.data_dir).map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to initialize admin database".to_string(),
-        )
+    let admin_db = AdminDatabase::new(&state.config.data_dir).map_err(|e| {
+        tracing::error!("Failed to initialize admin database: {}", e);
+        Problem::from(ProblemKind::InternalError)
     })?;
 
     let key = admin_db
         .get_api_key_by_value(api_key_str)
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to query admin database".to_string(),
-            )
+        .map_err(|e| {
+            tracing::error!("Failed to query admin database: {}", e);
+            Problem::from(ProblemKind::InternalError)
         })?
-        .ok_or((StatusCode::UNAUTHORIZED, "Invalid API key".to_string()))?;
+        .ok_or_else(|| unauthorized("Invalid API key"))?;
 
     // Check if API key is enabled
     if !key.enabled {
-        return Err((StatusCode::UNAUTHORIZED, "API key is disabled".to_string()));
+        return Err(unauthorized("API key is disabled"));
     }
 
     // Check if API key has expired
     if let Some(expires_at) = key.expires_at {
         if chrono::Utc::now() > expires_at {
-            return Err((StatusCode::UNAUTHORIZED, "API key has expired".to_string()));
+            return Err(unauthorized("API key has expired"));
         }
     }
 
@@ -66,24 +64,30 @@ fn validate_api_key_with_permission_sync(
     if !key.permissions.contains(&required_permission)
         && !key.permissions.contains(&wildcard_permission)
     {
-        return Err((
-            StatusCode::FORBIDDEN,
-            format!(
-                "API key does not have '{}' permission (requires '{}' or '{}')",
-                resource, required_permission, wildcard_permission
-            ),
-        ));
+        return Err(Problem::from(ProblemKind::Forbidden).with_detail(format!(
+            "API key does not have '{}' permission (requires '{}' or '{}')",
+            resource, required_permission, wildcard_permission
+        )));
     }
 
     Ok(key)
 }
 
+fn unauthorized(detail: &str) -> Problem {
+    Problem::from(ProblemKind::Unauthorized).with_detail(detail)
+}
+
+/// Render an authentication failure as a problem document
+fn reject(problem: Problem, request: &Request) -> Response {
+    problem.render(&RequestId::of(request).0, request.headers(), None)
+}
+
 /// API key authentication middleware for endpoints API
 pub async fn endpoints_api_key_auth(
     State(state): State<Arc<AppState>>,
     request: Request,
     next: Next,
-) -> Result<Response, (StatusCode, String)> {
+) -> Result<Response, Response> {
     let auth_header = request
         .headers()
         .get(AUTHORIZATION)
@@ -91,7 +95,8 @@ pub async fn endpoints_api_key_auth(
         .unwrap_or("")
         .to_string();
     let method = request.method().clone();
-    validate_api_key_with_permission_sync(&state, &auth_header, &method, "endpoints")?;
+    validate_api_key_with_permission_sync(&state, &auth_header, &method, "endpoints")
+        .map_err(|problem| reject(problem, &request))?;
     Ok(next.run(request).await)
 }
 
@@ -100,7 +105,7 @@ pub async fn services_api_key_auth(
     State(state): State<Arc<AppState>>,
     request: Request,
     next: Next,
-) -> Result<Response, (StatusCode, String)> {
+) -> Result<Response, Response> {
     let auth_header = request
         .headers()
         .get(AUTHORIZATION)
@@ -108,7 +113,8 @@ pub async fn services_api_key_auth(
         .unwrap_or("")
         .to_string();
     let method = request.method().clone();
-    validate_api_key_with_permission_sync(&state, &auth_header, &method, "services")?;
+    validate_api_key_with_permission_sync(&state, &auth_header, &method, "services")
+        .map_err(|problem| reject(problem, &request))?;
     Ok(next.run(request).await)
 }
 
@@ -117,7 +123,7 @@ pub async fn domains_api_key_auth(
     State(state): State<Arc<AppState>>,
     request: Request,
     next: Next,
-) -> Result<Response, (StatusCode, String)> {
+) -> Result<Response, Response> {
     let auth_header = request
         .headers()
         .get(AUTHORIZATION)
@@ -125,7 +131,8 @@ pub async fn domains_api_key_auth(
         .unwrap_or("")
         .to_string();
     let method = request.method().clone();
-    validate_api_key_with_permission_sync(&state, &auth_header, &method, "domains")?;
+    validate_api_key_with_permission_sync(&state, &auth_header, &method, "domains")
+        .map_err(|problem| reject(problem, &request))?;
     Ok(next.run(request).await)
 }
 
@@ -134,7 +141,7 @@ pub async fn collections_api_key_auth(
     State(state): State<Arc<AppState>>,
     request: Request,
     next: Next,
-) -> Result<Response, (StatusCode, String)> {
+) -> Result<Response, Response> {
     let auth_header = request
         .headers()
         .get(AUTHORIZATION)
@@ -142,6 +149,7 @@ pub async fn collections_api_key_auth(
         .unwrap_or("")
         .to_string();
     let method = request.method().clone();
-    validate_api_key_with_permission_sync(&state, &auth_header, &method, "collections")?;
+    validate_api_key_with_permission_sync(&state, &auth_header, &method, "collections")
+        .map_err(|problem| reject(problem, &request))?;
     Ok(next.run(request).await)
 }
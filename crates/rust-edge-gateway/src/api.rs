@@ -9,6 +9,18 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::router::cache::CacheStats;
+use crate::router::compression::CompressionSettings;
+use crate::router::cors::CorsSettings;
+use crate::router::middleware::{self, Middleware, MiddlewareKind};
+use crate::router::problem::ErrorPageSettings;
+use crate::router::proxy::ProxySettings;
+use crate::router::rules::{Outcome, Rule, RuleRequest, RuleSet};
+use crate::router::site::SiteSettings;
+use crate::router::upstream::{PoolStats, UpstreamSettings};
+use crate::runtime::bundle::manifest::{BundleManifest, TlsConfig};
+use crate::runtime::canary::{candidate_build_id, CanarySettings, VersionStats};
+use crate::runtime::mirror::{shadow_build_id, MirrorSettings, MirrorStats};
 use crate::AppState;
 
 // ============================================================================
@@ -19,29 +31,146 @@ use crate::AppState;
 pub struct Domain {
     pub id: String,
     pub name: String,
+    /// Host name, or a wildcard such as `*.example.com` matching one subdomain label
     pub host: String,
+    /// Additional hosts (exact or wildcard) served by this domain
+    #[serde(default)]
+    pub aliases: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     pub enabled: bool,
+    #[serde(default)]
+    pub settings: DomainSettings,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<String>,
 }
 
+/// Per-domain overrides of gateway-wide defaults
+///
+/// Stored as JSON in the `settings` column. Unset fields fall back to the
+/// gateway configuration.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DomainSettings {
+    /// Response compression policy (defaults to `RUST_EDGE_GATEWAY_COMPRESSION*`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionSettings>,
+
+    /// CORS policy applied to preflights and responses (no CORS headers if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cors: Option<CorsSettings>,
+
+    /// HTML pages for gateway errors, shown to clients that prefer HTML
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_pages: Option<ErrorPageSettings>,
+
+    /// Static site served for GET and HEAD requests that match no endpoint,
+    /// usually set from a bundle's `site` section
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site: Option<SiteSettings>,
+
+    /// TLS settings, usually set from a bundle's `tls` section. A provider
+    /// of `none` keeps the domain off the TLS port.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+
+    /// Middleware run for every route of the domain
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub middleware: Vec<Middleware>,
+}
+
+impl DomainSettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(compression) = &self.compression {
+            compression.validate()?;
+        }
+        if let Some(cors) = &self.cors {
+            cors.validate()?;
+        }
+        if let Some(error_pages) = &self.error_pages {
+            error_pages.validate()?;
+        }
+        if let Some(site) = &self.site {
+            site.validate()?;
+        }
+        if let Some(tls) = &self.tls {
+            tls.validate()?;
+        }
+        middleware::validate_list(&self.middleware)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateDomainRequest {
     pub name: String,
     pub host: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
     pub description: Option<String>,
+    #[serde(default)]
+    pub settings: DomainSettings,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateDomainRequest {
     pub name: Option<String>,
     pub host: Option<String>,
+    pub aliases: Option<Vec<String>>,
     pub description: Option<String>,
     pub enabled: Option<bool>,
+    pub settings: Option<DomainSettings>,
+}
+
+// ============================================================================
+// Domain rules - Redirects and rewrites run before endpoint matching
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainRule {
+    pub id: String,
+    pub domain_id: String,
+    /// Rules run in ascending position
+    pub position: i64,
+    pub rule: Rule,
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDomainRuleRequest {
+    pub rule: Rule,
+    /// Defaults to after the domain's last rule
+    pub position: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDomainRuleRequest {
+    pub rule: Option<Rule>,
+    pub position: Option<i64>,
+    pub enabled: Option<bool>,
+}
+
+/// A URL to run through a domain's rules
+#[derive(Debug, Deserialize)]
+pub struct TestRulesRequest {
+    /// Full URL, or a path on the domain's host
+    pub url: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestRulesResponse {
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    /// Endpoint the request is routed to, when it is not redirected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
 }
 
 // ============================================================================
@@ -57,12 +186,35 @@ pub struct Collection {
     pub description: Option<String>,
     pub base_path: String,
     pub enabled: bool,
+    #[serde(default)]
+    pub settings: CollectionSettings,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<String>,
 }
 
+/// Settings shared by a collection's endpoints
+///
+/// Stored as JSON in the `settings` column.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CollectionSettings {
+    /// Middleware run for every endpoint of the collection
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub middleware: Vec<Middleware>,
+
+    /// Domain middleware that does not run for the collection
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_middleware: Vec<MiddlewareKind>,
+}
+
+impl CollectionSettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        middleware::validate_list(&self.middleware)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateCollectionRequest {
     pub domain_id: String,
@@ -70,6 +222,8 @@ pub struct CreateCollectionRequest {
     pub description: Option<String>,
     #[serde(default)]
     pub base_path: String,
+    #[serde(default)]
+    pub settings: CollectionSettings,
 }
 
 #[derive(Debug, Deserialize)]
@@ -78,6 +232,7 @@ pub struct UpdateCollectionRequest {
     pub description: Option<String>,
     pub base_path: Option<String>,
     pub enabled: Option<bool>,
+    pub settings: Option<CollectionSettings>,
 }
 
 // ============================================================================
@@ -159,6 +314,57 @@ pub struct UpdateServiceRequest {
     pub enabled: Option<bool>,
 }
 
+// ============================================================================
+// Upstream - Named pools of backend URLs for proxy endpoints
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Upstream {
+    pub id: String,
+    /// Name proxy endpoints refer to in their `pool` setting
+    pub name: String,
+    /// Base URLs of the backends
+    pub targets: Vec<String>,
+    /// Balancing, health checks and outlier detection
+    #[serde(default)]
+    pub settings: UpstreamSettings,
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+impl Upstream {
+    /// Check that the pool is usable
+    pub fn validate(&self) -> Result<(), String> {
+        crate::router::upstream::validate_name(&self.name)?;
+        if self.targets.is_empty() {
+            return Err("An upstream needs at least one target".to_string());
+        }
+        for target in &self.targets {
+            crate::router::proxy::validate_base_url(target)?;
+        }
+        self.settings.validate()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUpstreamRequest {
+    pub name: String,
+    pub targets: Vec<String>,
+    #[serde(default)]
+    pub settings: UpstreamSettings,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUpstreamRequest {
+    pub name: Option<String>,
+    pub targets: Option<Vec<String>>,
+    pub settings: Option<UpstreamSettings>,
+    pub enabled: Option<bool>,
+}
+
 // ============================================================================
 // Endpoint - API endpoints within collections
 // ============================================================================
@@ -172,6 +378,9 @@ pub struct Endpoint {
     pub domain: String,
     pub path: String,
     pub method: String,
+    /// How requests to this endpoint are served
+    #[serde(default)]
+    pub kind: EndpointKind,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -182,12 +391,112 @@ pub struct Endpoint {
     pub dependencies: Option<serde_json::Value>,
     pub compiled: bool,
     pub enabled: bool,
+    /// Per-endpoint overrides of gateway defaults
+    #[serde(default)]
+    pub settings: EndpointSettings,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<String>,
 }
 
+impl Endpoint {
+    /// Check that the endpoint's kind, method and settings fit together
+    pub fn validate(&self) -> Result<(), String> {
+        if self.kind == EndpointKind::WebSocket && self.method != "GET" {
+            return Err("WebSocket endpoints must use method GET".to_string());
+        }
+        match (self.kind, &self.settings.proxy) {
+            (EndpointKind::Proxy, None) => return Err("Proxy endpoints need a proxy setting".to_string()),
+            (EndpointKind::Handler | EndpointKind::WebSocket, Some(_)) => {
+                return Err("The proxy setting is only allowed on proxy endpoints".to_string());
+            }
+            _ => {}
+        }
+        self.settings.validate()
+    }
+}
+
+/// How an endpoint serves requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EndpointKind {
+    /// Compiled handler called once per request
+    #[default]
+    Handler,
+    /// Compiled handler called for the events of WebSocket connections
+    WebSocket,
+    /// Requests forwarded to an upstream URL, without handler code
+    Proxy,
+}
+
+impl EndpointKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EndpointKind::Handler => "handler",
+            EndpointKind::WebSocket => "websocket",
+            EndpointKind::Proxy => "proxy",
+        }
+    }
+}
+
+impl std::str::FromStr for EndpointKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "handler" => Ok(EndpointKind::Handler),
+            "websocket" => Ok(EndpointKind::WebSocket),
+            "proxy" => Ok(EndpointKind::Proxy),
+            other => Err(format!("Unknown endpoint kind '{}'", other)),
+        }
+    }
+}
+
+/// Per-endpoint overrides of gateway-wide defaults
+///
+/// Stored as JSON in the `settings` column. Unset fields fall back to the
+/// gateway configuration.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EndpointSettings {
+    /// Maximum request body size in bytes (defaults to `RUST_EDGE_GATEWAY_MAX_BODY_SIZE`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_size: Option<usize>,
+
+    /// Handler timeout in seconds (defaults to `RUST_EDGE_GATEWAY_HANDLER_TIMEOUT_SECS`);
+    /// for proxy endpoints, how long to wait for the upstream's response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+
+    /// Upstream of a proxy endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<ProxySettings>,
+
+    /// Middleware run for the endpoint
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub middleware: Vec<Middleware>,
+
+    /// Domain or collection middleware that does not run for the endpoint
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_middleware: Vec<MiddlewareKind>,
+}
+
+impl EndpointSettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_body_size == Some(0) {
+            return Err("max_body_size must be greater than 0".to_string());
+        }
+        if self.timeout_secs == Some(0) {
+            return Err("timeout_secs must be greater than 0".to_string());
+        }
+        if let Some(proxy) = &self.proxy {
+            proxy.validate()?;
+        }
+        middleware::validate_list(&self.middleware)
+    }
+}
+
 /// Request to create a new endpoint
 #[derive(Debug, Deserialize)]
 pub struct CreateEndpointRequest {
@@ -197,11 +506,15 @@ pub struct CreateEndpointRequest {
     pub path: String,
     #[serde(default = "default_method")]
     pub method: String,
+    #[serde(default)]
+    pub kind: EndpointKind,
     pub description: Option<String>,
     pub code: Option<String>,
     /// Custom Cargo dependencies for this handler.
     /// Format mirrors Cargo.toml: {"regex": "1.10", "chrono": {"version": "0.4", "features": ["serde"]}}
     pub dependencies: Option<serde_json::Value>,
+    #[serde(default)]
+    pub settings: EndpointSettings,
 }
 
 fn default_method() -> String {
@@ -216,11 +529,15 @@ pub struct UpdateEndpointRequest {
     pub domain: Option<String>,
     pub path: Option<String>,
     pub method: Option<String>,
+    /// Changing the kind of a handler endpoint requires recompiling it
+    pub kind: Option<EndpointKind>,
     pub description: Option<String>,
     pub enabled: Option<bool>,
     /// Custom Cargo dependencies for this handler.
     /// Format mirrors Cargo.toml: {"regex": "1.10", "chrono": {"version": "0.4", "features": ["serde"]}}
     pub dependencies: Option<serde_json::Value>,
+    /// Replaces the endpoint's settings
+    pub settings: Option<EndpointSettings>,
 }
 
 /// Code update request
@@ -257,14 +574,32 @@ pub async fn health_check() -> Json<ApiResponse<&'static str>> {
 pub struct Stats {
     pub endpoint_count: i64,
     pub active_workers: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_cache: Option<CacheStats>,
+    /// Health and traffic of each enabled upstream pool
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub upstreams: Vec<PoolStats>,
 }
 
 pub async fn get_stats(State(state): State<Arc<AppState>>) -> Json<ApiResponse<Stats>> {
     let endpoint_count = state.db.endpoint_count().unwrap_or(0);
     let workers = state.workers.read().await;
     let active_workers = workers.active_count();
+    let response_cache = state.response_cache.as_ref().map(|c| c.stats());
+    let upstreams = state.upstreams.stats();
 
-    Json(ApiResponse::ok(Stats { endpoint_count, active_workers }))
+    Json(ApiResponse::ok(Stats { endpoint_count, active_workers, response_cache, upstreams }))
+}
+
+/// Drop every cached response
+pub async fn purge_response_cache(State(state): State<Arc<AppState>>) -> Json<ApiResponse<()>> {
+    match &state.response_cache {
+        Some(cache) => {
+            cache.purge();
+            Json(ApiResponse::ok(()))
+        }
+        None => Json(ApiResponse::err("Response cache is disabled")),
+    }
 }
 
 /// List all endpoints
@@ -292,17 +627,30 @@ pub async fn create_endpoint(
         domain: req.domain,
         path: req.path,
         method: req.method.to_uppercase(),
+        kind: req.kind,
         description: req.description,
         code: req.code,
         dependencies: req.dependencies,
         compiled: false,
         enabled: false,
+        settings: req.settings,
         created_at: None,
         updated_at: None,
     };
 
+    if let Err(e) = endpoint.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    if let Err(e) = check_route(&state, &endpoint) {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
     match state.db.create_endpoint(&endpoint) {
-        Ok(_) => Ok(Json(ApiResponse::ok(endpoint))),
+        Ok(_) => {
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(endpoint)))
+        }
         Err(e) => {
             tracing::error!("Failed to create endpoint: {}", e);
             Ok(Json(ApiResponse::err(e.to_string())))
@@ -334,6 +682,7 @@ pub async fn update_endpoint(
         Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
     };
 
+    let kind = req.kind.unwrap_or(existing.kind);
     let updated = Endpoint {
         id: existing.id,
         collection_id: req.collection_id.or(existing.collection_id),
@@ -341,21 +690,66 @@ pub async fn update_endpoint(
         domain: req.domain.unwrap_or(existing.domain),
         path: req.path.unwrap_or(existing.path),
         method: req.method.map(|m| m.to_uppercase()).unwrap_or(existing.method),
+        kind,
         description: req.description.or(existing.description),
         code: existing.code,
         dependencies: req.dependencies.or(existing.dependencies),
-        compiled: existing.compiled,
+        compiled: existing.compiled && kind == existing.kind,
         enabled: req.enabled.unwrap_or(existing.enabled),
+        settings: req.settings.unwrap_or(existing.settings),
         created_at: existing.created_at,
         updated_at: existing.updated_at,
     };
 
+    if let Err(e) = updated.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    if let Err(e) = check_route(&state, &updated) {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
     match state.db.update_endpoint(&updated) {
-        Ok(_) => Ok(Json(ApiResponse::ok(updated))),
+        Ok(_) => {
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(updated)))
+        }
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
 
+/// Check that an endpoint's route is valid and does not clash with any other
+/// endpoint (enabled or not), so that it can be started later without conflict
+fn check_route(state: &AppState, endpoint: &Endpoint) -> Result<(), String> {
+    let data = routing_data(state).map_err(|e| e.to_string())?;
+    crate::router::table::RouteTable::check(&data, endpoint).map_err(|e| e.to_string())
+}
+
+/// Check the routes of a batch of new endpoints against the saved endpoints
+/// and each other, before any of them is saved
+///
+/// `collection` is a collection the batch is about to be created in.
+fn check_routes(state: &AppState, collection: Option<&Collection>, endpoints: &[Endpoint]) -> Result<(), String> {
+    let mut data = routing_data(state).map_err(|e| e.to_string())?;
+    data.collections.extend(collection.cloned());
+    for endpoint in endpoints {
+        crate::router::table::RouteTable::check(&data, endpoint)
+            .map_err(|e| format!("Endpoint '{}': {}", endpoint.name, e))?;
+        data.endpoints.push(endpoint.clone());
+    }
+    Ok(())
+}
+
+/// Domains, collections and endpoints as saved, for checking routes
+fn routing_data(state: &AppState) -> anyhow::Result<crate::router::table::RoutingData> {
+    Ok(crate::router::table::RoutingData {
+        domains: state.db.list_domains()?,
+        collections: state.db.list_collections(None)?,
+        endpoints: state.db.list_endpoints()?,
+        ..Default::default()
+    })
+}
+
 /// Delete an endpoint
 pub async fn delete_endpoint(
     State(state): State<Arc<AppState>>,
@@ -365,7 +759,10 @@ pub async fn delete_endpoint(
     let _ = state.handler_registry.unload(&id).await;
 
     match state.db.delete_endpoint(&id) {
-        Ok(_) => Ok(Json(ApiResponse::ok(()))),
+        Ok(_) => {
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(())))
+        }
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
@@ -389,7 +786,11 @@ pub async fn update_endpoint_code(
     Json(req): Json<UpdateCodeRequest>,
 ) -> Result<Json<ApiResponse<()>>, StatusCode> {
     match state.db.update_endpoint_code(&id, &req.code) {
-        Ok(_) => Ok(Json(ApiResponse::ok(()))),
+        Ok(_) => {
+            // The route table keeps a copy of the compiled flag, now cleared
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(())))
+        }
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
@@ -405,15 +806,20 @@ pub async fn compile_endpoint(
         Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
     };
 
+    if endpoint.kind == EndpointKind::Proxy {
+        return Ok(Json(ApiResponse::err("Proxy endpoints have no code to compile")));
+    }
+
     let code = match endpoint.code {
         Some(c) => c,
         None => return Ok(Json(ApiResponse::err("No code to compile"))),
     };
 
     // Compile the handler
-    match crate::compiler::compile_handler(&state.config, &id, &code, endpoint.dependencies.as_ref()).await {
+    match crate::compiler::compile_handler(&state.config, &id, &code, endpoint.dependencies.as_ref(), endpoint.kind).await {
         Ok(binary_path) => {
             state.db.mark_compiled(&id, true).ok();
+            state.reload_routes();
             Ok(Json(ApiResponse::ok(format!("Compiled to {}", binary_path))))
         }
         Err(e) => Ok(Json(ApiResponse::err(format!("Compilation failed: {}", e)))),
@@ -426,16 +832,28 @@ pub async fn start_endpoint(
     Path(id): Path<String>,
 ) -> Result<Json<ApiResponse<()>>, StatusCode> {
     let endpoint = match state.db.get_endpoint(&id) {
-        Ok(Some(e)) if e.compiled => e,
+        Ok(Some(e)) if e.compiled || e.kind == EndpointKind::Proxy => e,
         Ok(Some(_)) => return Ok(Json(ApiResponse::err("Endpoint not compiled"))),
         Ok(None) => return Ok(Json(ApiResponse::err("Endpoint not found"))),
         Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
     };
 
+    // Proxy endpoints have no handler; starting one only adds its route
+    if endpoint.kind == EndpointKind::Proxy {
+        return match state.db.update_endpoint(&Endpoint { enabled: true, ..endpoint }) {
+            Ok(_) => {
+                state.reload_routes();
+                Ok(Json(ApiResponse::ok(())))
+            }
+            Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+        };
+    }
+
     // Use v2 handler registry to load the compiled handler
     match state.handler_registry.load(&endpoint.id).await {
         Ok(_) => {
             state.db.update_endpoint(&Endpoint { enabled: true, ..endpoint }).ok();
+            state.reload_routes();
             Ok(Json(ApiResponse::ok(())))
         }
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
@@ -448,7 +866,337 @@ pub async fn stop_endpoint(
     Path(id): Path<String>,
 ) -> Result<Json<ApiResponse<()>>, StatusCode> {
     // Use v2 handler registry to unload the handler
-    match state.handler_registry.unload(&id).await {
+    if let Err(e) = state.handler_registry.unload(&id).await {
+        return Ok(Json(ApiResponse::err(e.to_string())));
+    }
+
+    // Disable the endpoint so it is dropped from the route table and is not
+    // reloaded on the next startup; unloading also ended any canary release
+    // and mirroring
+    if let Ok(Some(endpoint)) = state.db.get_endpoint(&id) {
+        state.db.update_endpoint(&Endpoint { enabled: false, ..endpoint }).ok();
+    }
+    state.db.delete_canary(&id).ok();
+    state.db.delete_mirror(&id).ok();
+    state.reload_routes();
+
+    Ok(Json(ApiResponse::ok(())))
+}
+
+// ============================================================================
+// Canary Releases - A candidate handler version taking part of the traffic
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Canary {
+    pub endpoint_id: String,
+    /// Handler code of the candidate version
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dependencies: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub settings: CanarySettings,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartCanaryRequest {
+    pub code: String,
+    /// Dependencies of the candidate (default: the endpoint's)
+    pub dependencies: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub settings: CanarySettings,
+}
+
+/// A canary release with the counters of both versions
+#[derive(Debug, Serialize)]
+pub struct CanaryStatus {
+    #[serde(flatten)]
+    pub canary: Canary,
+    pub primary: Option<VersionStats>,
+    pub candidate: Option<VersionStats>,
+}
+
+/// How long a replaced handler version may finish its requests
+fn drain_timeout(state: &AppState) -> std::time::Duration {
+    std::time::Duration::from_secs(state.config.shutdown_timeout_secs)
+}
+
+async fn canary_status(state: &AppState, canary: Canary) -> CanaryStatus {
+    let (primary, candidate) = state.handler_registry.version_stats(&canary.endpoint_id).await;
+    CanaryStatus { canary, primary, candidate }
+}
+
+/// Get the canary release of an endpoint with per-version counters
+pub async fn get_canary(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<CanaryStatus>>, StatusCode> {
+    match state.db.get_canary(&id) {
+        Ok(Some(canary)) => Ok(Json(ApiResponse::ok(canary_status(&state, canary).await))),
+        Ok(None) => Ok(Json(ApiResponse::err("No canary release for this endpoint"))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Compile a candidate version of a running endpoint and route part of its
+/// traffic to it, replacing any previous candidate
+pub async fn start_canary(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(req): Json<StartCanaryRequest>,
+) -> Result<Json<ApiResponse<CanaryStatus>>, StatusCode> {
+    let endpoint = match state.db.get_endpoint(&id) {
+        Ok(Some(e)) => e,
+        Ok(None) => return Ok(Json(ApiResponse::err("Endpoint not found"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+    if endpoint.kind == EndpointKind::Proxy {
+        return Ok(Json(ApiResponse::err("Proxy endpoints have no handler to release")));
+    }
+    if let Err(e) = req.settings.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+    if !state.handler_registry.is_loaded(&id).await {
+        return Ok(Json(ApiResponse::err("Endpoint is not running; start it before releasing a candidate")));
+    }
+
+    let dependencies = req.dependencies.or(endpoint.dependencies);
+    let build_id = candidate_build_id(&id);
+    if let Err(e) = crate::compiler::compile_handler(&state.config, &build_id, &req.code, dependencies.as_ref(), endpoint.kind).await {
+        return Ok(Json(ApiResponse::err(format!("Compilation failed: {}", e))));
+    }
+    if let Err(e) = state.handler_registry.load_candidate(&id, req.settings.clone(), drain_timeout(&state)).await {
+        return Ok(Json(ApiResponse::err(e.to_string())));
+    }
+
+    let canary = Canary {
+        endpoint_id: id,
+        code: req.code,
+        dependencies,
+        settings: req.settings,
+        created_at: None,
+        updated_at: None,
+    };
+    if let Err(e) = state.db.create_canary(&canary) {
+        return Ok(Json(ApiResponse::err(e.to_string())));
+    }
+    Ok(Json(ApiResponse::ok(canary_status(&state, canary).await)))
+}
+
+/// Change the weight or stickiness of a canary release
+pub async fn update_canary(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(settings): Json<CanarySettings>,
+) -> Result<Json<ApiResponse<CanaryStatus>>, StatusCode> {
+    if let Err(e) = settings.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+    let canary = match state.db.get_canary(&id) {
+        Ok(Some(c)) => c,
+        Ok(None) => return Ok(Json(ApiResponse::err("No canary release for this endpoint"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+    if !state.handler_registry.set_canary_settings(&id, settings.clone()).await {
+        return Ok(Json(ApiResponse::err("Candidate handler is not loaded")));
+    }
+
+    match state.db.update_canary_settings(&id, &settings) {
+        Ok(_) => Ok(Json(ApiResponse::ok(canary_status(&state, Canary { settings, ..canary }).await))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Make the candidate the endpoint's handler for all traffic
+///
+/// The candidate's code becomes the endpoint's code; the previous version drains.
+pub async fn promote_canary(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let canary = match state.db.get_canary(&id) {
+        Ok(Some(c)) => c,
+        Ok(None) => return Ok(Json(ApiResponse::err("No canary release for this endpoint"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+    let endpoint = match state.db.get_endpoint(&id) {
+        Ok(Some(e)) => e,
+        Ok(None) => return Ok(Json(ApiResponse::err("Endpoint not found"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+    if let Err(e) = state.handler_registry.promote(&id, drain_timeout(&state)).await {
+        return Ok(Json(ApiResponse::err(e.to_string())));
+    }
+
+    let stored = state.db.update_endpoint_code(&id, &canary.code)
+        .and_then(|_| state.db.update_endpoint(&Endpoint {
+            dependencies: canary.dependencies,
+            compiled: true,
+            ..endpoint
+        }))
+        .and_then(|_| state.db.delete_canary(&id));
+    match stored {
+        Ok(_) => {
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(())))
+        }
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Stop routing traffic to the candidate and unload it
+pub async fn abort_canary(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    state.handler_registry.abort_candidate(&id, drain_timeout(&state)).await;
+    match state.db.delete_canary(&id) {
+        Ok(_) => Ok(Json(ApiResponse::ok(()))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+// ============================================================================
+// Traffic Mirroring - A shadow handler version receiving a copy of the traffic
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mirror {
+    pub endpoint_id: String,
+    /// Handler code of the shadow version
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dependencies: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub settings: MirrorSettings,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartMirrorRequest {
+    pub code: String,
+    /// Dependencies of the shadow (default: the endpoint's)
+    pub dependencies: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub settings: MirrorSettings,
+}
+
+/// A mirrored endpoint with the counters of both versions and the comparison
+#[derive(Debug, Serialize)]
+pub struct MirrorStatus {
+    #[serde(flatten)]
+    pub mirror: Mirror,
+    pub primary: Option<VersionStats>,
+    pub shadow: Option<VersionStats>,
+    pub comparison: Option<MirrorStats>,
+}
+
+async fn mirror_status(state: &AppState, mirror: Mirror) -> MirrorStatus {
+    let primary = state.handler_registry.get(&mirror.endpoint_id).await.map(|h| h.stats());
+    let shadow = state.handler_registry.shadow(&mirror.endpoint_id).await;
+    MirrorStatus {
+        mirror,
+        primary,
+        shadow: shadow.as_ref().map(|s| s.handler.stats()),
+        comparison: shadow.map(|s| s.counters.snapshot()),
+    }
+}
+
+/// Get the mirror of an endpoint with its comparison counters
+pub async fn get_mirror(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<MirrorStatus>>, StatusCode> {
+    match state.db.get_mirror(&id) {
+        Ok(Some(mirror)) => Ok(Json(ApiResponse::ok(mirror_status(&state, mirror).await))),
+        Ok(None) => Ok(Json(ApiResponse::err("Endpoint is not mirrored"))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Compile a shadow version of a running endpoint and mirror its requests
+/// to it, replacing any previous shadow
+pub async fn start_mirror(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(req): Json<StartMirrorRequest>,
+) -> Result<Json<ApiResponse<MirrorStatus>>, StatusCode> {
+    let endpoint = match state.db.get_endpoint(&id) {
+        Ok(Some(e)) => e,
+        Ok(None) => return Ok(Json(ApiResponse::err("Endpoint not found"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+    if endpoint.kind != EndpointKind::Handler {
+        return Ok(Json(ApiResponse::err("Only handler endpoints can be mirrored")));
+    }
+    if let Err(e) = req.settings.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+    if !state.handler_registry.is_loaded(&id).await {
+        return Ok(Json(ApiResponse::err("Endpoint is not running; start it before mirroring it")));
+    }
+
+    let dependencies = req.dependencies.or(endpoint.dependencies);
+    let build_id = shadow_build_id(&id);
+    if let Err(e) = crate::compiler::compile_handler(&state.config, &build_id, &req.code, dependencies.as_ref(), endpoint.kind).await {
+        return Ok(Json(ApiResponse::err(format!("Compilation failed: {}", e))));
+    }
+    if let Err(e) = state.handler_registry.load_shadow(&id, req.settings.clone(), drain_timeout(&state)).await {
+        return Ok(Json(ApiResponse::err(e.to_string())));
+    }
+
+    let mirror = Mirror {
+        endpoint_id: id,
+        code: req.code,
+        dependencies,
+        settings: req.settings,
+        created_at: None,
+        updated_at: None,
+    };
+    if let Err(e) = state.db.create_mirror(&mirror) {
+        return Ok(Json(ApiResponse::err(e.to_string())));
+    }
+    Ok(Json(ApiResponse::ok(mirror_status(&state, mirror).await)))
+}
+
+/// Change which requests are mirrored and how responses are compared
+pub async fn update_mirror(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(settings): Json<MirrorSettings>,
+) -> Result<Json<ApiResponse<MirrorStatus>>, StatusCode> {
+    if let Err(e) = settings.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+    let mirror = match state.db.get_mirror(&id) {
+        Ok(Some(m)) => m,
+        Ok(None) => return Ok(Json(ApiResponse::err("Endpoint is not mirrored"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+    if !state.handler_registry.set_mirror_settings(&id, settings.clone()).await {
+        return Ok(Json(ApiResponse::err("Shadow handler is not loaded")));
+    }
+
+    match state.db.update_mirror_settings(&id, &settings) {
+        Ok(_) => Ok(Json(ApiResponse::ok(mirror_status(&state, Mirror { settings, ..mirror }).await))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Stop mirroring requests and unload the shadow
+pub async fn stop_mirror(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    state.handler_registry.remove_shadow(&id, drain_timeout(&state)).await;
+    match state.db.delete_mirror(&id) {
         Ok(_) => Ok(Json(ApiResponse::ok(()))),
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
@@ -477,18 +1225,48 @@ pub async fn create_domain(
         id: Uuid::new_v4().to_string(),
         name: req.name,
         host: req.host,
+        aliases: req.aliases,
         description: req.description,
         enabled: true,
+        settings: req.settings,
         created_at: None,
         updated_at: None,
     };
 
+    if let Err(e) = domain.settings.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    let domain = match normalize_domain_hosts(domain) {
+        Ok(d) => d,
+        Err(e) => return Ok(Json(ApiResponse::err(e))),
+    };
+
     match state.db.create_domain(&domain) {
-        Ok(_) => Ok(Json(ApiResponse::ok(domain))),
+        Ok(_) => {
+            state.reload_routes();
+            state.reload_certificates();
+            state.acme.wake();
+            Ok(Json(ApiResponse::ok(domain)))
+        }
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
 
+/// Validate and normalize a domain's host and aliases
+fn normalize_domain_hosts(mut domain: Domain) -> Result<Domain, String> {
+    use crate::router::table::normalize_host_pattern;
+
+    domain.host = normalize_host_pattern(&domain.host).map_err(|e| e.to_string())?;
+    domain.aliases = domain.aliases.iter()
+        .map(|a| normalize_host_pattern(a).map_err(|e| e.to_string()))
+        .collect::<Result<_, _>>()?;
+    domain.aliases.retain(|a| *a != domain.host);
+    domain.aliases.sort();
+    domain.aliases.dedup();
+    Ok(domain)
+}
+
 /// Get a domain by ID
 pub async fn get_domain(
     State(state): State<Arc<AppState>>,
@@ -517,14 +1295,30 @@ pub async fn update_domain(
         id: existing.id,
         name: req.name.unwrap_or(existing.name),
         host: req.host.unwrap_or(existing.host),
+        aliases: req.aliases.unwrap_or(existing.aliases),
         description: req.description.or(existing.description),
         enabled: req.enabled.unwrap_or(existing.enabled),
+        settings: req.settings.unwrap_or(existing.settings),
         created_at: existing.created_at,
         updated_at: existing.updated_at,
     };
 
+    if let Err(e) = updated.settings.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    let updated = match normalize_domain_hosts(updated) {
+        Ok(d) => d,
+        Err(e) => return Ok(Json(ApiResponse::err(e))),
+    };
+
     match state.db.update_domain(&updated) {
-        Ok(_) => Ok(Json(ApiResponse::ok(updated))),
+        Ok(_) => {
+            state.reload_routes();
+            state.reload_certificates();
+            state.acme.wake();
+            Ok(Json(ApiResponse::ok(updated)))
+        }
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
@@ -534,112 +1328,402 @@ pub async fn delete_domain(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
 ) -> Result<Json<ApiResponse<()>>, StatusCode> {
-    match state.db.delete_domain(&id) {
-        Ok(_) => Ok(Json(ApiResponse::ok(()))),
+    match state.db.delete_domain(&id) {
+        Ok(_) => {
+            state.reload_routes();
+            state.reload_certificates();
+            Ok(Json(ApiResponse::ok(())))
+        }
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// TLS certificate status of a domain
+#[derive(Debug, Serialize)]
+pub struct CertificateStatus {
+    /// Host whose directory holds the certificate files
+    pub host: String,
+    /// Whether certificate files are present
+    pub installed: bool,
+    /// Whether the certificate is loaded and served on the TLS port
+    pub serving: bool,
+    /// Expiry of the installed leaf certificate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// Error from the last failed ACME issuance, if the domain uses ACME
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acme_error: Option<String>,
+}
+
+/// PEM certificate chain and private key for a domain
+#[derive(Debug, Deserialize)]
+pub struct UploadCertificateRequest {
+    /// Leaf certificate followed by any intermediates
+    pub certificate: String,
+    pub private_key: String,
+}
+
+fn certificate_status(state: &AppState, domain: &Domain) -> CertificateStatus {
+    CertificateStatus {
+        host: domain.host.clone(),
+        installed: state.certs.is_installed(&domain.host),
+        serving: state.certs.is_serving(&domain.host),
+        expires_at: state.certs.validity(&domain.host).map(|(_, end)| end.to_rfc3339()),
+        acme_error: state.acme.last_error(&domain.host),
+    }
+}
+
+/// Get a domain's TLS certificate status
+pub async fn get_domain_certificate(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<CertificateStatus>>, StatusCode> {
+    match state.db.get_domain(&id) {
+        Ok(Some(domain)) => Ok(Json(ApiResponse::ok(certificate_status(&state, &domain)))),
+        Ok(None) => Ok(Json(ApiResponse::err("Domain not found"))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Upload (or replace) a domain's TLS certificate
+///
+/// The new certificate is served to new connections immediately.
+pub async fn upload_domain_certificate(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(req): Json<UploadCertificateRequest>,
+) -> Result<Json<ApiResponse<CertificateStatus>>, StatusCode> {
+    let domain = match state.db.get_domain(&id) {
+        Ok(Some(d)) => d,
+        Ok(None) => return Ok(Json(ApiResponse::err("Domain not found"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+
+    if let Err(e) = state.certs.install(&domain.host, &req.certificate, &req.private_key) {
+        return Ok(Json(ApiResponse::err(format!("Failed to install certificate: {}", e))));
+    }
+    state.reload_certificates();
+    Ok(Json(ApiResponse::ok(certificate_status(&state, &domain))))
+}
+
+/// Remove a domain's TLS certificate
+pub async fn delete_domain_certificate(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<CertificateStatus>>, StatusCode> {
+    let domain = match state.db.get_domain(&id) {
+        Ok(Some(d)) => d,
+        Ok(None) => return Ok(Json(ApiResponse::err("Domain not found"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+
+    if let Err(e) = state.certs.uninstall(&domain.host) {
+        return Ok(Json(ApiResponse::err(format!("Failed to remove certificate: {}", e))));
+    }
+    state.reload_certificates();
+    Ok(Json(ApiResponse::ok(certificate_status(&state, &domain))))
+}
+
+/// Order a new certificate for a `letsencrypt` domain now
+///
+/// Waits for the ACME order to complete, which can take a while.
+pub async fn renew_domain_certificate(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<CertificateStatus>>, StatusCode> {
+    let domain = match state.db.get_domain(&id) {
+        Ok(Some(d)) => d,
+        Ok(None) => return Ok(Json(ApiResponse::err("Domain not found"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+    if !crate::acme::wants_certificate(&domain) {
+        return Ok(Json(ApiResponse::err("Domain is not enabled with tls.provider letsencrypt and http_challenge")));
+    }
+
+    if let Err(e) = state.acme.issue(&state.certs, &domain).await {
+        return Ok(Json(ApiResponse::err(format!("Certificate issuance failed: {:#}", e))));
+    }
+    state.reload_certificates();
+    Ok(Json(ApiResponse::ok(certificate_status(&state, &domain))))
+}
+
+// ============================================================================
+// Collection API Handlers
+// ============================================================================
+
+/// List collections (optionally by domain)
+pub async fn list_collections(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<Vec<Collection>>>, StatusCode> {
+    match state.db.list_collections(None) {
+        Ok(collections) => Ok(Json(ApiResponse::ok(collections))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// List collections for a domain
+pub async fn list_domain_collections(
+    State(state): State<Arc<AppState>>,
+    Path(domain_id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<Collection>>>, StatusCode> {
+    match state.db.list_collections(Some(&domain_id)) {
+        Ok(collections) => Ok(Json(ApiResponse::ok(collections))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Create a new collection
+pub async fn create_collection(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateCollectionRequest>,
+) -> Result<Json<ApiResponse<Collection>>, StatusCode> {
+    if let Err(e) = req.settings.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    let collection = Collection {
+        id: Uuid::new_v4().to_string(),
+        domain_id: req.domain_id,
+        name: req.name,
+        description: req.description,
+        base_path: req.base_path,
+        enabled: true,
+        settings: req.settings,
+        created_at: None,
+        updated_at: None,
+    };
+
+    match state.db.create_collection(&collection) {
+        Ok(_) => {
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(collection)))
+        }
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Get a collection by ID
+pub async fn get_collection(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<Collection>>, StatusCode> {
+    match state.db.get_collection(&id) {
+        Ok(Some(collection)) => Ok(Json(ApiResponse::ok(collection))),
+        Ok(None) => Ok(Json(ApiResponse::err("Collection not found"))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Update a collection
+pub async fn update_collection(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateCollectionRequest>,
+) -> Result<Json<ApiResponse<Collection>>, StatusCode> {
+    let existing = match state.db.get_collection(&id) {
+        Ok(Some(c)) => c,
+        Ok(None) => return Ok(Json(ApiResponse::err("Collection not found"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+
+    let updated = Collection {
+        id: existing.id,
+        domain_id: existing.domain_id,
+        name: req.name.unwrap_or(existing.name),
+        description: req.description.or(existing.description),
+        base_path: req.base_path.unwrap_or(existing.base_path),
+        enabled: req.enabled.unwrap_or(existing.enabled),
+        settings: req.settings.unwrap_or(existing.settings),
+        created_at: existing.created_at,
+        updated_at: existing.updated_at,
+    };
+    if let Err(e) = updated.settings.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    match state.db.update_collection(&updated) {
+        Ok(_) => {
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(updated)))
+        }
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Delete a collection
+pub async fn delete_collection(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    match state.db.delete_collection(&id) {
+        Ok(_) => {
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(())))
+        }
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
 
+
+
 // ============================================================================
-// Collection API Handlers
+// Domain Rule API Handlers
 // ============================================================================
 
-/// List collections (optionally by domain)
-pub async fn list_collections(
+/// List a domain's rules in evaluation order
+pub async fn list_domain_rules(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<ApiResponse<Vec<Collection>>>, StatusCode> {
-    match state.db.list_collections(None) {
-        Ok(collections) => Ok(Json(ApiResponse::ok(collections))),
+    Path(domain_id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<DomainRule>>>, StatusCode> {
+    match state.db.list_domain_rules(Some(&domain_id)) {
+        Ok(rules) => Ok(Json(ApiResponse::ok(rules))),
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
 
-/// List collections for a domain
-pub async fn list_domain_collections(
+/// Add a rule to a domain
+pub async fn create_domain_rule(
     State(state): State<Arc<AppState>>,
     Path(domain_id): Path<String>,
-) -> Result<Json<ApiResponse<Vec<Collection>>>, StatusCode> {
-    match state.db.list_collections(Some(&domain_id)) {
-        Ok(collections) => Ok(Json(ApiResponse::ok(collections))),
-        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    Json(req): Json<CreateDomainRuleRequest>,
+) -> Result<Json<ApiResponse<DomainRule>>, StatusCode> {
+    if let Err(e) = req.rule.validate() {
+        return Ok(Json(ApiResponse::err(e)));
     }
-}
+    match state.db.get_domain(&domain_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Ok(Json(ApiResponse::err("Domain not found"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+    let position = match req.position {
+        Some(position) => position,
+        None => match state.db.list_domain_rules(Some(&domain_id)) {
+            Ok(rules) => rules.iter().map(|r| r.position + 1).max().unwrap_or(0),
+            Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+        },
+    };
 
-/// Create a new collection
-pub async fn create_collection(
-    State(state): State<Arc<AppState>>,
-    Json(req): Json<CreateCollectionRequest>,
-) -> Result<Json<ApiResponse<Collection>>, StatusCode> {
-    let collection = Collection {
+    let rule = DomainRule {
         id: Uuid::new_v4().to_string(),
-        domain_id: req.domain_id,
-        name: req.name,
-        description: req.description,
-        base_path: req.base_path,
+        domain_id,
+        position,
+        rule: req.rule,
         enabled: true,
         created_at: None,
         updated_at: None,
     };
 
-    match state.db.create_collection(&collection) {
-        Ok(_) => Ok(Json(ApiResponse::ok(collection))),
+    match state.db.create_domain_rule(&rule) {
+        Ok(_) => {
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(rule)))
+        }
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
 
-/// Get a collection by ID
-pub async fn get_collection(
+/// Get a rule by ID
+pub async fn get_domain_rule(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-) -> Result<Json<ApiResponse<Collection>>, StatusCode> {
-    match state.db.get_collection(&id) {
-        Ok(Some(collection)) => Ok(Json(ApiResponse::ok(collection))),
-        Ok(None) => Ok(Json(ApiResponse::err("Collection not found"))),
+) -> Result<Json<ApiResponse<DomainRule>>, StatusCode> {
+    match state.db.get_domain_rule(&id) {
+        Ok(Some(rule)) => Ok(Json(ApiResponse::ok(rule))),
+        Ok(None) => Ok(Json(ApiResponse::err("Rule not found"))),
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
 
-/// Update a collection
-pub async fn update_collection(
+/// Update a rule
+pub async fn update_domain_rule(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-    Json(req): Json<UpdateCollectionRequest>,
-) -> Result<Json<ApiResponse<Collection>>, StatusCode> {
-    let existing = match state.db.get_collection(&id) {
-        Ok(Some(c)) => c,
-        Ok(None) => return Ok(Json(ApiResponse::err("Collection not found"))),
+    Json(req): Json<UpdateDomainRuleRequest>,
+) -> Result<Json<ApiResponse<DomainRule>>, StatusCode> {
+    let existing = match state.db.get_domain_rule(&id) {
+        Ok(Some(r)) => r,
+        Ok(None) => return Ok(Json(ApiResponse::err("Rule not found"))),
         Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
     };
 
-    let updated = Collection {
+    let updated = DomainRule {
         id: existing.id,
         domain_id: existing.domain_id,
-        name: req.name.unwrap_or(existing.name),
-        description: req.description.or(existing.description),
-        base_path: req.base_path.unwrap_or(existing.base_path),
+        position: req.position.unwrap_or(existing.position),
+        rule: req.rule.unwrap_or(existing.rule),
         enabled: req.enabled.unwrap_or(existing.enabled),
         created_at: existing.created_at,
         updated_at: existing.updated_at,
     };
 
-    match state.db.update_collection(&updated) {
-        Ok(_) => Ok(Json(ApiResponse::ok(updated))),
+    if let Err(e) = updated.rule.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    match state.db.update_domain_rule(&updated) {
+        Ok(_) => {
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(updated)))
+        }
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
 
-/// Delete a collection
-pub async fn delete_collection(
+/// Delete a rule
+pub async fn delete_domain_rule(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
 ) -> Result<Json<ApiResponse<()>>, StatusCode> {
-    match state.db.delete_collection(&id) {
-        Ok(_) => Ok(Json(ApiResponse::ok(()))),
+    match state.db.delete_domain_rule(&id) {
+        Ok(_) => {
+            state.reload_routes();
+            Ok(Json(ApiResponse::ok(())))
+        }
         Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
     }
 }
 
+/// Show what a domain's enabled rules do with a URL
+///
+/// A URL that is not redirected is also looked up in the route table with
+/// its rewritten path.
+pub async fn test_domain_rules(
+    State(state): State<Arc<AppState>>,
+    Path(domain_id): Path<String>,
+    Json(req): Json<TestRulesRequest>,
+) -> Result<Json<ApiResponse<TestRulesResponse>>, StatusCode> {
+    let domain = match state.db.get_domain(&domain_id) {
+        Ok(Some(d)) => d,
+        Ok(None) => return Ok(Json(ApiResponse::err("Domain not found"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+    let rules = match state.db.list_domain_rules(Some(&domain_id)) {
+        Ok(rules) => RuleSet::new(&rules),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+
+    let base = url::Url::parse(&format!("http://{}/", domain.host.trim_start_matches("*.")));
+    let url = match base.and_then(|base| base.join(&req.url)) {
+        Ok(url) => url,
+        Err(e) => return Ok(Json(ApiResponse::err(format!("Invalid URL: {}", e)))),
+    };
+    let host = crate::router::table::normalize_request_host(url.host_str().unwrap_or_default());
+
+    let outcome = rules.apply(&RuleRequest {
+        secure: url.scheme() == "https",
+        host: &host,
+        path: url.path(),
+        query: url.query(),
+    });
+    let endpoint = match &outcome {
+        Outcome::Pass { path, .. } => state.routes.load()
+            .lookup(&host, &req.method.to_uppercase(), path)
+            .map(|m| m.endpoint.name.clone()),
+        Outcome::Redirect { .. } => None,
+    };
 
+    Ok(Json(ApiResponse::ok(TestRulesResponse { outcome, endpoint })))
+}
 
 // ============================================================================
 // Service API Handlers
@@ -821,6 +1905,111 @@ pub async fn deactivate_service(
     }
 }
 
+// ============================================================================
+// Upstream API Handlers
+// ============================================================================
+
+/// List all upstreams
+pub async fn list_upstreams(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiResponse<Vec<Upstream>>>, StatusCode> {
+    match state.db.list_upstreams() {
+        Ok(upstreams) => Ok(Json(ApiResponse::ok(upstreams))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Create a new upstream
+pub async fn create_upstream(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateUpstreamRequest>,
+) -> Result<Json<ApiResponse<Upstream>>, StatusCode> {
+    let upstream = Upstream {
+        id: Uuid::new_v4().to_string(),
+        name: req.name,
+        targets: req.targets,
+        settings: req.settings,
+        enabled: true,
+        created_at: None,
+        updated_at: None,
+    };
+
+    if let Err(e) = upstream.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    match state.db.create_upstream(&upstream) {
+        Ok(_) => {
+            state.reload_upstreams();
+            Ok(Json(ApiResponse::ok(upstream)))
+        }
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Get an upstream by ID
+pub async fn get_upstream(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<Upstream>>, StatusCode> {
+    match state.db.get_upstream(&id) {
+        Ok(Some(upstream)) => Ok(Json(ApiResponse::ok(upstream))),
+        Ok(None) => Ok(Json(ApiResponse::err("Upstream not found"))),
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Update an upstream
+pub async fn update_upstream(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateUpstreamRequest>,
+) -> Result<Json<ApiResponse<Upstream>>, StatusCode> {
+    let existing = match state.db.get_upstream(&id) {
+        Ok(Some(u)) => u,
+        Ok(None) => return Ok(Json(ApiResponse::err("Upstream not found"))),
+        Err(e) => return Ok(Json(ApiResponse::err(e.to_string()))),
+    };
+
+    let updated = Upstream {
+        id: existing.id,
+        name: req.name.unwrap_or(existing.name),
+        targets: req.targets.unwrap_or(existing.targets),
+        settings: req.settings.unwrap_or(existing.settings),
+        enabled: req.enabled.unwrap_or(existing.enabled),
+        created_at: existing.created_at,
+        updated_at: existing.updated_at,
+    };
+
+    if let Err(e) = updated.validate() {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    match state.db.update_upstream(&updated) {
+        Ok(_) => {
+            state.reload_upstreams();
+            Ok(Json(ApiResponse::ok(updated)))
+        }
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
+/// Delete an upstream
+///
+/// Proxy endpoints still naming the pool answer `503` until they are changed.
+pub async fn delete_upstream(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    match state.db.delete_upstream(&id) {
+        Ok(_) => {
+            state.reload_upstreams();
+            Ok(Json(ApiResponse::ok(())))
+        }
+        Err(e) => Ok(Json(ApiResponse::err(e.to_string()))),
+    }
+}
+
 // ============================================================================
 // OpenAPI Import
 // ============================================================================
@@ -857,7 +2046,7 @@ pub async fn import_openapi(
         Err(e) => return Ok(Json(ApiResponse::err(format!("Failed to parse OpenAPI spec: {}", e)))),
     };
 
-    // Optionally create a collection
+    // Optionally create a collection, saved once the routes are checked
     let mut created_collection: Option<Collection> = None;
     let collection_id = if req.create_collection.unwrap_or(false) {
         let domain_id = match req.domain_id {
@@ -872,14 +2061,11 @@ pub async fn import_openapi(
             description: import_result.description.clone(),
             base_path: import_result.base_path.clone(),
             enabled: true,
+            settings: CollectionSettings::default(),
             created_at: None,
             updated_at: None,
         };
 
-        if let Err(e) = state.db.create_collection(&collection) {
-            return Ok(Json(ApiResponse::err(format!("Failed to create collection: {}", e))));
-        }
-
         let id = collection.id.clone();
         created_collection = Some(collection);
         Some(id)
@@ -896,6 +2082,17 @@ pub async fn import_openapi(
 
     let endpoints_created = endpoints.len();
 
+    // Refuse the whole import if any route clashes, before saving anything
+    if let Err(e) = check_routes(&state, created_collection.as_ref(), &endpoints) {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    if let Some(collection) = &created_collection {
+        if let Err(e) = state.db.create_collection(collection) {
+            return Ok(Json(ApiResponse::err(format!("Failed to create collection: {}", e))));
+        }
+    }
+
     // Save endpoints to database
     for endpoint in &endpoints {
         if let Err(e) = state.db.create_endpoint(endpoint) {
@@ -928,7 +2125,7 @@ pub struct ImportBundleQuery {
     /// Whether to compile handlers after import
     #[serde(default)]
     pub compile: bool,
-    /// Whether to start handlers after compilation
+    /// Whether to start endpoints after import (handlers must also be compiled)
     #[serde(default)]
     pub start: bool,
 }
@@ -941,6 +2138,8 @@ pub struct ImportBundleResponse {
     pub handlers_matched: usize,
     pub compiled: usize,
     pub started: usize,
+    /// Domains whose TLS, site, rules or middleware were set from the manifest
+    pub domains_updated: usize,
     pub endpoints: Vec<Endpoint>,
     pub errors: Vec<String>,
 }
@@ -989,10 +2188,13 @@ pub async fn import_bundle(
         handlers_matched: 0,
         compiled: 0,
         started: 0,
+        domains_updated: 0,
         endpoints: Vec::new(),
         errors: Vec::new(),
     };
 
+    let proxy_routes = bundle.manifest.iter().flat_map(|m| &m.routes).filter(|r| r.proxy.is_some());
+
     // If we have an OpenAPI spec, parse it and create/update endpoints
     let endpoints = if let Some(ref spec) = bundle.openapi_spec {
         let import_result = match crate::openapi::parse_openapi(spec) {
@@ -1000,7 +2202,7 @@ pub async fn import_bundle(
             Err(e) => return Ok(Json(ApiResponse::err(format!("Failed to parse OpenAPI spec: {}", e)))),
         };
 
-        // Optionally create a collection
+        // Optionally create a collection, saved once the routes are checked
         let collection_id = if query.create_collection {
             let domain_id = match query.domain_id {
                 Some(ref id) => id.clone(),
@@ -1014,14 +2216,11 @@ pub async fn import_bundle(
                 description: import_result.description.clone(),
                 base_path: import_result.base_path.clone(),
                 enabled: true,
+                settings: CollectionSettings::default(),
                 created_at: None,
                 updated_at: None,
             };
 
-            if let Err(e) = state.db.create_collection(&collection) {
-                return Ok(Json(ApiResponse::err(format!("Failed to create collection: {}", e))));
-            }
-
             let id = collection.id.clone();
             response.collection = Some(collection);
             Some(id)
@@ -1035,27 +2234,109 @@ pub async fn import_bundle(
             &query.domain,
             collection_id.as_deref(),
         )
-    } else if !bundle.handlers.is_empty() {
-        // No OpenAPI spec, but we have handlers - try to update existing endpoints
+    } else if !bundle.handlers.is_empty() || proxy_routes.clone().next().is_some() {
+        // No OpenAPI spec, but we have handlers to update existing endpoints
+        // or proxy routes to create
         Vec::new()
     } else {
-        return Ok(Json(ApiResponse::err("Bundle must contain an OpenAPI spec or handler files")));
+        return Ok(Json(ApiResponse::err("Bundle must contain an OpenAPI spec, handler files or proxy routes")));
     };
 
-    // Process each endpoint
-    for mut endpoint in endpoints {
-        // Try to find matching handler code
-        if let Some(handler_code) = crate::bundle::find_handler_for_operation(&bundle.handlers, &endpoint.name) {
-            endpoint.code = Some(handler_code);
-            response.handlers_matched += 1;
+    // Proxy routes the spec does not describe become endpoints of their own
+    let collection_id = response.collection.as_ref().map(|c| c.id.clone()).or(query.collection_id.clone());
+    let unmatched_proxies = proxy_routes
+        .filter(|r| !endpoints.iter().any(|e| r.method.eq_ignore_ascii_case(&e.method) && r.path == e.path))
+        .map(|r| Endpoint {
+            id: Uuid::new_v4().to_string(),
+            collection_id: collection_id.clone(),
+            name: if r.handler.is_empty() { format!("{} {}", r.method.to_uppercase(), r.path) } else { r.handler.clone() },
+            domain: query.domain.clone(),
+            path: r.path.clone(),
+            method: r.method.to_uppercase(),
+            kind: EndpointKind::Proxy,
+            description: None,
+            code: None,
+            dependencies: None,
+            compiled: false,
+            enabled: false,
+            settings: EndpointSettings::default(),
+            created_at: None,
+            updated_at: None,
+        })
+        .collect::<Vec<_>>();
+
+    // Prepare each endpoint
+    let mut staged = Vec::new();
+    for mut endpoint in endpoints.into_iter().chain(unmatched_proxies) {
+        // Apply per-route overrides from the manifest
+        let route = bundle.manifest.as_ref()
+            .and_then(|m| Some((m, m.find_route(&endpoint.method, &endpoint.path, &endpoint.name)?)));
+        if let Some((manifest, route)) = route {
+            if route.max_body_size.is_some() {
+                endpoint.settings.max_body_size = route.max_body_size;
+            }
+            if route.timeout_secs.is_some() {
+                endpoint.settings.timeout_secs = route.timeout_secs;
+            }
+            if let Some(proxy) = &route.proxy {
+                endpoint.kind = EndpointKind::Proxy;
+                endpoint.settings.proxy = Some(proxy.clone());
+            }
+            match manifest.resolve_middleware(&route.middleware) {
+                Ok(middleware) => endpoint.settings.middleware = middleware,
+                Err(e) => {
+                    response.errors.push(format!("Invalid middleware for endpoint '{}': {}", endpoint.name, e));
+                    continue;
+                }
+            }
+            endpoint.settings.skip_middleware = route.skip_middleware.clone();
+        }
+        if let Err(e) = endpoint.validate() {
+            response.errors.push(format!("Invalid settings for endpoint '{}': {}", endpoint.name, e));
+            continue;
+        }
+
+        if endpoint.kind != EndpointKind::Proxy {
+            // Try to find matching handler code
+            if let Some(handler_code) = crate::bundle::find_handler_for_operation(&bundle.handlers, &endpoint.name) {
+                endpoint.code = Some(handler_code);
+                response.handlers_matched += 1;
+            }
+
+            // Apply bundle-level dependencies to endpoint
+            if bundle.dependencies.is_some() {
+                endpoint.dependencies = bundle.dependencies.clone();
+            }
         }
 
-        // Apply bundle-level dependencies to endpoint
-        if bundle.dependencies.is_some() {
-            endpoint.dependencies = bundle.dependencies.clone();
+        staged.push(endpoint);
+    }
+
+    // Refuse the whole bundle if any route clashes, before saving anything
+    if let Err(e) = check_routes(&state, response.collection.as_ref(), &staged) {
+        return Ok(Json(ApiResponse::err(e)));
+    }
+
+    if let Some(manifest) = &bundle.manifest {
+        apply_bundle_tls(&state, manifest, &mut response);
+    }
+    apply_bundle_site(&state, &bundle, &mut response);
+    if let Some(manifest) = &bundle.manifest {
+        apply_bundle_rules(&state, manifest, &mut response);
+        apply_bundle_domain_middleware(&state, manifest, &mut response);
+    }
+
+    if let Some(collection) = &response.collection {
+        if let Err(e) = state.db.create_collection(collection) {
+            return Ok(Json(ApiResponse::err(format!("Failed to create collection: {}", e))));
         }
+    }
+    if let (Some(manifest), Some(collection_id)) = (&bundle.manifest, &collection_id) {
+        apply_bundle_collection_middleware(&state, manifest, collection_id, &mut response);
+    }
 
-        // Save endpoint
+    // Save each endpoint
+    for endpoint in staged {
         if let Err(e) = state.db.create_endpoint(&endpoint) {
             response.errors.push(format!("Failed to create endpoint '{}': {}", endpoint.name, e));
             continue;
@@ -1088,7 +2369,7 @@ pub async fn import_bundle(
     if query.compile {
         for endpoint in &response.endpoints {
             if let Some(ref code) = endpoint.code {
-                match crate::compiler::compile_handler(&state.config, &endpoint.id, code, endpoint.dependencies.as_ref()).await {
+                match crate::compiler::compile_handler(&state.config, &endpoint.id, code, endpoint.dependencies.as_ref(), endpoint.kind).await {
                     Ok(_) => {
                         state.db.mark_compiled(&endpoint.id, true).ok();
                         response.compiled += 1;
@@ -1101,11 +2382,20 @@ pub async fn import_bundle(
         }
     }
 
-    // Start if requested (requires compile) - use v2 handler registry
-    if query.start && query.compile {
+    // Starting reloads the routes below; otherwise pick up the new code and
+    // compiled flags now
+    if !query.start && (response.compiled > 0 || response.endpoints_updated > 0) {
+        state.reload_routes();
+    }
+
+    // Start if requested (handlers require compile) - use v2 handler registry
+    if query.start {
         for endpoint in &response.endpoints {
             if let Ok(Some(ep)) = state.db.get_endpoint(&endpoint.id) {
-                if ep.compiled {
+                if ep.kind == EndpointKind::Proxy {
+                    state.db.update_endpoint(&Endpoint { enabled: true, ..ep }).ok();
+                    response.started += 1;
+                } else if ep.compiled {
                     match state.handler_registry.load(&ep.id).await {
                         Ok(_) => {
                             state.db.update_endpoint(&Endpoint { enabled: true, ..ep }).ok();
@@ -1118,11 +2408,213 @@ pub async fn import_bundle(
                 }
             }
         }
+        state.reload_routes();
     }
 
     Ok(Json(ApiResponse::ok(response)))
 }
 
+/// Store a manifest's `tls` section as the TLS setting of its domains
+fn apply_bundle_tls(state: &AppState, manifest: &BundleManifest, response: &mut ImportBundleResponse) {
+    let Some(tls) = &manifest.tls else {
+        return;
+    };
+    if let Err(e) = tls.validate() {
+        response.errors.push(format!("Invalid TLS settings in manifest: {}", e));
+        return;
+    }
+
+    let mut updated = 0;
+    for domain in bundle_domains(state, manifest, "TLS", response) {
+        if domain.settings.tls.as_ref() == Some(tls) {
+            continue;
+        }
+        let mut domain = domain;
+        domain.settings.tls = Some(tls.clone());
+        match state.db.update_domain(&domain) {
+            Ok(_) => updated += 1,
+            Err(e) => response.errors.push(format!("Failed to update domain '{}': {}", domain.name, e)),
+        }
+    }
+    response.domains_updated += updated;
+    if updated > 0 {
+        state.reload_certificates();
+        state.acme.wake();
+    }
+}
+
+/// Install a bundle's `site/` files and store its manifest's `site`
+/// section as the site setting of its domains
+fn apply_bundle_site(state: &AppState, bundle: &crate::bundle::ParsedBundle, response: &mut ImportBundleResponse) {
+    let Some(manifest) = &bundle.manifest else {
+        if !bundle.site_files.is_empty() {
+            response.errors.push("Bundle has site files but no manifest; site not installed".to_string());
+        }
+        return;
+    };
+    if !bundle.site_files.is_empty() {
+        if let Err(e) = crate::router::site::install(&state.config.sites_dir, &manifest.bundle.name, &bundle.site_files) {
+            response.errors.push(format!("Failed to install site files: {}", e));
+            return;
+        }
+    }
+
+    let Some(site) = &manifest.site else {
+        return;
+    };
+    if let Err(e) = site.validate() {
+        response.errors.push(format!("Invalid site settings in manifest: {}", e));
+        return;
+    }
+
+    let mut updated = 0;
+    for domain in bundle_domains(state, manifest, "site", response) {
+        if domain.settings.site.as_ref() == Some(site) {
+            continue;
+        }
+        let mut domain = domain;
+        domain.settings.site = Some(site.clone());
+        match state.db.update_domain(&domain) {
+            Ok(_) => updated += 1,
+            Err(e) => response.errors.push(format!("Failed to update domain '{}': {}", domain.name, e)),
+        }
+    }
+    response.domains_updated += updated;
+    if updated > 0 {
+        state.reload_routes();
+    }
+}
+
+/// Replace the rules of a bundle's domains with its manifest's `rules`
+fn apply_bundle_rules(state: &AppState, manifest: &BundleManifest, response: &mut ImportBundleResponse) {
+    let Some(rules) = &manifest.rules else {
+        return;
+    };
+    for (i, rule) in rules.iter().enumerate() {
+        if let Err(e) = rule.validate() {
+            response.errors.push(format!("Invalid rule {} in manifest: {}", i + 1, e));
+            return;
+        }
+    }
+
+    let mut updated = 0;
+    for domain in bundle_domains(state, manifest, "rules", response) {
+        let current = match state.db.list_domain_rules(Some(&domain.id)) {
+            Ok(current) => current,
+            Err(e) => {
+                response.errors.push(format!("Failed to load rules of domain '{}': {}", domain.name, e));
+                continue;
+            }
+        };
+        if current.iter().all(|r| r.enabled) && current.iter().map(|r| &r.rule).eq(rules.iter()) {
+            continue;
+        }
+        match state.db.replace_domain_rules(&domain.id, rules) {
+            Ok(_) => updated += 1,
+            Err(e) => response.errors.push(format!("Failed to update rules of domain '{}': {}", domain.name, e)),
+        }
+    }
+    response.domains_updated += updated;
+    if updated > 0 {
+        state.reload_routes();
+    }
+}
+
+/// Replace the middleware of a bundle's domains with its manifest's
+/// `domain_middleware`
+fn apply_bundle_domain_middleware(state: &AppState, manifest: &BundleManifest, response: &mut ImportBundleResponse) {
+    let Some(names) = &manifest.domain_middleware else {
+        return;
+    };
+    let middleware = match manifest.resolve_middleware(names) {
+        Ok(middleware) => middleware,
+        Err(e) => {
+            response.errors.push(format!("Invalid domain_middleware in manifest: {}", e));
+            return;
+        }
+    };
+
+    let mut updated = 0;
+    for domain in bundle_domains(state, manifest, "middleware", response) {
+        if domain.settings.middleware == middleware {
+            continue;
+        }
+        let mut domain = domain;
+        domain.settings.middleware = middleware.clone();
+        match state.db.update_domain(&domain) {
+            Ok(_) => updated += 1,
+            Err(e) => response.errors.push(format!("Failed to update domain '{}': {}", domain.name, e)),
+        }
+    }
+    response.domains_updated += updated;
+    if updated > 0 {
+        state.reload_routes();
+    }
+}
+
+/// Replace the middleware of the collection a bundle is imported into with
+/// its manifest's `collection_middleware`
+fn apply_bundle_collection_middleware(state: &AppState, manifest: &BundleManifest, collection_id: &str, response: &mut ImportBundleResponse) {
+    let Some(names) = &manifest.collection_middleware else {
+        return;
+    };
+    let middleware = match manifest.resolve_middleware(names) {
+        Ok(middleware) => middleware,
+        Err(e) => {
+            response.errors.push(format!("Invalid collection_middleware in manifest: {}", e));
+            return;
+        }
+    };
+
+    let mut collection = match state.db.get_collection(collection_id) {
+        Ok(Some(collection)) => collection,
+        Ok(None) => {
+            response.errors.push(format!("Collection '{}' not found; middleware not applied", collection_id));
+            return;
+        }
+        Err(e) => {
+            response.errors.push(format!("Failed to load collection '{}': {}", collection_id, e));
+            return;
+        }
+    };
+    if collection.settings.middleware == middleware {
+        return;
+    }
+    collection.settings.middleware = middleware;
+    if let Err(e) = state.db.update_collection(&collection) {
+        response.errors.push(format!("Failed to update collection '{}': {}", collection.name, e));
+        return;
+    }
+    if let Some(created) = response.collection.as_mut().filter(|c| c.id == collection.id) {
+        created.settings = collection.settings;
+    }
+    state.reload_routes();
+}
+
+/// The domain records for a manifest's `domains`
+///
+/// Each entry must match the host or an alias of an existing domain
+/// record; entries that do not are reported as errors.
+fn bundle_domains(state: &AppState, manifest: &BundleManifest, settings: &str, response: &mut ImportBundleResponse) -> Vec<Domain> {
+    let domains = match state.db.list_domains() {
+        Ok(domains) => domains,
+        Err(e) => {
+            response.errors.push(format!("Failed to apply {} settings: {}", settings, e));
+            return Vec::new();
+        }
+    };
+
+    let mut matched = Vec::new();
+    for host in &manifest.domains {
+        let host = crate::router::table::normalize_request_host(host);
+        match domains.iter().find(|d| d.host == host || d.aliases.contains(&host)) {
+            Some(domain) => matched.push(domain.clone()),
+            None => response.errors.push(format!("No domain record for bundle domain '{}'; {} settings not applied", host, settings)),
+        }
+    }
+    matched
+}
+
 /// Update handler code for an endpoint by name (from bundle)
 #[derive(Debug, Deserialize)]
 #[allow(dead_code)]
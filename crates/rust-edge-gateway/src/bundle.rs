@@ -5,10 +5,13 @@
 //! bundle.zip
 //! ├── bundle.yaml (optional manifest with dependencies)
 //! ├── openapi.yaml (or openapi.json, api.yaml, api.json, spec.yaml, spec.json)
-//! └── handlers/
-//!     ├── get_pet.rs         # Matches operationId "get_pet" or "getPet"
-//!     ├── create_pet.rs      # Matches operationId "create_pet" or "createPet"
-//!     └── list_pets.rs       # Matches operationId "list_pets" or "listPets"
+//! ├── handlers/
+//! │   ├── get_pet.rs         # Matches operationId "get_pet" or "getPet"
+//! │   ├── create_pet.rs      # Matches operationId "create_pet" or "createPet"
+//! │   └── list_pets.rs       # Matches operationId "list_pets" or "listPets"
+//! └── site/ (optional static site files)
+//!     ├── index.html
+//!     └── assets/app.3f9c1a2b.js
 //! ```
 //!
 //! Handler files can also be at the root level or in a `src/` directory.
@@ -45,6 +48,8 @@ pub struct ParsedBundle {
     pub manifest: Option<BundleManifest>,
     /// Custom Cargo dependencies from manifest
     pub dependencies: Option<serde_json::Value>,
+    /// Static site files (path below `site/`, content)
+    pub site_files: Vec<(String, Vec<u8>)>,
 }
 
 /// Possible OpenAPI file names (in priority order)
@@ -63,6 +68,9 @@ const OPENAPI_FILENAMES: &[&str] = &[
     "swagger.json",
 ];
 
+/// Directory holding a bundle's static site
+const SITE_DIR: &str = "site/";
+
 /// Possible manifest file names
 const MANIFEST_FILENAMES: &[&str] = &[
     "bundle.yaml",
@@ -80,6 +88,7 @@ pub fn parse_bundle(zip_bytes: &[u8]) -> Result<ParsedBundle> {
     let mut openapi_spec = None;
     let mut handlers = HashMap::new();
     let mut manifest: Option<BundleManifest> = None;
+    let mut site_files = Vec::new();
 
     tracing::debug!("Parsing bundle with {} files", archive.len());
 
@@ -91,6 +100,9 @@ pub fn parse_bundle(zip_bytes: &[u8]) -> Result<ParsedBundle> {
         let name = raw_name.replace('\\', "/").to_lowercase();
 
         tracing::debug!("Bundle file {}: raw={:?} normalized={:?}", i, raw_name, name);
+        if name.starts_with(SITE_DIR) {
+            continue;
+        }
 
         // Check if this is an OpenAPI spec file
         let filename = name.rsplit('/').next().unwrap_or(&name);
@@ -124,6 +136,14 @@ pub fn parse_bundle(zip_bytes: &[u8]) -> Result<ParsedBundle> {
         // Normalize path separators (Windows uses backslashes)
         let path = raw_path.replace('\\', "/");
 
+        // Static site files are kept as-is
+        if let Some(site_path) = path.strip_prefix(SITE_DIR).filter(|_| !file.is_dir()) {
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            site_files.push((site_path.to_string(), content));
+            continue;
+        }
+
         // Skip directories and non-.rs files
         if file.is_dir() || !path.to_lowercase().ends_with(".rs") {
             continue;
@@ -151,11 +171,12 @@ pub fn parse_bundle(zip_bytes: &[u8]) -> Result<ParsedBundle> {
     // Extract dependencies from manifest
     let dependencies = manifest.as_ref().and_then(|m| m.dependencies.clone());
 
-    tracing::debug!("Parsed bundle: openapi={}, handlers={:?}, manifest={}, deps={}",
+    tracing::debug!("Parsed bundle: openapi={}, handlers={:?}, manifest={}, deps={}, site_files={}",
         openapi_spec.is_some(),
         handlers.keys().collect::<Vec<_>>(),
         manifest.is_some(),
-        dependencies.is_some()
+        dependencies.is_some(),
+        site_files.len()
     );
 
     Ok(ParsedBundle {
@@ -163,6 +184,7 @@ pub fn parse_bundle(zip_bytes: &[u8]) -> Result<ParsedBundle> {
         handlers,
         manifest,
         dependencies,
+        site_files,
     })
 }
 
@@ -7,6 +7,7 @@ use std::path::PathBuf;
 use std::process::Command;
 use tokio::task;
 
+use crate::api::EndpointKind;
 use crate::config::AppConfig;
 
 /// Template for handler Cargo.toml (v2 - dynamic library)
@@ -111,6 +112,8 @@ use rust_edge_gateway_sdk::prelude::*;
 
 mod handler;
 
+rust_edge_gateway_sdk::export_abi_version!();
+
 /// Entry point called by the gateway to handle requests.
 /// This is the v2 dynamic library interface.
 ///
@@ -121,6 +124,41 @@ pub extern "C" fn handler_entry(ctx: &Context, req: Request) -> Response {
 }
 "#;
 
+/// Template for WebSocket handler lib.rs wrapper
+///
+/// The user's handler.rs must define:
+/// ```ignore
+/// pub fn on_connect(ctx: &Context, socket: &WebSocket, req: Request) { }
+/// pub fn on_message(ctx: &Context, socket: &WebSocket, msg: Message) { }
+/// pub fn on_close(ctx: &Context, socket: &WebSocket, frame: Option<CloseFrame>) { }
+/// ```
+const WEBSOCKET_LIB_RS_TEMPLATE: &str = r#"//! Auto-generated WebSocket handler wrapper (v2 dynamic library)
+use rust_edge_gateway_sdk::prelude::*;
+
+mod handler;
+
+rust_edge_gateway_sdk::export_abi_version!();
+
+/// Plain HTTP requests are answered by the gateway; this is never called
+/// for upgrade requests.
+#[no_mangle]
+pub extern "C" fn handler_entry(_ctx: &Context, _req: Request) -> Response {
+    Response::text(426, "WebSocket upgrade required").with_header("Upgrade", "websocket")
+}
+
+/// Entry point called by the gateway for each WebSocket connection event.
+///
+/// Signature matches runtime SocketFn: extern "C" fn(&Context, &WebSocket, SocketEvent)
+#[no_mangle]
+pub extern "C" fn socket_entry(ctx: &Context, socket: &WebSocket, event: SocketEvent) {
+    match event {
+        SocketEvent::Connect(req) => handler::on_connect(ctx, socket, *req),
+        SocketEvent::Message(msg) => handler::on_message(ctx, socket, msg),
+        SocketEvent::Close(frame) => handler::on_close(ctx, socket, frame),
+    }
+}
+"#;
+
 /// Compile a handler from source code
 ///
 /// # Arguments
@@ -128,11 +166,13 @@ pub extern "C" fn handler_entry(ctx: &Context, req: Request) -> Response {
 /// * `id` - Handler ID (used for directory and package naming)
 /// * `code` - Handler source code
 /// * `dependencies` - Optional JSON dependencies to include in Cargo.toml
+/// * `kind` - Endpoint kind, which selects the entry point wrapper
 pub async fn compile_handler(
     config: &AppConfig,
     id: &str,
     code: &str,
     dependencies: Option<&serde_json::Value>,
+    kind: EndpointKind,
 ) -> Result<String> {
     let handlers_dir = config.handlers_dir.clone();
     let id = id.to_string();
@@ -141,7 +181,7 @@ pub async fn compile_handler(
 
     // Run compilation in a blocking task
     task::spawn_blocking(move || {
-        compile_handler_sync(&handlers_dir, &id, &code, deps.as_ref())
+        compile_handler_sync(&handlers_dir, &id, &code, deps.as_ref(), kind)
     }).await?
 }
 
@@ -150,6 +190,7 @@ fn compile_handler_sync(
     id: &str,
     code: &str,
     dependencies: Option<&serde_json::Value>,
+    kind: EndpointKind,
 ) -> Result<String> {
     // Create handler directory structure
     let handler_dir = handlers_dir.join(id);
@@ -176,7 +217,12 @@ fn compile_handler_sync(
     std::fs::write(handler_dir.join("Cargo.toml"), cargo_toml)?;
 
     // Write lib.rs wrapper (v2 dynamic library entry point)
-    std::fs::write(src_dir.join("lib.rs"), LIB_RS_TEMPLATE)?;
+    let lib_rs = match kind {
+        EndpointKind::Handler => LIB_RS_TEMPLATE,
+        EndpointKind::WebSocket => WEBSOCKET_LIB_RS_TEMPLATE,
+        EndpointKind::Proxy => return Err(anyhow!("Proxy endpoints have no handler code to compile")),
+    };
+    std::fs::write(src_dir.join("lib.rs"), lib_rs)?;
 
     // Write user's handler code
     std::fs::write(src_dir.join("handler.rs"), code)?;
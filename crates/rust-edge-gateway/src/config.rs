@@ -3,6 +3,11 @@
 use std::env;
 use std::path::PathBuf;
 
+use crate::acme::{AcmeConfig, LETS_ENCRYPT_DIRECTORY};
+use crate::net::TrustedProxies;
+use crate::router::cache::ResponseCacheConfig;
+use crate::router::compression::{CompressionPolicy, DEFAULT_MIN_SIZE};
+
 /// Application configuration loaded from environment variables
 #[derive(Debug, Clone)]
 pub struct AppConfig {
@@ -14,6 +19,9 @@ pub struct AppConfig {
     
     /// Directory for static admin UI files
     pub static_dir: PathBuf,
+
+    /// Directory holding the domains' static sites
+    pub sites_dir: PathBuf,
     
     /// Port for the main gateway (HTTP requests to handlers)
     pub gateway_port: u16,
@@ -40,17 +48,57 @@ pub struct AppConfig {
     /// Handler request timeout in seconds
     pub handler_timeout_secs: u64,
 
+    /// How long shutdown waits for open requests and handler calls
+    pub shutdown_timeout_secs: u64,
+
     /// Maximum handler memory in MB (for monitoring)
     pub handler_max_memory_mb: u64,
+
+    /// Maximum request body size in bytes (endpoints may override)
+    pub max_body_size: usize,
+
+    /// Response compression defaults (domains may override)
+    pub compression: CompressionPolicy,
+
+    /// Shared response cache limits (None when the cache is disabled)
+    pub response_cache: Option<ResponseCacheConfig>,
+
+    /// Host whose routes serve requests for hosts that match no domain
+    pub default_domain: Option<String>,
+
+    /// Proxies whose forwarding headers and PROXY headers are trusted
+    pub trusted_proxies: TrustedProxies,
+
+    /// Accept PROXY protocol v1/v2 headers from trusted proxies on the gateway port
+    pub proxy_protocol: bool,
+
+    /// Port for the gateway with TLS termination (None to serve plain HTTP only)
+    pub tls_port: Option<u16>,
+
+    /// Directory of per-domain TLS certificates
+    pub certs_dir: PathBuf,
+
+    /// ACME server used for `letsencrypt` domains
+    pub acme: AcmeConfig,
 }
 
 impl AppConfig {
     /// Load configuration from environment variables
     pub fn from_env() -> Self {
+        let data_dir = env::var("RUST_EDGE_GATEWAY_DATA_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from("./data"));
+
         Self {
-            data_dir: env::var("RUST_EDGE_GATEWAY_DATA_DIR")
+            certs_dir: env::var("RUST_EDGE_GATEWAY_TLS_CERTS_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| data_dir.join("certs")),
+
+            sites_dir: env::var("RUST_EDGE_GATEWAY_SITES_DIR")
                 .map(PathBuf::from)
-                .unwrap_or_else(|_| PathBuf::from("./data")),
+                .unwrap_or_else(|_| data_dir.join("sites")),
+
+            data_dir,
 
             handlers_dir: env::var("RUST_EDGE_GATEWAY_HANDLERS_DIR")
                 .map(PathBuf::from)
@@ -80,11 +128,73 @@ impl AppConfig {
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(30),
 
+            shutdown_timeout_secs: env::var("RUST_EDGE_GATEWAY_SHUTDOWN_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(30),
+
             handler_max_memory_mb: env::var("RUST_EDGE_GATEWAY_HANDLER_MAX_MEMORY_MB")
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(64),
 
+            max_body_size: env::var("RUST_EDGE_GATEWAY_MAX_BODY_SIZE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(10 * 1024 * 1024),
+
+            compression: CompressionPolicy {
+                enabled: env::var("RUST_EDGE_GATEWAY_COMPRESSION")
+                    .map(|s| !(s == "0" || s.eq_ignore_ascii_case("false")))
+                    .unwrap_or(true),
+                min_size: env::var("RUST_EDGE_GATEWAY_COMPRESSION_MIN_SIZE")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(DEFAULT_MIN_SIZE),
+                content_types: env::var("RUST_EDGE_GATEWAY_COMPRESSION_TYPES")
+                    .map(|s| s.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect())
+                    .unwrap_or_else(|_| CompressionPolicy::default().content_types),
+            },
+
+            response_cache: env::var("RUST_EDGE_GATEWAY_RESPONSE_CACHE")
+                .is_ok_and(|s| s == "1" || s.eq_ignore_ascii_case("true"))
+                .then(|| ResponseCacheConfig {
+                    max_entries: env::var("RUST_EDGE_GATEWAY_RESPONSE_CACHE_MAX_ENTRIES")
+                        .ok()
+                        .and_then(|s| s.parse().ok())
+                        .filter(|&n| n > 0)
+                        .unwrap_or(10_000),
+                    max_entry_size: env::var("RUST_EDGE_GATEWAY_RESPONSE_CACHE_MAX_ENTRY_SIZE")
+                        .ok()
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or(1024 * 1024),
+                }),
+
+            default_domain: env::var("RUST_EDGE_GATEWAY_DEFAULT_DOMAIN").ok(),
+
+            trusted_proxies: env::var("RUST_EDGE_GATEWAY_TRUSTED_PROXIES")
+                .map(|s| TrustedProxies::from_list(&s))
+                .unwrap_or_default(),
+
+            proxy_protocol: env::var("RUST_EDGE_GATEWAY_PROXY_PROTOCOL")
+                .map(|s| s == "1" || s.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+
+            tls_port: env::var("RUST_EDGE_GATEWAY_TLS_PORT")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+
+            acme: AcmeConfig {
+                directory_url: env::var("RUST_EDGE_GATEWAY_ACME_DIRECTORY")
+                    .unwrap_or_else(|_| LETS_ENCRYPT_DIRECTORY.to_string()),
+                ca_cert: env::var("RUST_EDGE_GATEWAY_ACME_CA_CERT").ok().map(PathBuf::from),
+                renew_before_days: env::var("RUST_EDGE_GATEWAY_ACME_RENEW_DAYS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(30),
+            },
+
             default_admin_password: env::var("DEFAULT_ADMIN_PASSWORD").ok(),
 
             recaptcha_site_key: env::var("RECAPTCHA_V3_SITE_KEY").ok(),
@@ -2,37 +2,12 @@
 
 use anyhow::{Context, Result};
 use rusqlite::{Connection, params, OptionalExtension};
-use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Mutex;
 
-use crate::api::{Collection, Domain, Endpoint, Service, ServiceType};
-
-/// Match a path pattern (e.g., "/pet/{petId}") against an actual path (e.g., "/pet/42")
-/// Returns extracted path parameters if matched
-fn match_path_pattern(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
-    let pattern_parts: Vec<&str> = pattern.split('/').collect();
-    let path_parts: Vec<&str> = path.split('/').collect();
-
-    if pattern_parts.len() != path_parts.len() {
-        return None;
-    }
-
-    let mut params = HashMap::new();
-
-    for (pattern_part, path_part) in pattern_parts.iter().zip(path_parts.iter()) {
-        if pattern_part.starts_with('{') && pattern_part.ends_with('}') {
-            // This is a path parameter
-            let param_name = &pattern_part[1..pattern_part.len()-1];
-            params.insert(param_name.to_string(), path_part.to_string());
-        } else if pattern_part != path_part {
-            // Static parts must match exactly
-            return None;
-        }
-    }
-
-    Some(params)
-}
+use crate::api::{Canary, Collection, Domain, DomainRule, Endpoint, Mirror, Service, ServiceType, Upstream};
+use crate::runtime::canary::CanarySettings;
+use crate::runtime::mirror::MirrorSettings;
 
 /// SQLite database wrapper
 pub struct Database {
@@ -66,6 +41,21 @@ impl Database {
                 updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
             );
 
+            -- Domain rules: redirects and rewrites run before endpoint matching
+            CREATE TABLE IF NOT EXISTS domain_rules (
+                id TEXT PRIMARY KEY,
+                domain_id TEXT NOT NULL,
+                position INTEGER NOT NULL DEFAULT 0,
+                rule TEXT NOT NULL,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (domain_id) REFERENCES domains(id) ON DELETE CASCADE
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_domain_rules_domain
+                ON domain_rules(domain_id);
+
             -- Collections: group endpoints within a domain (e.g., "Pet Store", "Users")
             CREATE TABLE IF NOT EXISTS collections (
                 id TEXT PRIMARY KEY,
@@ -96,6 +86,17 @@ impl Database {
             CREATE INDEX IF NOT EXISTS idx_services_type
                 ON services(service_type);
 
+            -- Upstreams: named pools of backend URLs for proxy endpoints
+            CREATE TABLE IF NOT EXISTS upstreams (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                targets TEXT NOT NULL,
+                settings TEXT,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            );
+
             -- Endpoints: API endpoints within collections
             CREATE TABLE IF NOT EXISTS endpoints (
                 id TEXT PRIMARY KEY,
@@ -124,6 +125,28 @@ impl Database {
             CREATE INDEX IF NOT EXISTS idx_endpoints_collection
                 ON endpoints(collection_id);
 
+            -- Canaries: candidate handler versions taking part of an endpoint's traffic
+            CREATE TABLE IF NOT EXISTS canaries (
+                endpoint_id TEXT PRIMARY KEY,
+                code TEXT NOT NULL,
+                dependencies TEXT,
+                settings TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (endpoint_id) REFERENCES endpoints(id) ON DELETE CASCADE
+            );
+
+            -- Mirrors: shadow handler versions receiving a copy of an endpoint's traffic
+            CREATE TABLE IF NOT EXISTS mirrors (
+                endpoint_id TEXT PRIMARY KEY,
+                code TEXT NOT NULL,
+                dependencies TEXT,
+                settings TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (endpoint_id) REFERENCES endpoints(id) ON DELETE CASCADE
+            );
+
             -- Endpoint-Service bindings: which services an endpoint can use
             CREATE TABLE IF NOT EXISTS endpoint_services (
                 endpoint_id TEXT NOT NULL,
@@ -173,6 +196,56 @@ impl Database {
             tracing::info!("Migration: Added 'dependencies' column to endpoints table");
         }
 
+        // Migration: Add settings column (JSON per-endpoint overrides) to endpoints
+        let has_settings: bool = conn
+            .prepare("SELECT settings FROM endpoints LIMIT 1")
+            .is_ok();
+
+        if !has_settings {
+            conn.execute("ALTER TABLE endpoints ADD COLUMN settings TEXT", [])?;
+            tracing::info!("Migration: Added 'settings' column to endpoints table");
+        }
+
+        // Migration: Add kind column (handler, websocket) to endpoints
+        let has_kind: bool = conn
+            .prepare("SELECT kind FROM endpoints LIMIT 1")
+            .is_ok();
+
+        if !has_kind {
+            conn.execute("ALTER TABLE endpoints ADD COLUMN kind TEXT NOT NULL DEFAULT 'handler'", [])?;
+            tracing::info!("Migration: Added 'kind' column to endpoints table");
+        }
+
+        // Migration: Add aliases column (JSON array of extra hosts) to domains
+        let has_aliases: bool = conn
+            .prepare("SELECT aliases FROM domains LIMIT 1")
+            .is_ok();
+
+        if !has_aliases {
+            conn.execute("ALTER TABLE domains ADD COLUMN aliases TEXT", [])?;
+            tracing::info!("Migration: Added 'aliases' column to domains table");
+        }
+
+        // Migration: Add settings column (JSON per-domain overrides) to domains
+        let has_domain_settings: bool = conn
+            .prepare("SELECT settings FROM domains LIMIT 1")
+            .is_ok();
+
+        if !has_domain_settings {
+            conn.execute("ALTER TABLE domains ADD COLUMN settings TEXT", [])?;
+            tracing::info!("Migration: Added 'settings' column to domains table");
+        }
+
+        // Migration: Add settings column (JSON shared endpoint settings) to collections
+        let has_collection_settings: bool = conn
+            .prepare("SELECT settings FROM collections LIMIT 1")
+            .is_ok();
+
+        if !has_collection_settings {
+            conn.execute("ALTER TABLE collections ADD COLUMN settings TEXT", [])?;
+            tracing::info!("Migration: Added 'settings' column to collections table");
+        }
+
         Ok(())
     }
     
@@ -180,12 +253,14 @@ impl Database {
     pub fn list_endpoints(&self) -> Result<Vec<Endpoint>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, collection_id, name, domain, path, method, description, dependencies, compiled, enabled, created_at, updated_at
+            "SELECT id, collection_id, name, domain, path, method, description, dependencies, compiled, enabled, created_at, updated_at, settings, kind
              FROM endpoints ORDER BY created_at DESC"
         )?;
 
         let endpoints = stmt.query_map([], |row| {
             let deps_str: Option<String> = row.get(7)?;
+            let settings_str: Option<String> = row.get(12)?;
+            let kind: String = row.get(13)?;
             Ok(Endpoint {
                 id: row.get(0)?,
                 collection_id: row.get(1)?,
@@ -193,11 +268,13 @@ impl Database {
                 domain: row.get(3)?,
                 path: row.get(4)?,
                 method: row.get(5)?,
+                kind: kind.parse().unwrap_or_default(),
                 description: row.get(6)?,
                 code: None,
                 dependencies: deps_str.and_then(|s| serde_json::from_str(&s).ok()),
                 compiled: row.get(8)?,
                 enabled: row.get(9)?,
+                settings: settings_str.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
                 created_at: row.get(10)?,
                 updated_at: row.get(11)?,
             })
@@ -210,12 +287,14 @@ impl Database {
     pub fn get_endpoint(&self, id: &str) -> Result<Option<Endpoint>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, collection_id, name, domain, path, method, description, code, dependencies, compiled, enabled, created_at, updated_at
+            "SELECT id, collection_id, name, domain, path, method, description, code, dependencies, compiled, enabled, created_at, updated_at, settings, kind
              FROM endpoints WHERE id = ?"
         )?;
 
         let endpoint = stmt.query_row([id], |row| {
             let deps_str: Option<String> = row.get(8)?;
+            let settings_str: Option<String> = row.get(13)?;
+            let kind: String = row.get(14)?;
             Ok(Endpoint {
                 id: row.get(0)?,
                 collection_id: row.get(1)?,
@@ -223,11 +302,13 @@ impl Database {
                 domain: row.get(3)?,
                 path: row.get(4)?,
                 method: row.get(5)?,
+                kind: kind.parse().unwrap_or_default(),
                 description: row.get(6)?,
                 code: row.get(7)?,
                 dependencies: deps_str.and_then(|s| serde_json::from_str(&s).ok()),
                 compiled: row.get(9)?,
                 enabled: row.get(10)?,
+                settings: settings_str.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
                 created_at: row.get(11)?,
                 updated_at: row.get(12)?,
             })
@@ -240,9 +321,10 @@ impl Database {
     pub fn create_endpoint(&self, endpoint: &Endpoint) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         let deps_str = endpoint.dependencies.as_ref().map(|d| serde_json::to_string(d).unwrap_or_default());
+        let settings_str = serde_json::to_string(&endpoint.settings)?;
         conn.execute(
-            "INSERT INTO endpoints (id, collection_id, name, domain, path, method, description, code, dependencies, compiled, enabled)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO endpoints (id, collection_id, name, domain, path, method, description, code, dependencies, compiled, enabled, settings, kind)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 endpoint.id,
                 endpoint.collection_id,
@@ -255,6 +337,8 @@ impl Database {
                 deps_str,
                 endpoint.compiled,
                 endpoint.enabled,
+                settings_str,
+                endpoint.kind.as_str(),
             ],
         )?;
         Ok(())
@@ -264,9 +348,10 @@ impl Database {
     pub fn update_endpoint(&self, endpoint: &Endpoint) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         let deps_str = endpoint.dependencies.as_ref().map(|d| serde_json::to_string(d).unwrap_or_default());
+        let settings_str = serde_json::to_string(&endpoint.settings)?;
         conn.execute(
             "UPDATE endpoints SET collection_id = ?, name = ?, domain = ?, path = ?, method = ?,
-             description = ?, dependencies = ?, compiled = ?, enabled = ?, updated_at = CURRENT_TIMESTAMP
+             description = ?, dependencies = ?, compiled = ?, enabled = ?, settings = ?, kind = ?, updated_at = CURRENT_TIMESTAMP
              WHERE id = ?",
             params![
                 endpoint.collection_id,
@@ -278,6 +363,8 @@ impl Database {
                 deps_str,
                 endpoint.compiled,
                 endpoint.enabled,
+                settings_str,
+                endpoint.kind.as_str(),
                 endpoint.id,
             ],
         )?;
@@ -308,21 +395,141 @@ impl Database {
     /// Delete an endpoint
     pub fn delete_endpoint(&self, id: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM canaries WHERE endpoint_id = ?", [id])?;
+        conn.execute("DELETE FROM mirrors WHERE endpoint_id = ?", [id])?;
         conn.execute("DELETE FROM endpoints WHERE id = ?", [id])?;
         Ok(())
     }
 
-    /// Find endpoint by domain, path pattern, and method
-    /// Returns (endpoint, extracted_path_params)
-    pub fn find_endpoint(&self, domain: &str, path: &str, method: &str) -> Result<Option<(Endpoint, std::collections::HashMap<String, String>)>> {
+    // ========================================================================
+    // Canary CRUD
+    // ========================================================================
+
+    /// List all canary releases
+    pub fn list_canaries(&self) -> Result<Vec<Canary>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, collection_id, name, domain, path, method, description, code, dependencies, compiled, enabled, created_at, updated_at
-             FROM endpoints WHERE domain = ? AND method = ? AND enabled = 1"
+            "SELECT endpoint_id, code, dependencies, settings, created_at, updated_at FROM canaries"
         )?;
 
-        let endpoints: Vec<Endpoint> = stmt.query_map(params![domain, method], |row| {
-            let deps_str: Option<String> = row.get(8)?;
+        let canaries = stmt.query_map([], canary_from_row)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(canaries.into_iter().flatten().collect())
+    }
+
+    /// Get the canary release of an endpoint
+    pub fn get_canary(&self, endpoint_id: &str) -> Result<Option<Canary>> {
+        let conn = self.conn.lock().unwrap();
+        let canary = conn.query_row(
+            "SELECT endpoint_id, code, dependencies, settings, created_at, updated_at
+             FROM canaries WHERE endpoint_id = ?",
+            [endpoint_id],
+            canary_from_row,
+        ).optional()?;
+        Ok(canary.flatten())
+    }
+
+    /// Store a new canary release, replacing any previous one of the endpoint
+    pub fn create_canary(&self, canary: &Canary) -> Result<()> {
+        let deps_str = canary.dependencies.as_ref().map(serde_json::to_string).transpose()?;
+        let settings_str = serde_json::to_string(&canary.settings)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO canaries (endpoint_id, code, dependencies, settings) VALUES (?, ?, ?, ?)",
+            params![canary.endpoint_id, canary.code, deps_str, settings_str],
+        )?;
+        Ok(())
+    }
+
+    /// Update how much traffic a canary release receives
+    pub fn update_canary_settings(&self, endpoint_id: &str, settings: &CanarySettings) -> Result<()> {
+        let settings_str = serde_json::to_string(settings)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE canaries SET settings = ?, updated_at = CURRENT_TIMESTAMP WHERE endpoint_id = ?",
+            params![settings_str, endpoint_id],
+        )?;
+        Ok(())
+    }
+
+    /// Delete the canary release of an endpoint
+    pub fn delete_canary(&self, endpoint_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM canaries WHERE endpoint_id = ?", [endpoint_id])?;
+        Ok(())
+    }
+
+    // ========================================================================
+    // Mirror CRUD
+    // ========================================================================
+
+    /// List all mirrored endpoints
+    pub fn list_mirrors(&self) -> Result<Vec<Mirror>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT endpoint_id, code, dependencies, settings, created_at, updated_at FROM mirrors"
+        )?;
+
+        let mirrors = stmt.query_map([], mirror_from_row)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(mirrors.into_iter().flatten().collect())
+    }
+
+    /// Get the mirror of an endpoint
+    pub fn get_mirror(&self, endpoint_id: &str) -> Result<Option<Mirror>> {
+        let conn = self.conn.lock().unwrap();
+        let mirror = conn.query_row(
+            "SELECT endpoint_id, code, dependencies, settings, created_at, updated_at
+             FROM mirrors WHERE endpoint_id = ?",
+            [endpoint_id],
+            mirror_from_row,
+        ).optional()?;
+        Ok(mirror.flatten())
+    }
+
+    /// Store a new mirror, replacing any previous one of the endpoint
+    pub fn create_mirror(&self, mirror: &Mirror) -> Result<()> {
+        let deps_str = mirror.dependencies.as_ref().map(serde_json::to_string).transpose()?;
+        let settings_str = serde_json::to_string(&mirror.settings)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO mirrors (endpoint_id, code, dependencies, settings) VALUES (?, ?, ?, ?)",
+            params![mirror.endpoint_id, mirror.code, deps_str, settings_str],
+        )?;
+        Ok(())
+    }
+
+    /// Update which requests are mirrored
+    pub fn update_mirror_settings(&self, endpoint_id: &str, settings: &MirrorSettings) -> Result<()> {
+        let settings_str = serde_json::to_string(settings)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE mirrors SET settings = ?, updated_at = CURRENT_TIMESTAMP WHERE endpoint_id = ?",
+            params![settings_str, endpoint_id],
+        )?;
+        Ok(())
+    }
+
+    /// Delete the mirror of an endpoint
+    pub fn delete_mirror(&self, endpoint_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM mirrors WHERE endpoint_id = ?", [endpoint_id])?;
+        Ok(())
+    }
+
+    /// List enabled endpoints for building the route table
+    ///
+    /// Ordered by creation so that the oldest endpoint wins when two
+    /// endpoints resolve to the same route.
+    pub fn list_enabled_endpoints(&self) -> Result<Vec<Endpoint>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, collection_id, name, domain, path, method, description, dependencies, compiled, enabled, created_at, updated_at, settings, kind
+             FROM endpoints WHERE enabled = 1 ORDER BY created_at, rowid"
+        )?;
+
+        let endpoints = stmt.query_map([], |row| {
+            let deps_str: Option<String> = row.get(7)?;
+            let settings_str: Option<String> = row.get(12)?;
+            let kind: String = row.get(13)?;
             Ok(Endpoint {
                 id: row.get(0)?,
                 collection_id: row.get(1)?,
@@ -330,24 +537,19 @@ impl Database {
                 domain: row.get(3)?,
                 path: row.get(4)?,
                 method: row.get(5)?,
+                kind: kind.parse().unwrap_or_default(),
                 description: row.get(6)?,
-                code: row.get(7)?,
+                code: None,
                 dependencies: deps_str.and_then(|s| serde_json::from_str(&s).ok()),
-                compiled: row.get(9)?,
-                enabled: row.get(10)?,
-                created_at: row.get(11)?,
-                updated_at: row.get(12)?,
+                compiled: row.get(8)?,
+                enabled: row.get(9)?,
+                settings: settings_str.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+                created_at: row.get(10)?,
+                updated_at: row.get(11)?,
             })
-        })?.filter_map(|r| r.ok()).collect();
-
-        // Try to match each endpoint's path pattern against the request path
-        for endpoint in endpoints {
-            if let Some(params) = match_path_pattern(&endpoint.path, path) {
-                return Ok(Some((endpoint, params)));
-            }
-        }
+        })?.collect::<Result<Vec<_>, _>>()?;
 
-        Ok(None)
+        Ok(endpoints)
     }
 
     /// Get endpoint count
@@ -365,19 +567,23 @@ impl Database {
     pub fn list_domains(&self) -> Result<Vec<Domain>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, name, host, description, enabled, created_at, updated_at
+            "SELECT id, name, host, aliases, description, enabled, created_at, updated_at, settings
              FROM domains ORDER BY name"
         )?;
 
         let domains = stmt.query_map([], |row| {
+            let aliases_str: Option<String> = row.get(3)?;
+            let settings_str: Option<String> = row.get(8)?;
             Ok(Domain {
                 id: row.get(0)?,
                 name: row.get(1)?,
                 host: row.get(2)?,
-                description: row.get(3)?,
-                enabled: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
+                aliases: aliases_str.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+                description: row.get(4)?,
+                enabled: row.get(5)?,
+                settings: settings_str.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+                created_at: row.get(6)?,
+                updated_at: row.get(7)?,
             })
         })?.collect::<Result<Vec<_>, _>>()?;
 
@@ -388,49 +594,133 @@ impl Database {
     pub fn get_domain(&self, id: &str) -> Result<Option<Domain>> {
         let conn = self.conn.lock().unwrap();
         let domain = conn.query_row(
-            "SELECT id, name, host, description, enabled, created_at, updated_at
+            "SELECT id, name, host, aliases, description, enabled, created_at, updated_at, settings
              FROM domains WHERE id = ?",
             [id],
-            |row| Ok(Domain {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                host: row.get(2)?,
-                description: row.get(3)?,
-                enabled: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
-            })
+            |row| {
+                let aliases_str: Option<String> = row.get(3)?;
+                let settings_str: Option<String> = row.get(8)?;
+                Ok(Domain {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    host: row.get(2)?,
+                    aliases: aliases_str.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+                    description: row.get(4)?,
+                    enabled: row.get(5)?,
+                    settings: settings_str.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+                    created_at: row.get(6)?,
+                    updated_at: row.get(7)?,
+                })
+            }
         ).optional()?;
         Ok(domain)
     }
 
     /// Create a new domain
     pub fn create_domain(&self, domain: &Domain) -> Result<()> {
+        let aliases_str = serde_json::to_string(&domain.aliases)?;
+        let settings_str = serde_json::to_string(&domain.settings)?;
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO domains (id, name, host, description, enabled) VALUES (?, ?, ?, ?, ?)",
-            params![domain.id, domain.name, domain.host, domain.description, domain.enabled],
+            "INSERT INTO domains (id, name, host, aliases, description, enabled, settings) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![domain.id, domain.name, domain.host, aliases_str, domain.description, domain.enabled, settings_str],
         )?;
         Ok(())
     }
 
     /// Update a domain
     pub fn update_domain(&self, domain: &Domain) -> Result<()> {
+        let aliases_str = serde_json::to_string(&domain.aliases)?;
+        let settings_str = serde_json::to_string(&domain.settings)?;
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "UPDATE domains SET name = ?, host = ?, description = ?, enabled = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
-            params![domain.name, domain.host, domain.description, domain.enabled, domain.id],
+            "UPDATE domains SET name = ?, host = ?, aliases = ?, description = ?, enabled = ?, settings = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            params![domain.name, domain.host, aliases_str, domain.description, domain.enabled, settings_str, domain.id],
         )?;
         Ok(())
     }
 
-    /// Delete a domain
+    /// Delete a domain and its rules
     pub fn delete_domain(&self, id: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM domain_rules WHERE domain_id = ?", [id])?;
         conn.execute("DELETE FROM domains WHERE id = ?", [id])?;
         Ok(())
     }
 
+    // ========================================================================
+    // Domain rule CRUD
+    // ========================================================================
+
+    /// List rules in evaluation order, optionally filtered by domain
+    pub fn list_domain_rules(&self, domain_id: Option<&str>) -> Result<Vec<DomainRule>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, domain_id, position, rule, enabled, created_at, updated_at
+             FROM domain_rules WHERE ?1 IS NULL OR domain_id = ?1
+             ORDER BY domain_id, position, created_at"
+        )?;
+
+        let rules = stmt.query_map([domain_id], domain_rule_from_row)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(rules.into_iter().flatten().collect())
+    }
+
+    /// Get a rule by ID
+    pub fn get_domain_rule(&self, id: &str) -> Result<Option<DomainRule>> {
+        let conn = self.conn.lock().unwrap();
+        let rule = conn.query_row(
+            "SELECT id, domain_id, position, rule, enabled, created_at, updated_at
+             FROM domain_rules WHERE id = ?",
+            [id],
+            domain_rule_from_row,
+        ).optional()?;
+        Ok(rule.flatten())
+    }
+
+    /// Create a new rule
+    pub fn create_domain_rule(&self, rule: &DomainRule) -> Result<()> {
+        let rule_str = serde_json::to_string(&rule.rule)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO domain_rules (id, domain_id, position, rule, enabled) VALUES (?, ?, ?, ?, ?)",
+            params![rule.id, rule.domain_id, rule.position, rule_str, rule.enabled],
+        )?;
+        Ok(())
+    }
+
+    /// Update a rule
+    pub fn update_domain_rule(&self, rule: &DomainRule) -> Result<()> {
+        let rule_str = serde_json::to_string(&rule.rule)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE domain_rules SET position = ?, rule = ?, enabled = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            params![rule.position, rule_str, rule.enabled, rule.id],
+        )?;
+        Ok(())
+    }
+
+    /// Delete a rule
+    pub fn delete_domain_rule(&self, id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM domain_rules WHERE id = ?", [id])?;
+        Ok(())
+    }
+
+    /// Replace all of a domain's rules, keeping their order
+    pub fn replace_domain_rules(&self, domain_id: &str, rules: &[crate::router::rules::Rule]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM domain_rules WHERE domain_id = ?", [domain_id])?;
+        for (position, rule) in rules.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO domain_rules (id, domain_id, position, rule, enabled) VALUES (?, ?, ?, ?, 1)",
+                params![uuid::Uuid::new_v4().to_string(), domain_id, position as i64, serde_json::to_string(rule)?],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
     // ========================================================================
     // Collection CRUD
     // ========================================================================
@@ -441,39 +731,17 @@ impl Database {
 
         if let Some(did) = domain_id {
             let mut stmt = conn.prepare(
-                "SELECT id, domain_id, name, description, base_path, enabled, created_at, updated_at
+                "SELECT id, domain_id, name, description, base_path, enabled, created_at, updated_at, settings
                  FROM collections WHERE domain_id = ? ORDER BY name"
             )?;
-            let collections = stmt.query_map([did], |row| {
-                Ok(Collection {
-                    id: row.get(0)?,
-                    domain_id: row.get(1)?,
-                    name: row.get(2)?,
-                    description: row.get(3)?,
-                    base_path: row.get(4)?,
-                    enabled: row.get(5)?,
-                    created_at: row.get(6)?,
-                    updated_at: row.get(7)?,
-                })
-            })?.collect::<Result<Vec<_>, _>>()?;
+            let collections = stmt.query_map([did], collection_from_row)?.collect::<Result<Vec<_>, _>>()?;
             Ok(collections)
         } else {
             let mut stmt = conn.prepare(
-                "SELECT id, domain_id, name, description, base_path, enabled, created_at, updated_at
+                "SELECT id, domain_id, name, description, base_path, enabled, created_at, updated_at, settings
                  FROM collections ORDER BY name"
             )?;
-            let collections = stmt.query_map([], |row| {
-                Ok(Collection {
-                    id: row.get(0)?,
-                    domain_id: row.get(1)?,
-                    name: row.get(2)?,
-                    description: row.get(3)?,
-                    base_path: row.get(4)?,
-                    enabled: row.get(5)?,
-                    created_at: row.get(6)?,
-                    updated_at: row.get(7)?,
-                })
-            })?.collect::<Result<Vec<_>, _>>()?;
+            let collections = stmt.query_map([], collection_from_row)?.collect::<Result<Vec<_>, _>>()?;
             Ok(collections)
         }
     }
@@ -482,39 +750,32 @@ impl Database {
     pub fn get_collection(&self, id: &str) -> Result<Option<Collection>> {
         let conn = self.conn.lock().unwrap();
         let collection = conn.query_row(
-            "SELECT id, domain_id, name, description, base_path, enabled, created_at, updated_at
+            "SELECT id, domain_id, name, description, base_path, enabled, created_at, updated_at, settings
              FROM collections WHERE id = ?",
             [id],
-            |row| Ok(Collection {
-                id: row.get(0)?,
-                domain_id: row.get(1)?,
-                name: row.get(2)?,
-                description: row.get(3)?,
-                base_path: row.get(4)?,
-                enabled: row.get(5)?,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
-            })
+            collection_from_row,
         ).optional()?;
         Ok(collection)
     }
 
     /// Create a new collection
     pub fn create_collection(&self, collection: &Collection) -> Result<()> {
+        let settings_str = serde_json::to_string(&collection.settings)?;
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO collections (id, domain_id, name, description, base_path, enabled) VALUES (?, ?, ?, ?, ?, ?)",
-            params![collection.id, collection.domain_id, collection.name, collection.description, collection.base_path, collection.enabled],
+            "INSERT INTO collections (id, domain_id, name, description, base_path, enabled, settings) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![collection.id, collection.domain_id, collection.name, collection.description, collection.base_path, collection.enabled, settings_str],
         )?;
         Ok(())
     }
 
     /// Update a collection
     pub fn update_collection(&self, collection: &Collection) -> Result<()> {
+        let settings_str = serde_json::to_string(&collection.settings)?;
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "UPDATE collections SET name = ?, description = ?, base_path = ?, enabled = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
-            params![collection.name, collection.description, collection.base_path, collection.enabled, collection.id],
+            "UPDATE collections SET name = ?, description = ?, base_path = ?, enabled = ?, settings = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            params![collection.name, collection.description, collection.base_path, collection.enabled, settings_str, collection.id],
         )?;
         Ok(())
     }
@@ -607,5 +868,159 @@ impl Database {
         conn.execute("DELETE FROM services WHERE id = ?", [id])?;
         Ok(())
     }
+
+    // ========================================================================
+    // Upstream CRUD
+    // ========================================================================
+
+    /// List all upstreams
+    pub fn list_upstreams(&self) -> Result<Vec<Upstream>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, targets, settings, enabled, created_at, updated_at
+             FROM upstreams ORDER BY name"
+        )?;
+
+        let upstreams = stmt.query_map([], upstream_from_row)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(upstreams)
+    }
+
+    /// Get an upstream by ID
+    pub fn get_upstream(&self, id: &str) -> Result<Option<Upstream>> {
+        let conn = self.conn.lock().unwrap();
+        let upstream = conn.query_row(
+            "SELECT id, name, targets, settings, enabled, created_at, updated_at
+             FROM upstreams WHERE id = ?",
+            [id],
+            upstream_from_row,
+        ).optional()?;
+        Ok(upstream)
+    }
+
+    /// Create a new upstream
+    pub fn create_upstream(&self, upstream: &Upstream) -> Result<()> {
+        let targets_str = serde_json::to_string(&upstream.targets)?;
+        let settings_str = serde_json::to_string(&upstream.settings)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO upstreams (id, name, targets, settings, enabled) VALUES (?, ?, ?, ?, ?)",
+            params![upstream.id, upstream.name, targets_str, settings_str, upstream.enabled],
+        )?;
+        Ok(())
+    }
+
+    /// Update an upstream
+    pub fn update_upstream(&self, upstream: &Upstream) -> Result<()> {
+        let targets_str = serde_json::to_string(&upstream.targets)?;
+        let settings_str = serde_json::to_string(&upstream.settings)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE upstreams SET name = ?, targets = ?, settings = ?, enabled = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            params![upstream.name, targets_str, settings_str, upstream.enabled, upstream.id],
+        )?;
+        Ok(())
+    }
+
+    /// Delete an upstream
+    pub fn delete_upstream(&self, id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM upstreams WHERE id = ?", [id])?;
+        Ok(())
+    }
+}
+
+fn upstream_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Upstream> {
+    let targets_str: String = row.get(2)?;
+    let settings_str: Option<String> = row.get(3)?;
+    Ok(Upstream {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        targets: serde_json::from_str(&targets_str).unwrap_or_default(),
+        settings: settings_str.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+        enabled: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+/// A rule row, or None if its rule no longer parses
+fn domain_rule_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Option<DomainRule>> {
+    let rule_str: String = row.get(3)?;
+    let id: String = row.get(0)?;
+    let rule = match serde_json::from_str(&rule_str) {
+        Ok(rule) => rule,
+        Err(e) => {
+            tracing::warn!(rule = %id, "Ignoring unreadable rule: {}", e);
+            return Ok(None);
+        }
+    };
+    Ok(Some(DomainRule {
+        id,
+        domain_id: row.get(1)?,
+        position: row.get(2)?,
+        rule,
+        enabled: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    }))
 }
 
+/// A canary row, or None if its settings no longer parse
+fn canary_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Option<Canary>> {
+    let endpoint_id: String = row.get(0)?;
+    let deps_str: Option<String> = row.get(2)?;
+    let settings_str: String = row.get(3)?;
+    let settings = match serde_json::from_str(&settings_str) {
+        Ok(settings) => settings,
+        Err(e) => {
+            tracing::warn!(endpoint = %endpoint_id, "Ignoring unreadable canary: {}", e);
+            return Ok(None);
+        }
+    };
+    Ok(Some(Canary {
+        endpoint_id,
+        code: row.get(1)?,
+        dependencies: deps_str.and_then(|d| serde_json::from_str(&d).ok()),
+        settings,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    }))
+}
+
+/// A mirror row, or None if its settings no longer parse
+fn mirror_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Option<Mirror>> {
+    let endpoint_id: String = row.get(0)?;
+    let deps_str: Option<String> = row.get(2)?;
+    let settings_str: String = row.get(3)?;
+    let settings = match serde_json::from_str(&settings_str) {
+        Ok(settings) => settings,
+        Err(e) => {
+            tracing::warn!(endpoint = %endpoint_id, "Ignoring unreadable mirror: {}", e);
+            return Ok(None);
+        }
+    };
+    Ok(Some(Mirror {
+        endpoint_id,
+        code: row.get(1)?,
+        dependencies: deps_str.and_then(|d| serde_json::from_str(&d).ok()),
+        settings,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    }))
+}
+
+/// A collection row
+fn collection_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Collection> {
+    let settings_str: Option<String> = row.get(8)?;
+    Ok(Collection {
+        id: row.get(0)?,
+        domain_id: row.get(1)?,
+        name: row.get(2)?,
+        description: row.get(3)?,
+        base_path: row.get(4)?,
+        enabled: row.get(5)?,
+        settings: settings_str.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
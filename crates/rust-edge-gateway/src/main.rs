@@ -21,6 +21,9 @@ mod admin_auth; // New: Admin authentication
 mod rate_limit; // Rate limiting for authentication
 mod session; // Session management for admin UI
 mod services; // Service connectors
+mod net; // Gateway listener and client IP resolution
+mod acme; // Certificate issuance through ACME
+mod shutdown; // Graceful shutdown and draining
 
 use anyhow::Result;
 use axum::{
@@ -39,9 +42,12 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use crate::api::Endpoint;
 use crate::config::AppConfig;
 use crate::db::Database;
+use crate::router::cache::ResponseCache;
+use crate::router::table::{RouteTable, RoutingData, SharedRouteTable};
 use crate::worker::WorkerManager;
 use crate::runtime::{
     Services as RuntimeServices,
+    AbiMismatch,
     HandlerRegistry,
     context::RuntimeConfig,
 };
@@ -55,11 +61,32 @@ pub struct AppState {
     pub db: Database,
     pub workers: RwLock<WorkerManager>,
 
+    // Compiled route table used by the gateway router
+    pub routes: SharedRouteTable,
+
     // New v2 runtime components
     pub runtime_services: RwLock<RuntimeServices>,
     pub handler_registry: HandlerRegistry,
     pub runtime_config: Arc<RuntimeConfig>,
 
+    // Shared cache of handler responses (None when disabled)
+    pub response_cache: Option<ResponseCache>,
+
+    // TLS certificates served on the TLS gateway port
+    pub certs: Arc<net::tls::CertStore>,
+
+    // Issues certificates for letsencrypt domains
+    pub acme: acme::AcmeManager,
+
+    // HTTP client for proxy endpoints
+    pub upstream_client: reqwest::Client,
+
+    // Upstream pools that proxy endpoints balance across
+    pub upstreams: router::upstream::UpstreamPools,
+
+    // Rate limiters of the rate-limit route middleware, kept across route reloads
+    pub route_rate_limits: router::middleware::RateLimits,
+
     // Rate limiters for authentication
     pub login_rate_limiter: Arc<rate_limit::RateLimiter>,
     pub api_key_rate_limiter: Arc<rate_limit::RateLimiter>,
@@ -73,11 +100,11 @@ impl AppState {
     ///
     /// This creates a Context that handlers receive, populated with
     /// service provider bridges that communicate with service actors.
-    pub async fn create_sdk_context(&self) -> SdkContext {
+    pub async fn create_sdk_context(&self, request_id: &str) -> SdkContext {
         let services = self.runtime_services.read().await;
 
         // Create SDK Context with bridges to service actors
-        let mut ctx = SdkContext::new(uuid::Uuid::new_v4().to_string());
+        let mut ctx = SdkContext::new(request_id.to_string());
 
         // Add MinIO bridge if service is active
         if let Some(minio_handle) = &services.minio {
@@ -91,6 +118,51 @@ impl AppState {
         ctx
     }
 
+    /// Rebuild the gateway route table from the database
+    ///
+    /// Called at startup and after every admin change that affects routing.
+    /// On failure the previous table stays in place.
+    pub fn reload_routes(&self) {
+        let load = || -> anyhow::Result<RouteTable> {
+            Ok(RouteTable::build(RoutingData {
+                domains: self.db.list_domains()?,
+                collections: self.db.list_collections(None)?,
+                endpoints: self.db.list_enabled_endpoints()?,
+                rules: self.db.list_domain_rules(None)?,
+                default_host: self.config.default_domain.clone(),
+            }))
+        };
+
+        match self.routes.rebuild(load) {
+            Ok(count) => tracing::debug!("Route table rebuilt with {} routes", count),
+            Err(e) => tracing::error!("Failed to rebuild route table: {}", e),
+        }
+    }
+
+    /// Rebuild the upstream pools from the database
+    ///
+    /// Targets that stay in a pool keep their health state.
+    pub fn reload_upstreams(&self) {
+        match self.db.list_upstreams() {
+            Ok(upstreams) => self.upstreams.reload(&upstreams),
+            Err(e) => tracing::error!("Failed to reload upstream pools: {}", e),
+        }
+    }
+
+    /// Reload the TLS certificates of all domains from the certificates directory
+    ///
+    /// Called at startup, after domain and certificate changes, and
+    /// periodically to pick up renewed files. Never interrupts open connections.
+    pub fn reload_certificates(&self) {
+        match self.db.list_domains() {
+            Ok(domains) => {
+                let count = self.certs.reload(&domains);
+                tracing::debug!("Loaded TLS certificates for {} domains", count);
+            }
+            Err(e) => tracing::error!("Failed to reload TLS certificates: {}", e),
+        }
+    }
+
     /// Update runtime services (e.g., when activating a new service actor)
     pub async fn update_services<F>(&self, f: F)
     where
@@ -145,7 +217,7 @@ async fn main() -> Result<()> {
     let handler_registry = HandlerRegistry::new(config.handlers_dir.clone());
     let runtime_config = Arc::new(RuntimeConfig {
         handler_timeout_secs: config.handler_timeout_secs,
-        max_body_size: 10 * 1024 * 1024, // 10MB
+        max_body_size: config.max_body_size,
         debug: std::env::var("RUST_LOG").map(|v| v.contains("debug")).unwrap_or(false),
     });
 
@@ -163,6 +235,12 @@ async fn main() -> Result<()> {
         for endpoint in enabled_endpoints {
             match handler_registry.load(&endpoint.id).await {
                 Ok(_) => tracing::info!("Reloaded handler: {} ({})", endpoint.name, endpoint.id),
+                Err(e) if e.is::<AbiMismatch>() => {
+                    // Built by an older gateway; it needs compiling again, not disabling
+                    tracing::warn!("Handler {} ({}) needs recompiling: {}. Marking as not compiled.",
+                        endpoint.name, endpoint.id, e);
+                    let _ = db.mark_compiled(&endpoint.id, false);
+                }
                 Err(e) => {
                     tracing::warn!("Failed to reload handler {} ({}): {}. Marking as disabled.",
                         endpoint.name, endpoint.id, e);
@@ -173,6 +251,28 @@ async fn main() -> Result<()> {
         }
     }
 
+    // Resume canary releases and mirroring of the handlers that were reloaded
+    let drain_timeout = std::time::Duration::from_secs(config.shutdown_timeout_secs);
+    for canary in db.list_canaries().unwrap_or_default() {
+        let weight = canary.settings.weight;
+        match handler_registry.load_candidate(&canary.endpoint_id, canary.settings, drain_timeout).await {
+            Ok(_) => tracing::info!("Resumed canary release of {} at {}%", canary.endpoint_id, weight),
+            Err(e) => {
+                tracing::warn!("Failed to resume canary release of {}: {}. Aborting it.", canary.endpoint_id, e);
+                let _ = db.delete_canary(&canary.endpoint_id);
+            }
+        }
+    }
+    for mirror in db.list_mirrors().unwrap_or_default() {
+        match handler_registry.load_shadow(&mirror.endpoint_id, mirror.settings, drain_timeout).await {
+            Ok(_) => tracing::info!("Resumed mirroring of {}", mirror.endpoint_id),
+            Err(e) => {
+                tracing::warn!("Failed to resume mirroring of {}: {}. Stopping it.", mirror.endpoint_id, e);
+                let _ = db.delete_mirror(&mirror.endpoint_id);
+            }
+        }
+    }
+
     // Initialize rate limiters
     // Login: 5 attempts per 15 minutes
     let login_rate_limiter = Arc::new(rate_limit::RateLimiter::new(
@@ -195,14 +295,26 @@ async fn main() -> Result<()> {
         config: config.clone(),
         db,
         workers: RwLock::new(workers),
+        routes: SharedRouteTable::new(),
         runtime_services: RwLock::new(runtime_services),
         handler_registry,
         runtime_config,
+        response_cache: config.response_cache.clone().map(ResponseCache::new),
+        certs: Arc::new(net::tls::CertStore::new(&config.certs_dir)),
+        acme: acme::AcmeManager::new(config.acme.clone(), config.data_dir.join("acme")),
+        upstream_client: router::proxy::client()?,
+        upstreams: router::upstream::UpstreamPools::new(),
+        route_rate_limits: router::middleware::RateLimits::new(),
         login_rate_limiter,
         api_key_rate_limiter,
         session_store,
     });
 
+    // Build the initial route table (after disabling handlers that failed to load)
+    state.reload_routes();
+    state.reload_upstreams();
+    state.reload_certificates();
+
     // ============================================================================
     // API Routes - Consolidated under /api
     // ============================================================================
@@ -215,6 +327,10 @@ async fn main() -> Result<()> {
         .route("/{id}/compile", post(api::compile_endpoint))
         .route("/{id}/start", post(api::start_endpoint))
         .route("/{id}/stop", post(api::stop_endpoint))
+        .route("/{id}/canary", get(api::get_canary).post(api::start_canary).put(api::update_canary))
+        .route("/{id}/canary/promote", post(api::promote_canary))
+        .route("/{id}/canary/abort", post(api::abort_canary))
+        .route("/{id}/mirror", get(api::get_mirror).post(api::start_mirror).put(api::update_mirror).delete(api::stop_mirror))
         .layer(axum::middleware::from_fn_with_state(state.clone(), endpoints_api_key_auth));
 
     // Services API - protected by API key with services:* permissions
@@ -226,12 +342,27 @@ async fn main() -> Result<()> {
         .route("/{id}/deactivate", post(api::deactivate_service))
         .layer(axum::middleware::from_fn_with_state(state.clone(), services_api_key_auth));
 
+    // Upstreams API - protected by API key with services:* permissions
+    let upstreams_api = Router::new()
+        .route("/", get(api::list_upstreams).post(api::create_upstream))
+        .route("/{id}", get(api::get_upstream).put(api::update_upstream).delete(api::delete_upstream))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), services_api_key_auth));
+
     // Domains API - protected by API key with endpoints:* permissions
     // Domains are organizational containers for endpoints, so they use endpoint permissions
     let domains_api = Router::new()
         .route("/", get(api::list_domains).post(api::create_domain))
         .route("/{id}", get(api::get_domain).put(api::update_domain).delete(api::delete_domain))
         .route("/{id}/collections", get(api::list_domain_collections))
+        .route("/{id}/certificate", get(api::get_domain_certificate).put(api::upload_domain_certificate).delete(api::delete_domain_certificate))
+        .route("/{id}/certificate/renew", post(api::renew_domain_certificate))
+        .route("/{id}/rules", get(api::list_domain_rules).post(api::create_domain_rule))
+        .route("/{id}/rules/test", post(api::test_domain_rules))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), endpoints_api_key_auth));
+
+    // Domain rules API - protected by API key with endpoints:* permissions
+    let rules_api = Router::new()
+        .route("/{id}", get(api::get_domain_rule).put(api::update_domain_rule).delete(api::delete_domain_rule))
         .layer(axum::middleware::from_fn_with_state(state.clone(), endpoints_api_key_auth));
 
     // Collections API - protected by API key with endpoints:* permissions
@@ -259,6 +390,7 @@ async fn main() -> Result<()> {
         .route("/recaptcha-site-key", get(admin_auth::get_recaptcha_site_key))
         // System stats and health (also available to admin UI)
         .route("/stats", get(api::get_stats))
+        .route("/cache/purge", post(api::purge_response_cache))
         // Import operations (session auth for Admin UI - API key auth available at /api/import/*)
         .route("/import/openapi", post(api::import_openapi))
         .route("/import/bundle", post(api::import_bundle))
@@ -269,16 +401,28 @@ async fn main() -> Result<()> {
         .route("/endpoints/{id}/compile", post(api::compile_endpoint))
         .route("/endpoints/{id}/start", post(api::start_endpoint))
         .route("/endpoints/{id}/stop", post(api::stop_endpoint))
+        .route("/endpoints/{id}/canary", get(api::get_canary).post(api::start_canary).put(api::update_canary))
+        .route("/endpoints/{id}/canary/promote", post(api::promote_canary))
+        .route("/endpoints/{id}/canary/abort", post(api::abort_canary))
+        .route("/endpoints/{id}/mirror", get(api::get_mirror).post(api::start_mirror).put(api::update_mirror).delete(api::stop_mirror))
         // Services management for Admin UI (session auth - API key auth available at /api/services/*)
         .route("/services", get(api::list_services).post(api::create_service))
         .route("/services/{id}", get(api::get_service).put(api::update_service).delete(api::delete_service))
         .route("/services/{id}/test", post(api::test_service))
         .route("/services/{id}/activate", post(api::activate_service))
         .route("/services/{id}/deactivate", post(api::deactivate_service))
+        // Upstreams management for Admin UI (session auth - API key auth available at /api/upstreams/*)
+        .route("/upstreams", get(api::list_upstreams).post(api::create_upstream))
+        .route("/upstreams/{id}", get(api::get_upstream).put(api::update_upstream).delete(api::delete_upstream))
         // Domains management for Admin UI (session auth - API key auth available at /api/domains/*)
         .route("/domains", get(api::list_domains).post(api::create_domain))
         .route("/domains/{id}", get(api::get_domain).put(api::update_domain).delete(api::delete_domain))
         .route("/domains/{id}/collections", get(api::list_domain_collections))
+        .route("/domains/{id}/certificate", get(api::get_domain_certificate).put(api::upload_domain_certificate).delete(api::delete_domain_certificate))
+        .route("/domains/{id}/certificate/renew", post(api::renew_domain_certificate))
+        .route("/domains/{id}/rules", get(api::list_domain_rules).post(api::create_domain_rule))
+        .route("/domains/{id}/rules/test", post(api::test_domain_rules))
+        .route("/rules/{id}", get(api::get_domain_rule).put(api::update_domain_rule).delete(api::delete_domain_rule))
         // Collections management for Admin UI (session auth - API key auth available at /api/collections/*)
         .route("/collections", get(api::list_collections).post(api::create_collection))
         .route("/collections/{id}", get(api::get_collection).put(api::update_collection).delete(api::delete_collection))
@@ -301,7 +445,9 @@ async fn main() -> Result<()> {
         .nest("/auth", create_admin_auth_router())    // Public auth routes (login, password change)
         .nest("/api/endpoints", endpoints_api)        // API key auth: endpoints:*
         .nest("/api/services", services_api)          // API key auth: services:*
+        .nest("/api/upstreams", upstreams_api)        // API key auth: services:*
         .nest("/api/domains", domains_api)            // API key auth: domains:*
+        .nest("/api/rules", rules_api)                // API key auth: endpoints:*
         .nest("/api/collections", collections_api)    // API key auth: collections:*
         .nest("/api/import", imports_api)           // API key auth: import:* or (endpoints:write + services:write)
         .nest("/api/admin", admin_api)                // Session auth: Admin UI only
@@ -317,34 +463,113 @@ async fn main() -> Result<()> {
     let admin_listener = tokio::net::TcpListener::bind(&admin_addr).await?;
     tracing::info!("Admin UI listening on {}", admin_addr);
 
+    // Servers stop accepting connections once this is triggered
+    let shutdown = shutdown::Shutdown::new();
+
     let admin_state = state.clone();
+    let admin_stop = shutdown.wait();
     let admin_handle = tokio::spawn(async move {
         let app = admin_router
             .layer(CorsLayer::permissive())
             .layer(TraceLayer::new_for_http())
+            .layer(axum::middleware::from_fn(net::request_id::assign_request_id))
             .with_state(admin_state);
         
-        axum::serve(admin_listener, app).await
+        axum::serve(admin_listener, app).with_graceful_shutdown(admin_stop).await
     });
 
     // Start gateway server on port 8080
     let gateway_addr = format!("0.0.0.0:{}", config.gateway_port);
-    let gateway_listener = tokio::net::TcpListener::bind(&gateway_addr).await?;
+    let tcp_listener = tokio::net::TcpListener::bind(&gateway_addr).await?;
+    if config.proxy_protocol && config.trusted_proxies.is_empty() {
+        tracing::warn!("PROXY protocol is enabled but RUST_EDGE_GATEWAY_TRUSTED_PROXIES is empty; headers will be ignored");
+    }
+    let gateway_listener = net::GatewayListener::new(
+        tcp_listener,
+        config.proxy_protocol,
+        Arc::new(config.trusted_proxies.clone()),
+    )?;
     tracing::info!("Gateway listening on {}", gateway_addr);
 
+    // Client IP resolution runs outside the trace layer so the span can record it
     let gateway_app = gateway_router
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+        .layer(TraceLayer::new_for_http().make_span_with(net::client_ip::make_span))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), net::client_ip::resolve_client_ip))
+        .layer(axum::middleware::from_fn(net::request_id::assign_request_id))
+        .with_state(state.clone());
+
+    // Start the TLS gateway port if configured; it serves the same router
+    let tls_handle = match config.tls_port {
+        Some(port) => {
+            let tls_addr = format!("0.0.0.0:{}", port);
+            let tcp_listener = tokio::net::TcpListener::bind(&tls_addr).await?;
+            let tls_listener = net::tls::TlsListener::new(
+                net::GatewayListener::new(tcp_listener, config.proxy_protocol, Arc::new(config.trusted_proxies.clone()))?,
+                net::tls::server_config(state.certs.clone()),
+            )?;
+            tracing::info!("Gateway (TLS) listening on {}, certificates in {}", tls_addr, config.certs_dir.display());
+
+            let app = gateway_app.clone().layer(axum::Extension(net::Secure));
+            let tls_stop = shutdown.wait();
+            Some(tokio::spawn(async move {
+                axum::serve(tls_listener, app.into_make_service_with_connect_info::<net::PeerAddr>())
+                    .with_graceful_shutdown(tls_stop)
+                    .await
+            }))
+        }
+        None => None,
+    };
+
+    // Pick up certificates renewed on disk
+    let reload_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(net::tls::RELOAD_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            reload_state.reload_certificates();
+        }
+    });
+
+    // Issue and renew certificates for letsencrypt domains
+    tokio::spawn(acme::run(state.clone()));
 
+    // Health-check the targets of upstream pools
+    tokio::spawn(router::upstream::run(state.clone()));
+
+    // Drop idle clients from the rate-limit middleware's limiters
+    tokio::spawn(router::middleware::run(state.clone()));
+
+    let gateway_stop = shutdown.wait();
     let gateway_handle = tokio::spawn(async move {
-        axum::serve(gateway_listener, gateway_app).await
+        axum::serve(
+            gateway_listener,
+            gateway_app.into_make_service_with_connect_info::<net::PeerAddr>(),
+        )
+        .with_graceful_shutdown(gateway_stop)
+        .await
     });
 
-    // Wait for both servers
+    // Run until a shutdown signal, or until a server fails
+    let mut servers = vec![("Admin", admin_handle), ("Gateway", gateway_handle)];
+    if let Some(handle) = tls_handle {
+        servers.push(("TLS gateway", handle));
+    }
     tokio::select! {
-        res = admin_handle => tracing::error!("Admin server exited: {:?}", res),
-        res = gateway_handle => tracing::error!("Gateway server exited: {:?}", res),
+        _ = shutdown::signal() => {}
+        (res, index, _) = futures::future::select_all(servers.iter_mut().map(|(_, handle)| handle)) => {
+            let (name, _) = servers.remove(index);
+            tracing::error!("{} server exited: {:?}", name, res);
+        }
     }
 
+    tracing::info!("Shutting down, draining for up to {}s", config.shutdown_timeout_secs);
+    shutdown.trigger();
+    shutdown::drain(
+        &state,
+        servers.into_iter().map(|(_, handle)| handle).collect(),
+        std::time::Duration::from_secs(config.shutdown_timeout_secs),
+    ).await;
+
     Ok(())
 }
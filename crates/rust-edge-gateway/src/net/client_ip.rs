@@ -0,0 +1,277 @@
+//! Client IP resolution
+//!
+//! The client IP starts as the connection's source address (the TCP peer,
+//! or the address from a PROXY protocol header). Forwarding headers are only
+//! honored when that address belongs to a trusted proxy; otherwise any client
+//! could claim an arbitrary IP.
+//!
+//! Headers are consulted in order of preference — `Forwarded` (RFC 7239),
+//! then `X-Forwarded-For`, then `X-Real-IP` — and the first one present is
+//! used. The hop list is walked from right to left, skipping trusted proxies;
+//! the first untrusted address is the client. If every hop is trusted the
+//! left-most address is used. An unparseable hop (such as `unknown`) stops the
+//! walk at the last address that could be verified.
+//!
+//! Requests that arrived over TLS carry the [`Secure`] marker. A trusted proxy
+//! can also vouch for the scheme with `Forwarded: proto=https` or
+//! `X-Forwarded-Proto: https`.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+
+use super::listener::PeerAddr;
+use crate::AppState;
+
+/// Resolved client IP, stored as a request extension by [`resolve_client_ip`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Marker extension for requests the client sent over HTTPS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Secure;
+
+/// Networks whose forwarding headers and PROXY headers are trusted
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    nets: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    /// Parse a comma-separated list of CIDRs or bare IPs
+    ///
+    /// Invalid entries are logged and skipped.
+    pub fn from_list(list: &str) -> Self {
+        let nets = list.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .filter_map(|entry| {
+                let parsed = entry.parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from));
+                if parsed.is_err() {
+                    tracing::warn!("Ignoring invalid trusted proxy entry '{}'", entry);
+                }
+                parsed.ok()
+            })
+            .collect();
+        Self { nets }
+    }
+
+    /// Whether `ip` belongs to a trusted proxy
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.nets.iter().any(|net| net.contains(&ip))
+    }
+
+    /// Whether no proxies are trusted
+    pub fn is_empty(&self) -> bool {
+        self.nets.is_empty()
+    }
+}
+
+/// Resolve the client IP for a request arriving from `source`
+pub fn resolve(source: IpAddr, headers: &HeaderMap, trusted: &TrustedProxies) -> IpAddr {
+    let mut client = source.to_canonical();
+    if !trusted.contains(client) {
+        return client;
+    }
+
+    for hop in forwarded_chain(headers).into_iter().rev() {
+        let Some(ip) = hop else {
+            break;
+        };
+        client = ip.to_canonical();
+        if !trusted.contains(client) {
+            break;
+        }
+    }
+    client
+}
+
+/// Collect the forwarding hop list from the preferred header that is present
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name: &str| headers.get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    let forwarded = values("forwarded");
+    if !forwarded.is_empty() {
+        return forwarded.iter()
+            .map(|element| {
+                element.split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(key, _)| key.eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_node(value))
+            })
+            .collect();
+    }
+
+    let xff = values("x-forwarded-for");
+    if !xff.is_empty() {
+        return xff.iter().map(|v| parse_node(v)).collect();
+    }
+
+    values("x-real-ip").iter().take(1).map(|v| parse_node(v)).collect()
+}
+
+/// The scheme a trusted proxy reports the client used, from `Forwarded` or
+/// `X-Forwarded-Proto`. The right-most value is the one the nearest proxy saw.
+fn forwarded_proto(headers: &HeaderMap) -> Option<String> {
+    let last = |name: &str| headers.get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .rfind(|s| !s.is_empty())
+        .map(str::to_string);
+
+    if let Some(element) = last("forwarded") {
+        return element.split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(key, _)| key.eq_ignore_ascii_case("proto"))
+            .map(|(_, value)| value.trim_matches('"').to_ascii_lowercase());
+    }
+    last("x-forwarded-proto").map(|v| v.to_ascii_lowercase())
+}
+
+/// Parse a node such as `192.0.2.1`, `192.0.2.1:4711`, `"[2001:db8::1]:4711"`
+/// or `2001:db8::1`. Returns None for `unknown` and obfuscated identifiers.
+fn parse_node(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+
+    if let Some(rest) = value.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+
+    value.parse::<IpAddr>().ok()
+        .or_else(|| value.parse::<std::net::SocketAddr>().ok().map(|a| a.ip()))
+}
+
+/// Middleware that stores the resolved [`ClientIp`] as a request extension,
+/// and marks the request [`Secure`] when a trusted proxy reports HTTPS
+///
+/// Requires the server to be started with
+/// `into_make_service_with_connect_info::<PeerAddr>()`; without connect info
+/// no `ClientIp` is set.
+pub async fn resolve_client_ip(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ConnectInfo(addr)) = request.extensions().get::<ConnectInfo<PeerAddr>>().copied() {
+        let trusted = &state.config.trusted_proxies;
+        let ip = resolve(addr.source().ip(), request.headers(), trusted);
+        request.extensions_mut().insert(ClientIp(ip));
+
+        if trusted.contains(addr.source().ip())
+            && forwarded_proto(request.headers()).as_deref() == Some("https")
+        {
+            request.extensions_mut().insert(Secure);
+        }
+    }
+    next.run(request).await
+}
+
+/// Span for `TraceLayer` that includes the request id and resolved client IP
+pub fn make_span<B>(request: &axum::http::Request<B>) -> tracing::Span {
+    let client_ip = request.extensions().get::<ClientIp>().map(|ip| ip.0);
+    let request_id = request.extensions().get::<super::RequestId>().map(|id| id.0.as_str());
+    tracing::debug_span!(
+        "request",
+        request_id = request_id.unwrap_or_default(),
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        client_ip = ?client_ip,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_headers() {
+        let trusted = TrustedProxies::from_list("10.0.0.0/8");
+        let h = headers(&[("x-forwarded-for", "1.2.3.4")]);
+        assert_eq!(resolve(ip("203.0.113.9"), &h, &trusted), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn test_x_forwarded_for_skips_trusted_hops() {
+        let trusted = TrustedProxies::from_list("10.0.0.0/8, 192.168.1.1");
+        let h = headers(&[
+            ("x-forwarded-for", "6.6.6.6, 198.51.100.7"),
+            ("x-forwarded-for", "192.168.1.1"),
+        ]);
+        // 6.6.6.6 was supplied by the client and is not trusted
+        assert_eq!(resolve(ip("10.1.2.3"), &h, &trusted), ip("198.51.100.7"));
+    }
+
+    #[test]
+    fn test_forwarded_takes_precedence() {
+        let trusted = TrustedProxies::from_list("10.0.0.0/8");
+        let h = headers(&[
+            ("forwarded", r#"for="[2001:db8::7]:4711";proto=https, for=10.0.0.2"#),
+            ("x-forwarded-for", "198.51.100.7"),
+        ]);
+        assert_eq!(resolve(ip("10.0.0.1"), &h, &trusted), ip("2001:db8::7"));
+    }
+
+    #[test]
+    fn test_unknown_hop_and_real_ip() {
+        let trusted = TrustedProxies::from_list("10.0.0.0/8");
+
+        let h = headers(&[("x-forwarded-for", "198.51.100.7, unknown, 10.0.0.5")]);
+        assert_eq!(resolve(ip("10.0.0.1"), &h, &trusted), ip("10.0.0.5"));
+
+        let h = headers(&[("x-real-ip", "198.51.100.8")]);
+        assert_eq!(resolve(ip("10.0.0.1"), &h, &trusted), ip("198.51.100.8"));
+    }
+
+    #[test]
+    fn test_forwarded_proto() {
+        let h = headers(&[("forwarded", r#"for=198.51.100.7;proto=http, for=10.0.0.2;proto="HTTPS""#)]);
+        assert_eq!(forwarded_proto(&h).as_deref(), Some("https"));
+
+        let h = headers(&[("x-forwarded-proto", "https")]);
+        assert_eq!(forwarded_proto(&h).as_deref(), Some("https"));
+
+        assert_eq!(forwarded_proto(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_trusted_proxies_parsing() {
+        let trusted = TrustedProxies::from_list("10.0.0.0/8, ::1, bogus, ");
+        assert!(trusted.contains(ip("10.9.8.7")));
+        assert!(trusted.contains(ip("::1")));
+        assert!(trusted.contains(ip("::ffff:10.0.0.1")));
+        assert!(!trusted.contains(ip("11.0.0.1")));
+        assert!(TrustedProxies::from_list("").is_empty());
+    }
+}
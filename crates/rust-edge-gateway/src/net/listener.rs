@@ -0,0 +1,199 @@
+//! Gateway TCP listener
+//!
+//! Wraps a [`TcpListener`] for use with `axum::serve`, reporting a
+//! [`PeerAddr`] for each connection through `ConnectInfo`.
+//!
+//! With PROXY protocol enabled, connections from trusted proxies may start
+//! with a PROXY v1 or v2 header. The header is read in a per-connection task
+//! (so a slow proxy cannot stall the accept loop) and its source address is
+//! reported as [`PeerAddr::proxied`]. Connections from other peers are never
+//! inspected; a PROXY header sent by an untrusted peer is passed through to
+//! the HTTP parser and rejected there.
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::extract::connect_info::Connected;
+use axum::serve::{IncomingStream, Listener};
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+use super::client_ip::TrustedProxies;
+use super::proxy_protocol::{self, Parsed};
+
+/// How long a trusted proxy has to send its PROXY header
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Addresses of an accepted connection
+#[derive(Debug, Clone, Copy)]
+pub struct PeerAddr {
+    /// Address of the TCP peer
+    pub peer: SocketAddr,
+
+    /// Client address reported by a PROXY protocol header, if any
+    pub proxied: Option<SocketAddr>,
+}
+
+impl PeerAddr {
+    /// The best known address of the connecting client
+    pub fn source(&self) -> SocketAddr {
+        self.proxied.unwrap_or(self.peer)
+    }
+}
+
+impl Connected<IncomingStream<'_, GatewayListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, GatewayListener>) -> Self {
+        *stream.remote_addr()
+    }
+}
+
+/// A TCP stream with any bytes read past the PROXY header replayed first
+#[derive(Debug)]
+pub struct GatewayStream {
+    prefix: Bytes,
+    inner: TcpStream,
+}
+
+impl AsyncRead for GatewayStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if !self.prefix.is_empty() {
+            let n = self.prefix.len().min(buf.remaining());
+            buf.put_slice(&self.prefix[..n]);
+            self.prefix.advance(n);
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for GatewayStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Listener for the gateway port
+pub struct GatewayListener {
+    local_addr: SocketAddr,
+    incoming: mpsc::Receiver<(GatewayStream, PeerAddr)>,
+}
+
+impl GatewayListener {
+    /// Start accepting connections on `listener`
+    ///
+    /// When `proxy_protocol` is set, PROXY headers are read from peers in
+    /// `trusted`.
+    pub fn new(listener: TcpListener, proxy_protocol: bool, trusted: Arc<TrustedProxies>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (tx, incoming) = mpsc::channel(128);
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        handle_accept_error(e).await;
+                        continue;
+                    }
+                };
+
+                if !proxy_protocol || !trusted.contains(peer.ip()) {
+                    let conn = (GatewayStream { prefix: Bytes::new(), inner: stream }, PeerAddr { peer, proxied: None });
+                    if tx.send(conn).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let mut stream = stream;
+                    match tokio::time::timeout(PROXY_HEADER_TIMEOUT, read_proxy_header(&mut stream)).await {
+                        Ok(Ok((proxied, prefix))) => {
+                            let _ = tx.send((GatewayStream { prefix, inner: stream }, PeerAddr { peer, proxied })).await;
+                        }
+                        Ok(Err(e)) => tracing::debug!(%peer, "Dropping connection: {}", e),
+                        Err(_) => tracing::debug!(%peer, "Dropping connection: PROXY header timed out"),
+                    }
+                });
+            }
+        });
+
+        Ok(Self { local_addr, incoming })
+    }
+}
+
+impl Listener for GatewayListener {
+    type Io = GatewayStream;
+    type Addr = PeerAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.incoming.recv().await {
+            Some(conn) => conn,
+            // The accept task only exits once this receiver is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(PeerAddr { peer: self.local_addr, proxied: None })
+    }
+}
+
+/// Read an optional PROXY header, returning the client address it carries
+/// and any bytes read beyond it
+async fn read_proxy_header(stream: &mut TcpStream) -> io::Result<(Option<SocketAddr>, Bytes)> {
+    let mut buf = BytesMut::with_capacity(512);
+    loop {
+        match proxy_protocol::parse(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))? {
+            Parsed::NotProxy => return Ok((None, buf.freeze())),
+            Parsed::Header { len, source } => {
+                buf.advance(len);
+                return Ok((source, buf.freeze()));
+            }
+            Parsed::Incomplete => {
+                if stream.read_buf(&mut buf).await? == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+        }
+    }
+}
+
+/// Log an accept error, backing off unless it only affected one connection
+async fn handle_accept_error(e: io::Error) {
+    if matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset
+    ) {
+        return;
+    }
+    tracing::error!("Gateway accept error: {}", e);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
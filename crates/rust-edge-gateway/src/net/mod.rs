@@ -0,0 +1,20 @@
+//! Connection-level networking for the gateway listener
+//!
+//! - [`listener`]: TCP listener that reports peer addresses and optionally
+//!   accepts PROXY protocol headers from trusted proxies
+//! - [`proxy_protocol`]: PROXY protocol v1/v2 header parsing
+//! - [`client_ip`]: Resolves the client IP and scheme from the peer address
+//!   and forwarding headers set by trusted proxies
+//! - [`request_id`]: Assigns each request the id shared by logs, handlers
+//!   and the `X-Request-Id` response header
+//! - [`tls`]: TLS termination with per-domain certificates chosen by SNI
+
+pub mod client_ip;
+pub mod listener;
+pub mod proxy_protocol;
+pub mod request_id;
+pub mod tls;
+
+pub use client_ip::{ClientIp, Secure, TrustedProxies};
+pub use listener::{GatewayListener, PeerAddr};
+pub use request_id::RequestId;
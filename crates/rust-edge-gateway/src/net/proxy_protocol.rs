@@ -0,0 +1,202 @@
+//! PROXY protocol header parsing (v1 text and v2 binary)
+//!
+//! Load balancers such as HAProxy, AWS NLB and many ingress controllers can
+//! prepend a PROXY protocol header to each TCP connection carrying the
+//! original client address. See
+//! <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Signature that starts every v2 header
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Prefix of every v1 header
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// Longest legal v1 header, including the trailing CRLF
+const V1_MAX_LEN: usize = 107;
+
+/// Longest v2 header we accept (fixed part plus addresses and TLVs)
+pub const V2_MAX_LEN: usize = 16 + 4096;
+
+/// Errors in a PROXY protocol header
+#[derive(Debug, thiserror::Error)]
+pub enum ProxyHeaderError {
+    #[error("PROXY v1 header is not terminated within {V1_MAX_LEN} bytes")]
+    V1TooLong,
+
+    #[error("malformed PROXY v1 header: {0}")]
+    V1Malformed(String),
+
+    #[error("unsupported PROXY v2 version/command byte 0x{0:02x}")]
+    V2UnsupportedCommand(u8),
+
+    #[error("PROXY v2 header length {0} exceeds limit")]
+    V2TooLong(usize),
+
+    #[error("PROXY v2 address block is too short for its address family")]
+    V2Truncated,
+}
+
+/// Outcome of parsing the start of a connection
+#[derive(Debug, PartialEq, Eq)]
+pub enum Parsed {
+    /// More bytes are needed to decide
+    Incomplete,
+
+    /// The connection does not start with a PROXY header
+    NotProxy,
+
+    /// A complete header of `len` bytes. `source` is None for `UNKNOWN`
+    /// (v1) and `LOCAL` or non-IP (v2) headers, which carry no client address.
+    Header { len: usize, source: Option<SocketAddr> },
+}
+
+/// Parse a PROXY protocol header from the first bytes of a connection
+pub fn parse(buf: &[u8]) -> Result<Parsed, ProxyHeaderError> {
+    if buf.starts_with(V1_PREFIX) {
+        return parse_v1(buf);
+    }
+    if buf.starts_with(V2_SIGNATURE) {
+        return parse_v2(buf);
+    }
+    if V1_PREFIX.starts_with(buf) || V2_SIGNATURE.starts_with(buf) {
+        return Ok(Parsed::Incomplete);
+    }
+    Ok(Parsed::NotProxy)
+}
+
+fn parse_v1(buf: &[u8]) -> Result<Parsed, ProxyHeaderError> {
+    let window = &buf[..buf.len().min(V1_MAX_LEN)];
+    let Some(end) = window.windows(2).position(|w| w == b"\r\n") else {
+        return if buf.len() >= V1_MAX_LEN {
+            Err(ProxyHeaderError::V1TooLong)
+        } else {
+            Ok(Parsed::Incomplete)
+        };
+    };
+
+    let malformed = |reason: &str| ProxyHeaderError::V1Malformed(reason.to_string());
+    let line = std::str::from_utf8(&buf[..end]).map_err(|_| malformed("not ASCII"))?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    let len = end + 2;
+
+    match parts.get(1).copied() {
+        Some("UNKNOWN") => Ok(Parsed::Header { len, source: None }),
+        Some(family @ ("TCP4" | "TCP6")) => {
+            let [_, _, src, _dst, sport, _dport] = parts[..] else {
+                return Err(malformed("expected 'PROXY <family> <src> <dst> <sport> <dport>'"));
+            };
+            let ip: IpAddr = src.parse().map_err(|_| malformed("invalid source address"))?;
+            if ip.is_ipv4() != (family == "TCP4") {
+                return Err(malformed("source address does not match the address family"));
+            }
+            let port: u16 = sport.parse().map_err(|_| malformed("invalid source port"))?;
+            Ok(Parsed::Header { len, source: Some(SocketAddr::new(ip, port)) })
+        }
+        _ => Err(malformed("unknown address family")),
+    }
+}
+
+fn parse_v2(buf: &[u8]) -> Result<Parsed, ProxyHeaderError> {
+    if buf.len() < 16 {
+        return Ok(Parsed::Incomplete);
+    }
+
+    let version_command = buf[12];
+    let family_protocol = buf[13];
+    let addr_len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    let len = 16 + addr_len;
+
+    if len > V2_MAX_LEN {
+        return Err(ProxyHeaderError::V2TooLong(len));
+    }
+    if buf.len() < len {
+        return Ok(Parsed::Incomplete);
+    }
+
+    let source = match version_command {
+        // LOCAL: health checks from the proxy itself
+        0x20 => None,
+        // PROXY
+        0x21 => {
+            let addrs = &buf[16..len];
+            match family_protocol >> 4 {
+                // AF_INET
+                0x1 => {
+                    let a = addrs.get(..12).ok_or(ProxyHeaderError::V2Truncated)?;
+                    let ip = Ipv4Addr::new(a[0], a[1], a[2], a[3]);
+                    let port = u16::from_be_bytes([a[8], a[9]]);
+                    Some(SocketAddr::new(IpAddr::V4(ip), port))
+                }
+                // AF_INET6
+                0x2 => {
+                    let a = addrs.get(..36).ok_or(ProxyHeaderError::V2Truncated)?;
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(&a[..16]);
+                    let port = u16::from_be_bytes([a[32], a[33]]);
+                    Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port))
+                }
+                // AF_UNSPEC, AF_UNIX
+                _ => None,
+            }
+        }
+        other => return Err(ProxyHeaderError::V2UnsupportedCommand(other)),
+    };
+
+    Ok(Parsed::Header { len, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v1_tcp4() {
+        let buf = b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 443\r\nGET / HTTP/1.1\r\n";
+        let parsed = parse(buf).unwrap();
+        assert_eq!(parsed, Parsed::Header {
+            len: 43,
+            source: Some("203.0.113.7:51234".parse().unwrap()),
+        });
+    }
+
+    #[test]
+    fn test_v1_unknown_and_incomplete() {
+        assert_eq!(parse(b"PROXY UNKNOWN\r\n").unwrap(), Parsed::Header { len: 15, source: None });
+        assert_eq!(parse(b"PRO").unwrap(), Parsed::Incomplete);
+        assert_eq!(parse(b"PROXY TCP4 203.0.113.7").unwrap(), Parsed::Incomplete);
+        assert!(parse(b"PROXY TCP4 not-an-ip 10.0.0.1 1 2\r\n").is_err());
+
+        let mut long = V1_PREFIX.to_vec();
+        long.extend_from_slice(&[b'x'; 120]);
+        assert!(matches!(parse(&long), Err(ProxyHeaderError::V1TooLong)));
+    }
+
+    #[test]
+    fn test_v2_tcp6() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend_from_slice(&[0x21, 0x21, 0, 36]);
+        let src: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        buf.extend_from_slice(&src.octets());
+        buf.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        buf.extend_from_slice(&8443u16.to_be_bytes());
+        buf.extend_from_slice(&443u16.to_be_bytes());
+        buf.extend_from_slice(b"GET /");
+
+        assert_eq!(parse(&buf).unwrap(), Parsed::Header {
+            len: 52,
+            source: Some("[2001:db8::1]:8443".parse().unwrap()),
+        });
+        assert_eq!(parse(&buf[..20]).unwrap(), Parsed::Incomplete);
+    }
+
+    #[test]
+    fn test_v2_local_and_plain_http() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        assert_eq!(parse(&buf).unwrap(), Parsed::Header { len: 16, source: None });
+
+        assert_eq!(parse(b"GET / HTTP/1.1\r\n").unwrap(), Parsed::NotProxy);
+    }
+}
@@ -0,0 +1,116 @@
+//! Request IDs
+//!
+//! Every request gets one id, used for the SDK `Request` and `Context`, the
+//! tracing span, gateway error bodies and the `X-Request-Id` response
+//! header. A well-formed `X-Request-Id` from the client or an upstream proxy
+//! is kept so one id can follow a request across services; anything else is
+//! replaced with a fresh UUID.
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+/// Header the id is read from and echoed in
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest inbound id that is accepted
+pub const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// The request's id, stored as a request extension by [`assign_request_id`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// A fresh random id
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    /// The inbound id if it is valid, otherwise a fresh one
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers.get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|id| is_valid(id))
+            .map(|id| Self(id.to_string()))
+            .unwrap_or_else(Self::generate)
+    }
+
+    /// The id of a request, generating one if the middleware did not run
+    pub fn of(request: &Request) -> Self {
+        request.extensions().get::<RequestId>().cloned()
+            .unwrap_or_else(|| Self::from_headers(request.headers()))
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Whether an inbound id is short and made of safe characters
+///
+/// Ids end up in logs and response headers, so only letters, digits and
+/// `-_.:+=/@` are allowed.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.:+=/@".contains(&b))
+}
+
+/// Middleware that assigns the [`RequestId`] and echoes it in the response
+///
+/// Runs outside the trace layer so the request span can record the id.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let id = RequestId::from_headers(request.headers());
+    let header = HeaderValue::from_str(&id.0).ok();
+    request.extensions_mut().insert(id);
+
+    let mut response = next.run(request).await;
+    if let Some(header) = header {
+        response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    fn headers(id: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(id).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_inbound_id_validation() {
+        assert_eq!(RequestId::from_headers(&headers("abc-123")).0, "abc-123");
+        assert_eq!(RequestId::from_headers(&headers("Root=1-5759e988;Parent=53995c")).0.len(), 36);
+        assert_eq!(RequestId::from_headers(&headers(&"a".repeat(129))).0.len(), 36);
+        assert_eq!(RequestId::from_headers(&headers("")).0.len(), 36);
+        assert_eq!(RequestId::from_headers(&HeaderMap::new()).0.len(), 36);
+    }
+
+    #[tokio::test]
+    async fn test_middleware_shares_and_echoes_id() {
+        let app = Router::new()
+            .route("/", get(|request: Request| async move { RequestId::of(&request).0 }))
+            .layer(axum::middleware::from_fn(assign_request_id));
+
+        let request = axum::http::Request::get("/").header(REQUEST_ID_HEADER, "trace-42").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "trace-42");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "trace-42");
+
+        let response = app.oneshot(axum::http::Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+        assert!(Uuid::parse_str(response.headers()[REQUEST_ID_HEADER].to_str().unwrap()).is_ok());
+    }
+}
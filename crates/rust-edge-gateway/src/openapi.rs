@@ -118,11 +118,13 @@ pub fn create_endpoints_from_import(
             domain: domain.to_string(),
             path: parsed.path.clone(),
             method: parsed.method.clone(),
+            kind: Default::default(),
             description: parsed.description.clone(),
             code: Some(generate_default_handler(&parsed.name)),
             dependencies: None,
             compiled: false,
             enabled: true,
+            settings: Default::default(),
             created_at: None,
             updated_at: None,
         }
@@ -1,4 +1,5 @@
-//! Simple in-memory rate limiter for authentication endpoints
+//! Simple in-memory rate limiter for authentication endpoints and the
+//! `rate-limit` route middleware
 
 use dashmap::DashMap;
 use std::sync::Arc;
@@ -66,6 +67,11 @@ impl RateLimiter {
         self.entries.remove(identifier);
     }
 
+    /// Whether no identifier is being tracked
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
     /// Clean up expired entries (should be called periodically)
     pub fn cleanup(&self) {
         let now = Instant::now();
@@ -0,0 +1,427 @@
+//! Shared response cache
+//!
+//! Serves repeated GET requests from memory instead of calling the handler
+//! again. Only responses with an explicit lifetime are stored: `s-maxage` or
+//! `max-age` in `Cache-Control` (as set by `Response::with_cache`), or an
+//! `Expires` date. `no-store`, `no-cache`, `private` and responses that set
+//! cookies are never stored, nor are requests with an `Authorization` header.
+//!
+//! Entries are keyed by endpoint, host, method, path and query, with one
+//! variant per combination of the request headers named in the response's
+//! `Vary`. Each entry records the generation of the handler that produced
+//! it, so once an endpoint is reloaded or swapped its old entries no longer
+//! match and are replaced on the next store.
+//!
+//! Entries hold raw bytes and headers, so they live in the router rather
+//! than in the string-valued cache service.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, HttpBody};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use axum::response::Response;
+use bytes::Bytes;
+use dashmap::DashMap;
+use serde::Serialize;
+
+use super::problem::ProblemKind;
+
+/// Header telling clients whether the response came from the cache
+const X_CACHE: HeaderName = HeaderName::from_static("x-cache");
+
+/// Most `Vary` variants kept for one key
+const MAX_VARIANTS: usize = 16;
+
+/// Status codes whose responses may be stored
+const CACHEABLE_STATUS: &[u16] = &[200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// Response cache limits
+#[derive(Debug, Clone)]
+pub struct ResponseCacheConfig {
+    /// Maximum number of cached keys
+    pub max_entries: usize,
+
+    /// Largest response body, in bytes, that is stored
+    pub max_entry_size: usize,
+}
+
+/// Counters reported by the admin stats API
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CacheStats {
+    /// Cached responses, counting each `Vary` variant
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub stores: u64,
+    /// Keys dropped to make room for new ones
+    pub evictions: u64,
+}
+
+/// A cached response
+struct Entry {
+    generation: u64,
+    /// Request header values the response varies on
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    stored_at: Instant,
+    expires_at: Instant,
+}
+
+impl Entry {
+    fn matches(&self, request: &HeaderMap) -> bool {
+        self.vary.iter().all(|(name, value)| request.get(name) == value.as_ref())
+    }
+
+    fn to_response(&self, now: Instant) -> Response {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        let age = now.duration_since(self.stored_at).as_secs();
+        response.headers_mut().insert(header::AGE, HeaderValue::from(age));
+        response.headers_mut().insert(X_CACHE, HeaderValue::from_static("HIT"));
+        response
+    }
+}
+
+/// A request whose response may be served from or stored in the cache
+pub struct CacheableRequest {
+    key: String,
+    headers: HeaderMap,
+    /// False when the client asked for a fresh response (`no-cache`)
+    lookup: bool,
+}
+
+impl CacheableRequest {
+    /// Check whether a request can use the cache
+    ///
+    /// Returns None for anything but GET, for requests with credentials and
+    /// for requests sent with `Cache-Control: no-store`.
+    pub fn new(endpoint_id: &str, host: &str, method: &Method, uri: &Uri, headers: &HeaderMap) -> Option<Self> {
+        if method != Method::GET || headers.contains_key(header::AUTHORIZATION) {
+            return None;
+        }
+
+        let directives = cache_control(headers);
+        if has_directive(&directives, "no-store") {
+            return None;
+        }
+        let pragma_no_cache = headers.get(header::PRAGMA)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.eq_ignore_ascii_case("no-cache"));
+        let lookup = !(has_directive(&directives, "no-cache")
+            || directive_secs(&directives, "max-age") == Some(0)
+            || pragma_no_cache);
+
+        let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        Some(Self {
+            key: format!("{}\n{}\n{}\n{}", endpoint_id, host, method, path),
+            headers: headers.clone(),
+            lookup,
+        })
+    }
+}
+
+/// In-memory cache of handler responses
+pub struct ResponseCache {
+    entries: DashMap<String, Vec<Entry>>,
+    config: ResponseCacheConfig,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stores: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new(config: ResponseCacheConfig) -> Self {
+        Self {
+            entries: DashMap::new(),
+            config,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            stores: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Look up a fresh response produced by handler `generation`
+    pub fn get(&self, request: &CacheableRequest, generation: u64) -> Option<Response> {
+        let now = Instant::now();
+        let hit = request.lookup.then(|| {
+            self.entries.get(&request.key).and_then(|variants| {
+                variants.iter()
+                    .find(|e| e.generation == generation && e.expires_at > now && e.matches(&request.headers))
+                    .map(|e| e.to_response(now))
+            })
+        }).flatten();
+
+        let counter = if hit.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        hit
+    }
+
+    /// Store a response if it is cacheable, returning it to be sent
+    ///
+    /// Streamed bodies and bodies over the size limit pass through unstored.
+    /// Fails if the handler's body errors while it is buffered.
+    pub async fn store(&self, request: CacheableRequest, generation: u64, response: Response) -> Result<Response, ProblemKind> {
+        let mut response = self.try_store(request, generation, response).await?;
+        response.headers_mut().insert(X_CACHE, HeaderValue::from_static("MISS"));
+        Ok(response)
+    }
+
+    async fn try_store(&self, request: CacheableRequest, generation: u64, response: Response) -> Result<Response, ProblemKind> {
+        let Some(ttl) = freshness(response.status(), response.headers()) else {
+            return Ok(response);
+        };
+        let Some(vary) = vary_values(response.headers(), &request.headers) else {
+            return Ok(response);
+        };
+        let size = response.body().size_hint().exact();
+        if size.is_none_or(|n| n > self.config.max_entry_size as u64) {
+            return Ok(response);
+        }
+
+        let (parts, body) = response.into_parts();
+        let body = match axum::body::to_bytes(body, self.config.max_entry_size).await {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Failed to buffer response for cache: {}", e);
+                return Err(ProblemKind::HandlerFailed);
+            }
+        };
+
+        let now = Instant::now();
+        let entry = Entry {
+            generation,
+            vary,
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+            stored_at: now,
+            expires_at: now + ttl,
+        };
+
+        if !self.entries.contains_key(&request.key) && self.entries.len() >= self.config.max_entries {
+            self.make_room(now);
+        }
+
+        let mut variants = self.entries.entry(request.key).or_default();
+        variants.retain(|e| e.generation == generation && e.expires_at > now && e.vary != entry.vary);
+        variants.push(entry);
+        if variants.len() > MAX_VARIANTS {
+            variants.remove(0);
+        }
+        drop(variants);
+        self.stores.fetch_add(1, Ordering::Relaxed);
+
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    /// Drop expired entries, then the key closest to expiry if still full
+    fn make_room(&self, now: Instant) {
+        self.entries.retain(|_, variants| {
+            variants.retain(|e| e.expires_at > now);
+            !variants.is_empty()
+        });
+        if self.entries.len() < self.config.max_entries {
+            return;
+        }
+
+        let soonest = self.entries.iter()
+            .min_by_key(|item| item.value().iter().map(|e| e.expires_at).max())
+            .map(|item| item.key().clone());
+        if let Some(key) = soonest {
+            self.entries.remove(&key);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Remove every entry
+    pub fn purge(&self) {
+        self.entries.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.iter().map(|item| item.value().len()).sum(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            stores: self.stores.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Parsed `Cache-Control` directives (lowercase names, unquoted values)
+fn cache_control(headers: &HeaderMap) -> Vec<(String, Option<String>)> {
+    headers.get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|directive| {
+            let directive = directive.trim();
+            if directive.is_empty() {
+                return None;
+            }
+            Some(match directive.split_once('=') {
+                Some((name, value)) => (name.trim().to_ascii_lowercase(), Some(value.trim().trim_matches('"').to_string())),
+                None => (directive.to_ascii_lowercase(), None),
+            })
+        })
+        .collect()
+}
+
+fn has_directive(directives: &[(String, Option<String>)], name: &str) -> bool {
+    directives.iter().any(|(n, _)| n == name)
+}
+
+fn directive_secs(directives: &[(String, Option<String>)], name: &str) -> Option<u64> {
+    directives.iter()
+        .find(|(n, _)| n == name)
+        .and_then(|(_, v)| v.as_deref()?.parse().ok())
+}
+
+/// How long a response may be served from the cache, if at all
+fn freshness(status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+    if !CACHEABLE_STATUS.contains(&status.as_u16()) || headers.contains_key(header::SET_COOKIE) {
+        return None;
+    }
+
+    let directives = cache_control(headers);
+    if ["no-store", "no-cache", "private"].iter().any(|d| has_directive(&directives, d)) {
+        return None;
+    }
+
+    let ttl = match directive_secs(&directives, "s-maxage").or_else(|| directive_secs(&directives, "max-age")) {
+        Some(secs) => Duration::from_secs(secs),
+        None => {
+            let expires = headers.get(header::EXPIRES)?.to_str().ok()?;
+            let expires = chrono::DateTime::parse_from_rfc2822(expires).ok()?;
+            (expires.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()?
+        }
+    };
+    (!ttl.is_zero()).then_some(ttl)
+}
+
+/// The request's values for each header named in the response's `Vary`
+///
+/// Returns None for `Vary: *`, which can never be matched.
+fn vary_values(response: &HeaderMap, request: &HeaderMap) -> Option<Vec<(HeaderName, Option<HeaderValue>)>> {
+    let mut vary = Vec::new();
+    for value in response.get_all(header::VARY) {
+        for name in value.to_str().ok()?.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if name == "*" {
+                return None;
+            }
+            let name = HeaderName::from_bytes(name.as_bytes()).ok()?;
+            let value = request.get(&name).cloned();
+            vary.push((name, value));
+        }
+    }
+    vary.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+    vary.dedup_by(|a, b| a.0 == b.0);
+    Some(vary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache() -> ResponseCache {
+        ResponseCache::new(ResponseCacheConfig { max_entries: 2, max_entry_size: 1024 })
+    }
+
+    fn request(uri: &str, headers: &[(&str, &str)]) -> Option<CacheableRequest> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(HeaderName::from_bytes(name.as_bytes()).unwrap(), value.parse().unwrap());
+        }
+        CacheableRequest::new("ep", "example.com", &Method::GET, &uri.parse().unwrap(), &map)
+    }
+
+    fn response(headers: &[(&str, &str)], body: &'static str) -> Response {
+        let mut response = Response::new(Body::from(body));
+        for (name, value) in headers {
+            response.headers_mut().insert(HeaderName::from_bytes(name.as_bytes()).unwrap(), value.parse().unwrap());
+        }
+        response
+    }
+
+    async fn body(response: Response) -> Bytes {
+        axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_store_and_hit() {
+        let cache = cache();
+        assert!(cache.get(&request("/a?x=1", &[]).unwrap(), 1).is_none());
+
+        let stored = cache.store(request("/a?x=1", &[]).unwrap(), 1, response(&[("cache-control", "max-age=60")], "hello")).await.unwrap();
+        assert_eq!(stored.headers()["x-cache"], "MISS");
+        assert_eq!(body(stored).await, "hello");
+
+        let hit = cache.get(&request("/a?x=1", &[]).unwrap(), 1).unwrap();
+        assert_eq!(hit.headers()["x-cache"], "HIT");
+        assert_eq!(hit.headers()["age"], "0");
+        assert_eq!(body(hit).await, "hello");
+
+        // Different query, newer handler generation, or a client asking to revalidate
+        assert!(cache.get(&request("/a?x=2", &[]).unwrap(), 1).is_none());
+        assert!(cache.get(&request("/a?x=1", &[]).unwrap(), 2).is_none());
+        assert!(cache.get(&request("/a?x=1", &[("cache-control", "no-cache")]).unwrap(), 1).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses, stats.stores), (1, 1, 4, 1));
+    }
+
+    #[tokio::test]
+    async fn test_uncacheable_responses() {
+        let cache = cache();
+        for headers in [
+            vec![],
+            vec![("cache-control", "no-store, max-age=60")],
+            vec![("cache-control", "private, max-age=60")],
+            vec![("cache-control", "max-age=60"), ("set-cookie", "a=b")],
+            vec![("cache-control", "max-age=60"), ("vary", "*")],
+            vec![("expires", "Thu, 01 Jan 1970 00:00:00 GMT")],
+        ] {
+            cache.store(request("/b", &[]).unwrap(), 1, response(&headers, "x")).await.unwrap();
+        }
+        assert_eq!(cache.stats().stores, 0);
+
+        assert!(request("/b", &[("authorization", "Bearer t")]).is_none());
+        assert!(request("/b", &[("cache-control", "no-store")]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_vary_variants() {
+        let cache = cache();
+        for lang in ["en", "fr"] {
+            let req = request("/c", &[("accept-language", lang)]).unwrap();
+            let res = response(&[("cache-control", "s-maxage=60, max-age=0"), ("vary", "Accept-Language")], lang);
+            cache.store(req, 1, res).await.unwrap();
+        }
+
+        let fr = cache.get(&request("/c", &[("accept-language", "fr")]).unwrap(), 1).unwrap();
+        assert_eq!(body(fr).await, "fr");
+        assert!(cache.get(&request("/c", &[("accept-language", "de")]).unwrap(), 1).is_none());
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_eviction_when_full() {
+        let cache = cache();
+        for (path, ttl) in [("/1", "max-age=10"), ("/2", "max-age=60"), ("/3", "max-age=60")] {
+            cache.store(request(path, &[]).unwrap(), 1, response(&[("cache-control", ttl)], "x")).await.unwrap();
+        }
+        assert_eq!(cache.stats().evictions, 1);
+        assert!(cache.get(&request("/1", &[]).unwrap(), 1).is_none());
+        assert!(cache.get(&request("/3", &[]).unwrap(), 1).is_some());
+
+        cache.purge();
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
@@ -0,0 +1,248 @@
+//! Response compression
+//!
+//! Gateway responses are compressed with zstd, brotli or gzip, negotiated
+//! from the client's `Accept-Encoding` by tower-http's compression layer.
+//! Whether a response is eligible is decided by a [`CompressionPolicy`]: the
+//! gateway defaults, overridden per domain. The router attaches the matched
+//! domain's [`CompressionSettings`] to the response and [`PolicyPredicate`]
+//! merges them with the defaults.
+//!
+//! A response is sent as-is when:
+//! - the handler already encoded it (`Content-Encoding` is set)
+//! - its content type is not in the policy's list, or it is an event stream
+//! - its body is smaller than the policy's minimum size
+//! - its body is streamed, since the encoder would hold chunks back
+//!   until its buffer fills
+//! - it is a byte range (`Content-Range` is set)
+
+use std::sync::Arc;
+
+use axum::body::HttpBody;
+use axum::http::{header, HeaderMap, Response};
+use serde::{Deserialize, Serialize};
+use tower_http::compression::{predicate::Predicate, CompressionLayer};
+
+/// Content types compressed when no list is configured
+pub const DEFAULT_CONTENT_TYPES: &[&str] = &[
+    "text/*",
+    "application/json",
+    "application/*+json",
+    "application/javascript",
+    "application/xml",
+    "application/*+xml",
+    "application/wasm",
+    "image/svg+xml",
+];
+
+/// Minimum body size compressed when none is configured
+pub const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// Effective compression policy
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionPolicy {
+    /// Whether responses are compressed at all
+    pub enabled: bool,
+
+    /// Smallest body, in bytes, worth compressing
+    pub min_size: usize,
+
+    /// Media types to compress. An entry may contain one `*`, as in `text/*`
+    /// or `application/*+json`.
+    pub content_types: Vec<String>,
+}
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_size: DEFAULT_MIN_SIZE,
+            content_types: DEFAULT_CONTENT_TYPES.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+}
+
+impl CompressionPolicy {
+    /// Whether a response with these headers and body size may be compressed
+    ///
+    /// `overrides` are the serving domain's settings; unset fields fall back
+    /// to this policy. `size` is None for streamed bodies.
+    pub fn allows(&self, overrides: Option<&CompressionSettings>, headers: &HeaderMap, size: Option<u64>) -> bool {
+        let enabled = overrides.and_then(|o| o.enabled).unwrap_or(self.enabled);
+        let min_size = overrides.and_then(|o| o.min_size).unwrap_or(self.min_size);
+        let content_types = overrides
+            .and_then(|o| o.content_types.as_deref())
+            .unwrap_or(&self.content_types);
+
+        let Some(size) = size else {
+            return false;
+        };
+        if !enabled || size < min_size as u64 {
+            return false;
+        }
+
+        let Some(media_type) = headers.get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(';').next().unwrap_or("").trim().to_ascii_lowercase())
+        else {
+            return false;
+        };
+        if media_type == "text/event-stream" || headers.contains_key(header::CONTENT_RANGE) {
+            return false;
+        }
+        content_types.iter().any(|pattern| media_type_matches(pattern, &media_type))
+    }
+}
+
+/// Per-domain overrides of the gateway's compression defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompressionSettings {
+    /// Turn compression on or off for the domain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+
+    /// Smallest body, in bytes, worth compressing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_size: Option<usize>,
+
+    /// Media types to compress, replacing the default list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_types: Option<Vec<String>>,
+}
+
+impl CompressionSettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        for pattern in self.content_types.iter().flatten() {
+            let valid = pattern.split_once('/')
+                .is_some_and(|(kind, subtype)| !kind.is_empty() && !subtype.is_empty())
+                && pattern.matches('*').count() <= 1
+                && !pattern.contains(';');
+            if !valid {
+                return Err(format!("Invalid compression content type: {}", pattern));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `media_type` (lowercase, without parameters) matches `pattern`
+fn media_type_matches(pattern: &str, media_type: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            media_type.len() >= prefix.len() + suffix.len()
+                && media_type.starts_with(prefix)
+                && media_type.ends_with(suffix)
+        }
+        None => pattern == media_type,
+    }
+}
+
+/// Decides per response whether to compress, from the gateway defaults and
+/// any [`CompressionSettings`] the router attached to the response
+#[derive(Debug, Clone)]
+pub struct PolicyPredicate {
+    defaults: Arc<CompressionPolicy>,
+}
+
+impl Predicate for PolicyPredicate {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        self.defaults.allows(
+            response.extensions().get::<CompressionSettings>(),
+            response.headers(),
+            response.body().size_hint().exact(),
+        )
+    }
+}
+
+/// Build the compression layer for the gateway router
+pub fn layer(defaults: &CompressionPolicy) -> CompressionLayer<PolicyPredicate> {
+    CompressionLayer::new().compress_when(PolicyPredicate {
+        defaults: Arc::new(defaults.clone()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    fn headers(content_type: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_content_type_patterns() {
+        assert!(media_type_matches("application/json", "application/json"));
+        assert!(media_type_matches("text/*", "text/html"));
+        assert!(media_type_matches("application/*+json", "application/problem+json"));
+        assert!(!media_type_matches("application/*+json", "application/json"));
+        assert!(!media_type_matches("text/*", "image/png"));
+    }
+
+    #[test]
+    fn test_policy_and_overrides() {
+        let policy = CompressionPolicy::default();
+        let json = headers("application/json; charset=utf-8");
+
+        assert!(policy.allows(None, &json, Some(4096)));
+        assert!(!policy.allows(None, &json, Some(100)));
+        assert!(!policy.allows(None, &json, None));
+        assert!(!policy.allows(None, &headers("image/png"), Some(4096)));
+        assert!(!policy.allows(None, &headers("text/event-stream"), Some(4096)));
+        let mut range = json.clone();
+        range.insert(header::CONTENT_RANGE, "bytes 0-4095/8192".parse().unwrap());
+        assert!(!policy.allows(None, &range, Some(4096)));
+
+        let small = CompressionSettings { min_size: Some(10), ..Default::default() };
+        assert!(policy.allows(Some(&small), &json, Some(100)));
+
+        let off = CompressionSettings { enabled: Some(false), ..Default::default() };
+        assert!(!policy.allows(Some(&off), &json, Some(4096)));
+
+        let csv_only = CompressionSettings { content_types: Some(vec!["text/csv".into()]), ..Default::default() };
+        assert!(!policy.allows(Some(&csv_only), &json, Some(4096)));
+        assert!(policy.allows(Some(&csv_only), &headers("text/csv"), Some(4096)));
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        let bad = CompressionSettings { content_types: Some(vec!["json".into()]), ..Default::default() };
+        assert!(bad.validate().is_err());
+        let good = CompressionSettings { content_types: Some(vec!["text/*".into()]), ..Default::default() };
+        assert!(good.validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_layer_negotiates_and_skips_encoded() {
+        let app = Router::new()
+            .route("/json", get(|| async {
+                ([(header::CONTENT_TYPE, "application/json")], "x".repeat(4096))
+            }))
+            .route("/encoded", get(|| async {
+                ([(header::CONTENT_TYPE, "application/json"), (header::CONTENT_ENCODING, "gzip")], "x".repeat(4096))
+            }))
+            .layer(layer(&CompressionPolicy::default()));
+
+        let request = |path: &str, accept: &str| {
+            Request::get(path).header(header::ACCEPT_ENCODING, accept).body(Body::empty()).unwrap()
+        };
+
+        let response = app.clone().oneshot(request("/json", "gzip, br;q=0.5")).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+
+        let response = app.clone().oneshot(request("/json", "identity")).await.unwrap();
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+
+        let response = app.oneshot(request("/encoded", "br")).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.len(), 4096);
+    }
+}
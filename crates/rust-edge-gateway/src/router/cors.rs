@@ -0,0 +1,261 @@
+//! OPTIONS and CORS handling
+//!
+//! An OPTIONS request that no endpoint handles is answered by the gateway
+//! with an `Allow` header listing the methods routed at the path. If the
+//! request is a CORS preflight and the route it asks about has a
+//! [`CorsSettings`] policy that allows the origin, the
+//! `Access-Control-Allow-*` headers are added. A route's policy is its
+//! `cors` middleware, which defaults to the domain's `cors` setting.
+//!
+//! The same policy adds `Access-Control-Allow-Origin` to the route's
+//! regular responses, so handlers do not need `Response::with_cors`. A
+//! handler that sets the header itself is left alone.
+
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+
+use super::table::AllowedMethods;
+
+/// Per-domain CORS policy
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CorsSettings {
+    /// Origins allowed to make requests: `*`, an exact origin such as
+    /// `https://app.example.com`, or a subdomain wildcard such as
+    /// `https://*.example.com`
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+
+    /// Methods allowed in preflights (defaults to the methods routed at the path)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_methods: Option<Vec<String>>,
+
+    /// Request headers allowed in preflights (defaults to the headers the
+    /// preflight asks for)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_headers: Option<Vec<String>>,
+
+    /// Response headers exposed to scripts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exposed_headers: Vec<String>,
+
+    /// Allow cookies and HTTP authentication
+    #[serde(default)]
+    pub allow_credentials: bool,
+
+    /// How long browsers may cache a preflight result, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+}
+
+impl CorsSettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        for origin in &self.allowed_origins {
+            if origin == "*" {
+                if self.allow_credentials {
+                    return Err("CORS origin * cannot be used with allow_credentials".to_string());
+                }
+                continue;
+            }
+            let valid = origin.split_once("://").is_some_and(|(scheme, host)| {
+                !scheme.is_empty()
+                    && !host.is_empty()
+                    && !host.contains('/')
+                    && host.rfind('*').is_none_or(|i| i == 0 && host.starts_with("*."))
+            });
+            if !valid {
+                return Err(format!("Invalid CORS origin: {}", origin));
+            }
+        }
+        Ok(())
+    }
+
+    /// The `Access-Control-Allow-Origin` value for a request origin, if allowed
+    fn allow_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        let value = origin.to_str().ok()?;
+
+        // A literal `*` is only valid without credentials; `validate` refuses
+        // it with them, and it never admits an origin with credentials here
+        let any = self.allowed_origins.iter().any(|p| p == "*");
+        if any && !self.allow_credentials {
+            return Some(HeaderValue::from_static("*"));
+        }
+
+        let allowed = self.allowed_origins.iter().any(|pattern| {
+            pattern.eq_ignore_ascii_case(value) || origin_matches_wildcard(pattern, value)
+        });
+        allowed.then(|| origin.clone())
+    }
+
+    /// Add CORS headers to a response for a request from `origin`
+    pub fn apply(&self, origin: &HeaderValue, response: &mut Response) {
+        let headers = response.headers_mut();
+        if headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN) {
+            return;
+        }
+        let Some(allow_origin) = self.allow_origin(origin) else {
+            return;
+        };
+
+        if allow_origin != "*" {
+            headers.append(header::VARY, HeaderValue::from_static("origin"));
+        }
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if self.allow_credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+        if let Some(exposed) = header_list(&self.exposed_headers) {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, exposed);
+        }
+    }
+}
+
+/// Whether `origin` matches a subdomain wildcard such as `https://*.example.com`
+fn origin_matches_wildcard(pattern: &str, origin: &str) -> bool {
+    let Some((scheme, suffix)) = pattern.split_once("://*") else {
+        return false;
+    };
+    let Some(host) = origin.strip_prefix(scheme).and_then(|o| o.strip_prefix("://")) else {
+        return false;
+    };
+    host.len() > suffix.len()
+        && host.to_ascii_lowercase().ends_with(&suffix.to_ascii_lowercase())
+}
+
+/// Join a list into one comma-separated header value
+fn header_list(items: &[String]) -> Option<HeaderValue> {
+    if items.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&items.join(", ")).ok()
+}
+
+/// Answer an OPTIONS request that no endpoint handles, applying `cors` to
+/// preflights
+pub fn options_response(allowed: &AllowedMethods, cors: Option<&CorsSettings>, request: &HeaderMap) -> Response {
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    if let Some(allow) = header_list(&allowed.methods) {
+        response.headers_mut().insert(header::ALLOW, allow);
+    }
+
+    let origin = request.get(header::ORIGIN);
+    let preflight = request.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    let (Some(cors), Some(origin), true) = (cors, origin, preflight) else {
+        return response;
+    };
+
+    cors.apply(origin, &mut response);
+    if !response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN) {
+        return response;
+    }
+
+    let headers = response.headers_mut();
+    headers.remove(header::ACCESS_CONTROL_EXPOSE_HEADERS);
+    let methods = cors.allowed_methods.as_deref().unwrap_or(&allowed.methods);
+    if let Some(methods) = header_list(methods) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+    }
+    let allow_headers = match &cors.allowed_headers {
+        Some(list) => header_list(list),
+        None => request.get(header::ACCESS_CONTROL_REQUEST_HEADERS).cloned(),
+    };
+    if let Some(allow_headers) = allow_headers {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+    }
+    if let Some(max_age) = cors.max_age_secs {
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed() -> AllowedMethods {
+        AllowedMethods {
+            methods: vec!["GET".into(), "HEAD".into(), "OPTIONS".into(), "POST".into()],
+            domain: None,
+        }
+    }
+
+    fn preflight(origin: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ORIGIN, origin.parse().unwrap());
+        headers.insert(header::ACCESS_CONTROL_REQUEST_METHOD, "POST".parse().unwrap());
+        headers.insert(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_options_without_policy() {
+        let response = options_response(&allowed(), None, &preflight("https://app.test"));
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[header::ALLOW], "GET, HEAD, OPTIONS, POST");
+        assert!(!response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[test]
+    fn test_preflight_with_policy() {
+        let cors = CorsSettings {
+            allowed_origins: vec!["https://*.example.com".into()],
+            allow_credentials: true,
+            max_age_secs: Some(600),
+            ..Default::default()
+        };
+
+        let response = options_response(&allowed(), Some(&cors), &preflight("https://app.example.com"));
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, HEAD, OPTIONS, POST");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "content-type");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        assert_eq!(headers[header::VARY], "origin");
+
+        let response = options_response(&allowed(), Some(&cors), &preflight("https://evil.test"));
+        assert!(!response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[test]
+    fn test_apply_to_response() {
+        let cors = CorsSettings {
+            allowed_origins: vec!["*".into()],
+            exposed_headers: vec!["x-request-id".into()],
+            ..Default::default()
+        };
+        let origin = HeaderValue::from_static("https://app.test");
+
+        let mut response = StatusCode::OK.into_response();
+        cors.apply(&origin, &mut response);
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS], "x-request-id");
+
+        // A handler's own header wins
+        let mut response = ([(header::ACCESS_CONTROL_ALLOW_ORIGIN, "https://mine.test")], "").into_response();
+        cors.apply(&origin, &mut response);
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://mine.test");
+    }
+
+    #[test]
+    fn test_validate_origins() {
+        let valid = CorsSettings { allowed_origins: vec!["*".into(), "https://a.test".into(), "https://*.a.test".into()], ..Default::default() };
+        assert!(valid.validate().is_ok());
+        for origin in ["a.test", "https://a.test/", "https://a.*.test"] {
+            let invalid = CorsSettings { allowed_origins: vec![origin.into()], ..Default::default() };
+            assert!(invalid.validate().is_err(), "{}", origin);
+        }
+    }
+
+    #[test]
+    fn test_any_origin_with_credentials() {
+        let cors = CorsSettings { allowed_origins: vec!["*".into(), "https://a.test".into()], allow_credentials: true, ..Default::default() };
+        assert!(cors.validate().is_err());
+        assert!(CorsSettings { allow_credentials: false, ..cors.clone() }.validate().is_ok());
+
+        // Settings that skipped validation still only echo listed origins
+        assert_eq!(cors.allow_origin(&HeaderValue::from_static("https://a.test")).unwrap(), "https://a.test");
+        assert!(cors.allow_origin(&HeaderValue::from_static("https://evil.test")).is_none());
+    }
+}
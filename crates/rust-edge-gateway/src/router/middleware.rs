@@ -0,0 +1,1068 @@
+//! Route middleware
+//!
+//! Built-in middleware runs around an endpoint's handler or upstream. It is
+//! configured on domains, collections and endpoints, and the middleware of a
+//! route combines the three: a kind set on an inner level replaces the same
+//! kind from an outer one, and `skip_middleware` drops an outer kind. A
+//! domain's `cors` setting counts as a domain-level `cors` middleware.
+//!
+//! Whichever level it comes from, middleware runs in the order of
+//! [`MiddlewareKind`]: `cors`, `ip-filter`, `request-size`, `rate-limit`,
+//! `api-key`, `jwt`, `header-transform`, `cache`. Each checks or changes
+//! the request in that order, then the response in reverse order. A
+//! middleware that rejects a request answers it with a problem response,
+//! which only the middleware before it gets to change.
+//!
+//! The [`Chain`] of each route is resolved when the route table is built.
+//! Rate limit counters live in [`RateLimits`], so they survive rebuilds.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, Method};
+use axum::response::Response;
+use base64::Engine;
+use dashmap::DashMap;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::cors::CorsSettings;
+use super::problem::{ErrorPageSettings, Problem, ProblemKind};
+use crate::net::ClientIp;
+use crate::rate_limit::RateLimiter;
+use crate::AppState;
+
+/// How often idle rate limit counters are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Request attribute holding the `sub` claim of a verified JWT
+pub const JWT_SUBJECT_ATTRIBUTE: &str = "jwt_sub";
+
+/// Request attribute holding the claims of a verified JWT, as JSON
+pub const JWT_CLAIMS_ATTRIBUTE: &str = "jwt_claims";
+
+/// A built-in middleware with its settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Middleware {
+    /// CORS policy for the route's preflights and responses
+    Cors(CorsSettings),
+    /// Admit or refuse clients by IP
+    IpFilter(IpFilterSettings),
+    /// Refuse request bodies over a size
+    RequestSize(RequestSizeSettings),
+    /// Limit the requests of each client in a time window
+    RateLimit(RateLimitSettings),
+    /// Require one of a set of keys in a header
+    ApiKey(ApiKeySettings),
+    /// Require a signed bearer token
+    Jwt(JwtSettings),
+    /// Set and remove request and response headers
+    HeaderTransform(HeaderTransformSettings),
+    /// Give successful responses a cache lifetime
+    Cache(CacheSettings),
+}
+
+/// The built-in middleware, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MiddlewareKind {
+    Cors,
+    IpFilter,
+    RequestSize,
+    RateLimit,
+    ApiKey,
+    Jwt,
+    HeaderTransform,
+    Cache,
+}
+
+impl MiddlewareKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MiddlewareKind::Cors => "cors",
+            MiddlewareKind::IpFilter => "ip-filter",
+            MiddlewareKind::RequestSize => "request-size",
+            MiddlewareKind::RateLimit => "rate-limit",
+            MiddlewareKind::ApiKey => "api-key",
+            MiddlewareKind::Jwt => "jwt",
+            MiddlewareKind::HeaderTransform => "header-transform",
+            MiddlewareKind::Cache => "cache",
+        }
+    }
+}
+
+/// Client IPs or networks to admit and refuse
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IpFilterSettings {
+    /// CIDRs or IPs admitted; when empty, every client not denied is
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+
+    /// CIDRs or IPs refused, even when allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
+/// Largest request body accepted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestSizeSettings {
+    pub max_bytes: usize,
+}
+
+/// Requests allowed per client and window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitSettings {
+    /// Requests allowed in each window
+    pub requests: u32,
+
+    /// Length of the window, in seconds
+    pub window_secs: u64,
+
+    /// What identifies a client
+    #[serde(default)]
+    pub key: RateLimitKey,
+}
+
+/// What a rate limit counts requests by
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    /// The resolved client IP
+    #[default]
+    ClientIp,
+    /// The client IP together with the value of a request header
+    ///
+    /// The header is sent by the client, so it only splits a client IP's
+    /// count between values; it never replaces the IP.
+    Header(String),
+}
+
+/// Keys accepted in a request header
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKeySettings {
+    /// Header carrying the key
+    #[serde(default = "default_api_key_header")]
+    pub header: String,
+
+    /// Accepted keys
+    pub keys: Vec<String>,
+}
+
+fn default_api_key_header() -> String {
+    "x-api-key".to_string()
+}
+
+/// How bearer tokens are verified
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JwtSettings {
+    /// Signature algorithm tokens must use
+    pub algorithm: JwtAlgorithm,
+
+    /// Shared secret, for the HMAC algorithms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+
+    /// PEM public key (`-----BEGIN PUBLIC KEY-----`), for RS256 and ES256
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+
+    /// Required `iss` claim
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+
+    /// Required `aud` claim (or one of its values)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audience: Option<String>,
+
+    /// Clock skew allowed when checking `exp` and `nbf`, in seconds
+    #[serde(default = "default_leeway_secs")]
+    pub leeway_secs: u64,
+}
+
+fn default_leeway_secs() -> u64 {
+    60
+}
+
+/// Supported JWT signature algorithms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JwtAlgorithm {
+    HS256,
+    HS384,
+    HS512,
+    RS256,
+    ES256,
+}
+
+impl JwtAlgorithm {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JwtAlgorithm::HS256 => "HS256",
+            JwtAlgorithm::HS384 => "HS384",
+            JwtAlgorithm::HS512 => "HS512",
+            JwtAlgorithm::RS256 => "RS256",
+            JwtAlgorithm::ES256 => "ES256",
+        }
+    }
+}
+
+/// Header changes made to requests and responses
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeaderTransformSettings {
+    /// Changes to the request the endpoint receives
+    #[serde(default)]
+    pub request: HeaderEdits,
+
+    /// Changes to the response the client receives
+    #[serde(default)]
+    pub response: HeaderEdits,
+}
+
+/// Headers to set and remove
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeaderEdits {
+    /// Headers to set, replacing any existing values
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, String>,
+
+    /// Headers to remove
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+}
+
+/// Lifetime given to responses without one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheSettings {
+    pub ttl_secs: u64,
+}
+
+impl Middleware {
+    pub fn kind(&self) -> MiddlewareKind {
+        match self {
+            Middleware::Cors(_) => MiddlewareKind::Cors,
+            Middleware::IpFilter(_) => MiddlewareKind::IpFilter,
+            Middleware::RequestSize(_) => MiddlewareKind::RequestSize,
+            Middleware::RateLimit(_) => MiddlewareKind::RateLimit,
+            Middleware::ApiKey(_) => MiddlewareKind::ApiKey,
+            Middleware::Jwt(_) => MiddlewareKind::Jwt,
+            Middleware::HeaderTransform(_) => MiddlewareKind::HeaderTransform,
+            Middleware::Cache(_) => MiddlewareKind::Cache,
+        }
+    }
+
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        Stage::new(self, "").map(|_| ())
+    }
+}
+
+/// Check a level's middleware list; each kind may appear once
+pub fn validate_list(middleware: &[Middleware]) -> Result<(), String> {
+    for (i, entry) in middleware.iter().enumerate() {
+        if middleware[..i].iter().any(|m| m.kind() == entry.kind()) {
+            return Err(format!("Middleware '{}' is configured twice", entry.kind().as_str()));
+        }
+        entry.validate().map_err(|e| format!("Middleware '{}': {}", entry.kind().as_str(), e))?;
+    }
+    Ok(())
+}
+
+/// Where a route's middleware was configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Domain,
+    Collection,
+    Endpoint,
+}
+
+/// The middleware configured on one level of a route
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub scope: Scope,
+    /// ID of the domain, collection or endpoint
+    pub id: String,
+    pub middleware: Vec<Middleware>,
+    /// Kinds from outer levels that do not run
+    pub skip: Vec<MiddlewareKind>,
+}
+
+/// A middleware ready to run, or why it could not be set up
+#[derive(Debug)]
+struct Step {
+    kind: MiddlewareKind,
+    stage: Result<Stage, String>,
+}
+
+/// The resolved middleware of a route
+#[derive(Debug, Default)]
+pub struct Chain {
+    steps: Vec<Step>,
+}
+
+/// A request the middleware let through
+#[derive(Debug, Default)]
+pub struct Admitted {
+    /// Attributes for the handler's request
+    pub attributes: HashMap<String, String>,
+
+    /// Body size limit set by `request-size`
+    pub body_limit: Option<usize>,
+
+    /// Steps whose response side runs
+    pub depth: usize,
+}
+
+/// A request a middleware answered itself
+#[derive(Debug)]
+pub struct Rejected {
+    pub problem: Problem,
+    retry_after: Option<Duration>,
+
+    /// Steps whose response side runs
+    pub depth: usize,
+}
+
+impl Rejected {
+    /// Render the rejection for a request with the given headers
+    pub fn render(&self, request_id: &str, request: &HeaderMap, pages: Option<&ErrorPageSettings>) -> Response {
+        let mut response = self.problem.render(request_id, request, pages);
+        if let Some(retry_after) = self.retry_after {
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+        }
+        response
+    }
+}
+
+impl Chain {
+    /// Combine the levels of a route, outermost (the domain) first
+    pub fn resolve(layers: Vec<Layer>) -> Self {
+        let mut resolved: BTreeMap<MiddlewareKind, (Scope, String, Middleware)> = BTreeMap::new();
+        for layer in layers {
+            resolved.retain(|kind, _| !layer.skip.contains(kind));
+            for middleware in layer.middleware {
+                resolved.insert(middleware.kind(), (layer.scope, layer.id.clone(), middleware));
+            }
+        }
+
+        let steps = resolved.into_iter()
+            .map(|(kind, (scope, id, middleware))| {
+                let scope_key = format!("{}:{}", scope_name(scope), id);
+                let stage = Stage::new(&middleware, &scope_key);
+                if let Err(e) = &stage {
+                    // Requests are refused rather than let through unchecked
+                    tracing::warn!(scope = %scope_key, "Middleware '{}' is invalid and refuses requests: {}", kind.as_str(), e);
+                }
+                Step { kind, stage }
+            })
+            .collect();
+        Self { steps }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// The kinds that run, in order
+    pub fn kinds(&self) -> Vec<MiddlewareKind> {
+        self.steps.iter().map(|s| s.kind).collect()
+    }
+
+    /// The route's CORS policy, if it has one
+    pub fn cors(&self) -> Option<&CorsSettings> {
+        self.steps.iter().find_map(|s| match &s.stage {
+            Ok(Stage::Cors(cors)) => Some(cors),
+            _ => None,
+        })
+    }
+
+    /// Run the request side of each middleware, in order
+    ///
+    /// Changes the request's headers in place. Stops at the first
+    /// middleware that rejects the request.
+    pub fn before(&self, limits: &RateLimits, request: &mut Parts) -> Result<Admitted, Rejected> {
+        let mut admitted = Admitted::default();
+        for (depth, step) in self.steps.iter().enumerate() {
+            let reject = |kind: ProblemKind, retry_after: Option<Duration>| Rejected {
+                problem: kind.into(),
+                retry_after,
+                depth,
+            };
+            let stage = match &step.stage {
+                Ok(stage) => stage,
+                Err(_) => return Err(reject(ProblemKind::InternalError, None)),
+            };
+            let client_ip = request.extensions.get::<ClientIp>().map(|ip| ip.0);
+
+            match stage {
+                Stage::Cors(_) | Stage::Cache(_) => {}
+                Stage::IpFilter { allow, deny } => {
+                    let admitted = client_ip.is_some_and(|ip| {
+                        let ip = ip.to_canonical();
+                        (allow.is_empty() || allow.iter().any(|net| net.contains(&ip)))
+                            && !deny.iter().any(|net| net.contains(&ip))
+                    });
+                    if !admitted {
+                        return Err(reject(ProblemKind::Forbidden, None));
+                    }
+                }
+                Stage::RequestSize(max_bytes) => {
+                    let declared = request.headers.get(header::CONTENT_LENGTH)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse::<u64>().ok());
+                    if declared.is_some_and(|len| len > *max_bytes as u64) {
+                        return Err(reject(ProblemKind::PayloadTooLarge, None));
+                    }
+                    admitted.body_limit = Some(*max_bytes);
+                }
+                Stage::RateLimit { scope, settings } => {
+                    let header = match &settings.key {
+                        RateLimitKey::Header(name) => request.headers.get(name.as_str()).and_then(|v| v.to_str().ok()),
+                        RateLimitKey::ClientIp => None,
+                    };
+                    let ip = client_ip.map_or_else(|| "unknown".to_string(), |ip| format!("ip:{}", ip));
+                    let client = match header {
+                        Some(value) => format!("{}|h:{}", ip, value),
+                        None => ip,
+                    };
+                    if let Err(retry_after) = limits.check(scope, settings, &client) {
+                        return Err(reject(ProblemKind::RateLimited, Some(retry_after)));
+                    }
+                }
+                Stage::ApiKey { header, keys } => {
+                    let key = request.headers.get(header).map(|v| v.as_bytes());
+                    let valid = key.is_some_and(|key| {
+                        // Compare against every key so timing does not tell which matched
+                        keys.iter().fold(false, |found, k| constant_time_eq(k.as_bytes(), key) | found)
+                    });
+                    if !valid {
+                        return Err(reject(ProblemKind::Unauthorized, None));
+                    }
+                }
+                Stage::Jwt(verifier) => {
+                    let token = request.headers.get(header::AUTHORIZATION)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.strip_prefix("Bearer ").or_else(|| v.strip_prefix("bearer ")));
+                    let now = chrono::Utc::now().timestamp();
+                    match token.ok_or("missing bearer token").and_then(|t| verifier.verify(t.trim(), now)) {
+                        Ok(claims) => {
+                            if let Some(sub) = claims.get("sub").and_then(Value::as_str) {
+                                admitted.attributes.insert(JWT_SUBJECT_ATTRIBUTE.to_string(), sub.to_string());
+                            }
+                            admitted.attributes.insert(JWT_CLAIMS_ATTRIBUTE.to_string(), Value::Object(claims).to_string());
+                        }
+                        Err(reason) => {
+                            tracing::debug!("Rejected bearer token: {}", reason);
+                            return Err(reject(ProblemKind::Unauthorized, None));
+                        }
+                    }
+                }
+                Stage::HeaderTransform { request: edits, .. } => edits.apply(&mut request.headers),
+            }
+        }
+        admitted.depth = self.steps.len();
+        Ok(admitted)
+    }
+
+    /// Run the response side of the first `depth` middleware, in reverse order
+    ///
+    /// `origin` is the request's `Origin` header, as the client sent it.
+    pub fn after(&self, depth: usize, method: &Method, origin: Option<&HeaderValue>, response: &mut Response) {
+        for step in self.steps[..depth.min(self.steps.len())].iter().rev() {
+            match &step.stage {
+                Ok(Stage::Cors(cors)) => {
+                    if let Some(origin) = origin {
+                        cors.apply(origin, response);
+                    }
+                }
+                Ok(Stage::HeaderTransform { response: edits, .. }) => edits.apply(response.headers_mut()),
+                Ok(Stage::Cache(_)) => self.set_cache_lifetime(method, response),
+                _ => {}
+            }
+        }
+    }
+
+    /// Give a successful GET or HEAD response the `cache` middleware's
+    /// lifetime, unless it already has a `Cache-Control` header
+    ///
+    /// Part of the response side of `cache`; also called on handler
+    /// responses before they reach the response cache.
+    pub fn set_cache_lifetime(&self, method: &Method, response: &mut Response) {
+        let lifetime = self.steps.iter().find_map(|s| match &s.stage {
+            Ok(Stage::Cache(value)) => Some(value),
+            _ => None,
+        });
+        let Some(lifetime) = lifetime else {
+            return;
+        };
+        if (method == Method::GET || method == Method::HEAD)
+            && response.status().is_success()
+            && !response.headers().contains_key(header::CACHE_CONTROL)
+        {
+            response.headers_mut().insert(header::CACHE_CONTROL, lifetime.clone());
+        }
+    }
+}
+
+fn scope_name(scope: Scope) -> &'static str {
+    match scope {
+        Scope::Domain => "domain",
+        Scope::Collection => "collection",
+        Scope::Endpoint => "endpoint",
+    }
+}
+
+/// A middleware with its settings parsed
+#[derive(Debug)]
+enum Stage {
+    Cors(CorsSettings),
+    IpFilter { allow: Vec<IpNet>, deny: Vec<IpNet> },
+    RequestSize(usize),
+    RateLimit { scope: String, settings: RateLimitSettings },
+    ApiKey { header: HeaderName, keys: Vec<String> },
+    Jwt(Box<JwtVerifier>),
+    HeaderTransform { request: Edits, response: Edits },
+    Cache(HeaderValue),
+}
+
+impl Stage {
+    /// Parse a middleware configured at `scope`, which keys its rate limit
+    fn new(middleware: &Middleware, scope: &str) -> Result<Self, String> {
+        Ok(match middleware {
+            Middleware::Cors(cors) => {
+                cors.validate()?;
+                Stage::Cors(cors.clone())
+            }
+            Middleware::IpFilter(settings) => {
+                if settings.allow.is_empty() && settings.deny.is_empty() {
+                    return Err("allow or deny must list at least one network".to_string());
+                }
+                Stage::IpFilter { allow: parse_nets(&settings.allow)?, deny: parse_nets(&settings.deny)? }
+            }
+            Middleware::RequestSize(settings) => {
+                if settings.max_bytes == 0 {
+                    return Err("max_bytes must be greater than 0".to_string());
+                }
+                Stage::RequestSize(settings.max_bytes)
+            }
+            Middleware::RateLimit(settings) => {
+                if settings.requests == 0 || settings.window_secs == 0 {
+                    return Err("requests and window_secs must be greater than 0".to_string());
+                }
+                if let RateLimitKey::Header(name) = &settings.key {
+                    header_name(name)?;
+                }
+                Stage::RateLimit { scope: scope.to_string(), settings: settings.clone() }
+            }
+            Middleware::ApiKey(settings) => {
+                if settings.keys.is_empty() || settings.keys.iter().any(|k| k.is_empty()) {
+                    return Err("keys must list at least one non-empty key".to_string());
+                }
+                Stage::ApiKey { header: header_name(&settings.header)?, keys: settings.keys.clone() }
+            }
+            Middleware::Jwt(settings) => Stage::Jwt(Box::new(JwtVerifier::new(settings)?)),
+            Middleware::HeaderTransform(settings) => Stage::HeaderTransform {
+                request: Edits::new(&settings.request)?,
+                response: Edits::new(&settings.response)?,
+            },
+            Middleware::Cache(settings) => {
+                if settings.ttl_secs == 0 {
+                    return Err("ttl_secs must be greater than 0".to_string());
+                }
+                Stage::Cache(HeaderValue::from_str(&format!("public, max-age={}", settings.ttl_secs)).map_err(|e| e.to_string())?)
+            }
+        })
+    }
+}
+
+fn header_name(name: &str) -> Result<HeaderName, String> {
+    HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name: {}", name))
+}
+
+/// Parse CIDRs or bare IPs
+fn parse_nets(entries: &[String]) -> Result<Vec<IpNet>, String> {
+    entries.iter()
+        .map(|entry| {
+            entry.parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("Invalid network: {}", entry))
+        })
+        .collect()
+}
+
+/// Compare two byte strings in time independent of where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Parsed header edits
+#[derive(Debug)]
+struct Edits {
+    set: Vec<(HeaderName, HeaderValue)>,
+    remove: Vec<HeaderName>,
+}
+
+impl Edits {
+    fn new(edits: &HeaderEdits) -> Result<Self, String> {
+        let set = edits.set.iter()
+            .map(|(name, value)| {
+                let value = HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header {}", name))?;
+                Ok((header_name(name)?, value))
+            })
+            .collect::<Result<_, String>>()?;
+        let remove = edits.remove.iter().map(|name| header_name(name)).collect::<Result<_, _>>()?;
+        Ok(Self { set, remove })
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        for name in &self.remove {
+            headers.remove(name);
+        }
+        for (name, value) in &self.set {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+/// Key a JWT signature is checked with
+enum JwtKey {
+    Hmac(ring::hmac::Key),
+    /// PKCS#1 `RSAPublicKey`
+    Rsa(Vec<u8>),
+    /// Uncompressed P-256 point
+    Ecdsa(Vec<u8>),
+}
+
+/// Checks bearer tokens against a key and the expected claims
+struct JwtVerifier {
+    algorithm: JwtAlgorithm,
+    key: JwtKey,
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway: i64,
+}
+
+impl std::fmt::Debug for JwtVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtVerifier").field("algorithm", &self.algorithm).finish_non_exhaustive()
+    }
+}
+
+impl JwtVerifier {
+    fn new(settings: &JwtSettings) -> Result<Self, String> {
+        let hmac = |algorithm| match settings.secret.as_deref() {
+            Some(secret) if !secret.is_empty() => Ok(JwtKey::Hmac(ring::hmac::Key::new(algorithm, secret.as_bytes()))),
+            _ => Err(format!("{} needs a secret", settings.algorithm.as_str())),
+        };
+        let public_key = || {
+            let pem = settings.public_key.as_deref()
+                .ok_or_else(|| format!("{} needs a public_key", settings.algorithm.as_str()))?;
+            parse_public_key(pem, settings.algorithm)
+        };
+        let key = match settings.algorithm {
+            JwtAlgorithm::HS256 => hmac(ring::hmac::HMAC_SHA256)?,
+            JwtAlgorithm::HS384 => hmac(ring::hmac::HMAC_SHA384)?,
+            JwtAlgorithm::HS512 => hmac(ring::hmac::HMAC_SHA512)?,
+            JwtAlgorithm::RS256 => JwtKey::Rsa(public_key()?),
+            JwtAlgorithm::ES256 => JwtKey::Ecdsa(public_key()?),
+        };
+        Ok(Self {
+            algorithm: settings.algorithm,
+            key,
+            issuer: settings.issuer.clone(),
+            audience: settings.audience.clone(),
+            leeway: settings.leeway_secs.min(i64::MAX as u64) as i64,
+        })
+    }
+
+    /// The claims of a token that is valid at `now` (Unix seconds)
+    fn verify(&self, token: &str, now: i64) -> Result<serde_json::Map<String, Value>, &'static str> {
+        use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED, RSA_PKCS1_2048_8192_SHA256};
+
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return Err("malformed token");
+        };
+        let decode = |part: &str| base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(part).map_err(|_| "malformed token");
+
+        let header: Value = serde_json::from_slice(&decode(header)?).map_err(|_| "malformed header")?;
+        if header.get("alg").and_then(Value::as_str) != Some(self.algorithm.as_str()) {
+            return Err("unexpected algorithm");
+        }
+
+        let signed = &token[..token.len() - signature.len() - 1];
+        let signature = decode(signature)?;
+        let verified = match &self.key {
+            JwtKey::Hmac(key) => ring::hmac::verify(key, signed.as_bytes(), &signature),
+            JwtKey::Rsa(key) => UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, key).verify(signed.as_bytes(), &signature),
+            JwtKey::Ecdsa(key) => UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, key).verify(signed.as_bytes(), &signature),
+        };
+        verified.map_err(|_| "invalid signature")?;
+
+        let Ok(Value::Object(claims)) = serde_json::from_slice(&decode(payload)?) else {
+            return Err("malformed claims");
+        };
+        let time = |name: &str| claims.get(name).map(|v| v.as_i64().ok_or("malformed time claim")).transpose();
+        if time("exp")?.is_some_and(|exp| now > exp.saturating_add(self.leeway)) {
+            return Err("token expired");
+        }
+        if time("nbf")?.is_some_and(|nbf| now < nbf.saturating_sub(self.leeway)) {
+            return Err("token not yet valid");
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+                return Err("unexpected issuer");
+            }
+        }
+        if let Some(audience) = &self.audience {
+            let matches = match claims.get("aud") {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|a| a.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err("unexpected audience");
+            }
+        }
+        Ok(claims)
+    }
+}
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OID: u8 = 0x06;
+
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+/// The key of a PEM `PUBLIC KEY` for `algorithm`
+///
+/// The key must be RSA for RS256, giving the PKCS#1 `RSAPublicKey`, or
+/// P-256 for ES256, giving the encoded point.
+fn parse_public_key(pem: &str, algorithm: JwtAlgorithm) -> Result<Vec<u8>, String> {
+    let body: String = pem.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect();
+    let der = base64::engine::general_purpose::STANDARD.decode(body)
+        .map_err(|_| "public_key is not a PEM public key".to_string())?;
+    let spki = SubjectPublicKey::parse(&der)
+        .ok_or_else(|| "public_key is not a PEM public key".to_string())?;
+
+    let (expected, matches) = match algorithm {
+        JwtAlgorithm::RS256 => ("an RSA", spki.algorithm == OID_RSA_ENCRYPTION),
+        JwtAlgorithm::ES256 => ("a P-256", spki.algorithm == OID_EC_PUBLIC_KEY && spki.curve == Some(OID_PRIME256V1)),
+        _ => ("no", false),
+    };
+    if !matches {
+        return Err(format!("{} needs {} public_key", algorithm.as_str(), expected));
+    }
+    Ok(spki.key.to_vec())
+}
+
+/// A DER `SubjectPublicKeyInfo`
+struct SubjectPublicKey<'a> {
+    /// Algorithm OID
+    algorithm: &'a [u8],
+    /// Curve OID, for EC keys
+    curve: Option<&'a [u8]>,
+    key: &'a [u8],
+}
+
+impl<'a> SubjectPublicKey<'a> {
+    fn parse(spki: &'a [u8]) -> Option<Self> {
+        let (DER_SEQUENCE, spki, _) = der_read(spki)? else { return None };
+        let (DER_SEQUENCE, algorithm, rest) = der_read(spki)? else { return None };
+        let (DER_OID, algorithm, parameters) = der_read(algorithm)? else { return None };
+        let curve = der_read(parameters).and_then(|(tag, curve, _)| (tag == DER_OID).then_some(curve));
+        let (DER_BIT_STRING, key, _) = der_read(rest)? else { return None };
+        Some(Self { algorithm, curve, key: key.strip_prefix(&[0])? })
+    }
+}
+
+/// Split one DER tag-length-value off the front of `input`
+///
+/// Returns the tag, the content and the remaining input.
+fn der_read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, mut rest) = rest.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let (bytes, after) = rest.split_at_checked(count)?;
+        rest = after;
+        bytes.iter().fold(0, |len, &b| (len << 8) | b as usize)
+    };
+    let (content, rest) = rest.split_at_checked(len)?;
+    Some((tag, content, rest))
+}
+
+/// Request counters of the `rate-limit` middleware
+///
+/// Each configured limit gets its own counters, keyed by where it was
+/// configured, so a domain-level limit counts requests to all of the
+/// domain's routes together.
+#[derive(Default)]
+pub struct RateLimits {
+    limiters: DashMap<String, Arc<RateLimiter>>,
+}
+
+impl RateLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a request from `client`, or the time until it may retry
+    fn check(&self, scope: &str, settings: &RateLimitSettings, client: &str) -> Result<(), Duration> {
+        let key = format!("{}:{}/{}", scope, settings.requests, settings.window_secs);
+        let limiter = self.limiters.entry(key)
+            .or_insert_with(|| Arc::new(RateLimiter::new(settings.requests, Duration::from_secs(settings.window_secs))))
+            .clone();
+        limiter.check(client)
+    }
+
+    /// Drop expired counters, and limiters without any
+    pub fn sweep(&self) {
+        self.limiters.retain(|_, limiter| {
+            limiter.cleanup();
+            !limiter.is_empty()
+        });
+    }
+}
+
+/// Periodically drop idle rate limit counters
+pub async fn run(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        state.route_rate_limits.sweep();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{Request, StatusCode};
+    use axum::response::IntoResponse;
+
+    fn layer(scope: Scope, middleware: Vec<Middleware>, skip: Vec<MiddlewareKind>) -> Layer {
+        Layer { scope, id: "id".to_string(), middleware, skip }
+    }
+
+    fn parts(headers: &[(&str, &str)], client_ip: &str) -> Parts {
+        let mut request = Request::builder().uri("/x");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        parts.extensions.insert(ClientIp(client_ip.parse().unwrap()));
+        parts
+    }
+
+    fn hs256(claims: Value, secret: &str) -> String {
+        let encode = |v: &[u8]| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(v);
+        let signed = format!("{}.{}", encode(br#"{"alg":"HS256","typ":"JWT"}"#), encode(claims.to_string().as_bytes()));
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+        let signature = ring::hmac::sign(&key, signed.as_bytes());
+        format!("{}.{}", signed, encode(signature.as_ref()))
+    }
+
+    fn api_key(keys: &[&str]) -> Middleware {
+        Middleware::ApiKey(ApiKeySettings { header: default_api_key_header(), keys: keys.iter().map(|k| k.to_string()).collect() })
+    }
+
+    #[test]
+    fn test_resolve_order_and_overrides() {
+        let cors = Middleware::Cors(CorsSettings { allowed_origins: vec!["*".into()], ..Default::default() });
+        let cache = Middleware::Cache(CacheSettings { ttl_secs: 60 });
+        let size = |max_bytes| Middleware::RequestSize(RequestSizeSettings { max_bytes });
+
+        let chain = Chain::resolve(vec![
+            layer(Scope::Domain, vec![cache, api_key(&["a"]), size(10)], vec![]),
+            layer(Scope::Collection, vec![cors], vec![MiddlewareKind::ApiKey]),
+            layer(Scope::Endpoint, vec![size(20)], vec![]),
+        ]);
+        assert_eq!(chain.kinds(), vec![MiddlewareKind::Cors, MiddlewareKind::RequestSize, MiddlewareKind::Cache]);
+
+        // The endpoint's limit replaced the domain's
+        let admitted = chain.before(&RateLimits::new(), &mut parts(&[], "10.0.0.1")).unwrap();
+        assert_eq!(admitted.body_limit, Some(20));
+        assert_eq!(admitted.depth, 3);
+    }
+
+    #[test]
+    fn test_api_key_and_ip_filter() {
+        let filter = Middleware::IpFilter(IpFilterSettings { allow: vec!["10.0.0.0/8".into()], deny: vec!["10.0.0.9".into()] });
+        let chain = Chain::resolve(vec![layer(Scope::Endpoint, vec![api_key(&["k1", "k2"]), filter], vec![])]);
+        let limits = RateLimits::new();
+
+        assert!(chain.before(&limits, &mut parts(&[("x-api-key", "k2")], "10.1.2.3")).is_ok());
+
+        let rejected = chain.before(&limits, &mut parts(&[("x-api-key", "nope")], "10.1.2.3")).unwrap_err();
+        assert_eq!(rejected.problem.kind, ProblemKind::Unauthorized);
+        // The IP filter runs before the key check, and its response side still runs
+        assert_eq!(rejected.depth, 1);
+
+        for ip in ["192.168.0.1", "10.0.0.9"] {
+            let rejected = chain.before(&limits, &mut parts(&[("x-api-key", "k1")], ip)).unwrap_err();
+            assert_eq!(rejected.problem.kind, ProblemKind::Forbidden, "{}", ip);
+            assert_eq!(rejected.depth, 0);
+        }
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limit = Middleware::RateLimit(RateLimitSettings { requests: 2, window_secs: 60, key: RateLimitKey::Header("x-user".into()) });
+        let chain = Chain::resolve(vec![layer(Scope::Domain, vec![limit], vec![])]);
+        let limits = RateLimits::new();
+
+        let alice = || parts(&[("x-user", "alice")], "10.0.0.1");
+        assert!(chain.before(&limits, &mut alice()).is_ok());
+        assert!(chain.before(&limits, &mut alice()).is_ok());
+        let rejected = chain.before(&limits, &mut alice()).unwrap_err();
+        assert_eq!(rejected.problem.kind, ProblemKind::RateLimited);
+        let response = rejected.render("r", &HeaderMap::new(), None);
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+        // Other clients, and the same client on another chain, have their own count
+        assert!(chain.before(&limits, &mut parts(&[("x-user", "bob")], "10.0.0.1")).is_ok());
+        assert!(chain.before(&limits, &mut parts(&[("x-user", "alice")], "10.0.0.2")).is_ok());
+        let other = Chain::resolve(vec![Layer { id: "other".into(), ..layer(Scope::Domain, vec![], vec![]) }]);
+        assert!(other.before(&limits, &mut alice()).is_ok());
+    }
+
+    #[test]
+    fn test_jwt() {
+        let settings = JwtSettings {
+            algorithm: JwtAlgorithm::HS256,
+            secret: Some("s3cret".into()),
+            public_key: None,
+            issuer: Some("https://auth.test".into()),
+            audience: Some("api".into()),
+            leeway_secs: 0,
+        };
+        let chain = Chain::resolve(vec![layer(Scope::Endpoint, vec![Middleware::Jwt(settings.clone())], vec![])]);
+        let limits = RateLimits::new();
+        let now = chrono::Utc::now().timestamp();
+        let claims = serde_json::json!({ "sub": "user-1", "iss": "https://auth.test", "aud": ["web", "api"], "exp": now + 60 });
+
+        let token = hs256(claims.clone(), "s3cret");
+        let admitted = chain.before(&limits, &mut parts(&[("authorization", &format!("Bearer {}", token))], "10.0.0.1")).unwrap();
+        assert_eq!(admitted.attributes[JWT_SUBJECT_ATTRIBUTE], "user-1");
+        assert!(admitted.attributes[JWT_CLAIMS_ATTRIBUTE].contains("auth.test"));
+
+        let verifier = JwtVerifier::new(&settings).unwrap();
+        assert_eq!(verifier.verify(&hs256(claims.clone(), "wrong"), now).unwrap_err(), "invalid signature");
+        assert_eq!(verifier.verify(&token, now + 120).unwrap_err(), "token expired");
+        let other_audience = serde_json::json!({ "iss": "https://auth.test", "aud": "web" });
+        assert_eq!(verifier.verify(&hs256(other_audience, "s3cret"), now).unwrap_err(), "unexpected audience");
+
+        // alg must be the configured one
+        let encode = |v: &[u8]| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(v);
+        let unsigned = format!("{}.{}.", encode(br#"{"alg":"none"}"#), encode(claims.to_string().as_bytes()));
+        assert_eq!(verifier.verify(&unsigned, now).unwrap_err(), "unexpected algorithm");
+
+        let rejected = chain.before(&limits, &mut parts(&[], "10.0.0.1")).unwrap_err();
+        assert_eq!(rejected.problem.kind, ProblemKind::Unauthorized);
+    }
+
+    #[test]
+    fn test_jwt_es256() {
+        use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+
+        // SubjectPublicKeyInfo for a P-256 key
+        let mut spki = vec![0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01,
+            0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00];
+        spki.extend_from_slice(pair.public_key().as_ref());
+        let pem = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            base64::engine::general_purpose::STANDARD.encode(&spki),
+        );
+
+        let encode = |v: &[u8]| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(v);
+        let signed = format!("{}.{}", encode(br#"{"alg":"ES256"}"#), encode(br#"{"sub":"svc"}"#));
+        let signature = pair.sign(&rng, signed.as_bytes()).unwrap();
+        let token = format!("{}.{}", signed, encode(signature.as_ref()));
+
+        let settings = JwtSettings {
+            algorithm: JwtAlgorithm::ES256,
+            secret: None,
+            public_key: Some(pem),
+            issuer: None,
+            audience: None,
+            leeway_secs: 60,
+        };
+        let verifier = JwtVerifier::new(&settings).unwrap();
+        assert_eq!(verifier.verify(&token, 0).unwrap()["sub"], "svc");
+
+        // The key must suit the algorithm
+        let err = JwtVerifier::new(&JwtSettings { algorithm: JwtAlgorithm::RS256, ..settings }).unwrap_err();
+        assert_eq!(err, "RS256 needs an RSA public_key");
+    }
+
+    #[test]
+    fn test_response_side() {
+        let cors = Middleware::Cors(CorsSettings { allowed_origins: vec!["https://app.test".into()], ..Default::default() });
+        let transform = Middleware::HeaderTransform(HeaderTransformSettings {
+            request: HeaderEdits { set: BTreeMap::from([("x-from-gateway".into(), "1".into())]), remove: vec!["cookie".into()] },
+            response: HeaderEdits { set: BTreeMap::new(), remove: vec!["server".into()] },
+        });
+        let cache = Middleware::Cache(CacheSettings { ttl_secs: 30 });
+        let chain = Chain::resolve(vec![layer(Scope::Domain, vec![cors, transform, cache], vec![])]);
+
+        let mut request = parts(&[("cookie", "a=b")], "10.0.0.1");
+        let admitted = chain.before(&RateLimits::new(), &mut request).unwrap();
+        assert_eq!(request.headers["x-from-gateway"], "1");
+        assert!(!request.headers.contains_key("cookie"));
+
+        let mut response = ([("server", "handler")], "ok").into_response();
+        let origin = HeaderValue::from_static("https://app.test");
+        chain.after(admitted.depth, &Method::GET, Some(&origin), &mut response);
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.test");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "public, max-age=30");
+        assert!(!response.headers().contains_key("server"));
+
+        // Only cors runs for a response to a request rejected after it
+        let mut response = StatusCode::OK.into_response();
+        chain.after(1, &Method::GET, Some(&origin), &mut response);
+        assert!(response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert!(!response.headers().contains_key(header::CACHE_CONTROL));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate_list(&[api_key(&["a"]), api_key(&["b"])]).is_err());
+        assert!(validate_list(&[api_key(&[])]).is_err());
+        assert!(Middleware::IpFilter(IpFilterSettings { allow: vec!["10.0.0.0/33".into()], deny: vec![] }).validate().is_err());
+        assert!(Middleware::Jwt(JwtSettings {
+            algorithm: JwtAlgorithm::RS256,
+            secret: Some("x".into()),
+            public_key: None,
+            issuer: None,
+            audience: None,
+            leeway_secs: 60,
+        }).validate().is_err());
+
+        let parsed: Vec<Middleware> = serde_json::from_str(
+            r#"[{"rate-limit": {"requests": 10, "window_secs": 1}}, {"api-key": {"keys": ["k"]}}]"#
+        ).unwrap();
+        assert_eq!(parsed[0].kind(), MiddlewareKind::RateLimit);
+        assert!(matches!(&parsed[1], Middleware::ApiKey(s) if s.header == "x-api-key"));
+        validate_list(&parsed).unwrap();
+    }
+}
@@ -0,0 +1,635 @@
+//! Gateway router - routes HTTP requests to handler libraries (v2 architecture)
+//!
+//! Uses dynamic library loading with graceful draining for zero-downtime deployments.
+//! Requests are matched against the in-memory route table in [`table`].
+
+pub mod cache;
+pub mod compression;
+pub mod cors;
+pub mod middleware;
+pub mod problem;
+pub mod proxy;
+pub mod rules;
+pub mod site;
+pub mod socket;
+pub mod stream;
+pub mod table;
+pub mod upstream;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{Request, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{any, get},
+    Router,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tower_http::services::ServeDir;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::api::{Domain, Endpoint, EndpointKind};
+use crate::net::{ClientIp, RequestId};
+use crate::runtime::canary::SplitRequest;
+use crate::runtime::mirror;
+use crate::runtime::handler::{ExecuteError, RequestGuard};
+use crate::AppState;
+use middleware::{Admitted, Chain};
+use problem::{Problem, ProblemKind};
+
+/// Create the gateway router that handles all incoming requests
+///
+/// The gateway does NOT require API key authentication - it just routes requests
+/// to compiled handlers. Any authentication/authorization is the responsibility
+/// of the individual handlers themselves.
+pub fn create_gateway_router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/health", get(health_check))
+        // Static file routes must come before catch-all routes
+        .route("/favicon.ico", get(serve_static_file))
+        .route("/favicon.png", get(serve_static_file))
+        .route("/favicon.svg", get(serve_static_file))
+        .route("/apple-touch-icon.png", get(serve_static_file))
+        .route("/favicon-192x192.png", get(serve_static_file))
+        .route("/favicon-512x512.png", get(serve_static_file))
+        .route("/robots.txt", get(serve_static_file))
+        .route("/site.webmanifest", get(serve_static_file))
+        .route("/static", any(serve_static_dir))
+        .route("/static/{*path}", any(serve_static_dir))
+        // Catch-all routes must come last
+        .route("/{*path}", any(handle_gateway_request))
+        .route("/", any(handle_gateway_request))
+        .layer(compression::layer(&state.config.compression))
+}
+
+/// Health check endpoint for the gateway
+async fn health_check() -> impl IntoResponse {
+    (StatusCode::OK, "OK")
+}
+
+/// Whether the request's domain hosts a static site, which then serves the
+/// paths the gateway otherwise answers from the static directory
+fn hosts_site(state: &AppState, request: &Request<Body>) -> bool {
+    let host = request.headers()
+        .get("host")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost");
+    state.routes.load()
+        .domain(&table::normalize_request_host(host))
+        .is_some_and(|d| d.settings.site.is_some())
+}
+
+/// Serve static files from the static directory
+async fn serve_static_file(
+    State(state): State<Arc<AppState>>,
+    request: Request<Body>,
+) -> Response {
+    if hosts_site(&state, &request) {
+        return handle_gateway_request(State(state), request).await;
+    }
+    let request_id = RequestId::of(&request).0;
+    let problem = |kind: ProblemKind| Problem::from(kind).render(&request_id, &Default::default(), None);
+
+    let filename = request.uri().path().trim_start_matches('/').to_string();
+    let static_path = PathBuf::from(&state.config.static_dir).join(&filename);
+    
+    tracing::debug!("Attempting to serve static file: {} (path: {})", filename, static_path.display());
+    
+    if static_path.exists() {
+        tracing::debug!("File found, reading content");
+        match fs::read(static_path) {
+            Ok(content) => {
+                // Determine content type based on file extension
+                let content_type = match filename.to_lowercase().as_str() {
+                    _ if filename.ends_with(".ico") => "image/x-icon",
+                    _ if filename.ends_with(".png") => "image/png",
+                    _ if filename.ends_with(".svg") => "image/svg+xml",
+                    _ if filename.ends_with(".txt") => "text/plain",
+                    _ if filename.ends_with(".webmanifest") => "application/manifest+json",
+                    _ => "application/octet-stream",
+                };
+                
+                tracing::debug!("Serving file {} with content type {}", filename, content_type);
+                
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header("Content-Type", content_type)
+                    .body(Body::from(content))
+                    .unwrap_or_else(|_| problem(ProblemKind::InternalError))
+            }
+            Err(e) => {
+                tracing::error!("Failed to read file {}: {}", filename, e);
+                problem(ProblemKind::InternalError)
+            }
+        }
+    } else {
+        tracing::warn!("Static file not found: {} (looked at: {})", filename, static_path.display());
+        problem(ProblemKind::NotFound)
+    }
+}
+
+/// Serve `/static` from the static directory
+async fn serve_static_dir(
+    State(state): State<Arc<AppState>>,
+    request: Request<Body>,
+) -> Response {
+    use tower::ServiceExt;
+
+    if hosts_site(&state, &request) {
+        return handle_gateway_request(State(state), request).await;
+    }
+    let (mut parts, body) = request.into_parts();
+    let path = parts.uri.path().strip_prefix("/static").filter(|p| !p.is_empty()).unwrap_or("/");
+    let path_and_query = match parts.uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+    if let Ok(uri) = path_and_query.parse() {
+        parts.uri = uri;
+    }
+    match ServeDir::new(&state.config.static_dir).oneshot(Request::from_parts(parts, body)).await {
+        Ok(response) => response.into_response(),
+        Err(never) => match never {},
+    }
+}
+
+/// Handle an incoming gateway request using v2 handler registry
+async fn handle_gateway_request(
+    State(state): State<Arc<AppState>>,
+    mut request: Request<Body>,
+) -> Response {
+    let method = request.method().to_string();
+    let mut path = request.uri().path().to_string();
+    let request_id = RequestId::of(&request).0;
+    let client_ip = request.extensions().get::<ClientIp>().map(|ip| ip.0.to_string());
+
+    // Extract domain from Host header (strip port if present)
+    let host = request.headers()
+        .get("host")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost");
+    let domain = table::normalize_request_host(host);
+    let domain = domain.as_str();
+
+    tracing::debug!(
+        request_id = %request_id,
+        domain = %domain,
+        method = %method,
+        path = %path,
+        client_ip = ?client_ip,
+        "Incoming request"
+    );
+
+    // HTTP-01 challenges for certificates the gateway is ordering
+    if method == "GET" || method == "HEAD" {
+        let token = path.strip_prefix(crate::acme::CHALLENGE_PATH);
+        if let Some(key_authorization) = token.and_then(|t| state.acme.challenge(t)) {
+            return key_authorization.into_response();
+        }
+    }
+
+    // Redirect and rewrite rules run before endpoint matching
+    let routes = state.routes.load();
+    if let Some(rules) = routes.rules(domain) {
+        let outcome = rules.apply(&rules::RuleRequest {
+            secure: request.extensions().get::<crate::net::Secure>().is_some(),
+            host: domain,
+            path: &path,
+            query: request.uri().query(),
+        });
+        match outcome {
+            rules::Outcome::Redirect { status, location, rule_id } => {
+                tracing::debug!(request_id = %request_id, rule = %rule_id, location = %location, "Redirected by rule");
+                let domain_record = routes.domain(domain);
+                return match rules::redirect_response(status, &location) {
+                    Ok(response) => with_domain_policy(response, domain_record.as_deref(), request.headers().get(axum::http::header::ORIGIN)),
+                    Err(problem) => problem_response(problem, &request_id, request.headers(), domain_record.as_deref()),
+                };
+            }
+            rules::Outcome::Pass { path: rewritten, rewritten_by } if !rewritten_by.is_empty() => {
+                tracing::debug!(request_id = %request_id, rules = ?rewritten_by, path = %rewritten, "Rewritten by rules");
+                let path_and_query = match request.uri().query() {
+                    Some(query) => format!("{}?{}", rewritten, query),
+                    None => rewritten.clone(),
+                };
+                match path_and_query.parse() {
+                    Ok(uri) => {
+                        *request.uri_mut() = uri;
+                        path = rewritten;
+                    }
+                    Err(_) => tracing::warn!(request_id = %request_id, path = %rewritten, "Ignoring rewrite to an invalid path"),
+                }
+            }
+            rules::Outcome::Pass { .. } => {}
+        }
+    }
+
+    // Find the endpoint for this request (with path parameter extraction).
+    // HEAD without its own route runs the GET handler, and OPTIONS without
+    // one is answered from the route table.
+    let mut route = routes.lookup(domain, &method, &path);
+    let head_via_get = route.is_none() && method == "HEAD";
+    if head_via_get {
+        route = routes.lookup(domain, "GET", &path);
+    }
+    if route.is_none() && method == "OPTIONS" {
+        if let Some(allowed) = routes.allowed_methods(domain, &path) {
+            // A preflight gets the CORS policy of the route it asks about
+            let requested = request.headers()
+                .get(axum::http::header::ACCESS_CONTROL_REQUEST_METHOD)
+                .and_then(|m| m.to_str().ok())
+                .and_then(|m| routes.lookup(domain, m, &path));
+            let policy = match &requested {
+                Some(m) => m.middleware.cors(),
+                None => allowed.domain.as_ref().and_then(|d| d.settings.cors.as_ref()),
+            };
+            return cors::options_response(&allowed, policy, request.headers());
+        }
+    }
+
+    let (endpoint, domain_record, params, subdomain, middleware) = match route {
+        Some(m) => {
+            tracing::debug!(
+                request_id = %request_id,
+                endpoint = %m.endpoint.id,
+                domain_record = ?m.domain.as_ref().map(|d| &d.name),
+                "Matched route"
+            );
+            (m.endpoint, m.domain, m.params, m.subdomain, m.middleware)
+        }
+        None => {
+            tracing::debug!("No endpoint found for {} {} {}", domain, method, path);
+            let domain_record = routes.domain(domain);
+            let site = domain_record.as_ref().and_then(|d| d.settings.site.as_ref());
+            if let Some(site) = site.filter(|_| method == "GET" || method == "HEAD") {
+                let (parts, _body) = request.into_parts();
+                return match site::serve(&state, site, &parts).await {
+                    Ok(response) => with_domain_policy(response, domain_record.as_deref(), parts.headers.get(axum::http::header::ORIGIN)),
+                    Err(problem) => problem_response(problem, &request_id, &parts.headers, domain_record.as_deref()),
+                };
+            }
+            return problem_response(ProblemKind::NotFound, &request_id, request.headers(), domain_record.as_deref());
+        }
+    };
+    let domain_record = domain_record.as_deref();
+
+    // Middleware checks the request before the endpoint runs, and changes
+    // the response afterwards (including the CORS policy)
+    let origin = request.headers().get(axum::http::header::ORIGIN).cloned();
+    let request_method = request.method().clone();
+    let (mut parts, body) = request.into_parts();
+    let (mut response, depth) = match middleware.before(&state.route_rate_limits, &mut parts) {
+        Ok(admitted) => {
+            let depth = admitted.depth;
+            let routed = Routed {
+                endpoint: &endpoint,
+                domain: domain_record,
+                host: domain,
+                params,
+                subdomain,
+                head_via_get,
+                request_id: &request_id,
+                client_ip,
+                middleware: &middleware,
+                admitted,
+            };
+            (serve_endpoint(&state, routed, Request::from_parts(parts, body)).await, depth)
+        }
+        Err(rejected) => {
+            tracing::debug!(request_id = %request_id, endpoint = %endpoint.id, problem = rejected.problem.kind.slug(), "Rejected by middleware");
+            let pages = domain_record.and_then(|d| d.settings.error_pages.as_ref());
+            (rejected.render(&request_id, &parts.headers, pages), rejected.depth)
+        }
+    };
+    middleware.after(depth, &request_method, origin.as_ref(), &mut response);
+    with_compression(response, domain_record)
+}
+
+/// A request matched to an endpoint and admitted by its middleware
+struct Routed<'a> {
+    endpoint: &'a Endpoint,
+    /// The domain record serving the request, if any
+    domain: Option<&'a Domain>,
+    /// The normalized request host
+    host: &'a str,
+    params: HashMap<String, String>,
+    subdomain: Option<String>,
+    /// A HEAD request served by the GET handler
+    head_via_get: bool,
+    request_id: &'a str,
+    client_ip: Option<String>,
+    middleware: &'a Chain,
+    admitted: Admitted,
+}
+
+/// Serve a routed request from its endpoint's upstream or handler
+///
+/// The domain's CORS policy and compression are left to the caller.
+async fn serve_endpoint(state: &Arc<AppState>, routed: Routed<'_>, request: Request<Body>) -> Response {
+    let Routed { endpoint, domain: domain_record, host: domain, request_id, .. } = routed;
+    let problem_response = |problem: ProblemKind, headers: &axum::http::HeaderMap| {
+        render_problem(problem, request_id, headers, domain_record)
+    };
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let body_limit = endpoint.settings.max_body_size
+        .unwrap_or(state.runtime_config.max_body_size)
+        .min(routed.admitted.body_limit.unwrap_or(usize::MAX));
+
+    // Proxy endpoints forward to their upstream without handler code
+    if let Some(settings) = endpoint.settings.proxy.as_ref().filter(|_| endpoint.kind == EndpointKind::Proxy) {
+        let (parts, body) = request.into_parts();
+        let forwarded = proxy::forward(&state.upstream_client, &state.upstreams, settings, proxy::ProxyRequest {
+            parts: &parts,
+            body,
+            params: &routed.params,
+            client_ip: routed.client_ip.as_deref(),
+            request_id,
+            body_limit,
+            timeout: Duration::from_secs(endpoint.settings.timeout_secs.unwrap_or(state.config.handler_timeout_secs)),
+        }).await;
+        return match forwarded {
+            Ok(response) => response,
+            Err(problem) => render_problem(problem, request_id, &parts.headers, domain_record),
+        };
+    }
+
+    // Check if endpoint is compiled
+    if !endpoint.compiled {
+        tracing::debug!(request_id = %request_id, endpoint = %endpoint.id, "Endpoint not compiled");
+        return problem_response(ProblemKind::EndpointUnavailable, request.headers());
+    }
+
+    // Build the SDK request
+    let query: std::collections::HashMap<String, String> = request.uri()
+        .query()
+        .map(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let headers: std::collections::HashMap<String, String> = request.headers()
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+        .collect();
+
+    let mut attributes = routed.admitted.attributes;
+    if let Some(subdomain) = routed.subdomain {
+        attributes.insert("subdomain".to_string(), subdomain);
+    }
+
+    let mut sdk_request = rust_edge_gateway_sdk::Request {
+        method: if routed.head_via_get { "GET".to_string() } else { method.clone() },
+        path: path.clone(),
+        query,
+        headers,
+        body: None,
+        params: routed.params,
+        client_ip: routed.client_ip,
+        request_id: request_id.to_string(),
+        attributes,
+    };
+
+    // WebSocket endpoints hand the connection over to the handler's callbacks
+    if endpoint.kind == EndpointKind::WebSocket {
+        let (mut parts, _body) = request.into_parts();
+        return match socket::upgrade(state, endpoint, &mut parts, sdk_request).await {
+            Ok(response) => response,
+            Err(problem) => render_problem(problem, request_id, &parts.headers, domain_record),
+        };
+    }
+
+    // Pick the handler version, which differs during a canary release
+    let handler = state.handler_registry.select(&endpoint.id, &SplitRequest {
+        headers: request.headers(),
+        client_ip: sdk_request.client_ip.as_deref(),
+        request_id,
+    }).await;
+
+    // Serve from the response cache while the same handler version is loaded
+    let cacheable = state.response_cache.as_ref().and_then(|_| {
+        cache::CacheableRequest::new(&endpoint.id, domain, request.method(), request.uri(), request.headers())
+    });
+    if let (Some(cache), Some(cacheable), Some(handler)) = (&state.response_cache, &cacheable, &handler) {
+        if let Some(response) = cache.get(cacheable, handler.generation) {
+            tracing::debug!(request_id = %request_id, "Served from response cache");
+            return response;
+        }
+    }
+
+    // Get body, enforcing the endpoint's limit (or the gateway default)
+    let declared_len = request.headers()
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared_len.is_some_and(|len| len > body_limit as u64) {
+        tracing::debug!(request_id = %request_id, limit = body_limit, "Request body too large");
+        return problem_response(ProblemKind::PayloadTooLarge, request.headers());
+    }
+
+    let (parts, body) = request.into_parts();
+    let body_bytes = match axum::body::to_bytes(body, body_limit).await {
+        Ok(b) => b,
+        Err(e) if is_length_limit_error(&e) => {
+            tracing::debug!(request_id = %request_id, limit = body_limit, "Request body too large");
+            return problem_response(ProblemKind::PayloadTooLarge, &parts.headers);
+        }
+        Err(e) => {
+            tracing::error!("Failed to read body: {}", e);
+            return problem_response(ProblemKind::InvalidBody, &parts.headers);
+        }
+    };
+
+    if !body_bytes.is_empty() {
+        sdk_request.body = Some(rust_edge_gateway_sdk::Body::from(body_bytes));
+    }
+
+    // Execute via v2 handler registry with timeout and graceful draining support
+    let timeout = Duration::from_secs(
+        endpoint.settings.timeout_secs.unwrap_or(state.config.handler_timeout_secs),
+    );
+    let ctx = state.create_sdk_context(request_id).await;
+
+    // Copy the request for the endpoint's shadow, if it mirrors this one
+    let mirrored = match state.handler_registry.shadow(&endpoint.id).await {
+        Some(shadow) if shadow.settings.samples(request_id) => {
+            if shadow.handler.active_request_count() < shadow.settings.max_in_flight {
+                Some((shadow, sdk_request.clone()))
+            } else {
+                shadow.counters.skip();
+                None
+            }
+        }
+        _ => None,
+    };
+
+    let started = std::time::Instant::now();
+    let response = match handler {
+        Some(handler) => handler.execute_with_timeout(&ctx, sdk_request, timeout).await,
+        None => Err(ExecuteError::NotLoaded(endpoint.id.clone())),
+    };
+
+    let problem = match response {
+        Ok((sdk_response, guard)) => {
+            // The shadow runs after the client's response is ready
+            if let Some((shadow, request)) = mirrored {
+                let observed = mirror::Observed::of(&sdk_response, started.elapsed());
+                tokio::spawn(mirror::replay(shadow, ctx, request, observed, timeout));
+            }
+            let generation = guard.generation();
+            let mut response = match into_http_response(sdk_response, Some(guard)) {
+                Ok(response) => response,
+                Err(problem) => return problem_response(problem, &parts.headers),
+            };
+            // The cache middleware's lifetime applies before the response is stored
+            routed.middleware.set_cache_lifetime(&parts.method, &mut response);
+            if let (Some(cache), Some(cacheable)) = (&state.response_cache, cacheable) {
+                response = match cache.store(cacheable, generation, response).await {
+                    Ok(response) => response,
+                    Err(problem) => return problem_response(problem, &parts.headers),
+                };
+            }
+            if routed.head_via_get {
+                response = strip_body(response);
+            }
+            return response;
+        }
+        // Draining during a hot swap is expected; clients should retry
+        Err(ExecuteError::Draining) => {
+            tracing::info!(request_id = %request_id, "Handler is draining, returning 503");
+            ProblemKind::HandlerDraining
+        }
+        Err(e @ ExecuteError::TimedOut(_)) => {
+            tracing::warn!(request_id = %request_id, endpoint = %endpoint.id, "{}", e);
+            ProblemKind::HandlerTimeout
+        }
+        Err(e @ ExecuteError::NotLoaded(_)) => {
+            tracing::warn!(request_id = %request_id, "Handler error: {}", e);
+            ProblemKind::EndpointUnavailable
+        }
+        Err(e) => {
+            tracing::error!(request_id = %request_id, "Handler error: {}", e);
+            ProblemKind::HandlerFailed
+        }
+    };
+
+    problem_response(problem, &parts.headers)
+}
+
+/// Drop the body of a GET response sent for a HEAD request
+///
+/// The length of a buffered body is kept in `Content-Length`. A streamed
+/// body is dropped unread, which closes the handler's stream.
+fn strip_body(response: Response) -> Response {
+    use axum::body::HttpBody;
+
+    let (mut parts, body) = response.into_parts();
+    if let Some(len) = body.size_hint().exact() {
+        parts.headers.entry(axum::http::header::CONTENT_LENGTH).or_insert(len.into());
+    }
+    Response::from_parts(parts, Body::empty())
+}
+
+/// Apply the serving domain's CORS policy, and attach its compression
+/// settings for the compression layer
+///
+/// For requests that match no route; routed requests get the CORS policy
+/// from their middleware.
+fn with_domain_policy(
+    mut response: Response,
+    domain: Option<&Domain>,
+    origin: Option<&axum::http::HeaderValue>,
+) -> Response {
+    if let (Some(cors), Some(origin)) = (domain.and_then(|d| d.settings.cors.as_ref()), origin) {
+        cors.apply(origin, &mut response);
+    }
+    with_compression(response, domain)
+}
+
+/// Attach the serving domain's compression settings for the compression layer
+fn with_compression(mut response: Response, domain: Option<&Domain>) -> Response {
+    if let Some(settings) = domain.and_then(|d| d.settings.compression.clone()) {
+        response.extensions_mut().insert(settings);
+    }
+    response
+}
+
+/// Render a gateway error with the serving domain's error pages
+fn render_problem(
+    problem: impl Into<Problem>,
+    request_id: &str,
+    request: &axum::http::HeaderMap,
+    domain: Option<&Domain>,
+) -> Response {
+    let pages = domain.and_then(|d| d.settings.error_pages.as_ref());
+    problem.into().render(request_id, request, pages)
+}
+
+/// Render a gateway error with the serving domain's error pages and CORS policy
+fn problem_response(
+    problem: impl Into<Problem>,
+    request_id: &str,
+    request: &axum::http::HeaderMap,
+    domain: Option<&Domain>,
+) -> Response {
+    let response = render_problem(problem, request_id, request, domain);
+    with_domain_policy(response, domain, request.get(axum::http::header::ORIGIN))
+}
+
+/// Whether reading or forwarding a body failed because it exceeded the size limit
+fn is_length_limit_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(e) = source {
+        if e.is::<http_body_util::LengthLimitError>() {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// Convert a handler response into an HTTP response
+///
+/// Bodies are passed through as raw bytes. A streaming body is forwarded
+/// chunk by chunk, with `guard` turned into an open stream until it ends. A
+/// response that cannot be sent as-is (an invalid header) is a handler
+/// failure.
+fn into_http_response(
+    mut sdk_response: rust_edge_gateway_sdk::Response,
+    guard: Option<RequestGuard>,
+) -> Result<Response, ProblemKind> {
+    let mut builder = Response::builder()
+        .status(StatusCode::from_u16(sdk_response.status).unwrap_or(StatusCode::OK));
+
+    for (key, value) in sdk_response.headers {
+        builder = builder.header(&key, &value);
+    }
+
+    let body = match sdk_response.stream.take().and_then(|s| s.take()) {
+        Some(reader) => stream::into_body(reader, guard.map(RequestGuard::into_stream)),
+        None => Body::from(sdk_response.body.map(|b| b.into_bytes()).unwrap_or_default()),
+    };
+    builder.body(body).map_err(|e| {
+        tracing::error!("Handler returned an invalid response: {}", e);
+        ProblemKind::HandlerFailed
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_body_limit_error_is_detected() {
+        let err = axum::body::to_bytes(axum::body::Body::from(vec![0u8; 16]), 8).await.unwrap_err();
+        assert!(is_length_limit_error(&err));
+
+        let ok = axum::body::to_bytes(axum::body::Body::from(vec![0u8; 8]), 8).await;
+        assert_eq!(ok.unwrap().len(), 8);
+    }
+}
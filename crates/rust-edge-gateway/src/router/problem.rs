@@ -0,0 +1,387 @@
+//! Gateway error responses
+//!
+//! Errors raised by the gateway itself, rather than returned by a handler,
+//! are sent as RFC 7807 `application/problem+json` documents:
+//!
+//! ```json
+//! {
+//!   "type": "urn:rust-edge-gateway:problem:handler-timeout",
+//!   "title": "Handler Timed Out",
+//!   "status": 504,
+//!   "detail": "The handler did not respond in time.",
+//!   "request_id": "8d0f..."
+//! }
+//! ```
+//!
+//! `type` is stable for each [`ProblemKind`] and is what clients should
+//! match on. `detail` is a fixed, client-safe sentence; the underlying error
+//! is only logged.
+//!
+//! Clients that prefer `text/html` get the serving domain's error page
+//! instead, if it has one (see [`ErrorPageSettings`]).
+
+use std::collections::BTreeMap;
+
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+
+/// Prefix of every problem `type` URI
+pub const TYPE_PREFIX: &str = "urn:rust-edge-gateway:problem:";
+
+/// Largest accepted error page template, in bytes
+pub const MAX_TEMPLATE_SIZE: usize = 64 * 1024;
+
+/// Content type of problem documents
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// The errors the gateway can raise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// No endpoint matches the request
+    NotFound,
+    /// The endpoint exists but has no loaded handler
+    EndpointUnavailable,
+    /// The request body exceeds the endpoint's limit
+    PayloadTooLarge,
+    /// The request body could not be read
+    InvalidBody,
+    /// A WebSocket endpoint was called without an upgrade
+    UpgradeRequired,
+    /// The handler is being replaced
+    HandlerDraining,
+    /// The handler did not respond within its timeout
+    HandlerTimeout,
+    /// The handler panicked or returned an unusable response
+    HandlerFailed,
+    /// A proxy endpoint's upstream could not be reached or was unavailable
+    UpstreamFailed,
+    /// A proxy endpoint's upstream did not respond within its timeout
+    UpstreamTimeout,
+    /// A proxy endpoint's upstream pool has no available target
+    UpstreamUnavailable,
+    /// Missing or invalid credentials
+    Unauthorized,
+    /// Valid credentials without the required permission
+    Forbidden,
+    /// The client sent more requests than a rate limit allows
+    RateLimited,
+    /// Any other failure inside the gateway
+    InternalError,
+}
+
+impl ProblemKind {
+    /// HTTP status sent for this kind
+    pub fn status(self) -> StatusCode {
+        match self {
+            ProblemKind::NotFound => StatusCode::NOT_FOUND,
+            ProblemKind::EndpointUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ProblemKind::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ProblemKind::InvalidBody => StatusCode::BAD_REQUEST,
+            ProblemKind::UpgradeRequired => StatusCode::UPGRADE_REQUIRED,
+            ProblemKind::HandlerDraining => StatusCode::SERVICE_UNAVAILABLE,
+            ProblemKind::HandlerTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ProblemKind::HandlerFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            ProblemKind::UpstreamFailed => StatusCode::BAD_GATEWAY,
+            ProblemKind::UpstreamTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ProblemKind::UpstreamUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ProblemKind::Unauthorized => StatusCode::UNAUTHORIZED,
+            ProblemKind::Forbidden => StatusCode::FORBIDDEN,
+            ProblemKind::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ProblemKind::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Last part of the `type` URI
+    pub fn slug(self) -> &'static str {
+        match self {
+            ProblemKind::NotFound => "not-found",
+            ProblemKind::EndpointUnavailable => "endpoint-unavailable",
+            ProblemKind::PayloadTooLarge => "payload-too-large",
+            ProblemKind::InvalidBody => "invalid-body",
+            ProblemKind::UpgradeRequired => "upgrade-required",
+            ProblemKind::HandlerDraining => "handler-draining",
+            ProblemKind::HandlerTimeout => "handler-timeout",
+            ProblemKind::HandlerFailed => "handler-failed",
+            ProblemKind::UpstreamFailed => "upstream-failed",
+            ProblemKind::UpstreamTimeout => "upstream-timeout",
+            ProblemKind::UpstreamUnavailable => "upstream-unavailable",
+            ProblemKind::Unauthorized => "unauthorized",
+            ProblemKind::Forbidden => "forbidden",
+            ProblemKind::RateLimited => "rate-limited",
+            ProblemKind::InternalError => "internal-error",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            ProblemKind::NotFound => "Not Found",
+            ProblemKind::EndpointUnavailable => "Endpoint Unavailable",
+            ProblemKind::PayloadTooLarge => "Payload Too Large",
+            ProblemKind::InvalidBody => "Invalid Request Body",
+            ProblemKind::UpgradeRequired => "Upgrade Required",
+            ProblemKind::HandlerDraining => "Handler Updating",
+            ProblemKind::HandlerTimeout => "Handler Timed Out",
+            ProblemKind::HandlerFailed => "Handler Failed",
+            ProblemKind::UpstreamFailed => "Upstream Failed",
+            ProblemKind::UpstreamTimeout => "Upstream Timed Out",
+            ProblemKind::UpstreamUnavailable => "Upstream Unavailable",
+            ProblemKind::Unauthorized => "Unauthorized",
+            ProblemKind::Forbidden => "Forbidden",
+            ProblemKind::RateLimited => "Too Many Requests",
+            ProblemKind::InternalError => "Internal Error",
+        }
+    }
+
+    fn detail(self) -> &'static str {
+        match self {
+            ProblemKind::NotFound => "No endpoint matches this request.",
+            ProblemKind::EndpointUnavailable => "The endpoint is not deployed.",
+            ProblemKind::PayloadTooLarge => "The request body exceeds the endpoint's size limit.",
+            ProblemKind::InvalidBody => "The request body could not be read.",
+            ProblemKind::UpgradeRequired => "This endpoint only accepts WebSocket connections.",
+            ProblemKind::HandlerDraining => "The handler is being updated. Retry the request.",
+            ProblemKind::HandlerTimeout => "The handler did not respond in time.",
+            ProblemKind::HandlerFailed => "The handler failed to produce a response.",
+            ProblemKind::UpstreamFailed => "The upstream service could not be reached.",
+            ProblemKind::UpstreamTimeout => "The upstream service did not respond in time.",
+            ProblemKind::UpstreamUnavailable => "No healthy upstream server is available.",
+            ProblemKind::Unauthorized => "Valid credentials are required.",
+            ProblemKind::Forbidden => "The credentials do not grant access to this resource.",
+            ProblemKind::RateLimited => "Too many requests. Retry later.",
+            ProblemKind::InternalError => "The gateway could not complete the request.",
+        }
+    }
+}
+
+/// A gateway error, ready to be rendered for a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    detail: Option<String>,
+}
+
+impl From<ProblemKind> for Problem {
+    fn from(kind: ProblemKind) -> Self {
+        Self { kind, detail: None }
+    }
+}
+
+impl Problem {
+    /// Replace the kind's default detail
+    ///
+    /// Only for text that is safe to show clients, never for error messages
+    /// from handlers or the database.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// The problem document
+    pub fn to_json(&self, request_id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "type": format!("{}{}", TYPE_PREFIX, self.kind.slug()),
+            "title": self.kind.title(),
+            "status": self.kind.status().as_u16(),
+            "detail": self.detail.as_deref().unwrap_or(self.kind.detail()),
+            "request_id": request_id,
+        })
+    }
+
+    /// Render for a request with the given headers
+    ///
+    /// `pages` are the serving domain's error pages; one is used when the
+    /// client prefers HTML and a template matches the status.
+    pub fn render(&self, request_id: &str, request: &HeaderMap, pages: Option<&ErrorPageSettings>) -> Response {
+        let status = self.kind.status();
+        let page = pages
+            .filter(|_| prefers_html(request))
+            .and_then(|p| p.template(status))
+            .map(|template| self.fill(template, request_id));
+
+        let mut response = match page {
+            Some(html) => (
+                status,
+                [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+                html,
+            ).into_response(),
+            None => (
+                status,
+                [(header::CONTENT_TYPE, PROBLEM_JSON)],
+                self.to_json(request_id).to_string(),
+            ).into_response(),
+        };
+
+        let headers = response.headers_mut();
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        match self.kind {
+            ProblemKind::HandlerDraining => {
+                headers.insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+            }
+            ProblemKind::UpgradeRequired => {
+                headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+            }
+            _ => {}
+        }
+        response
+    }
+
+    /// Substitute the problem's fields into an HTML template
+    fn fill(&self, template: &str, request_id: &str) -> String {
+        let status = self.kind.status().as_u16().to_string();
+        let kind_type = format!("{}{}", TYPE_PREFIX, self.kind.slug());
+        let detail = self.detail.as_deref().unwrap_or(self.kind.detail());
+        template
+            .replace("{{status}}", &status)
+            .replace("{{title}}", &escape_html(self.kind.title()))
+            .replace("{{detail}}", &escape_html(detail))
+            .replace("{{type}}", &escape_html(&kind_type))
+            .replace("{{request_id}}", &escape_html(request_id))
+    }
+}
+
+/// Per-domain HTML error pages
+///
+/// Templates may use `{{status}}`, `{{title}}`, `{{detail}}`, `{{type}}` and
+/// `{{request_id}}`, which are replaced with HTML-escaped values.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ErrorPageSettings {
+    /// Templates keyed by status code (`"404"`), status class (`"5xx"`) or
+    /// `"default"`; the most specific key wins
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+}
+
+impl ErrorPageSettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        for (key, template) in &self.templates {
+            let valid = matches!(key.as_str(), "4xx" | "5xx" | "default")
+                || key.parse::<u16>().is_ok_and(|code| (400..600).contains(&code));
+            if !valid {
+                return Err(format!("Invalid error page key: {} (use a 4xx/5xx status, \"4xx\", \"5xx\" or \"default\")", key));
+            }
+            if template.len() > MAX_TEMPLATE_SIZE {
+                return Err(format!("Error page '{}' exceeds {} bytes", key, MAX_TEMPLATE_SIZE));
+            }
+        }
+        Ok(())
+    }
+
+    /// The template for a status, if any
+    fn template(&self, status: StatusCode) -> Option<&str> {
+        let class = if status.is_client_error() { "4xx" } else { "5xx" };
+        self.templates.get(status.as_str())
+            .or_else(|| self.templates.get(class))
+            .or_else(|| self.templates.get("default"))
+            .map(String::as_str)
+    }
+}
+
+/// Whether the client ranks `text/html` above JSON in its `Accept` header
+fn prefers_html(request: &HeaderMap) -> bool {
+    let Some(accept) = request.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+
+    let quality = |wanted: &str| {
+        accept.split(',')
+            .filter_map(|item| {
+                let mut params = item.split(';');
+                let media_type = params.next()?.trim();
+                if !media_type.eq_ignore_ascii_case(wanted) {
+                    return None;
+                }
+                let q = params
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .find_map(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some(q)
+            })
+            .fold(0.0_f32, f32::max)
+    };
+
+    let html = quality("text/html");
+    html > 0.0 && html > quality("application/json").max(quality(PROBLEM_JSON))
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, value.parse().unwrap());
+        headers
+    }
+
+    async fn body(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    fn pages() -> ErrorPageSettings {
+        ErrorPageSettings {
+            templates: BTreeMap::from([
+                ("404".to_string(), "<h1>{{title}}</h1><p>{{request_id}}</p>".to_string()),
+                ("5xx".to_string(), "<h1>{{status}} {{detail}}</h1>".to_string()),
+            ]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_problem_json() {
+        let response = Problem::from(ProblemKind::HandlerDraining).render("req-1", &HeaderMap::new(), None);
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/problem+json");
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        let json: serde_json::Value = serde_json::from_str(&body(response).await).unwrap();
+        assert_eq!(json["type"], "urn:rust-edge-gateway:problem:handler-draining");
+        assert_eq!(json["status"], 503);
+        assert_eq!(json["request_id"], "req-1");
+    }
+
+    #[tokio::test]
+    async fn test_html_page_for_browsers() {
+        let browser = accept("text/html,application/xhtml+xml,*/*;q=0.8");
+
+        let response = Problem::from(ProblemKind::NotFound).render("<id>", &browser, Some(&pages()));
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert_eq!(body(response).await, "<h1>Not Found</h1><p>&lt;id&gt;</p>");
+
+        // Status class fallback
+        let response = Problem::from(ProblemKind::HandlerTimeout).render("r", &browser, Some(&pages()));
+        assert_eq!(body(response).await, "<h1>504 The handler did not respond in time.</h1>");
+
+        // No template for 413, and API clients always get JSON
+        let response = Problem::from(ProblemKind::PayloadTooLarge).render("r", &browser, Some(&pages()));
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/problem+json");
+        let response = Problem::from(ProblemKind::NotFound).render("r", &accept("application/json, text/html;q=0.5"), Some(&pages()));
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/problem+json");
+    }
+
+    #[test]
+    fn test_validate_pages() {
+        assert!(pages().validate().is_ok());
+        for key in ["200", "600", "4XX", "other"] {
+            let invalid = ErrorPageSettings { templates: BTreeMap::from([(key.to_string(), String::new())]) };
+            assert!(invalid.validate().is_err(), "{}", key);
+        }
+    }
+}
@@ -0,0 +1,553 @@
+//! Reverse-proxy endpoints
+//!
+//! A `proxy` endpoint forwards requests to an upstream base URL instead of
+//! calling handler code. The upstream path is the request path, optionally
+//! with a prefix stripped or replaced by a template filled from the route's
+//! path parameters. The query string is passed through unchanged.
+//!
+//! Request and response bodies are streamed in both directions. The only
+//! exception is a request that may be retried: idempotent methods with
+//! `retries` set have their body buffered (up to the endpoint's body limit)
+//! so it can be sent again. Retries happen after a connection failure, a
+//! timeout or a 502/503/504 from the upstream.
+//!
+//! Hop-by-hop headers are dropped in both directions and the `Host` header
+//! is set from the upstream URL. The upstream receives the resolved client
+//! IP in `X-Forwarded-For`, the original host in `X-Forwarded-Host` and the
+//! request id in `X-Request-Id`.
+//!
+//! Instead of a fixed `upstream`, an endpoint may name an upstream `pool`
+//! (see [`super::upstream`]). Every attempt then goes to a target picked
+//! from the pool, so a retry can reach a different backend, and each
+//! outcome feeds the pool's outlier detection.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use axum::body::{Body, HttpBody};
+use axum::http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+
+use super::problem::{Problem, ProblemKind};
+use super::upstream::{SelectedTarget, UpstreamPools};
+use crate::net::request_id::REQUEST_ID_HEADER;
+
+/// Most retries an endpoint may configure
+pub const MAX_RETRIES: u32 = 5;
+
+/// Pause before the first retry; doubled for each further one
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Headers that describe a single connection and are never forwarded
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Upstream of a `proxy` endpoint
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProxySettings {
+    /// Base URL requests are forwarded to, such as `http://users.internal:8080/v1`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub upstream: String,
+
+    /// Upstream pool whose targets requests are forwarded to, instead of `upstream`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+
+    /// Prefix removed from the request path before it is appended to `upstream`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strip_prefix: Option<String>,
+
+    /// Path appended to `upstream` instead of the request path, with
+    /// `{name}` replaced by the route's path parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewrite: Option<String>,
+
+    /// Changes to the headers sent upstream
+    #[serde(default, skip_serializing_if = "HeaderRules::is_empty")]
+    pub request_headers: HeaderRules,
+
+    /// Changes to the headers sent back to the client
+    #[serde(default, skip_serializing_if = "HeaderRules::is_empty")]
+    pub response_headers: HeaderRules,
+
+    /// How many times a failed request with an idempotent method is retried
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub retries: u32,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// Headers to remove and set, applied in that order
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeaderRules {
+    /// Headers set on the message, replacing any existing value
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub add: BTreeMap<String, String>,
+
+    /// Headers removed from the message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+}
+
+impl HeaderRules {
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for name in self.add.keys().chain(&self.remove) {
+            HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name: {}", name))?;
+        }
+        for (name, value) in &self.add {
+            HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header {}", name))?;
+        }
+        Ok(())
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        for name in &self.remove {
+            if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+                headers.remove(name);
+            }
+        }
+        for (name, value) in &self.add {
+            if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+                headers.insert(name, value);
+            }
+        }
+    }
+}
+
+impl ProxySettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.pool {
+            None if self.upstream.is_empty() => return Err("Set either upstream or pool".to_string()),
+            None => validate_base_url(&self.upstream)?,
+            Some(_) if !self.upstream.is_empty() => return Err("Set either upstream or pool, not both".to_string()),
+            Some(pool) => super::upstream::validate_name(pool)?,
+        }
+        if self.strip_prefix.is_some() && self.rewrite.is_some() {
+            return Err("Set either strip_prefix or rewrite, not both".to_string());
+        }
+        for path in self.strip_prefix.iter().chain(&self.rewrite) {
+            if !path.starts_with('/') {
+                return Err(format!("Proxy paths must start with '/': {}", path));
+            }
+        }
+        if self.retries > MAX_RETRIES {
+            return Err(format!("retries must be at most {}", MAX_RETRIES));
+        }
+        self.request_headers.validate()?;
+        self.response_headers.validate()
+    }
+
+    /// The upstream URL for a request path and query, below `base`
+    fn upstream_url(&self, base: &str, path: &str, query: Option<&str>, params: &HashMap<String, String>) -> String {
+        let path = match (&self.rewrite, &self.strip_prefix) {
+            (Some(template), _) => expand_template(template, params),
+            (None, Some(prefix)) => {
+                let rest = path.strip_prefix(prefix.trim_end_matches('/')).unwrap_or(path);
+                if rest.starts_with('/') { rest.to_string() } else { format!("/{}", rest) }
+            }
+            (None, None) => path.to_string(),
+        };
+
+        let mut url = format!("{}{}", base.trim_end_matches('/'), path);
+        if let Some(query) = query {
+            url.push('?');
+            url.push_str(query);
+        }
+        url
+    }
+}
+
+/// Replace each `{name}` in a rewrite template with the path parameter of
+/// that name
+///
+/// The template is read once from left to right, so parameter values are
+/// inserted as they are: a value containing `{other}` is not expanded again.
+/// Placeholders without a parameter are kept.
+fn expand_template(template: &str, params: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let placeholder = &rest[open..];
+        match placeholder.find('}').and_then(|close| Some((params.get(&placeholder[1..close])?, close))) {
+            Some((value, close)) => {
+                out.push_str(value);
+                rest = &placeholder[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &placeholder[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Check that a URL can be used as an upstream base URL
+pub fn validate_base_url(url: &str) -> Result<(), String> {
+    let parsed = url::Url::parse(url)
+        .map_err(|e| format!("Invalid upstream URL {}: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(format!("Upstream URL must be http:// or https:// with a host: {}", url));
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err(format!("Upstream URL must not have a query or fragment: {}", url));
+    }
+    Ok(())
+}
+
+/// HTTP client shared by all proxy endpoints
+///
+/// Redirects are passed back to the client rather than followed.
+pub fn client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .no_proxy()
+        .build()
+}
+
+/// A request to forward, after routing
+pub struct ProxyRequest<'a> {
+    pub parts: &'a Parts,
+    pub body: Body,
+    /// Path parameters of the matched route
+    pub params: &'a HashMap<String, String>,
+    pub client_ip: Option<&'a str>,
+    pub request_id: &'a str,
+    pub body_limit: usize,
+    /// How long each attempt waits for the upstream's response headers
+    pub timeout: Duration,
+}
+
+/// What is sent as the upstream request body
+enum UpstreamBody {
+    Empty,
+    /// Kept so the request can be retried
+    Buffered(bytes::Bytes),
+    /// Sent once, as it arrives from the client
+    Streamed(Option<reqwest::Body>),
+}
+
+impl UpstreamBody {
+    fn take(&mut self) -> Option<reqwest::Body> {
+        match self {
+            UpstreamBody::Empty => None,
+            UpstreamBody::Buffered(bytes) => Some(bytes.clone().into()),
+            UpstreamBody::Streamed(body) => body.take(),
+        }
+    }
+}
+
+/// Forward a request to the endpoint's upstream
+pub async fn forward(
+    client: &reqwest::Client,
+    pools: &UpstreamPools,
+    settings: &ProxySettings,
+    request: ProxyRequest<'_>,
+) -> Result<Response, Problem> {
+    let ProxyRequest { parts, body, params, client_ip, request_id, body_limit, timeout } = request;
+
+    let declared_len = parts.headers.get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared_len.is_some_and(|len| len > body_limit as u64) {
+        return Err(ProblemKind::PayloadTooLarge.into());
+    }
+
+    let attempts = if is_idempotent(&parts.method) { settings.retries + 1 } else { 1 };
+    let mut body = if body.is_end_stream() {
+        UpstreamBody::Empty
+    } else if attempts > 1 {
+        match axum::body::to_bytes(body, body_limit).await {
+            Ok(bytes) => UpstreamBody::Buffered(bytes),
+            Err(e) if super::is_length_limit_error(&e) => return Err(ProblemKind::PayloadTooLarge.into()),
+            Err(e) => {
+                tracing::debug!(request_id = %request_id, "Failed to read body: {}", e);
+                return Err(ProblemKind::InvalidBody.into());
+            }
+        }
+    } else {
+        let limited = Body::new(http_body_util::Limited::new(body, body_limit));
+        UpstreamBody::Streamed(Some(reqwest::Body::wrap_stream(limited.into_data_stream())))
+    };
+
+    let headers = upstream_headers(settings, parts, client_ip, request_id);
+
+    let mut problem = ProblemKind::UpstreamFailed;
+    for attempt in 1..=attempts {
+        if attempt > 1 {
+            tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt - 2)).await;
+            tracing::debug!(request_id = %request_id, attempt, "Retrying upstream request");
+        }
+
+        let target = match &settings.pool {
+            Some(pool) => match pools.select(pool, &parts.headers, client_ip) {
+                Ok(target) => Some(target),
+                Err(kind) if attempt == 1 => return Err(kind.into()),
+                Err(_) => break,
+            },
+            None => None,
+        };
+        let base = target.as_ref().map_or(settings.upstream.as_str(), |t| t.url());
+        let url = settings.upstream_url(base, parts.uri.path(), parts.uri.query(), params);
+
+        let mut upstream = client.request(parts.method.clone(), &url).headers(headers.clone());
+        if let Some(body) = body.take() {
+            upstream = upstream.body(body);
+        }
+
+        let result = tokio::time::timeout(timeout, upstream.send()).await;
+        if let Some(target) = &target {
+            target.report(match &result {
+                Ok(Ok(response)) => !is_retryable(response.status()),
+                Ok(Err(e)) => super::is_length_limit_error(e),
+                Err(_) => false,
+            });
+        }
+
+        match result {
+            Ok(Ok(response)) if attempt < attempts && is_retryable(response.status()) => {
+                tracing::warn!(request_id = %request_id, url = %url, status = %response.status(), "Upstream unavailable");
+                problem = ProblemKind::UpstreamFailed;
+            }
+            Ok(Ok(response)) => return Ok(into_response(settings, response, target)),
+            Ok(Err(e)) if super::is_length_limit_error(&e) => return Err(ProblemKind::PayloadTooLarge.into()),
+            Ok(Err(e)) => {
+                tracing::warn!(request_id = %request_id, url = %url, "Upstream request failed: {}", e);
+                problem = ProblemKind::UpstreamFailed;
+            }
+            Err(_) => {
+                tracing::warn!(request_id = %request_id, url = %url, "Upstream did not respond within {:?}", timeout);
+                problem = ProblemKind::UpstreamTimeout;
+            }
+        }
+    }
+    Err(problem.into())
+}
+
+/// The client's headers as sent upstream
+fn upstream_headers(settings: &ProxySettings, parts: &Parts, client_ip: Option<&str>, request_id: &str) -> HeaderMap {
+    let mut headers = parts.headers.clone();
+    remove_hop_by_hop(&mut headers);
+    let host = headers.remove(header::HOST);
+
+    headers.remove("x-forwarded-for");
+    if let Some(ip) = client_ip.and_then(|ip| HeaderValue::from_str(ip).ok()) {
+        headers.insert("x-forwarded-for", ip);
+    }
+    if let Some(host) = host {
+        headers.insert("x-forwarded-host", host);
+    }
+    if let Ok(id) = HeaderValue::from_str(request_id) {
+        headers.insert(REQUEST_ID_HEADER, id);
+    }
+
+    settings.request_headers.apply(&mut headers);
+    headers
+}
+
+/// Stream an upstream response back to the client
+///
+/// A pool `target` stays in flight until the body has been sent.
+fn into_response(settings: &ProxySettings, upstream: reqwest::Response, target: Option<SelectedTarget>) -> Response {
+    use futures::StreamExt;
+
+    let status = upstream.status();
+    let mut headers = upstream.headers().clone();
+    remove_hop_by_hop(&mut headers);
+    settings.response_headers.apply(&mut headers);
+
+    let body = upstream.bytes_stream().map(move |chunk| {
+        let _ = &target;
+        chunk
+    });
+    let mut response = Response::new(Body::from_stream(body));
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    response
+}
+
+/// Drop hop-by-hop headers, including any named in `Connection`
+fn remove_hop_by_hop(headers: &mut HeaderMap) {
+    let named: Vec<String> = headers.get_all(header::CONNECTION).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    for name in HOP_BY_HOP.iter().copied().chain(named.iter().map(String::as_str)) {
+        headers.remove(name);
+    }
+}
+
+/// Methods that can be sent twice without changing the outcome (RFC 9110)
+fn is_idempotent(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE | Method::TRACE)
+}
+
+/// Upstream statuses worth another attempt
+fn is_retryable(status: StatusCode) -> bool {
+    matches!(status, StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(upstream: &str) -> ProxySettings {
+        ProxySettings { upstream: upstream.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_upstream_url() {
+        let params = HashMap::from([("id".to_string(), "42".to_string())]);
+
+        let plain = settings("http://users.internal:8080/v1/");
+        assert_eq!(plain.upstream_url(&plain.upstream, "/users/42", Some("a=1"), &params), "http://users.internal:8080/v1/users/42?a=1");
+
+        let strip = ProxySettings { strip_prefix: Some("/api/".to_string()), ..settings("http://users.internal") };
+        assert_eq!(strip.upstream_url(&strip.upstream, "/api/users/42", None, &params), "http://users.internal/users/42");
+        assert_eq!(strip.upstream_url(&strip.upstream, "/api", None, &params), "http://users.internal/");
+        assert_eq!(strip.upstream_url(&strip.upstream, "/other", None, &params), "http://users.internal/other");
+
+        let rewrite = ProxySettings { rewrite: Some("/accounts/{id}/profile".to_string()), ..settings("http://users.internal") };
+        assert_eq!(rewrite.upstream_url(&rewrite.upstream, "/users/42", Some("x=y"), &params), "http://users.internal/accounts/42/profile?x=y");
+    }
+
+    #[test]
+    fn test_expand_template() {
+        let params = HashMap::from([
+            ("a".to_string(), "{b}".to_string()),
+            ("b".to_string(), "two".to_string()),
+        ]);
+
+        // Values are not expanded again, whatever order the parameters come in
+        assert_eq!(expand_template("/x/{a}/{b}", &params), "/x/{b}/two");
+        assert_eq!(expand_template("/{b}{a}", &params), "/two{b}");
+        assert_eq!(expand_template("/{missing}/{b}/{", &params), "/{missing}/two/{");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(settings("http://users.internal:8080").validate().is_ok());
+        assert!(settings("users.internal").validate().is_err());
+        assert!(settings("ftp://users.internal").validate().is_err());
+        assert!(settings("http://users.internal/?a=1").validate().is_err());
+
+        let both = ProxySettings {
+            strip_prefix: Some("/api".to_string()),
+            rewrite: Some("/x".to_string()),
+            ..settings("http://users.internal")
+        };
+        assert!(both.validate().is_err());
+
+        let retries = ProxySettings { retries: MAX_RETRIES + 1, ..settings("http://users.internal") };
+        assert!(retries.validate().is_err());
+
+        let mut headers = settings("http://users.internal");
+        headers.request_headers.add.insert("bad header".to_string(), "x".to_string());
+        assert!(headers.validate().is_err());
+
+        let pool = ProxySettings { pool: Some("users".to_string()), ..Default::default() };
+        assert!(pool.validate().is_ok());
+        assert!(ProxySettings { upstream: "http://users.internal".to_string(), ..pool }.validate().is_err());
+        assert!(ProxySettings::default().validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_forward_to_pool() {
+        use crate::router::upstream::UpstreamSettings;
+
+        // Two local backends that name themselves, one of them failing
+        async fn backend(name: &'static str, status: StatusCode) -> String {
+            let app = axum::Router::new().fallback(move || async move { (status, name) });
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, app).await });
+            url
+        }
+        let good = backend("good", StatusCode::OK).await;
+        let bad = backend("bad", StatusCode::SERVICE_UNAVAILABLE).await;
+
+        let pools = UpstreamPools::new();
+        pools.reload(&[crate::api::Upstream {
+            id: "1".to_string(),
+            name: "users".to_string(),
+            targets: vec![bad, good],
+            settings: UpstreamSettings::default(),
+            enabled: true,
+            created_at: None,
+            updated_at: None,
+        }]);
+        let settings = ProxySettings { pool: Some("users".to_string()), retries: 1, ..Default::default() };
+        let client = client().unwrap();
+        let (parts, _) = axum::http::Request::builder().uri("/users").body(()).unwrap().into_parts();
+        let params = HashMap::new();
+
+        // The first attempt hits the failing target, the retry the other one
+        for _ in 0..2 {
+            let response = forward(&client, &pools, &settings, ProxyRequest {
+                parts: &parts,
+                body: Body::empty(),
+                params: &params,
+                client_ip: None,
+                request_id: "req-1",
+                body_limit: 1024,
+                timeout: Duration::from_secs(5),
+            }).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+            assert_eq!(body, "good");
+        }
+
+        let stats = &pools.stats()[0];
+        assert_eq!(stats.targets.iter().map(|t| (t.requests, t.failures, t.active)).collect::<Vec<_>>(), [(2, 2, 0), (2, 0, 0)]);
+    }
+
+    #[test]
+    fn test_upstream_headers() {
+        let (parts, _) = axum::http::Request::builder()
+            .uri("/users")
+            .header("host", "api.example.com")
+            .header("connection", "keep-alive, x-hop")
+            .header("x-hop", "1")
+            .header("x-forwarded-for", "6.6.6.6")
+            .header("authorization", "Bearer secret")
+            .header("accept", "application/json")
+            .body(())
+            .unwrap()
+            .into_parts();
+
+        let mut settings = settings("http://users.internal");
+        settings.request_headers.remove.push("authorization".to_string());
+        settings.request_headers.add.insert("x-api-version".to_string(), "2".to_string());
+
+        let headers = upstream_headers(&settings, &parts, Some("198.51.100.7"), "req-1");
+        assert_eq!(headers.get("x-forwarded-for").unwrap(), "198.51.100.7");
+        assert_eq!(headers.get("x-forwarded-host").unwrap(), "api.example.com");
+        assert_eq!(headers.get("x-request-id").unwrap(), "req-1");
+        assert_eq!(headers.get("x-api-version").unwrap(), "2");
+        assert_eq!(headers.get("accept").unwrap(), "application/json");
+        for dropped in ["host", "connection", "x-hop", "authorization"] {
+            assert!(!headers.contains_key(dropped), "{} was forwarded", dropped);
+        }
+    }
+}
@@ -0,0 +1,386 @@
+//! Redirect and rewrite rules
+//!
+//! Each domain has an ordered list of rules that runs before endpoint
+//! matching, so common redirects do not need a compiled handler:
+//!
+//! - `https` redirects requests that did not arrive over HTTPS
+//! - `canonical_host` redirects requests for any other host of the domain,
+//!   such as a `www.` alias, to one host
+//! - `redirect` answers requests whose path matches a prefix or a regex with
+//!   a 301, 302, 307 or 308 to another path or URL
+//! - `rewrite` changes the path the request is routed with; the following
+//!   rules see the rewritten path
+//!
+//! Rules run top to bottom and the first redirect ends evaluation. Redirect
+//! targets keep the request's query string.
+//!
+//! A prefix matches whole segments: `/docs` matches `/docs` and `/docs/intro`
+//! but not `/docsearch`, and the rest of the path is appended to the target.
+//! A regex must match the whole path (it is anchored at both ends), and the
+//! target may refer to its groups as `$1` or `$name`.
+
+use axum::body::Body;
+use axum::http::{header, StatusCode};
+use axum::response::Response;
+use regex_lite::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::problem::ProblemKind;
+use crate::api::DomainRule;
+
+/// Statuses a redirect may use
+const REDIRECT_STATUSES: [u16; 4] = [301, 302, 307, 308];
+
+/// One redirect or rewrite rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// Redirect requests that did not arrive over HTTPS
+    Https {
+        #[serde(default = "default_status")]
+        status: u16,
+        /// Port in the redirect URL (default 443)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        port: Option<u16>,
+    },
+    /// Redirect requests for any other host to `host`
+    CanonicalHost {
+        host: String,
+        #[serde(default = "default_status")]
+        status: u16,
+    },
+    /// Redirect requests whose path matches to `to`, a path or URL
+    Redirect {
+        #[serde(flatten)]
+        path: PathMatch,
+        to: String,
+        #[serde(default = "default_status")]
+        status: u16,
+    },
+    /// Route requests whose path matches as if they were for `to`
+    Rewrite {
+        #[serde(flatten)]
+        path: PathMatch,
+        to: String,
+    },
+}
+
+fn default_status() -> u16 {
+    301
+}
+
+/// Path condition of a `redirect` or `rewrite` rule; exactly one is set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PathMatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+}
+
+impl Rule {
+    /// Check that the rule is usable
+    pub fn validate(&self) -> Result<(), String> {
+        let check_status = |status: &u16| {
+            if REDIRECT_STATUSES.contains(status) {
+                Ok(())
+            } else {
+                Err(format!("Invalid redirect status {}: use 301, 302, 307 or 308", status))
+            }
+        };
+        match self {
+            Rule::Https { status, port } => {
+                check_status(status)?;
+                if *port == Some(0) {
+                    return Err("Invalid HTTPS port 0".to_string());
+                }
+            }
+            Rule::CanonicalHost { host, status } => {
+                check_status(status)?;
+                let valid = super::table::normalize_host_pattern(host)
+                    .is_ok_and(|normalized| normalized == *host && !host.contains('*'));
+                if !valid {
+                    return Err(format!("Invalid canonical host '{}': use a lowercase host name", host));
+                }
+            }
+            Rule::Redirect { path, to, status } => {
+                check_status(status)?;
+                path.validate()?;
+                if !(to.starts_with('/') || to.starts_with("http://") || to.starts_with("https://")) {
+                    return Err(format!("Invalid redirect target '{}': use a path or an http(s) URL", to));
+                }
+            }
+            Rule::Rewrite { path, to } => {
+                path.validate()?;
+                if !to.starts_with('/') || to.contains(['?', '#']) {
+                    return Err(format!("Invalid rewrite target '{}': use a path without a query", to));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PathMatch {
+    fn validate(&self) -> Result<(), String> {
+        match (&self.prefix, &self.regex) {
+            (Some(prefix), None) if prefix.starts_with('/') => Ok(()),
+            (Some(prefix), None) => Err(format!("Invalid prefix '{}': must start with '/'", prefix)),
+            (None, Some(regex)) => Regex::new(regex).map(|_| ()).map_err(|e| format!("Invalid regex '{}': {}", regex, e)),
+            _ => Err("Set exactly one of 'prefix' and 'regex'".to_string()),
+        }
+    }
+}
+
+/// The parts of a request rules look at
+#[derive(Debug, Clone, Copy)]
+pub struct RuleRequest<'a> {
+    /// Whether the request arrived over HTTPS
+    pub secure: bool,
+    /// Normalized request host
+    pub host: &'a str,
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+}
+
+/// Result of running a domain's rules
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Outcome {
+    /// Answer with a redirect
+    Redirect { status: u16, location: String, rule_id: String },
+    /// Route the request with `path`, rewritten by the listed rules
+    Pass { path: String, rewritten_by: Vec<String> },
+}
+
+/// A rule with its regex compiled, anchored to match whole paths
+#[derive(Debug)]
+struct CompiledRule {
+    id: String,
+    rule: Rule,
+    regex: Option<Regex>,
+}
+
+impl CompiledRule {
+    /// Where a matching path goes, or None if the path does not match
+    fn target(&self, path_match: &PathMatch, path: &str, to: &str) -> Option<String> {
+        if let Some(regex) = &self.regex {
+            let captures = regex.captures(path)?;
+            let mut target = String::new();
+            captures.expand(to, &mut target);
+            return Some(target);
+        }
+        let rest = strip_path_prefix(path, path_match.prefix.as_deref()?)?;
+        Some(match (to.ends_with('/'), rest.starts_with('/')) {
+            (true, true) => format!("{}{}", to, &rest[1..]),
+            (false, false) if !rest.is_empty() => format!("{}/{}", to, rest),
+            _ => format!("{}{}", to, rest),
+        })
+    }
+}
+
+/// The rest of `path` after `prefix`, if the prefix covers whole segments
+fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+    (rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/')).then_some(rest)
+}
+
+/// Append the request's query string to a redirect target
+fn with_query(target: &str, query: Option<&str>) -> String {
+    match query.filter(|q| !q.is_empty()) {
+        Some(query) if target.contains('?') => format!("{}&{}", target, query),
+        Some(query) => format!("{}?{}", target, query),
+        None => target.to_string(),
+    }
+}
+
+/// A domain's enabled rules, ready to run
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    /// Compile rules in order; disabled and invalid rules are left out
+    pub fn new<'a>(rules: impl IntoIterator<Item = &'a DomainRule>) -> Self {
+        let rules = rules.into_iter()
+            .filter(|r| r.enabled)
+            .filter_map(|r| {
+                if let Err(e) = r.rule.validate() {
+                    tracing::warn!(rule = %r.id, "Skipping rule: {}", e);
+                    return None;
+                }
+                let regex = match &r.rule {
+                    Rule::Redirect { path, .. } | Rule::Rewrite { path, .. } => path.regex.as_deref().and_then(|re| Regex::new(&format!("^(?:{})$", re)).ok()),
+                    _ => None,
+                };
+                Some(CompiledRule { id: r.id.clone(), rule: r.rule.clone(), regex })
+            })
+            .collect();
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Run the rules against a request
+    pub fn apply(&self, request: &RuleRequest<'_>) -> Outcome {
+        let mut path = request.path.to_string();
+        let mut rewritten_by = Vec::new();
+
+        for compiled in &self.rules {
+            let redirect = |status: u16, location: String| Outcome::Redirect {
+                status,
+                location,
+                rule_id: compiled.id.clone(),
+            };
+            match &compiled.rule {
+                Rule::Https { status, port } if !request.secure => {
+                    let host = match port {
+                        Some(port) if *port != 443 => format!("{}:{}", request.host, port),
+                        _ => request.host.to_string(),
+                    };
+                    return redirect(*status, format!("https://{}{}", host, with_query(request.path, request.query)));
+                }
+                Rule::CanonicalHost { host, status } if host != request.host => {
+                    let scheme = if request.secure { "https" } else { "http" };
+                    return redirect(*status, format!("{}://{}{}", scheme, host, with_query(request.path, request.query)));
+                }
+                Rule::Redirect { path: path_match, to, status } => {
+                    if let Some(target) = compiled.target(path_match, &path, to) {
+                        return redirect(*status, with_query(&target, request.query));
+                    }
+                }
+                Rule::Rewrite { path: path_match, to } => {
+                    if let Some(target) = compiled.target(path_match, &path, to) {
+                        path = target;
+                        rewritten_by.push(compiled.id.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Outcome::Pass { path, rewritten_by }
+    }
+}
+
+/// Build the response for a redirect outcome
+pub fn redirect_response(status: u16, location: &str) -> Result<Response, ProblemKind> {
+    Response::builder()
+        .status(StatusCode::from_u16(status).map_err(|_| ProblemKind::InternalError)?)
+        .header(header::LOCATION, location)
+        .body(Body::empty())
+        .map_err(|_| ProblemKind::InternalError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: &str, json: &str) -> DomainRule {
+        DomainRule {
+            id: id.to_string(),
+            domain_id: "d".to_string(),
+            position: 0,
+            rule: serde_json::from_str(json).unwrap(),
+            enabled: true,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn request<'a>(secure: bool, host: &'a str, path: &'a str, query: Option<&'a str>) -> RuleRequest<'a> {
+        RuleRequest { secure, host, path, query }
+    }
+
+    fn location(outcome: Outcome) -> String {
+        match outcome {
+            Outcome::Redirect { location, .. } => location,
+            other => panic!("expected a redirect, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_https_and_canonical_host() {
+        let rules = [
+            rule("https", r#"{"https": {"status": 308}}"#),
+            rule("apex", r#"{"canonical_host": {"host": "example.com"}}"#),
+        ];
+        let set = RuleSet::new(&rules);
+
+        let outcome = set.apply(&request(false, "www.example.com", "/a", Some("x=1")));
+        assert_eq!(outcome, Outcome::Redirect {
+            status: 308,
+            location: "https://www.example.com/a?x=1".to_string(),
+            rule_id: "https".to_string(),
+        });
+
+        let outcome = set.apply(&request(true, "www.example.com", "/a", None));
+        assert_eq!(location(outcome), "https://example.com/a");
+
+        let outcome = set.apply(&request(true, "example.com", "/a", None));
+        assert!(matches!(outcome, Outcome::Pass { .. }));
+
+        let port = RuleSet::new(&[rule("https", r#"{"https": {"port": 8443}}"#)]);
+        assert_eq!(location(port.apply(&request(false, "example.com", "/", None))), "https://example.com:8443/");
+    }
+
+    #[test]
+    fn test_prefix_and_regex_redirects() {
+        let rules = [
+            rule("docs", r#"{"redirect": {"prefix": "/docs", "to": "https://docs.example.com", "status": 302}}"#),
+            rule("posts", r#"{"redirect": {"regex": "^/blog/(?P<id>\\d+)$", "to": "/posts/$id"}}"#),
+            rule("tags", r#"{"redirect": {"regex": "/tag/(\\w+)", "to": "/tags/$1"}}"#),
+        ];
+        let set = RuleSet::new(&rules);
+
+        assert_eq!(location(set.apply(&request(false, "example.com", "/docs/intro", None))), "https://docs.example.com/intro");
+        assert_eq!(location(set.apply(&request(false, "example.com", "/docs", Some("q=1")))), "https://docs.example.com?q=1");
+        assert!(matches!(set.apply(&request(false, "example.com", "/docsearch", None)), Outcome::Pass { .. }));
+        assert_eq!(location(set.apply(&request(false, "example.com", "/blog/42", None))), "/posts/42");
+        assert!(matches!(set.apply(&request(false, "example.com", "/blog/latest", None)), Outcome::Pass { .. }));
+
+        // A regex without anchors still has to match the whole path
+        assert_eq!(location(set.apply(&request(false, "example.com", "/tag/rust", None))), "/tags/rust");
+        assert!(matches!(set.apply(&request(false, "example.com", "/old/tag/rust", None)), Outcome::Pass { .. }));
+        assert!(matches!(set.apply(&request(false, "example.com", "/tag/rust/feed", None)), Outcome::Pass { .. }));
+    }
+
+    #[test]
+    fn test_rewrites_chain_into_later_rules() {
+        let rules = [
+            rule("v1", r#"{"rewrite": {"prefix": "/api/v1/", "to": "/"}}"#),
+            rule("old", r#"{"redirect": {"prefix": "/legacy", "to": "/modern", "status": 307}}"#),
+        ];
+        let set = RuleSet::new(&rules);
+
+        assert_eq!(set.apply(&request(false, "example.com", "/api/v1/users/7", None)), Outcome::Pass {
+            path: "/users/7".to_string(),
+            rewritten_by: vec!["v1".to_string()],
+        });
+        assert_eq!(location(set.apply(&request(false, "example.com", "/api/v1/legacy/x", Some("a=b")))), "/modern/x?a=b");
+    }
+
+    #[test]
+    fn test_disabled_and_invalid_rules_are_skipped() {
+        let mut disabled = rule("off", r#"{"https": {}}"#);
+        disabled.enabled = false;
+        let invalid = rule("bad", r#"{"redirect": {"prefix": "/a", "to": "/b", "status": 200}}"#);
+        assert!(RuleSet::new(&[disabled, invalid]).is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let parse = |json: &str| serde_json::from_str::<Rule>(json).unwrap();
+        assert!(parse(r#"{"redirect": {"prefix": "/a", "to": "/b"}}"#).validate().is_ok());
+        assert!(parse(r#"{"redirect": {"prefix": "a", "to": "/b"}}"#).validate().is_err());
+        assert!(parse(r#"{"redirect": {"prefix": "/a", "regex": "^/a", "to": "/b"}}"#).validate().is_err());
+        assert!(parse(r#"{"redirect": {"regex": "(", "to": "/b"}}"#).validate().is_err());
+        assert!(parse(r#"{"redirect": {"prefix": "/a", "to": "b"}}"#).validate().is_err());
+        assert!(parse(r#"{"rewrite": {"prefix": "/a", "to": "/b?c=d"}}"#).validate().is_err());
+        assert!(parse(r#"{"canonical_host": {"host": "example.com", "status": 302}}"#).validate().is_ok());
+        assert!(parse(r#"{"canonical_host": {"host": "*.example.com"}}"#).validate().is_err());
+        assert!(parse(r#"{"https": {"port": 0}}"#).validate().is_err());
+    }
+}
@@ -0,0 +1,647 @@
+//! Static site hosting
+//!
+//! A domain with [`SiteSettings`] serves files for GET and HEAD requests
+//! that match none of its endpoints, so a frontend can live on the same
+//! host as its API. Files come from a directory under
+//! `RUST_EDGE_GATEWAY_SITES_DIR` or from a key prefix in the bucket of the
+//! active MinIO service.
+//!
+//! - `/` and paths ending in `/` serve that directory's index file; a
+//!   directory requested without the slash is redirected to it
+//! - with `spa` set, a path that matches no file and has no extension
+//!   serves the root index, so client-side routes work on reload
+//! - responses carry `ETag` and `Last-Modified`, and conditional requests
+//!   get `304 Not Modified`
+//! - a single byte range is answered with `206 Partial Content`
+//! - hashed asset names such as `app.3f9c1a2b.js` are cached for a year as
+//!   immutable; everything else is revalidated (`no-cache`)
+
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::body::Body;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::response::Response;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use super::problem::ProblemKind;
+use crate::runtime::services::MinioHandle;
+use crate::AppState;
+
+/// Files up to this size are read whole, so the compression layer can
+/// compress them; larger ones are streamed
+const INLINE_LIMIT: u64 = 1024 * 1024;
+
+/// Read size when streaming a file
+const CHUNK_SIZE: usize = 64 * 1024;
+
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+const REVALIDATE: &str = "no-cache";
+
+/// Per-domain static site
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SiteSettings {
+    /// Where the files are stored
+    pub source: SiteSource,
+
+    /// File served for directory paths (default `index.html`)
+    #[serde(default = "default_index")]
+    pub index: String,
+
+    /// Serve the root index for extensionless paths that match no file
+    #[serde(default)]
+    pub spa: bool,
+}
+
+fn default_index() -> String {
+    "index.html".to_string()
+}
+
+/// Storage backing a site
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SiteSource {
+    /// Directory relative to the sites directory
+    Dir(String),
+    /// Key prefix in the bucket of the active MinIO service
+    Minio(String),
+}
+
+impl SiteSettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.source {
+            SiteSource::Dir(dir) if dir.trim_end_matches('/').is_empty() || !is_relative_path(dir.trim_end_matches('/')) => {
+                return Err(format!("Invalid site directory: '{}'", dir));
+            }
+            SiteSource::Minio(prefix) if !prefix.trim_end_matches('/').is_empty() && !is_relative_path(prefix.trim_end_matches('/')) => {
+                return Err(format!("Invalid site prefix: '{}'", prefix));
+            }
+            _ => {}
+        }
+        if !is_segment(&self.index) {
+            return Err(format!("Invalid site index file: '{}'", self.index));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `name` is a single usable path segment
+fn is_segment(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', '\0'])
+}
+
+/// Whether `path` is a relative path of usable segments, such as `shop/v2`
+pub fn is_relative_path(path: &str) -> bool {
+    path.split('/').all(is_segment)
+}
+
+/// Serve a file of the domain's site
+///
+/// Returns `NotFound` when no file matches, and `InternalError` when the
+/// storage cannot be read.
+pub async fn serve(state: &AppState, settings: &SiteSettings, request: &Parts) -> Result<Response, ProblemKind> {
+    let store = match &settings.source {
+        SiteSource::Dir(dir) => Store::Dir(state.config.sites_dir.join(dir)),
+        SiteSource::Minio(prefix) => {
+            let Some(handle) = state.runtime_services.read().await.minio.clone() else {
+                tracing::warn!("Site is stored in MinIO but no MinIO service is active");
+                return Err(ProblemKind::InternalError);
+            };
+            let prefix = prefix.trim_end_matches('/');
+            Store::Minio {
+                handle,
+                prefix: if prefix.is_empty() { String::new() } else { format!("{}/", prefix) },
+            }
+        }
+    };
+    serve_from(&store, settings, request).await
+}
+
+/// Install a site's files as `<sites_dir>/<name>`, replacing the previous ones
+///
+/// The files are written to a new directory that is then swapped in, so
+/// requests never see a half-written site.
+pub fn install(sites_dir: &Path, name: &str, files: &[(String, Vec<u8>)]) -> std::io::Result<()> {
+    use std::io::{Error, ErrorKind};
+
+    if !is_segment(name) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("invalid site name '{}'", name)));
+    }
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    let staging = sites_dir.join(format!(".{}.new-{}", name, suffix));
+    let target = sites_dir.join(name);
+    let previous = sites_dir.join(format!(".{}.old-{}", name, suffix));
+
+    let write = || -> std::io::Result<()> {
+        for (path, content) in files {
+            if !is_relative_path(path) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("invalid site file path '{}'", path)));
+            }
+            let file = staging.join(path);
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(file, content)?;
+        }
+        Ok(())
+    };
+    std::fs::create_dir_all(&staging)?;
+    if let Err(e) = write() {
+        let _ = std::fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    let replaced = target.exists();
+    if replaced {
+        std::fs::rename(&target, &previous)?;
+    }
+    std::fs::rename(&staging, &target)?;
+    if replaced {
+        std::fs::remove_dir_all(&previous)?;
+    }
+    Ok(())
+}
+
+/// What a stored file looks like
+#[derive(Debug, Clone)]
+struct FileMeta {
+    len: u64,
+    modified: Option<SystemTime>,
+    etag: Option<String>,
+    content_type: Option<String>,
+}
+
+/// Where a site's files are read from
+enum Store {
+    Dir(PathBuf),
+    Minio { handle: MinioHandle, prefix: String },
+}
+
+impl Store {
+    /// Metadata of the file at `key`, or None if there is no such file
+    async fn stat(&self, key: &str) -> Result<Option<FileMeta>, ProblemKind> {
+        match self {
+            Store::Dir(root) => {
+                let metadata = match tokio::fs::metadata(root.join(key)).await {
+                    Ok(metadata) => metadata,
+                    Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory) => return Ok(None),
+                    Err(e) => {
+                        tracing::error!("Failed to read site file {}: {}", root.join(key).display(), e);
+                        return Err(ProblemKind::InternalError);
+                    }
+                };
+                if !metadata.is_file() {
+                    return Ok(None);
+                }
+                let modified = metadata.modified().ok();
+                let version = modified
+                    .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_millis());
+                Ok(Some(FileMeta {
+                    len: metadata.len(),
+                    modified,
+                    etag: Some(format!("\"{:x}-{:x}\"", version, metadata.len())),
+                    content_type: None,
+                }))
+            }
+            Store::Minio { handle, prefix } => {
+                let info = handle.head_object(&handle.default_bucket, &format!("{}{}", prefix, key)).await
+                    .map_err(|e| {
+                        tracing::error!("Failed to read site object {}{}: {}", prefix, key, e);
+                        ProblemKind::InternalError
+                    })?;
+                Ok(info.map(|info| FileMeta {
+                    len: info.size,
+                    modified: httpdate::parse_http_date(&info.last_modified).ok(),
+                    etag: info.etag.map(|etag| {
+                        if etag.starts_with('"') { etag } else { format!("\"{}\"", etag) }
+                    }),
+                    content_type: info.content_type,
+                }))
+            }
+        }
+    }
+
+    /// Body with bytes `start..=end` of the file at `key`, which is `len` bytes long
+    async fn read(&self, key: &str, start: u64, end: u64, len: u64) -> Result<Body, ProblemKind> {
+        match self {
+            Store::Dir(root) => {
+                let path = root.join(key);
+                let failed = |e: std::io::Error| {
+                    tracing::error!("Failed to read site file {}: {}", path.display(), e);
+                    ProblemKind::InternalError
+                };
+                let mut file = tokio::fs::File::open(&path).await.map_err(failed)?;
+                if start > 0 {
+                    file.seek(SeekFrom::Start(start)).await.map_err(failed)?;
+                }
+                let count = end + 1 - start;
+                if count <= INLINE_LIMIT {
+                    let mut buffer = vec![0; count as usize];
+                    file.read_exact(&mut buffer).await.map_err(failed)?;
+                    return Ok(Body::from(buffer));
+                }
+                let chunks = futures::stream::unfold((file, count), |(mut file, remaining)| async move {
+                    if remaining == 0 {
+                        return None;
+                    }
+                    let mut buffer = vec![0; remaining.min(CHUNK_SIZE as u64) as usize];
+                    match file.read(&mut buffer).await {
+                        Ok(0) => None,
+                        Ok(n) => {
+                            buffer.truncate(n);
+                            Some((Ok(Bytes::from(buffer)), (file, remaining - n as u64)))
+                        }
+                        Err(e) => Some((Err(e), (file, 0))),
+                    }
+                });
+                Ok(Body::from_stream(chunks))
+            }
+            Store::Minio { handle, prefix } => {
+                let key = format!("{}{}", prefix, key);
+                let result = if start == 0 && end + 1 == len {
+                    handle.get_object(&handle.default_bucket, &key).await
+                } else {
+                    handle.get_object_range(&handle.default_bucket, &key, start, Some(end)).await
+                };
+                result.map(Body::from).map_err(|e| {
+                    tracing::error!("Failed to read site object {}: {}", key, e);
+                    ProblemKind::InternalError
+                })
+            }
+        }
+    }
+}
+
+/// How a request path maps onto the site
+enum Resolved {
+    File(String, FileMeta),
+    /// A directory requested without its trailing slash
+    Directory,
+    Missing,
+}
+
+/// Decode a request path into segments; the last one is empty for a
+/// directory path. None if a segment could escape the site.
+fn request_segments(path: &str) -> Option<Vec<String>> {
+    let segments: Vec<&str> = path.strip_prefix('/').unwrap_or(path).split('/').collect();
+    let last = segments.len() - 1;
+    segments.iter().enumerate().map(|(i, segment)| {
+        let decoded = percent_encoding::percent_decode_str(segment).decode_utf8().ok()?;
+        (is_segment(&decoded) || (i == last && decoded.is_empty())).then(|| decoded.into_owned())
+    }).collect()
+}
+
+async fn resolve(store: &Store, settings: &SiteSettings, path: &str) -> Result<Resolved, ProblemKind> {
+    let Some(mut segments) = request_segments(path) else {
+        return Ok(Resolved::Missing);
+    };
+    let requested = segments.pop().unwrap_or_default();
+    let directory = requested.is_empty();
+    segments.push(if directory { settings.index.clone() } else { requested.clone() });
+
+    let key = segments.join("/");
+    if let Some(meta) = store.stat(&key).await? {
+        return Ok(Resolved::File(key, meta));
+    }
+    if !directory && store.stat(&format!("{}/{}", key, settings.index)).await?.is_some() {
+        return Ok(Resolved::Directory);
+    }
+    if settings.spa && !requested.contains('.') {
+        if let Some(meta) = store.stat(&settings.index).await? {
+            return Ok(Resolved::File(settings.index.clone(), meta));
+        }
+    }
+    Ok(Resolved::Missing)
+}
+
+async fn serve_from(store: &Store, settings: &SiteSettings, request: &Parts) -> Result<Response, ProblemKind> {
+    let (key, meta) = match resolve(store, settings, request.uri.path()).await? {
+        Resolved::File(key, meta) => (key, meta),
+        Resolved::Directory => {
+            let location = match request.uri.query() {
+                Some(query) => format!("{}/?{}", request.uri.path(), query),
+                None => format!("{}/", request.uri.path()),
+            };
+            return Response::builder()
+                .status(StatusCode::MOVED_PERMANENTLY)
+                .header(header::LOCATION, location)
+                .body(Body::empty())
+                .map_err(|_| ProblemKind::InternalError);
+        }
+        Resolved::Missing => return Err(ProblemKind::NotFound),
+    };
+
+    let headers = &request.headers;
+    let mut builder = Response::builder()
+        .header(header::CACHE_CONTROL, if is_hashed(&key) { IMMUTABLE } else { REVALIDATE })
+        .header(header::ACCEPT_RANGES, "bytes");
+    if let Some(etag) = &meta.etag {
+        builder = builder.header(header::ETAG, etag);
+    }
+    if let Some(modified) = meta.modified {
+        builder = builder.header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified));
+    }
+
+    if not_modified(headers, &meta) {
+        return builder.status(StatusCode::NOT_MODIFIED).body(Body::empty()).map_err(|_| ProblemKind::InternalError);
+    }
+
+    builder = builder.header(header::CONTENT_TYPE, content_type(&key, meta.content_type.as_deref()));
+    let range = headers.get(header::RANGE)
+        .filter(|_| if_range_matches(headers, &meta))
+        .and_then(|value| parse_range(value, meta.len));
+    let (start, end) = match range {
+        None if meta.len == 0 => {
+            return builder.header(header::CONTENT_LENGTH, 0).body(Body::empty()).map_err(|_| ProblemKind::InternalError);
+        }
+        None => (0, meta.len - 1),
+        Some(Ok((start, end))) => {
+            builder = builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, meta.len));
+            (start, end)
+        }
+        Some(Err(())) => {
+            return builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", meta.len))
+                .body(Body::empty())
+                .map_err(|_| ProblemKind::InternalError);
+        }
+    };
+
+    let body = if request.method == Method::HEAD {
+        Body::empty()
+    } else {
+        store.read(&key, start, end, meta.len).await?
+    };
+    builder
+        .header(header::CONTENT_LENGTH, end + 1 - start)
+        .body(body)
+        .map_err(|_| ProblemKind::InternalError)
+}
+
+/// Whether the client's cached copy is still current
+fn not_modified(headers: &HeaderMap, meta: &FileMeta) -> bool {
+    if let Some(tags) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        let Some(etag) = &meta.etag else {
+            return false;
+        };
+        return tags.split(',').map(str::trim).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/"));
+    }
+    let since = headers.get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok());
+    match (since, meta.modified) {
+        (Some(since), Some(modified)) => unix_secs(modified) <= unix_secs(since),
+        _ => false,
+    }
+}
+
+/// Whether a `Range` request should be honoured: without `If-Range`, or
+/// when its validator still matches
+fn if_range_matches(headers: &HeaderMap, meta: &FileMeta) -> bool {
+    let Some(value) = headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) else {
+        return true;
+    };
+    if value.starts_with('"') {
+        return meta.etag.as_deref() == Some(value);
+    }
+    match (httpdate::parse_http_date(value), meta.modified) {
+        (Ok(date), Some(modified)) => unix_secs(date) == unix_secs(modified),
+        _ => false,
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Parse a single `bytes=` range into inclusive offsets
+///
+/// None means the header is ignored and the whole file is sent (it is
+/// malformed or asks for several ranges); `Err` means the range lies
+/// outside the file.
+fn parse_range(value: &HeaderValue, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = value.to_str().ok()?.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        let suffix: u64 = last.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(Err(()));
+        }
+        return Some(Ok((len.saturating_sub(suffix), len - 1)));
+    }
+    let start: u64 = first.parse().ok()?;
+    let end = match last {
+        "" => None,
+        last => Some(last.parse::<u64>().ok()?),
+    };
+    if end.is_some_and(|end| end < start) {
+        return None;
+    }
+    if start >= len {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end.map_or(len - 1, |end| end.min(len - 1)))))
+}
+
+/// Whether a file name carries a content hash, as in `app.3f9c1a2b.js` or
+/// `index-B2x9aZ3k.css`
+///
+/// The hash must follow the first part of the name and precede the
+/// extension, and be 8 or more letters and digits including a digit.
+fn is_hashed(key: &str) -> bool {
+    let name = key.rsplit('/').next().unwrap_or(key);
+    let Some((stem, _extension)) = name.rsplit_once('.') else {
+        return false;
+    };
+    stem.split(['.', '-']).skip(1).any(|part| {
+        part.len() >= 8
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && part.chars().any(|c| c.is_ascii_digit())
+    })
+}
+
+/// Content type from the file extension, falling back to the stored type
+fn content_type(key: &str, stored: Option<&str>) -> String {
+    match mime_guess::from_path(key).first() {
+        Some(mime) if mime.type_() == mime_guess::mime::TEXT || mime.subtype() == mime_guess::mime::JAVASCRIPT => {
+            format!("{}; charset=utf-8", mime.essence_str())
+        }
+        Some(mime) => mime.essence_str().to_string(),
+        None => stored.unwrap_or("application/octet-stream").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    fn site(spa: bool) -> (tempfile::TempDir, Store, SiteSettings) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("docs")).unwrap();
+        std::fs::create_dir_all(dir.path().join("assets")).unwrap();
+        std::fs::write(dir.path().join("index.html"), "<h1>home</h1>").unwrap();
+        std::fs::write(dir.path().join("docs/index.html"), "<h1>docs</h1>").unwrap();
+        std::fs::write(dir.path().join("assets/app.3f9c1a2b.js"), "console.log(1)").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "0123456789").unwrap();
+        let store = Store::Dir(dir.path().to_path_buf());
+        let settings = SiteSettings { source: SiteSource::Dir("site".to_string()), index: default_index(), spa };
+        (dir, store, settings)
+    }
+
+    fn get(path: &str, headers: &[(&str, &str)]) -> Parts {
+        let mut builder = Request::builder().uri(path);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(()).unwrap().into_parts().0
+    }
+
+    async fn body(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_index_and_directories() {
+        let (_dir, store, settings) = site(false);
+
+        let response = serve_from(&store, &settings, &get("/", &[])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert_eq!(body(response).await, "<h1>home</h1>");
+
+        let response = serve_from(&store, &settings, &get("/docs/", &[])).await.unwrap();
+        assert_eq!(body(response).await, "<h1>docs</h1>");
+
+        let response = serve_from(&store, &settings, &get("/docs?v=1", &[])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[header::LOCATION], "/docs/?v=1");
+
+        let missing = serve_from(&store, &settings, &get("/pricing", &[])).await;
+        assert_eq!(missing.unwrap_err(), ProblemKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_spa_fallback() {
+        let (_dir, store, settings) = site(true);
+
+        let response = serve_from(&store, &settings, &get("/account/settings", &[])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], REVALIDATE);
+        assert_eq!(body(response).await, "<h1>home</h1>");
+
+        // Missing assets are still errors
+        let missing = serve_from(&store, &settings, &get("/assets/missing.js", &[])).await;
+        assert_eq!(missing.unwrap_err(), ProblemKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_traversal_is_rejected() {
+        let (_dir, store, settings) = site(true);
+        for path in ["/../secret", "/docs/%2e%2e/index.html", "/docs%2Findex.html", "/a//index.html"] {
+            let result = serve_from(&store, &settings, &get(path, &[])).await;
+            assert_eq!(result.unwrap_err(), ProblemKind::NotFound, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests() {
+        let (_dir, store, settings) = site(false);
+
+        let response = serve_from(&store, &settings, &get("/notes.txt", &[])).await.unwrap();
+        let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+        let modified = response.headers()[header::LAST_MODIFIED].to_str().unwrap().to_string();
+
+        let response = serve_from(&store, &settings, &get("/notes.txt", &[("if-none-match", &etag)])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(body(response).await, "");
+
+        let response = serve_from(&store, &settings, &get("/notes.txt", &[("if-modified-since", &modified)])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let response = serve_from(&store, &settings, &get("/notes.txt", &[("if-none-match", "\"other\"")])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_ranges() {
+        let (_dir, store, settings) = site(false);
+
+        let response = serve_from(&store, &settings, &get("/notes.txt", &[("range", "bytes=2-4")])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 2-4/10");
+        assert_eq!(body(response).await, "234");
+
+        let response = serve_from(&store, &settings, &get("/notes.txt", &[("range", "bytes=-3")])).await.unwrap();
+        assert_eq!(body(response).await, "789");
+
+        let response = serve_from(&store, &settings, &get("/notes.txt", &[("range", "bytes=20-")])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */10");
+
+        // A stale If-Range gets the whole file
+        let response = serve_from(&store, &settings, &get("/notes.txt", &[("range", "bytes=2-4"), ("if-range", "\"old\"")])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await, "0123456789");
+    }
+
+    #[tokio::test]
+    async fn test_hashed_assets_are_immutable() {
+        let (_dir, store, settings) = site(false);
+
+        let response = serve_from(&store, &settings, &get("/assets/app.3f9c1a2b.js", &[])).await.unwrap();
+        assert_eq!(response.headers()[header::CACHE_CONTROL], IMMUTABLE);
+
+        assert!(is_hashed("assets/index-B2x9aZ3k.css"));
+        assert!(!is_hashed("report2024q1.pdf"));
+        assert!(!is_hashed("app.js"));
+    }
+
+    #[tokio::test]
+    async fn test_head_has_no_body() {
+        let (_dir, store, settings) = site(false);
+        let (request, _) = Request::builder().method(Method::HEAD).uri("/notes.txt").body(()).unwrap().into_parts();
+        let response = serve_from(&store, &settings, &request).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "10");
+        assert_eq!(body(response).await, "");
+    }
+
+    #[test]
+    fn test_install_replaces_site() {
+        let dir = tempfile::tempdir().unwrap();
+        install(dir.path(), "shop", &[("index.html".to_string(), b"v1".to_vec()), ("old.js".to_string(), Vec::new())]).unwrap();
+        install(dir.path(), "shop", &[("index.html".to_string(), b"v2".to_vec())]).unwrap();
+        assert_eq!(std::fs::read(dir.path().join("shop/index.html")).unwrap(), b"v2");
+        assert!(!dir.path().join("shop/old.js").exists());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        assert!(install(dir.path(), "shop", &[("../escape".to_string(), Vec::new())]).is_err());
+        assert!(install(dir.path(), "..", &[]).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let settings = |source| SiteSettings { source, index: default_index(), spa: false };
+        assert!(settings(SiteSource::Dir("shop/v2".to_string())).validate().is_ok());
+        assert!(settings(SiteSource::Dir("../shop".to_string())).validate().is_err());
+        assert!(settings(SiteSource::Dir(String::new())).validate().is_err());
+        assert!(settings(SiteSource::Minio(String::new())).validate().is_ok());
+        assert!(settings(SiteSource::Minio("sites/shop/".to_string())).validate().is_ok());
+        assert!(settings(SiteSource::Minio("/sites".to_string())).validate().is_err());
+        assert!(SiteSettings { index: "a/b.html".to_string(), ..settings(SiteSource::Minio(String::new())) }.validate().is_err());
+    }
+}
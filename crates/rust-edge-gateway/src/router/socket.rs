@@ -0,0 +1,325 @@
+//! WebSocket endpoints
+//!
+//! The gateway performs the upgrade and owns the connection. Handler code is
+//! only called through its `socket_entry` export, once per event (connect,
+//! each message, close). Callbacks for a connection run one at a time on the
+//! blocking pool, each bounded by the endpoint's handler timeout.
+//!
+//! Frames sent through the SDK [`WebSocket`] handle are queued to a writer
+//! task, so handlers can push from any thread at any time. The queue is
+//! bounded: a client that does not read fast enough to keep it from filling
+//! up is closed with code 1013 (try again later) and further sends fail.
+//!
+//! Each connection holds a [`RequestGuard`] for its whole lifetime, so it
+//! counts as an active request during a graceful swap. When the handler
+//! starts draining the client is sent close code 1012 (service restart) and
+//! can reconnect to the new version. During a canary release a connection
+//! stays on the version picked when it was opened.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{self, WebSocketUpgrade},
+        FromRequestParts,
+    },
+    http::{header, request::Parts},
+    response::Response,
+};
+use futures::{SinkExt, StreamExt};
+use rust_edge_gateway_sdk::socket::{CloseFrame, Message, SocketClosed, SocketEvent, SocketSink, WebSocket};
+use rust_edge_gateway_sdk::Context as SdkContext;
+use tokio::sync::{mpsc, Notify};
+
+use super::problem::{Problem, ProblemKind};
+use crate::api::Endpoint;
+use crate::runtime::canary::SplitRequest;
+use crate::runtime::handler::{ExecuteError, RequestGuard, SocketFn};
+use crate::AppState;
+
+/// How often an open connection checks whether its handler is draining
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Close code sent when the handler is being replaced
+const CLOSE_SERVICE_RESTART: u16 = 1012;
+
+/// Close code sent when a callback fails or times out
+const CLOSE_INTERNAL_ERROR: u16 = 1011;
+
+/// Close code sent when the client does not keep up with outgoing frames
+const CLOSE_TRY_AGAIN_LATER: u16 = 1013;
+
+/// Outgoing frames queued for a connection before it counts as too slow
+const SEND_QUEUE_CAPACITY: usize = 256;
+
+/// How long a closed connection's writer may take to flush its last frames
+const WRITER_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upgrade a request for a WebSocket endpoint
+///
+/// `request` is the SDK request passed to the handler's connect callback.
+pub async fn upgrade(
+    state: &Arc<AppState>,
+    endpoint: &Endpoint,
+    parts: &mut Parts,
+    request: rust_edge_gateway_sdk::Request,
+) -> Result<Response, Problem> {
+    let wants_websocket = parts.headers.get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+    if !wants_websocket {
+        return Err(ProblemKind::UpgradeRequired.into());
+    }
+
+    let upgrade = match WebSocketUpgrade::from_request_parts(parts, state).await {
+        Ok(upgrade) => upgrade,
+        Err(rejection) => {
+            tracing::debug!(request_id = %request.request_id, "WebSocket upgrade rejected: {}", rejection);
+            return Err(Problem::from(ProblemKind::InvalidBody)
+                .with_detail("The WebSocket upgrade request is invalid."));
+        }
+    };
+
+    let split = SplitRequest {
+        headers: &parts.headers,
+        client_ip: request.client_ip.as_deref(),
+        request_id: &request.request_id,
+    };
+    let (entry, guard) = match state.handler_registry.acquire_socket(&endpoint.id, &split).await {
+        Ok(socket) => socket,
+        Err(ExecuteError::Draining) => return Err(ProblemKind::HandlerDraining.into()),
+        Err(e) => {
+            tracing::error!(request_id = %request.request_id, "WebSocket handler unavailable: {}", e);
+            return Err(ProblemKind::EndpointUnavailable.into());
+        }
+    };
+
+    let connection = Connection {
+        id: request.request_id.clone(),
+        entry,
+        ctx: state.create_sdk_context(&request.request_id).await,
+        timeout: Duration::from_secs(
+            endpoint.settings.timeout_secs.unwrap_or(state.config.handler_timeout_secs),
+        ),
+        guard,
+    };
+
+    Ok(upgrade.on_upgrade(move |socket| connection.run(socket, request)))
+}
+
+/// An upgraded connection and the handler serving it
+struct Connection {
+    id: String,
+    entry: SocketFn,
+    ctx: SdkContext,
+    timeout: Duration,
+    guard: RequestGuard,
+}
+
+impl Connection {
+    async fn run(self, socket: ws::WebSocket, request: rust_edge_gateway_sdk::Request) {
+        let (mut outgoing, mut incoming) = socket.split();
+        let (sink, mut rx) = ConnectionSink::new(SEND_QUEUE_CAPACITY);
+        let sink = Arc::new(sink);
+        let handle = WebSocket::new(self.id.clone(), sink.clone());
+
+        let overflow = sink.clone();
+        let mut writer = tokio::spawn(async move {
+            loop {
+                let message = tokio::select! {
+                    biased;
+                    // Frames still queued are dropped; the client is too slow for them
+                    _ = overflow.overflowed.notified() => ws::Message::Close(Some(ws::CloseFrame {
+                        code: CLOSE_TRY_AGAIN_LATER,
+                        reason: "Send queue full".into(),
+                    })),
+                    message = rx.recv() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
+                };
+                let closing = matches!(message, ws::Message::Close(_));
+                if outgoing.send(message).await.is_err() || closing {
+                    break;
+                }
+            }
+        });
+
+        tracing::debug!(connection = %self.id, "WebSocket connected");
+        let mut client_close = None;
+        let mut healthy = self.call(&handle, SocketEvent::Connect(Box::new(request))).await;
+        let mut drain_check = tokio::time::interval(DRAIN_CHECK_INTERVAL);
+
+        while healthy && !handle.is_closed() {
+            tokio::select! {
+                frame = incoming.next() => {
+                    let message = match frame {
+                        Some(Ok(ws::Message::Text(text))) => Message::Text(text.to_string()),
+                        Some(Ok(ws::Message::Binary(data))) => Message::Binary(data),
+                        Some(Ok(ws::Message::Close(frame))) => {
+                            client_close = frame.map(|f| CloseFrame { code: f.code, reason: f.reason.to_string() });
+                            break;
+                        }
+                        // Pings are answered by the server
+                        Some(Ok(_)) => continue,
+                        Some(Err(_)) | None => break,
+                    };
+                    healthy = self.call(&handle, SocketEvent::Message(message)).await;
+                }
+                _ = drain_check.tick() => {
+                    if self.guard.is_draining() {
+                        handle.close(CLOSE_SERVICE_RESTART, "Service restarting");
+                    }
+                }
+            }
+        }
+
+        // A timed-out callback may still be running; don't overlap it
+        if healthy {
+            self.call(&handle, SocketEvent::Close(client_close)).await;
+        }
+
+        sink.shutdown();
+        // A client that stopped reading could hold the writer forever
+        if tokio::time::timeout(WRITER_CLOSE_TIMEOUT, &mut writer).await.is_err() {
+            writer.abort();
+        }
+        tracing::debug!(connection = %self.id, "WebSocket closed");
+    }
+
+    /// Run one callback, returning false if it panicked or timed out
+    async fn call(&self, socket: &WebSocket, event: SocketEvent) -> bool {
+        let entry = self.entry;
+        let ctx = self.ctx.clone();
+        let handle = socket.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            // Safety: the guard keeps the library loaded for the connection's lifetime
+            unsafe { entry(&ctx, &handle, event) }
+        });
+
+        match tokio::time::timeout(self.timeout, task).await {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => {
+                tracing::error!(connection = %self.id, "WebSocket callback panicked: {}", e);
+                socket.close(CLOSE_INTERNAL_ERROR, "Internal error");
+                false
+            }
+            Err(_) => {
+                tracing::warn!(connection = %self.id, timeout = ?self.timeout, "WebSocket callback timed out");
+                socket.close(CLOSE_INTERNAL_ERROR, "Internal error");
+                false
+            }
+        }
+    }
+}
+
+/// Queues frames from the SDK handle to the connection's writer task
+struct ConnectionSink {
+    tx: Mutex<Option<mpsc::Sender<ws::Message>>>,
+    closed: AtomicBool,
+
+    /// Tells the writer to close the connection because the queue filled up
+    overflowed: Notify,
+}
+
+impl ConnectionSink {
+    /// A sink queuing up to `capacity` frames, and the writer's receiving end
+    fn new(capacity: usize) -> (Self, mpsc::Receiver<ws::Message>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let sink = Self { tx: Mutex::new(Some(tx)), closed: AtomicBool::new(false), overflowed: Notify::new() };
+        (sink, rx)
+    }
+
+    fn queue(&self, message: ws::Message) -> Result<(), SocketClosed> {
+        let tx = self.tx.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(tx) = tx.as_ref() else {
+            return Err(SocketClosed);
+        };
+        match tx.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                tracing::warn!("WebSocket send queue full, closing the connection");
+                self.closed.store(true, Ordering::SeqCst);
+                self.overflowed.notify_one();
+                Err(SocketClosed)
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(SocketClosed),
+        }
+    }
+
+    /// Stop accepting frames and let the writer finish
+    fn shutdown(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.tx.lock().unwrap_or_else(|e| e.into_inner()).take();
+    }
+}
+
+impl SocketSink for ConnectionSink {
+    fn send(&self, message: Message) -> Result<(), SocketClosed> {
+        if self.is_closed() {
+            return Err(SocketClosed);
+        }
+        self.queue(match message {
+            Message::Text(text) => ws::Message::Text(text.into()),
+            Message::Binary(data) => ws::Message::Binary(data),
+        })
+    }
+
+    fn close(&self, frame: CloseFrame) {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let _ = self.queue(ws::Message::Close(Some(ws::CloseFrame {
+            code: frame.code,
+            reason: frame.reason.into(),
+        })));
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sink_closes_once() {
+        let (sink, mut rx) = ConnectionSink::new(8);
+        let sink = Arc::new(sink);
+        let socket = WebSocket::new("c1", sink.clone());
+
+        socket.send_text("hi").unwrap();
+        socket.close(1000, "bye");
+        socket.close(1001, "again");
+        assert_eq!(socket.send_text("late"), Err(SocketClosed));
+        assert!(socket.is_closed());
+
+        sink.shutdown();
+        assert_eq!(rx.recv().await, Some(ws::Message::Text("hi".into())));
+        assert!(matches!(rx.recv().await, Some(ws::Message::Close(Some(f))) if f.code == 1000));
+        assert_eq!(rx.recv().await, None);
+    }
+    #[tokio::test]
+    async fn test_full_queue_closes() {
+        let (sink, mut rx) = ConnectionSink::new(2);
+        let sink = Arc::new(sink);
+        let socket = WebSocket::new("c1", sink.clone());
+
+        socket.send_text("1").unwrap();
+        socket.send_text("2").unwrap();
+        assert_eq!(socket.send_text("3"), Err(SocketClosed));
+        assert!(socket.is_closed());
+        assert_eq!(socket.send_text("4"), Err(SocketClosed));
+
+        // The writer is told to close rather than wait for the queue
+        tokio::time::timeout(Duration::from_secs(1), sink.overflowed.notified()).await.unwrap();
+        sink.shutdown();
+        assert_eq!(rx.recv().await, Some(ws::Message::Text("1".into())));
+        assert_eq!(rx.recv().await, Some(ws::Message::Text("2".into())));
+        assert_eq!(rx.recv().await, None);
+    }
+}
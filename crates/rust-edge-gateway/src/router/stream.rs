@@ -0,0 +1,96 @@
+//! Streaming handler responses
+//!
+//! A handler's [`StreamReader`] blocks while waiting for chunks, so it is
+//! drained on tokio's blocking pool, which bounds how many threads streams
+//! and handler calls can hold between them. Chunks are handed to the
+//! response body through a one-slot channel: the pump only pulls the next
+//! chunk once the client has taken the previous one.
+//!
+//! An open stream is not an active request: it is counted on its own, and
+//! the body ends as soon as its handler starts draining or the gateway shuts
+//! down, so a long-lived SSE stream does not hold up either.
+//!
+//! When the client disconnects or the body ends, the next send fails and
+//! the pump drops the reader, which makes the handler's writes fail with
+//! `StreamClosed`. A handler that is waiting to produce its next chunk
+//! notices at that write.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::body::Body;
+use bytes::Bytes;
+use rust_edge_gateway_sdk::stream::StreamReader;
+use tokio::sync::mpsc;
+
+use crate::runtime::handler::StreamGuard;
+
+/// Build a response body that forwards the chunks of `reader`
+///
+/// `guard` keeps the handler's library loaded until the pump is done, since
+/// the reader may run code from the library, and ends the body when the
+/// handler asks its streams to end.
+pub fn into_body(reader: StreamReader, guard: Option<StreamGuard>) -> Body {
+    let (tx, rx) = mpsc::channel::<Bytes>(1);
+    let guard = guard.map(Arc::new);
+
+    let pump_guard = guard.clone();
+    tokio::task::spawn_blocking(move || {
+        let _guard = pump_guard;
+        for chunk in reader {
+            if tx.blocking_send(chunk).is_err() {
+                tracing::debug!("Response body closed, ending response stream");
+                break;
+            }
+        }
+    });
+
+    let chunks = futures::stream::unfold((rx, guard), |(mut rx, guard)| async move {
+        let chunk = match &guard {
+            Some(g) => tokio::select! {
+                chunk = rx.recv() => chunk,
+                _ = g.ended() => None,
+            },
+            None => rx.recv().await,
+        }?;
+        Some((Ok::<_, Infallible>(chunk), (rx, guard)))
+    });
+    Body::from_stream(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_edge_gateway_sdk::BodyStream;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_stream_is_forwarded() {
+        let (writer, stream) = BodyStream::channel(2);
+        std::thread::spawn(move || {
+            for i in 0..3 {
+                writer.write(format!("chunk{};", i)).unwrap();
+            }
+        });
+
+        let body = into_body(stream.take().unwrap(), None);
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(&bytes[..], b"chunk0;chunk1;chunk2;");
+    }
+
+    #[tokio::test]
+    async fn test_dropped_body_closes_stream() {
+        let (writer, stream) = BodyStream::channel(1);
+        drop(into_body(stream.take().unwrap(), None));
+
+        let closed = tokio::task::spawn_blocking(move || {
+            // The first writes may still be buffered before the pump notices
+            (0..10).any(|_| {
+                let failed = writer.write("tick").is_err();
+                std::thread::sleep(Duration::from_millis(10));
+                failed
+            })
+        });
+        assert!(closed.await.unwrap());
+    }
+}
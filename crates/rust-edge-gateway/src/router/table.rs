@@ -0,0 +1,1298 @@
+//! In-memory route table
+//!
+//! The gateway matches requests against a compiled route table instead of
+//! querying SQLite on every request. The table is a segment trie keyed by
+//! host and method, built from the enabled rows of the `endpoints` table.
+//!
+//! The table is immutable once built. Admin operations that change routing
+//! (create, update, delete, start, stop) build a fresh table and swap it in
+//! atomically, so in-flight requests keep matching against a consistent
+//! snapshot and the request path never touches the database.
+//!
+//! # Hosts and base paths
+//!
+//! An endpoint that belongs to a collection is served on the host of the
+//! collection's `Domain` record, mounted under the collection's `base_path`
+//! (so `/pets` in a collection with base path `/v1` is served at `/v1/pets`).
+//! Endpoints without a collection are served on their own `domain` string at
+//! their own path. Endpoints of disabled collections, or whose host belongs to
+//! a disabled domain, are left out of the table.
+//!
+//! # Host resolution
+//!
+//! The request host (lowercased, port stripped) is resolved to one set of
+//! routes before the path is matched:
+//!
+//! 1. A domain host or alias equal to the request host
+//! 2. A wildcard host or alias `*.example.com` whose suffix matches; the
+//!    wildcard covers exactly one label, so `a.example.com` matches but
+//!    `a.b.example.com` does not. The matched label is reported as the
+//!    request's subdomain.
+//! 3. The configured default host, if any
+//!
+//! Resolution stops at the first host found, so a path that is missing on a
+//! matched host is a 404 rather than falling through to the default host.
+//!
+//! # Pattern syntax
+//!
+//! | Segment | Matches |
+//! |---------|---------|
+//! | `users` | The literal segment `users` |
+//! | `{id}` | Any single segment |
+//! | `{id:int}` | A single segment that parses as a signed integer |
+//! | `{id:uuid}` | A single segment that is a hyphenated UUID |
+//! | `{slug:[a-z-]+}` | A single segment matching the regex (anchored) |
+//! | `{*rest}` | All remaining segments (must be last) |
+//!
+//! # Precedence
+//!
+//! At every segment the matcher tries, in order: a literal segment, typed
+//! and regex parameters, a plain parameter, then a catch-all. Typed
+//! parameters are tried `int` first, then `uuid`, then a regex, so
+//! `/posts/{id:int}` and `/posts/{slug:[a-z-]+}` can live side by side. Two
+//! different regexes at the same segment could both match with no order
+//! between them, so such routes are rejected. If a branch fails further down
+//! the path the
+//! matcher backtracks to the next candidate, so `/pet/findByStatus` always
+//! wins over `/pet/{id}` regardless of the order the endpoints were created
+//! in.
+//!
+//! # Middleware
+//!
+//! Each route carries the [`Chain`] of middleware resolved from its domain,
+//! collection and endpoint settings, so requests do not combine them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use anyhow::Result;
+
+use super::middleware::{Chain, Layer, Middleware, MiddlewareKind, Scope};
+use super::rules::RuleSet;
+use crate::api::{Collection, Domain, DomainRule, Endpoint};
+
+/// Errors raised when an endpoint's path cannot be added to the table
+#[derive(Debug, thiserror::Error)]
+pub enum RouteError {
+    #[error("Invalid route pattern '{pattern}': {reason}")]
+    InvalidPattern { pattern: String, reason: String },
+
+    #[error("Invalid host '{host}': {reason}")]
+    InvalidHost { host: String, reason: String },
+
+    /// `route` is "METHOD /path", `existing` is "'name' (/path)"
+    #[error("Route {route} conflicts with endpoint {existing}")]
+    Conflict { route: String, existing: String },
+
+    #[error("Route {route} is ambiguous with endpoint {existing}: \
+             regex constraints '{constraint}' and '{existing_constraint}' overlap at the same segment; \
+             use a literal segment to tell them apart")]
+    Ambiguous {
+        route: String,
+        existing: String,
+        constraint: String,
+        existing_constraint: String,
+    },
+}
+
+/// The rows a route table is built from
+#[derive(Debug, Clone, Default)]
+pub struct RoutingData {
+    pub domains: Vec<Domain>,
+    pub collections: Vec<Collection>,
+    pub endpoints: Vec<Endpoint>,
+
+    /// Redirect and rewrite rules, in order within each domain
+    pub rules: Vec<DomainRule>,
+
+    /// Host whose routes serve requests for unknown hosts
+    pub default_host: Option<String>,
+}
+
+/// A successful route lookup
+#[derive(Debug, Clone)]
+pub struct RouteMatch {
+    /// The matched endpoint
+    pub endpoint: Arc<Endpoint>,
+
+    /// The domain record for the request host, if one exists
+    pub domain: Option<Arc<Domain>>,
+
+    /// The label matched by a wildcard host (`tenant` for `tenant.example.com`
+    /// against `*.example.com`)
+    pub subdomain: Option<String>,
+
+    /// Path parameters extracted from the request path
+    pub params: HashMap<String, String>,
+
+    /// Middleware run around the endpoint
+    pub middleware: Arc<Chain>,
+}
+
+/// Methods served at a path, from [`RouteTable::allowed_methods`]
+#[derive(Debug, Clone)]
+pub struct AllowedMethods {
+    /// Uppercase method names, sorted
+    pub methods: Vec<String>,
+
+    /// The domain record for the request host, if one exists
+    pub domain: Option<Arc<Domain>>,
+}
+
+/// Constraint on a path parameter value
+#[derive(Debug, Clone)]
+pub enum Constraint {
+    /// Signed integer (`{id:int}`)
+    Int,
+    /// Hyphenated UUID (`{id:uuid}`)
+    Uuid,
+    /// Anchored regular expression (`{slug:[a-z-]+}`)
+    Regex { source: String, regex: regex_lite::Regex },
+}
+
+impl Constraint {
+    fn parse(spec: &str, pattern: &str) -> Result<Self, RouteError> {
+        match spec {
+            "int" => Ok(Constraint::Int),
+            "uuid" => Ok(Constraint::Uuid),
+            _ => {
+                let regex = regex_lite::Regex::new(&format!("^(?:{})$", spec))
+                    .map_err(|e| invalid(pattern, format!("bad regex '{}': {}", spec, e)))?;
+                Ok(Constraint::Regex { source: spec.to_string(), regex })
+            }
+        }
+    }
+
+    /// Check whether a segment value satisfies the constraint
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Constraint::Int => value.parse::<i64>().is_ok(),
+            Constraint::Uuid => uuid::Uuid::try_parse(value).is_ok() && value.len() == 36,
+            Constraint::Regex { regex, .. } => regex.is_match(value),
+        }
+    }
+
+    /// Order typed children are tried in: built-in types, then a regex
+    fn rank(&self) -> u8 {
+        match self {
+            Constraint::Int => 0,
+            Constraint::Uuid => 1,
+            Constraint::Regex { .. } => 2,
+        }
+    }
+
+    /// The constraint as written in the pattern
+    pub fn as_str(&self) -> &str {
+        match self {
+            Constraint::Int => "int",
+            Constraint::Uuid => "uuid",
+            Constraint::Regex { source, .. } => source,
+        }
+    }
+}
+
+impl PartialEq for Constraint {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+/// A parsed pattern segment
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Static(String),
+    Param { name: String, constraint: Option<Constraint> },
+    CatchAll(String),
+}
+
+fn invalid(pattern: &str, reason: impl Into<String>) -> RouteError {
+    RouteError::InvalidPattern { pattern: pattern.to_string(), reason: reason.into() }
+}
+
+/// Parse a path pattern into segments
+fn parse_pattern(pattern: &str) -> Result<Vec<Segment>, RouteError> {
+    if !pattern.starts_with('/') {
+        return Err(invalid(pattern, "must start with '/'"));
+    }
+
+    let raw: Vec<&str> = pattern.split('/').collect();
+    let mut segments = Vec::with_capacity(raw.len());
+    let mut names: Vec<&str> = Vec::new();
+
+    for (i, part) in raw.iter().enumerate() {
+        let Some(inner) = part.strip_prefix('{').and_then(|p| p.strip_suffix('}')) else {
+            if part.contains('{') || part.contains('}') {
+                return Err(invalid(pattern, format!("segment '{}' mixes literal text and a parameter", part)));
+            }
+            segments.push(Segment::Static(part.to_string()));
+            continue;
+        };
+
+        let (name, segment) = if let Some(name) = inner.strip_prefix('*') {
+            if i != raw.len() - 1 {
+                return Err(invalid(pattern, format!("catch-all '{{*{}}}' must be the last segment", name)));
+            }
+            (name, Segment::CatchAll(name.to_string()))
+        } else {
+            let (name, constraint) = match inner.split_once(':') {
+                Some((name, spec)) => (name, Some(Constraint::parse(spec, pattern)?)),
+                None => (inner, None),
+            };
+            (name, Segment::Param { name: name.to_string(), constraint })
+        };
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(invalid(pattern, format!("invalid parameter name '{}'", name)));
+        }
+        if names.contains(&name) {
+            return Err(invalid(pattern, format!("duplicate parameter name '{}'", name)));
+        }
+        names.push(name);
+        segments.push(segment);
+    }
+
+    Ok(segments)
+}
+
+/// An endpoint stored at a trie leaf
+#[derive(Debug)]
+struct Route {
+    endpoint: Arc<Endpoint>,
+
+    /// Full path pattern, including any collection base path
+    pattern: String,
+
+    /// Parameter names in the order they appear in the pattern
+    param_names: Vec<String>,
+
+    middleware: Arc<Chain>,
+}
+
+/// A node in the segment trie
+#[derive(Debug, Default)]
+struct Node {
+    /// Children for literal segments
+    statics: HashMap<String, Node>,
+
+    /// Children for typed and regex `{param:constraint}` segments, one per
+    /// constraint, in the order they are tried
+    typed: Vec<(Constraint, Node)>,
+
+    /// Child for a plain `{param}` segment
+    param: Option<Box<Node>>,
+
+    /// Route for a `{*rest}` segment (always terminal)
+    catch_all: Option<Route>,
+
+    /// Route terminating at this node
+    route: Option<Route>,
+}
+
+impl Node {
+    /// Walk the trie in precedence order.
+    ///
+    /// Parameter values are pushed onto `values` as they are captured and
+    /// popped again when a branch fails, so on success `values` holds one
+    /// entry per parameter in pattern order.
+    fn find<'a>(&'a self, segments: &[&str], values: &mut Vec<String>) -> Option<&'a Route> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return self.route.as_ref();
+        };
+
+        if let Some(child) = self.statics.get(*segment) {
+            if let Some(route) = child.find(rest, values) {
+                return Some(route);
+            }
+        }
+
+        for (constraint, child) in &self.typed {
+            if constraint.matches(segment) {
+                values.push(segment.to_string());
+                if let Some(route) = child.find(rest, values) {
+                    return Some(route);
+                }
+                values.pop();
+            }
+        }
+
+        if let Some(child) = &self.param {
+            values.push(segment.to_string());
+            if let Some(route) = child.find(rest, values) {
+                return Some(route);
+            }
+            values.pop();
+        }
+
+        if let Some(route) = &self.catch_all {
+            values.push(segments.join("/"));
+            return Some(route);
+        }
+
+        None
+    }
+
+    /// Any route at or below this node (used to name the other side of a conflict)
+    fn any_route(&self) -> Option<&Route> {
+        self.route.as_ref()
+            .or(self.catch_all.as_ref())
+            .or_else(|| self.statics.values().find_map(Node::any_route))
+            .or_else(|| self.typed.iter().find_map(|(_, n)| n.any_route()))
+            .or_else(|| self.param.as_ref().and_then(|n| n.any_route()))
+    }
+}
+
+/// Routes served on a single host
+#[derive(Debug, Default)]
+struct HostRoutes {
+    /// Domain record for this host (None for endpoints without one)
+    domain: Option<Arc<Domain>>,
+
+    /// Method -> segment trie
+    methods: HashMap<String, Node>,
+
+    /// The domain's redirect and rewrite rules
+    rules: Option<Arc<RuleSet>>,
+}
+
+/// Where an endpoint is served
+struct Mount {
+    host: String,
+    path: String,
+}
+
+/// Resolves endpoints to hosts and full paths using domain and collection records
+struct Mounter<'a> {
+    domains_by_id: HashMap<&'a str, &'a Domain>,
+    domains_by_host: HashMap<&'a str, &'a Domain>,
+    collections: HashMap<&'a str, &'a Collection>,
+}
+
+impl<'a> Mounter<'a> {
+    fn new(domains: &'a [Domain], collections: &'a [Collection]) -> Self {
+        // Aliases first so that a domain's own host always wins
+        let mut domains_by_host = HashMap::new();
+        for domain in domains {
+            for alias in &domain.aliases {
+                domains_by_host.entry(alias.as_str()).or_insert(domain);
+            }
+        }
+        for domain in domains {
+            domains_by_host.insert(domain.host.as_str(), domain);
+        }
+
+        Self {
+            domains_by_id: domains.iter().map(|d| (d.id.as_str(), d)).collect(),
+            domains_by_host,
+            collections: collections.iter().map(|c| (c.id.as_str(), c)).collect(),
+        }
+    }
+
+    /// Resolve an endpoint's mount point, or None if it should not be served.
+    ///
+    /// With `include_disabled` set, disabled domains and collections are
+    /// treated as enabled; this is used to detect conflicts ahead of time.
+    fn mount(&self, endpoint: &Endpoint, include_disabled: bool) -> Option<Mount> {
+        let collection = endpoint.collection_id.as_deref()
+            .and_then(|id| self.collections.get(id));
+
+        if let Some(collection) = collection {
+            if !collection.enabled && !include_disabled {
+                return None;
+            }
+        }
+
+        let mut host = collection
+            .and_then(|c| self.domains_by_id.get(c.domain_id.as_str()))
+            .map(|d| d.host.clone())
+            .unwrap_or_else(|| endpoint.domain.to_ascii_lowercase());
+
+        // An endpoint on an alias is served on the domain's canonical host
+        if let Some(domain) = self.domains_by_host.get(host.as_str()) {
+            if !domain.enabled && !include_disabled {
+                return None;
+            }
+            host = domain.host.clone();
+        }
+
+        let base = collection.map(|c| normalize_base_path(&c.base_path)).unwrap_or_default();
+        Some(Mount { host, path: format!("{}{}", base, endpoint.path) })
+    }
+
+    fn domain(&self, host: &str) -> Option<Arc<Domain>> {
+        self.domains_by_host.get(host).map(|d| Arc::new((*d).clone()))
+    }
+
+    /// Resolve the middleware of an endpoint served on `host`
+    ///
+    /// The domain's `cors` setting is its `cors` middleware unless it
+    /// configures one explicitly.
+    fn middleware(&self, endpoint: &Endpoint, host: &str) -> Chain {
+        let mut layers = Vec::new();
+        if let Some(domain) = self.domains_by_host.get(host) {
+            let mut middleware = domain.settings.middleware.clone();
+            if let Some(cors) = &domain.settings.cors {
+                if !middleware.iter().any(|m| m.kind() == MiddlewareKind::Cors) {
+                    middleware.push(Middleware::Cors(cors.clone()));
+                }
+            }
+            layers.push(Layer { scope: Scope::Domain, id: domain.id.clone(), middleware, skip: Vec::new() });
+        }
+        let collection = endpoint.collection_id.as_deref().and_then(|id| self.collections.get(id));
+        if let Some(collection) = collection {
+            layers.push(Layer {
+                scope: Scope::Collection,
+                id: collection.id.clone(),
+                middleware: collection.settings.middleware.clone(),
+                skip: collection.settings.skip_middleware.clone(),
+            });
+        }
+        layers.push(Layer {
+            scope: Scope::Endpoint,
+            id: endpoint.id.clone(),
+            middleware: endpoint.settings.middleware.clone(),
+            skip: endpoint.settings.skip_middleware.clone(),
+        });
+        Chain::resolve(layers)
+    }
+}
+
+/// Normalize a host name or wildcard host pattern for use as a domain host or alias
+///
+/// Lowercases the host and strips a trailing dot. A `*` is only allowed as the
+/// whole first label (`*.example.com`).
+pub fn normalize_host_pattern(host: &str) -> Result<String, RouteError> {
+    let invalid = |reason: &str| RouteError::InvalidHost { host: host.to_string(), reason: reason.to_string() };
+
+    let normalized = host.trim().trim_end_matches('.').to_ascii_lowercase();
+    if normalized.is_empty() {
+        return Err(invalid("host is empty"));
+    }
+
+    let (labels, wildcard) = match normalized.strip_prefix("*.") {
+        Some(rest) => (rest, true),
+        None => (normalized.as_str(), false),
+    };
+    if wildcard && labels.is_empty() {
+        return Err(invalid("wildcard must be followed by a domain"));
+    }
+
+    for label in labels.split('.') {
+        if label.is_empty() {
+            return Err(invalid("empty label"));
+        }
+        if label.contains('*') {
+            return Err(invalid("'*' is only allowed as the first label"));
+        }
+        if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(invalid("hosts may only contain letters, digits, '-', '_' and '.'; omit the scheme and port"));
+        }
+    }
+
+    Ok(normalized)
+}
+
+/// Normalize a request `Host` header: strip the port, lowercase and drop a trailing dot
+pub fn normalize_request_host(host: &str) -> String {
+    let host = host.trim();
+    let without_port = if let Some(rest) = host.strip_prefix('[') {
+        // IPv6 literal: "[::1]:8080"
+        rest.split(']').next().unwrap_or(rest)
+    } else {
+        host.split(':').next().unwrap_or(host)
+    };
+    without_port.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Normalize a collection base path to "" or "/segment[/segment...]"
+fn normalize_base_path(base: &str) -> String {
+    let trimmed = base.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+/// Compiled routing table: host -> method -> segment trie
+#[derive(Debug, Default)]
+pub struct RouteTable {
+    hosts: HashMap<String, HostRoutes>,
+
+    /// Alias host -> canonical host in `hosts`
+    aliases: HashMap<String, String>,
+
+    /// Host used when the request host matches nothing else
+    default_host: Option<String>,
+
+    len: usize,
+}
+
+impl RouteTable {
+    /// Create an empty route table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a route table from domain, collection and endpoint rows
+    ///
+    /// Endpoints are inserted in order. Endpoints whose path is invalid or
+    /// conflicts with an earlier endpoint are logged and skipped.
+    pub fn build(data: RoutingData) -> Self {
+        let mut table = Self::new();
+        let mounter = Mounter::new(&data.domains, &data.collections);
+
+        for endpoint in data.endpoints {
+            let Some(mount) = mounter.mount(&endpoint, false) else {
+                continue;
+            };
+            let id = endpoint.id.clone();
+            let domain = mounter.domain(&mount.host);
+            let middleware = Arc::new(mounter.middleware(&endpoint, &mount.host));
+            if let Err(e) = table.insert(mount, domain, endpoint, middleware) {
+                tracing::warn!(endpoint = %id, "Skipping route: {}", e);
+            }
+        }
+
+        // Every enabled domain owns its host and aliases, even without routes,
+        // so that its requests are not served by a wildcard or the default host
+        for domain in data.domains.iter().filter(|d| d.enabled) {
+            let routes = table.hosts.entry(domain.host.clone()).or_default();
+            routes.domain.get_or_insert_with(|| Arc::new(domain.clone()));
+
+            let rules = RuleSet::new(data.rules.iter().filter(|r| r.domain_id == domain.id));
+            if !rules.is_empty() {
+                routes.rules = Some(Arc::new(rules));
+            }
+
+            for alias in &domain.aliases {
+                if table.hosts.contains_key(alias) {
+                    tracing::warn!(domain = %domain.name, "Ignoring alias '{}': it is another domain's host", alias);
+                    continue;
+                }
+                match table.aliases.get(alias) {
+                    Some(owner) if *owner != domain.host => {
+                        tracing::warn!(domain = %domain.name, "Ignoring alias '{}': already an alias of '{}'", alias, owner);
+                    }
+                    _ => {
+                        table.aliases.insert(alias.clone(), domain.host.clone());
+                    }
+                }
+            }
+        }
+
+        table.default_host = data.default_host
+            .map(|h| h.to_ascii_lowercase())
+            .map(|h| table.aliases.get(&h).cloned().unwrap_or(h));
+        table
+    }
+
+    /// Check whether `candidate` could be added alongside the endpoints in `data`
+    ///
+    /// Used by the admin API to reject invalid, duplicate or ambiguous routes
+    /// before they are saved. Disabled domains, collections and endpoints are
+    /// included so that enabling them later cannot introduce a conflict.
+    /// Endpoints with the same ID as the candidate are ignored so that updates
+    /// do not conflict with themselves.
+    pub fn check(data: &RoutingData, candidate: &Endpoint) -> Result<(), RouteError> {
+        let mounter = Mounter::new(&data.domains, &data.collections);
+        let mut table = Self::new();
+
+        for endpoint in data.endpoints.iter().filter(|e| e.id != candidate.id) {
+            if let Some(mount) = mounter.mount(endpoint, true) {
+                let _ = table.insert(mount, None, endpoint.clone(), Default::default());
+            }
+        }
+
+        let Some(mount) = mounter.mount(candidate, true) else {
+            return Ok(());
+        };
+        let segments = parse_pattern(&mount.path)?;
+        table.find_conflict(&mount, &candidate.method, &segments)
+    }
+
+    /// Insert an endpoint at its mount point
+    fn insert(&mut self, mount: Mount, domain: Option<Arc<Domain>>, endpoint: Endpoint, middleware: Arc<Chain>) -> Result<(), RouteError> {
+        let segments = parse_pattern(&mount.path)?;
+        self.find_conflict(&mount, &endpoint.method, &segments)?;
+
+        let host = self.hosts.entry(mount.host).or_default();
+        if host.domain.is_none() {
+            host.domain = domain;
+        }
+
+        let mut node = host.methods
+            .entry(endpoint.method.to_uppercase())
+            .or_default();
+
+        let mut param_names = Vec::new();
+        for segment in segments {
+            node = match segment {
+                Segment::Static(s) => node.statics.entry(s).or_default(),
+                Segment::Param { name, constraint: None } => {
+                    param_names.push(name);
+                    node.param.get_or_insert_with(Default::default)
+                }
+                Segment::Param { name, constraint: Some(c) } => {
+                    param_names.push(name);
+                    let index = match node.typed.iter().position(|(existing, _)| *existing == c) {
+                        Some(index) => index,
+                        None => {
+                            // After the children of the same or a lower rank
+                            let index = node.typed.partition_point(|(existing, _)| existing.rank() <= c.rank());
+                            node.typed.insert(index, (c, Default::default()));
+                            index
+                        }
+                    };
+                    &mut node.typed[index].1
+                }
+                Segment::CatchAll(name) => {
+                    param_names.push(name);
+                    node.catch_all = Some(Route { endpoint: Arc::new(endpoint), pattern: mount.path, param_names, middleware });
+                    self.len += 1;
+                    return Ok(());
+                }
+            };
+        }
+
+        node.route = Some(Route { endpoint: Arc::new(endpoint), pattern: mount.path, param_names, middleware });
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Walk the trie along `segments` without modifying it, reporting the
+    /// route that would be shadowed by or ambiguous with `endpoint`
+    fn find_conflict(&self, mount: &Mount, method: &str, segments: &[Segment]) -> Result<(), RouteError> {
+        let method = method.to_uppercase();
+        let Some(mut node) = self.hosts.get(&mount.host).and_then(|h| h.methods.get(&method)) else {
+            return Ok(());
+        };
+
+        let describe = |route: Option<&Route>| route
+            .map(|r| format!("'{}' ({})", r.endpoint.name, r.pattern))
+            .unwrap_or_default();
+        let conflict = |route: &Route| RouteError::Conflict {
+            route: format!("{} {}", method, mount.path),
+            existing: describe(Some(route)),
+        };
+
+        for segment in segments {
+            node = match segment {
+                Segment::Static(s) => match node.statics.get(s) {
+                    Some(child) => child,
+                    None => return Ok(()),
+                },
+                Segment::Param { constraint: None, .. } => match &node.param {
+                    Some(child) => child,
+                    None => return Ok(()),
+                },
+                Segment::Param { constraint: Some(c), .. } => {
+                    if let Some((_, child)) = node.typed.iter().find(|(existing, _)| existing == c) {
+                        child
+                    } else if let Some((existing, child)) = node.typed.iter()
+                        .find(|(existing, _)| matches!((existing, c), (Constraint::Regex { .. }, Constraint::Regex { .. })))
+                    {
+                        return Err(RouteError::Ambiguous {
+                            route: format!("{} {}", method, mount.path),
+                            existing: describe(child.any_route()),
+                            constraint: c.as_str().to_string(),
+                            existing_constraint: existing.as_str().to_string(),
+                        });
+                    } else {
+                        return Ok(());
+                    }
+                }
+                Segment::CatchAll(_) => {
+                    return match &node.catch_all {
+                        Some(route) => Err(conflict(route)),
+                        None => Ok(()),
+                    };
+                }
+            };
+        }
+
+        match &node.route {
+            Some(route) => Err(conflict(route)),
+            None => Ok(()),
+        }
+    }
+
+    /// Resolve a request host to its routes and wildcard label
+    fn resolve_host(&self, host: &str) -> Option<(&HostRoutes, Option<String>)> {
+        let exact = |h: &str| self.hosts.get(h)
+            .or_else(|| self.aliases.get(h).and_then(|c| self.hosts.get(c)));
+
+        if let Some(routes) = exact(host) {
+            return Some((routes, None));
+        }
+
+        if let Some((label, parent)) = host.split_once('.') {
+            if let Some(routes) = exact(&format!("*.{}", parent)) {
+                return Some((routes, Some(label.to_string())));
+            }
+        }
+
+        self.default_host.as_deref()
+            .and_then(|h| self.hosts.get(h))
+            .map(|routes| (routes, None))
+    }
+
+    /// Find the endpoint for a request
+    ///
+    /// `host` should already be normalized with [`normalize_request_host`].
+    pub fn lookup(&self, host: &str, method: &str, path: &str) -> Option<RouteMatch> {
+        let (host, subdomain) = self.resolve_host(host)?;
+        let root = host.methods.get(method)?;
+        let segments: Vec<&str> = path.split('/').collect();
+
+        let mut values = Vec::new();
+        let route = root.find(&segments, &mut values)?;
+
+        Some(RouteMatch {
+            endpoint: Arc::clone(&route.endpoint),
+            domain: host.domain.clone(),
+            subdomain,
+            params: route.param_names.iter().cloned().zip(values).collect(),
+            middleware: Arc::clone(&route.middleware),
+        })
+    }
+
+    /// The domain record serving `host`, for requests that match no route
+    pub fn domain(&self, host: &str) -> Option<Arc<Domain>> {
+        self.resolve_host(host).and_then(|(routes, _)| routes.domain.clone())
+    }
+
+    /// The redirect and rewrite rules of the domain serving `host`, if it has any
+    pub fn rules(&self, host: &str) -> Option<Arc<RuleSet>> {
+        self.resolve_host(host).and_then(|(routes, _)| routes.rules.clone())
+    }
+
+    /// Methods that have a route for `path` on `host`, for answering OPTIONS
+    ///
+    /// HEAD is included wherever GET is, and OPTIONS whenever any method
+    /// matches. Returns None if no method matches.
+    pub fn allowed_methods(&self, host: &str, path: &str) -> Option<AllowedMethods> {
+        let (host, _) = self.resolve_host(host)?;
+        let segments: Vec<&str> = path.split('/').collect();
+
+        let mut methods: Vec<String> = host.methods.iter()
+            .filter(|(_, root)| root.find(&segments, &mut Vec::new()).is_some())
+            .map(|(method, _)| method.clone())
+            .collect();
+        if methods.is_empty() {
+            return None;
+        }
+        if methods.iter().any(|m| m == "GET") {
+            methods.push("HEAD".to_string());
+        }
+        methods.push("OPTIONS".to_string());
+        methods.sort();
+        methods.dedup();
+
+        Some(AllowedMethods { methods, domain: host.domain.clone() })
+    }
+
+    /// Number of routes in the table
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the table has no routes
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Shared, atomically swappable route table
+///
+/// Readers take a cheap `Arc` snapshot; rebuilds are serialized so that two
+/// concurrent admin changes cannot store tables out of order.
+#[derive(Debug, Default)]
+pub struct SharedRouteTable {
+    current: RwLock<Arc<RouteTable>>,
+    rebuild_lock: Mutex<()>,
+}
+
+impl SharedRouteTable {
+    /// Create a shared table holding an empty route table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the current route table snapshot
+    pub fn load(&self) -> Arc<RouteTable> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Build a new table and swap it in
+    ///
+    /// `build` runs while holding the rebuild lock, so the data it reads is
+    /// never older than the data read by a previous rebuild.
+    pub fn rebuild<F>(&self, build: F) -> Result<usize>
+    where
+        F: FnOnce() -> Result<RouteTable>,
+    {
+        let _guard = self.rebuild_lock.lock().unwrap();
+        let table = build()?;
+        let len = table.len();
+        *self.current.write().unwrap() = Arc::new(table);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(id: &str, domain: &str, method: &str, path: &str) -> Endpoint {
+        Endpoint {
+            id: id.to_string(),
+            collection_id: None,
+            name: id.to_string(),
+            domain: domain.to_string(),
+            path: path.to_string(),
+            method: method.to_string(),
+            kind: Default::default(),
+            description: None,
+            code: None,
+            dependencies: None,
+            compiled: true,
+            enabled: true,
+            settings: Default::default(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn build(endpoints: impl IntoIterator<Item = Endpoint>) -> RouteTable {
+        RouteTable::build(RoutingData {
+            endpoints: endpoints.into_iter().collect(),
+            ..Default::default()
+        })
+    }
+
+    fn domain(id: &str, host: &str, enabled: bool) -> Domain {
+        Domain {
+            id: id.to_string(),
+            name: id.to_string(),
+            host: host.to_string(),
+            aliases: Vec::new(),
+            description: None,
+            enabled,
+            settings: Default::default(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn collection(id: &str, domain_id: &str, base_path: &str, enabled: bool) -> Collection {
+        Collection {
+            id: id.to_string(),
+            domain_id: domain_id.to_string(),
+            name: id.to_string(),
+            description: None,
+            base_path: base_path.to_string(),
+            enabled,
+            settings: Default::default(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn in_collection(mut endpoint: Endpoint, collection_id: &str) -> Endpoint {
+        endpoint.collection_id = Some(collection_id.to_string());
+        endpoint
+    }
+
+    #[test]
+    fn test_static_and_param_routes() {
+        let table = build(vec![
+            endpoint("get-pet", "api.example.com", "GET", "/pet/{petId}"),
+            endpoint("list-pets", "api.example.com", "GET", "/pets"),
+        ]);
+
+        assert_eq!(table.len(), 2);
+
+        let m = table.lookup("api.example.com", "GET", "/pet/42").unwrap();
+        assert_eq!(m.endpoint.id, "get-pet");
+        assert_eq!(m.params.get("petId").map(String::as_str), Some("42"));
+
+        let m = table.lookup("api.example.com", "GET", "/pets").unwrap();
+        assert_eq!(m.endpoint.id, "list-pets");
+        assert!(m.params.is_empty());
+
+        assert!(table.lookup("api.example.com", "GET", "/pet/42/photos").is_none());
+        assert!(table.lookup("api.example.com", "POST", "/pets").is_none());
+        assert!(table.lookup("other.example.com", "GET", "/pets").is_none());
+    }
+
+    #[test]
+    fn test_static_segment_preferred_over_param() {
+        // Insertion order must not matter
+        for order in [["get-pet", "find-by-status"], ["find-by-status", "get-pet"]] {
+            let table = build(order.iter().map(|id| match *id {
+                "get-pet" => endpoint(id, "localhost", "GET", "/pet/{id}"),
+                _ => endpoint(id, "localhost", "GET", "/pet/findByStatus"),
+            }));
+
+            let m = table.lookup("localhost", "GET", "/pet/findByStatus").unwrap();
+            assert_eq!(m.endpoint.id, "find-by-status");
+
+            let m = table.lookup("localhost", "GET", "/pet/7").unwrap();
+            assert_eq!(m.endpoint.id, "get-pet");
+        }
+    }
+
+    #[test]
+    fn test_backtracks_from_static_to_param() {
+        let table = build(vec![
+            endpoint("user-posts", "localhost", "GET", "/users/{id}/posts"),
+            endpoint("me-profile", "localhost", "GET", "/users/me/profile"),
+        ]);
+
+        let m = table.lookup("localhost", "GET", "/users/me/posts").unwrap();
+        assert_eq!(m.endpoint.id, "user-posts");
+        assert_eq!(m.params.get("id").map(String::as_str), Some("me"));
+    }
+
+    #[test]
+    fn test_param_names_are_per_route() {
+        let table = build(vec![
+            endpoint("get", "localhost", "GET", "/items/{itemId}"),
+            endpoint("delete", "localhost", "DELETE", "/items/{id}"),
+        ]);
+
+        let m = table.lookup("localhost", "DELETE", "/items/9").unwrap();
+        assert_eq!(m.params.get("id").map(String::as_str), Some("9"));
+    }
+
+    #[test]
+    fn test_duplicate_route_keeps_first() {
+        let table = build(vec![
+            endpoint("first", "localhost", "GET", "/a/{x}"),
+            endpoint("second", "localhost", "GET", "/a/{y}"),
+        ]);
+
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.lookup("localhost", "GET", "/a/1").unwrap().endpoint.id, "first");
+    }
+
+    #[test]
+    fn test_typed_params() {
+        let table = build(vec![
+            endpoint("by-id", "localhost", "GET", "/posts/{id:int}"),
+            endpoint("by-slug", "localhost", "GET", "/posts/{slug}"),
+            endpoint("by-uuid", "localhost", "GET", "/orders/{id:uuid}"),
+            endpoint("tag", "localhost", "GET", "/tags/{tag:[a-z-]+}"),
+        ]);
+
+        let m = table.lookup("localhost", "GET", "/posts/-12").unwrap();
+        assert_eq!(m.endpoint.id, "by-id");
+        assert_eq!(m.params.get("id").map(String::as_str), Some("-12"));
+
+        let m = table.lookup("localhost", "GET", "/posts/hello-world").unwrap();
+        assert_eq!(m.endpoint.id, "by-slug");
+
+        assert!(table.lookup("localhost", "GET", "/orders/67e55044-10b1-426f-9247-bb680e5fe0c8").is_some());
+        assert!(table.lookup("localhost", "GET", "/orders/42").is_none());
+
+        assert!(table.lookup("localhost", "GET", "/tags/rust-lang").is_some());
+        assert!(table.lookup("localhost", "GET", "/tags/Rust").is_none());
+    }
+
+    #[test]
+    fn test_typed_params_at_one_segment() {
+        let table = build(vec![
+            endpoint("by-slug", "localhost", "GET", "/posts/{slug:[a-z0-9]+}"),
+            endpoint("by-uuid", "localhost", "GET", "/posts/{id:uuid}"),
+            endpoint("by-id", "localhost", "GET", "/posts/{id:int}"),
+            endpoint("comments", "localhost", "GET", "/posts/{slug:[a-z0-9]+}/comments"),
+        ]);
+        assert_eq!(table.len(), 4);
+
+        let m = table.lookup("localhost", "GET", "/posts/42").unwrap();
+        assert_eq!(m.endpoint.id, "by-id");
+        assert_eq!(m.params.get("id").map(String::as_str), Some("42"));
+        let m = table.lookup("localhost", "GET", "/posts/hello").unwrap();
+        assert_eq!(m.endpoint.id, "by-slug");
+        assert_eq!(m.params.get("slug").map(String::as_str), Some("hello"));
+        let m = table.lookup("localhost", "GET", "/posts/67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        assert_eq!(m.endpoint.id, "by-uuid");
+
+        // A segment matching an earlier constraint falls back to a later one
+        let m = table.lookup("localhost", "GET", "/posts/42/comments").unwrap();
+        assert_eq!(m.endpoint.id, "comments");
+        assert_eq!(m.params.get("slug").map(String::as_str), Some("42"));
+        assert!(table.lookup("localhost", "GET", "/posts/Hello").is_none());
+    }
+
+    #[test]
+    fn test_catch_all() {
+        let table = build(vec![
+            endpoint("files", "localhost", "GET", "/files/{*rest}"),
+            endpoint("readme", "localhost", "GET", "/files/README"),
+            endpoint("file", "localhost", "GET", "/files/{name}"),
+        ]);
+
+        let m = table.lookup("localhost", "GET", "/files/a/b/c.txt").unwrap();
+        assert_eq!(m.endpoint.id, "files");
+        assert_eq!(m.params.get("rest").map(String::as_str), Some("a/b/c.txt"));
+
+        // Literal and single-segment parameters beat the catch-all
+        assert_eq!(table.lookup("localhost", "GET", "/files/README").unwrap().endpoint.id, "readme");
+        assert_eq!(table.lookup("localhost", "GET", "/files/x.txt").unwrap().endpoint.id, "file");
+
+        // A catch-all needs at least one (possibly empty) segment
+        assert!(table.lookup("localhost", "GET", "/files").is_none());
+        assert_eq!(table.lookup("localhost", "GET", "/files/").unwrap().endpoint.id, "file");
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for path in [
+            "no-slash",
+            "/files/{*rest}/more",
+            "/a/{id}/{id}",
+            "/a/pre{id}",
+            "/a/{}",
+            "/a/{id:[unclosed}",
+        ] {
+            let err = RouteTable::check(&RoutingData::default(), &endpoint("x", "localhost", "GET", path));
+            assert!(matches!(err, Err(RouteError::InvalidPattern { .. })), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_check_rejects_conflicts() {
+        let existing = RoutingData {
+            endpoints: vec![
+                endpoint("get-pet", "localhost", "GET", "/pet/{petId}"),
+                endpoint("by-id", "localhost", "GET", "/posts/{id:int}"),
+                endpoint("by-slug", "localhost", "GET", "/posts/{slug:[a-z]+}/edit"),
+                endpoint("files", "localhost", "GET", "/files/{*path}"),
+            ],
+            ..Default::default()
+        };
+
+        // Same shape with a different parameter name
+        let err = RouteTable::check(&existing, &endpoint("new", "localhost", "GET", "/pet/{id}"));
+        assert!(matches!(err, Err(RouteError::Conflict { .. })));
+
+        // The same constraint under another name
+        let err = RouteTable::check(&existing, &endpoint("new", "localhost", "GET", "/posts/{n:int}"));
+        assert!(matches!(err, Err(RouteError::Conflict { .. })));
+
+        let err = RouteTable::check(&existing, &endpoint("new", "localhost", "GET", "/files/{*rest}"));
+        assert!(matches!(err, Err(RouteError::Conflict { .. })));
+
+        // Two different regexes at the same position, even with different suffixes
+        let err = RouteTable::check(&existing, &endpoint("new", "localhost", "GET", "/posts/{code:[a-z0-9]+}/comments"));
+        assert!(matches!(err, Err(RouteError::Ambiguous { .. })));
+
+        // Distinct routes are fine
+        assert!(RouteTable::check(&existing, &endpoint("new", "localhost", "GET", "/pet/findByStatus")).is_ok());
+        assert!(RouteTable::check(&existing, &endpoint("new", "localhost", "GET", "/posts/{slug:[a-z]+}")).is_ok());
+        assert!(RouteTable::check(&existing, &endpoint("new", "localhost", "GET", "/posts/{id:uuid}")).is_ok());
+        assert!(RouteTable::check(&existing, &endpoint("new", "localhost", "POST", "/pet/{id}")).is_ok());
+        assert!(RouteTable::check(&existing, &endpoint("new", "other", "GET", "/pet/{id}")).is_ok());
+
+        // An endpoint never conflicts with its own previous version
+        assert!(RouteTable::check(&existing, &endpoint("get-pet", "localhost", "GET", "/pet/{id}")).is_ok());
+    }
+
+    #[test]
+    fn test_collection_base_path_and_domain_host() {
+        let table = RouteTable::build(RoutingData {
+            domains: vec![domain("d1", "api.example.com", true)],
+            collections: vec![
+                collection("pets", "d1", "/v1", true),
+                collection("users", "d1", "v2/", true),
+            ],
+            endpoints: vec![
+                // The collection's domain host wins over the endpoint's own domain string
+                in_collection(endpoint("list-pets", "legacy.example.com", "GET", "/pets"), "pets"),
+                in_collection(endpoint("get-user", "api.example.com", "GET", "/users/{id}"), "users"),
+                endpoint("health", "api.example.com", "GET", "/health"),
+            ],
+            ..Default::default()
+        });
+
+        let m = table.lookup("api.example.com", "GET", "/v1/pets").unwrap();
+        assert_eq!(m.endpoint.id, "list-pets");
+        assert_eq!(m.domain.as_ref().map(|d| d.id.as_str()), Some("d1"));
+
+        let m = table.lookup("api.example.com", "GET", "/v2/users/5").unwrap();
+        assert_eq!(m.params.get("id").map(String::as_str), Some("5"));
+
+        assert!(table.lookup("api.example.com", "GET", "/pets").is_none());
+        assert!(table.lookup("legacy.example.com", "GET", "/v1/pets").is_none());
+        assert!(table.lookup("api.example.com", "GET", "/health").is_some());
+    }
+
+    #[test]
+    fn test_disabled_domain_and_collection_are_not_routed() {
+        let table = RouteTable::build(RoutingData {
+            domains: vec![
+                domain("off", "off.example.com", false),
+                domain("on", "on.example.com", true),
+            ],
+            collections: vec![
+                collection("c-off", "on", "", false),
+                collection("c-on", "on", "", true),
+            ],
+            endpoints: vec![
+                endpoint("no-collection", "off.example.com", "GET", "/a"),
+                in_collection(endpoint("disabled-collection", "on.example.com", "GET", "/b"), "c-off"),
+                in_collection(endpoint("enabled", "on.example.com", "GET", "/c"), "c-on"),
+            ],
+            ..Default::default()
+        });
+
+        assert!(table.lookup("off.example.com", "GET", "/a").is_none());
+        assert!(table.lookup("on.example.com", "GET", "/b").is_none());
+        assert!(table.lookup("on.example.com", "GET", "/c").is_some());
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_check_uses_mounted_paths() {
+        let data = RoutingData {
+            domains: vec![domain("d1", "api.example.com", true)],
+            collections: vec![collection("v1", "d1", "/v1", false)],
+            endpoints: vec![in_collection(endpoint("list", "api.example.com", "GET", "/pets"), "v1")],
+            ..Default::default()
+        };
+
+        // Conflicts are reported even while the collection is disabled
+        let err = RouteTable::check(&data, &endpoint("new", "api.example.com", "GET", "/v1/pets"));
+        assert!(matches!(err, Err(RouteError::Conflict { .. })));
+
+        assert!(RouteTable::check(&data, &endpoint("new", "api.example.com", "GET", "/pets")).is_ok());
+    }
+
+    #[test]
+    fn test_wildcard_host_reports_subdomain() {
+        let table = RouteTable::build(RoutingData {
+            domains: vec![
+                domain("tenants", "*.example.com", true),
+                domain("www", "www.example.com", true),
+            ],
+            endpoints: vec![
+                endpoint("tenant-home", "*.example.com", "GET", "/"),
+                endpoint("www-home", "www.example.com", "GET", "/"),
+            ],
+            ..Default::default()
+        });
+
+        let m = table.lookup("acme.example.com", "GET", "/").unwrap();
+        assert_eq!(m.endpoint.id, "tenant-home");
+        assert_eq!(m.subdomain.as_deref(), Some("acme"));
+
+        // Exact hosts win over wildcards
+        let m = table.lookup("www.example.com", "GET", "/").unwrap();
+        assert_eq!(m.endpoint.id, "www-home");
+        assert_eq!(m.subdomain, None);
+
+        // A wildcard covers exactly one label
+        assert!(table.lookup("a.b.example.com", "GET", "/").is_none());
+        assert!(table.lookup("example.com", "GET", "/").is_none());
+    }
+
+    #[test]
+    fn test_aliases_and_default_host() {
+        let mut apex = domain("apex", "example.com", true);
+        apex.aliases = vec!["www.example.com".to_string()];
+
+        let table = RouteTable::build(RoutingData {
+            domains: vec![apex, domain("empty", "empty.example.com", true)],
+            endpoints: vec![
+                endpoint("home", "example.com", "GET", "/"),
+                endpoint("about", "www.example.com", "GET", "/about"),
+            ],
+            default_host: Some("www.example.com".to_string()),
+            ..Default::default()
+        });
+
+        // Endpoints on an alias are mounted on the canonical host
+        let m = table.lookup("www.example.com", "GET", "/").unwrap();
+        assert_eq!(m.endpoint.id, "home");
+        assert_eq!(m.domain.as_ref().map(|d| d.id.as_str()), Some("apex"));
+        assert!(table.lookup("example.com", "GET", "/about").is_some());
+
+        // Unknown hosts fall back to the default host
+        assert_eq!(table.lookup("unknown.test", "GET", "/").unwrap().endpoint.id, "home");
+
+        // A known host without the route does not fall back
+        assert!(table.lookup("empty.example.com", "GET", "/").is_none());
+    }
+
+    #[test]
+    fn test_route_middleware() {
+        use crate::router::cors::CorsSettings;
+        use crate::router::middleware::{CacheSettings, RequestSizeSettings};
+
+        let mut api = domain("api", "api.example.com", true);
+        api.settings.cors = Some(CorsSettings { allowed_origins: vec!["*".to_string()], ..Default::default() });
+        api.settings.middleware = vec![Middleware::Cache(CacheSettings { ttl_secs: 60 })];
+        let mut v1 = collection("v1", "api", "/v1", true);
+        v1.settings.middleware = vec![Middleware::RequestSize(RequestSizeSettings { max_bytes: 1024 })];
+        let mut upload = in_collection(endpoint("upload", "", "POST", "/upload"), "v1");
+        upload.settings.skip_middleware = vec![MiddlewareKind::Cache, MiddlewareKind::RequestSize];
+
+        let table = RouteTable::build(RoutingData {
+            domains: vec![api],
+            collections: vec![v1],
+            endpoints: vec![in_collection(endpoint("list", "", "GET", "/items"), "v1"), upload],
+            ..Default::default()
+        });
+
+        let list = table.lookup("api.example.com", "GET", "/v1/items").unwrap();
+        assert_eq!(list.middleware.kinds(), vec![MiddlewareKind::Cors, MiddlewareKind::RequestSize, MiddlewareKind::Cache]);
+        assert!(list.middleware.cors().is_some());
+
+        let upload = table.lookup("api.example.com", "POST", "/v1/upload").unwrap();
+        assert_eq!(upload.middleware.kinds(), vec![MiddlewareKind::Cors]);
+    }
+
+    #[test]
+    fn test_normalize_hosts() {
+        assert_eq!(normalize_host_pattern("*.Example.COM.").unwrap(), "*.example.com");
+        assert!(normalize_host_pattern("").is_err());
+        assert!(normalize_host_pattern("*.").is_err());
+        assert!(normalize_host_pattern("a.*.example.com").is_err());
+        assert!(normalize_host_pattern("example.com:8080").is_err());
+
+        assert_eq!(normalize_request_host("API.example.com:8080"), "api.example.com");
+        assert_eq!(normalize_request_host("example.com."), "example.com");
+        assert_eq!(normalize_request_host("[::1]:8080"), "::1");
+    }
+
+    #[test]
+    fn test_allowed_methods() {
+        let table = build(vec![
+            endpoint("list", "api.test", "GET", "/pets"),
+            endpoint("create", "api.test", "POST", "/pets"),
+            endpoint("delete", "api.test", "DELETE", "/pets/{id:int}"),
+        ]);
+
+        let allowed = table.allowed_methods("api.test", "/pets").unwrap();
+        assert_eq!(allowed.methods, vec!["GET", "HEAD", "OPTIONS", "POST"]);
+        assert_eq!(table.allowed_methods("api.test", "/pets/7").unwrap().methods, vec!["DELETE", "OPTIONS"]);
+        assert!(table.allowed_methods("api.test", "/pets/abc").is_none());
+        assert!(table.allowed_methods("other.test", "/pets").is_none());
+    }
+
+    #[test]
+    fn test_shared_table_rebuild_swaps_snapshot() {
+        let shared = SharedRouteTable::new();
+        let before = shared.load();
+        assert!(before.is_empty());
+
+        let len = shared.rebuild(|| Ok(build(vec![
+            endpoint("root", "localhost", "GET", "/"),
+        ]))).unwrap();
+
+        assert_eq!(len, 1);
+        assert!(before.is_empty());
+        assert!(shared.load().lookup("localhost", "GET", "/").is_some());
+    }
+}
@@ -0,0 +1,673 @@
+//! Upstream pools
+//!
+//! A pool is a named group of backend base URLs that proxy endpoints can
+//! forward to instead of a single `upstream`. Each request is sent to one
+//! target, picked round-robin, by fewest in-flight requests, or by
+//! consistent hashing of the client IP (or a request header) so that a
+//! client keeps reaching the same backend while the pool is unchanged.
+//!
+//! Targets leave the rotation in two ways. Active health checks request a
+//! path on every target at an interval; a target is marked down after
+//! `unhealthy_threshold` failed checks in a row and up again after
+//! `healthy_threshold` passing ones. Outlier detection watches live traffic:
+//! after `consecutive_failures` connection errors, timeouts or 502/503/504
+//! responses in a row, the target is ejected for `ejection_secs`.
+//!
+//! Pools are rebuilt from the database after every admin change. A target
+//! whose URL is still listed keeps its health state and counters.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+use super::problem::ProblemKind;
+use crate::api::Upstream;
+use crate::AppState;
+
+/// How often the health check loop looks for due checks
+const CHECK_TICK: Duration = Duration::from_secs(1);
+
+/// Points each target gets on the consistent hash ring
+const VIRTUAL_NODES: usize = 64;
+
+/// How a pool picks the target for a request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Balance {
+    /// Each target in turn
+    #[default]
+    RoundRobin,
+    /// The target with the fewest requests in flight
+    LeastConnections,
+    /// The target the request's hash key maps to
+    ConsistentHash,
+}
+
+/// Balancing and health settings of an upstream pool
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UpstreamSettings {
+    #[serde(default)]
+    pub balance: Balance,
+
+    /// Request header hashed by `consistent_hash` (default: the client IP)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_header: Option<String>,
+
+    /// Active health checks; without them every target counts as healthy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheck>,
+
+    /// Passive ejection of targets that keep failing live requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outlier_detection: Option<OutlierDetection>,
+}
+
+/// Periodic `GET` of a path on every target
+///
+/// A check passes on a 2xx or 3xx response within `timeout_secs`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    /// Path requested on each target, such as `/health`
+    pub path: String,
+
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+
+    #[serde(default = "default_check_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// Passing checks in a row that bring a target back
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32,
+
+    /// Failed checks in a row that take a target out
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+}
+
+fn default_interval_secs() -> u64 {
+    10
+}
+
+fn default_check_timeout_secs() -> u64 {
+    2
+}
+
+fn default_healthy_threshold() -> u32 {
+    2
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+/// Ejection of targets after failed requests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutlierDetection {
+    /// Failed requests in a row that eject a target
+    #[serde(default = "default_consecutive_failures")]
+    pub consecutive_failures: u32,
+
+    /// How long an ejected target is skipped
+    #[serde(default = "default_ejection_secs")]
+    pub ejection_secs: u64,
+}
+
+fn default_consecutive_failures() -> u32 {
+    5
+}
+
+fn default_ejection_secs() -> u64 {
+    30
+}
+
+impl UpstreamSettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(name) = &self.hash_header {
+            axum::http::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("Invalid header name: {}", name))?;
+        }
+        if let Some(check) = &self.health_check {
+            if !check.path.starts_with('/') {
+                return Err(format!("Health check path must start with '/': {}", check.path));
+            }
+            if check.interval_secs == 0 || check.timeout_secs == 0 {
+                return Err("Health check interval_secs and timeout_secs must be greater than 0".to_string());
+            }
+            if check.healthy_threshold == 0 || check.unhealthy_threshold == 0 {
+                return Err("Health check thresholds must be greater than 0".to_string());
+            }
+        }
+        if let Some(outlier) = &self.outlier_detection {
+            if outlier.consecutive_failures == 0 || outlier.ejection_secs == 0 {
+                return Err("consecutive_failures and ejection_secs must be greater than 0".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that a pool name can be referenced from proxy settings
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid upstream name '{}' (use letters, digits, '-', '_' and '.')", name))
+    }
+}
+
+/// Health and traffic state of a target
+#[derive(Debug)]
+struct TargetState {
+    /// Result of the active health checks
+    healthy: bool,
+    /// Passing or failing checks in a row, whichever is current
+    check_streak: u32,
+    /// Failed requests in a row, for outlier detection
+    failure_streak: u32,
+    ejected_until: Option<Instant>,
+}
+
+/// One backend of a pool
+#[derive(Debug)]
+struct Target {
+    url: String,
+    state: Mutex<TargetState>,
+    active: AtomicUsize,
+    requests: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl Target {
+    fn new(url: String) -> Self {
+        Self {
+            url,
+            state: Mutex::new(TargetState { healthy: true, check_streak: 0, failure_streak: 0, ejected_until: None }),
+            active: AtomicUsize::new(0),
+            requests: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    fn is_available(&self, now: Instant) -> bool {
+        let state = self.state.lock().unwrap();
+        state.healthy && state.ejected_until.is_none_or(|until| until <= now)
+    }
+
+    /// Record the result of a health check
+    fn record_check(&self, passed: bool, check: &HealthCheck) {
+        let mut state = self.state.lock().unwrap();
+        if passed == state.healthy {
+            state.check_streak = 0;
+            return;
+        }
+        state.check_streak += 1;
+        let threshold = if passed { check.healthy_threshold } else { check.unhealthy_threshold };
+        if state.check_streak >= threshold {
+            state.healthy = passed;
+            state.check_streak = 0;
+            if passed {
+                tracing::info!(target = %self.url, "Upstream target is healthy again");
+            } else {
+                tracing::warn!(target = %self.url, "Upstream target failed its health checks");
+            }
+        }
+    }
+}
+
+/// A named group of targets
+struct Pool {
+    name: String,
+    settings: UpstreamSettings,
+    targets: Vec<Arc<Target>>,
+    /// Hash ring for `consistent_hash`: point and target index, sorted
+    ring: Vec<(u64, usize)>,
+    next: AtomicUsize,
+    next_check: Mutex<Instant>,
+}
+
+impl Pool {
+    fn new(upstream: &Upstream, previous: Option<&Pool>) -> Self {
+        let targets: Vec<Arc<Target>> = upstream.targets.iter()
+            .map(|url| {
+                let kept = previous.and_then(|p| p.targets.iter().find(|t| t.url == *url)).cloned();
+                kept.unwrap_or_else(|| Arc::new(Target::new(url.clone())))
+            })
+            .collect();
+
+        // Without health checks nothing would ever mark a target healthy again
+        if upstream.settings.health_check.is_none() {
+            for target in &targets {
+                target.state.lock().unwrap().healthy = true;
+            }
+        }
+
+        let mut ring: Vec<(u64, usize)> = targets.iter().enumerate()
+            .flat_map(|(i, t)| (0..VIRTUAL_NODES).map(move |v| (hash(&format!("{}#{}", t.url, v)), i)))
+            .collect();
+        ring.sort_unstable();
+
+        Self {
+            name: upstream.name.clone(),
+            settings: upstream.settings.clone(),
+            targets,
+            ring,
+            next: AtomicUsize::new(0),
+            next_check: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Pick an available target
+    fn select(&self, headers: &HeaderMap, client_ip: Option<&str>) -> Option<&Arc<Target>> {
+        let now = Instant::now();
+        let available: Vec<bool> = self.targets.iter().map(|t| t.is_available(now)).collect();
+        let len = self.targets.len();
+
+        let index = match self.settings.balance {
+            Balance::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..len).map(|i| (start + i) % len).find(|&i| available[i])
+            }
+            // Rotating the starting point spreads ties between idle targets
+            Balance::LeastConnections => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..len).map(|i| (start + i) % len)
+                    .filter(|&i| available[i])
+                    .min_by_key(|&i| self.targets[i].active.load(Ordering::Relaxed))
+            }
+            Balance::ConsistentHash => {
+                let key = match &self.settings.hash_header {
+                    Some(name) => headers.get(name.as_str()).and_then(|v| v.to_str().ok()),
+                    None => client_ip,
+                };
+                let point = hash(key.unwrap_or_default());
+                let start = self.ring.partition_point(|&(p, _)| p < point);
+                (0..self.ring.len())
+                    .map(|i| self.ring[(start + i) % self.ring.len()].1)
+                    .find(|&i| available[i])
+            }
+        };
+        index.map(|i| &self.targets[i])
+    }
+
+    /// Check every target's health, if the pool has checks and they are due
+    async fn check_if_due(&self, client: &reqwest::Client) {
+        let Some(check) = &self.settings.health_check else {
+            return;
+        };
+        {
+            let mut next_check = self.next_check.lock().unwrap();
+            let now = Instant::now();
+            if *next_check > now {
+                return;
+            }
+            *next_check = now + Duration::from_secs(check.interval_secs);
+        }
+        self.check(client, check).await;
+    }
+
+    async fn check(&self, client: &reqwest::Client, check: &HealthCheck) {
+        let timeout = Duration::from_secs(check.timeout_secs);
+        let checks = self.targets.iter().map(|target| async move {
+            let url = format!("{}{}", target.url.trim_end_matches('/'), check.path);
+            let passed = match client.get(&url).timeout(timeout).send().await {
+                Ok(response) => response.status().is_success() || response.status().is_redirection(),
+                Err(e) => {
+                    tracing::debug!(pool = %self.name, target = %target.url, "Health check failed: {}", e);
+                    false
+                }
+            };
+            target.record_check(passed, check);
+        });
+        futures::future::join_all(checks).await;
+    }
+}
+
+/// A target picked for one request
+///
+/// Counts as in flight until dropped; keep it alive while the response
+/// body streams.
+pub struct SelectedTarget {
+    target: Arc<Target>,
+    outlier_detection: Option<OutlierDetection>,
+}
+
+impl SelectedTarget {
+    fn new(target: Arc<Target>, outlier_detection: Option<OutlierDetection>) -> Self {
+        target.active.fetch_add(1, Ordering::Relaxed);
+        target.requests.fetch_add(1, Ordering::Relaxed);
+        Self { target, outlier_detection }
+    }
+
+    /// Base URL of the target
+    pub fn url(&self) -> &str {
+        &self.target.url
+    }
+
+    /// Record whether the target served the request, for outlier detection
+    pub fn report(&self, ok: bool) {
+        let mut state = self.target.state.lock().unwrap();
+        if ok {
+            state.failure_streak = 0;
+            return;
+        }
+        self.target.failures.fetch_add(1, Ordering::Relaxed);
+        state.failure_streak += 1;
+        if let Some(outlier) = &self.outlier_detection {
+            if state.failure_streak >= outlier.consecutive_failures {
+                state.failure_streak = 0;
+                state.ejected_until = Some(Instant::now() + Duration::from_secs(outlier.ejection_secs));
+                tracing::warn!(target = %self.target.url, "Ejecting upstream target for {}s", outlier.ejection_secs);
+            }
+        }
+    }
+}
+
+impl Drop for SelectedTarget {
+    fn drop(&mut self) {
+        self.target.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// State of a pool, reported by the admin stats API
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    pub name: String,
+    pub balance: Balance,
+    pub targets: Vec<TargetStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetStats {
+    pub url: String,
+    /// Result of the active health checks
+    pub healthy: bool,
+    /// Skipped after failed requests
+    pub ejected: bool,
+    /// Requests in flight
+    pub active: usize,
+    pub requests: u64,
+    pub failures: u64,
+}
+
+/// All enabled upstream pools, by name
+#[derive(Default)]
+pub struct UpstreamPools {
+    pools: RwLock<HashMap<String, Arc<Pool>>>,
+}
+
+impl UpstreamPools {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the pools with the enabled upstreams
+    pub fn reload(&self, upstreams: &[Upstream]) {
+        let mut pools = self.pools.write().unwrap();
+        let rebuilt = upstreams.iter()
+            .filter(|u| u.enabled)
+            .map(|u| (u.name.clone(), Arc::new(Pool::new(u, pools.get(&u.name).map(Arc::as_ref)))))
+            .collect();
+        *pools = rebuilt;
+    }
+
+    /// Pick a target of pool `name` for a request
+    ///
+    /// Fails when the pool does not exist (or is disabled) or none of its
+    /// targets is available.
+    pub fn select(&self, name: &str, headers: &HeaderMap, client_ip: Option<&str>) -> Result<SelectedTarget, ProblemKind> {
+        let pool = self.pools.read().unwrap().get(name).cloned().ok_or_else(|| {
+            tracing::warn!(pool = %name, "Unknown upstream pool");
+            ProblemKind::UpstreamUnavailable
+        })?;
+        let target = pool.select(headers, client_ip).ok_or_else(|| {
+            tracing::warn!(pool = %name, "No available upstream target");
+            ProblemKind::UpstreamUnavailable
+        })?;
+        Ok(SelectedTarget::new(target.clone(), pool.settings.outlier_detection.clone()))
+    }
+
+    /// Health and traffic of every pool, sorted by name
+    pub fn stats(&self) -> Vec<PoolStats> {
+        let now = Instant::now();
+        let pools = self.pools.read().unwrap();
+        let mut stats: Vec<PoolStats> = pools.values()
+            .map(|pool| PoolStats {
+                name: pool.name.clone(),
+                balance: pool.settings.balance,
+                targets: pool.targets.iter().map(|t| {
+                    let state = t.state.lock().unwrap();
+                    TargetStats {
+                        url: t.url.clone(),
+                        healthy: state.healthy,
+                        ejected: state.ejected_until.is_some_and(|until| until > now),
+                        active: t.active.load(Ordering::Relaxed),
+                        requests: t.requests.load(Ordering::Relaxed),
+                        failures: t.failures.load(Ordering::Relaxed),
+                    }
+                }).collect(),
+            })
+            .collect();
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        stats
+    }
+
+    /// Run the health checks that are due
+    pub async fn check_due(&self, client: &reqwest::Client) {
+        let pools: Vec<Arc<Pool>> = self.pools.read().unwrap().values().cloned().collect();
+        futures::future::join_all(pools.iter().map(|pool| pool.check_if_due(client))).await;
+    }
+}
+
+/// Run active health checks for as long as the gateway runs
+pub async fn run(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(CHECK_TICK);
+    loop {
+        interval.tick().await;
+        state.upstreams.check_due(&state.upstream_client).await;
+    }
+}
+
+/// FNV-1a, stable across builds and platforms unlike the std hasher
+///
+/// The result is mixed (as in splitmix64) so that keys differing only in
+/// their last bytes, like neighbouring IPs, spread over the whole ring.
+pub(crate) fn hash(key: &str) -> u64 {
+    let hash = key.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    let hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU16;
+
+    fn upstream(name: &str, targets: &[&str], settings: UpstreamSettings) -> Upstream {
+        Upstream {
+            id: name.to_string(),
+            name: name.to_string(),
+            targets: targets.iter().map(|t| t.to_string()).collect(),
+            settings,
+            enabled: true,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn pools(upstream: Upstream) -> UpstreamPools {
+        let pools = UpstreamPools::new();
+        pools.reload(&[upstream]);
+        pools
+    }
+
+    fn pick(pools: &UpstreamPools, client_ip: &str) -> String {
+        pools.select("users", &HeaderMap::new(), Some(client_ip)).unwrap().url().to_string()
+    }
+
+    /// A local backend answering every request with the status in the returned cell
+    async fn backend() -> (String, Arc<AtomicU16>) {
+        let status = Arc::new(AtomicU16::new(200));
+        let shared = status.clone();
+        let app = axum::Router::new().fallback(move || {
+            let status = shared.load(Ordering::Relaxed);
+            async move { axum::http::StatusCode::from_u16(status).unwrap() }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, status)
+    }
+
+    #[test]
+    fn test_round_robin_and_least_connections() {
+        let round_robin = pools(upstream("users", &["http://a", "http://b", "http://c"], Default::default()));
+        let picks: Vec<String> = (0..6).map(|_| pick(&round_robin, "")).collect();
+        assert_eq!(picks, ["http://a", "http://b", "http://c", "http://a", "http://b", "http://c"]);
+
+        let settings = UpstreamSettings { balance: Balance::LeastConnections, ..Default::default() };
+        let least = pools(upstream("users", &["http://a", "http://b"], settings));
+        let first = least.select("users", &HeaderMap::new(), None).unwrap();
+        // While the first request is in flight, new ones go to the other target
+        for _ in 0..3 {
+            assert_ne!(pick(&least, ""), first.url());
+        }
+        drop(first);
+        assert_eq!(least.stats()[0].targets.iter().map(|t| t.active).sum::<usize>(), 0);
+    }
+
+    #[test]
+    fn test_consistent_hash() {
+        let settings = UpstreamSettings { balance: Balance::ConsistentHash, ..Default::default() };
+        let three = pools(upstream("users", &["http://a", "http://b", "http://c"], settings.clone()));
+        let clients: Vec<String> = (0..50).map(|i| format!("10.0.0.{}", i)).collect();
+        let before: Vec<String> = clients.iter().map(|ip| pick(&three, ip)).collect();
+        assert_eq!(before, clients.iter().map(|ip| pick(&three, ip)).collect::<Vec<_>>());
+        assert!(["http://a", "http://b", "http://c"].iter().all(|t| before.iter().any(|p| p == t)));
+
+        // Removing a target only moves the clients that were on it
+        three.reload(&[upstream("users", &["http://a", "http://b"], settings)]);
+        for (ip, old) in clients.iter().zip(&before) {
+            let new = pick(&three, ip);
+            if old != "http://c" {
+                assert_eq!(&new, old);
+            }
+        }
+
+        // A configured header is hashed instead of the client IP
+        let settings = UpstreamSettings {
+            balance: Balance::ConsistentHash,
+            hash_header: Some("x-user".to_string()),
+            ..Default::default()
+        };
+        let by_header = pools(upstream("users", &["http://a", "http://b", "http://c"], settings));
+        let mut headers = HeaderMap::new();
+        headers.insert("x-user", "alice".parse().unwrap());
+        let alice = by_header.select("users", &headers, Some("10.0.0.1")).unwrap().url().to_string();
+        for i in 2..20 {
+            let ip = format!("10.0.0.{}", i);
+            assert_eq!(by_header.select("users", &headers, Some(&ip)).unwrap().url(), alice);
+        }
+    }
+
+    #[test]
+    fn test_outlier_ejection() {
+        let settings = UpstreamSettings {
+            outlier_detection: Some(OutlierDetection { consecutive_failures: 2, ejection_secs: 60 }),
+            ..Default::default()
+        };
+        let pools = pools(upstream("users", &["http://a", "http://b"], settings));
+
+        let a = pools.select("users", &HeaderMap::new(), None).unwrap();
+        assert_eq!(a.url(), "http://a");
+        a.report(false);
+        a.report(true);
+        a.report(false);
+        assert!(pools.stats()[0].targets.iter().all(|t| !t.ejected));
+        a.report(false);
+        drop(a);
+
+        let stats = pools.stats();
+        assert!(stats[0].targets[0].ejected);
+        assert_eq!(stats[0].targets[0].failures, 3);
+        for _ in 0..4 {
+            assert_eq!(pick(&pools, ""), "http://b");
+        }
+
+        // Unknown pools and pools without available targets are unavailable
+        assert!(matches!(pools.select("other", &HeaderMap::new(), None), Err(ProblemKind::UpstreamUnavailable)));
+        pools.select("users", &HeaderMap::new(), None).unwrap().report(false);
+        pools.select("users", &HeaderMap::new(), None).unwrap().report(false);
+        assert!(matches!(pools.select("users", &HeaderMap::new(), None), Err(ProblemKind::UpstreamUnavailable)));
+    }
+
+    #[tokio::test]
+    async fn test_health_checks_against_local_backends() {
+        let (up, _) = backend().await;
+        let (flaky, flaky_status) = backend().await;
+        let check = HealthCheck {
+            path: "/health".to_string(),
+            interval_secs: 60,
+            timeout_secs: 1,
+            healthy_threshold: 2,
+            unhealthy_threshold: 2,
+        };
+        let settings = UpstreamSettings { health_check: Some(check.clone()), ..Default::default() };
+        let pools = pools(upstream("users", &[&up, &flaky], settings));
+        let client = super::super::proxy::client().unwrap();
+        let pool = pools.pools.read().unwrap()["users"].clone();
+        let healthy = || pools.stats()[0].targets.iter().map(|t| t.healthy).collect::<Vec<_>>();
+
+        // The first check runs right away; the next one waits for the interval
+        flaky_status.store(503, Ordering::Relaxed);
+        pools.check_due(&client).await;
+        pools.check_due(&client).await;
+        assert_eq!(healthy(), [true, true]);
+
+        pool.check(&client, &check).await;
+        assert_eq!(healthy(), [true, false]);
+        for _ in 0..4 {
+            assert_eq!(pick(&pools, ""), up);
+        }
+
+        flaky_status.store(200, Ordering::Relaxed);
+        pool.check(&client, &check).await;
+        assert_eq!(healthy(), [true, false]);
+        pool.check(&client, &check).await;
+        assert_eq!(healthy(), [true, true]);
+
+        // Health state survives a reload that keeps the target
+        flaky_status.store(503, Ordering::Relaxed);
+        pool.check(&client, &check).await;
+        pool.check(&client, &check).await;
+        let settings = UpstreamSettings { health_check: Some(check), balance: Balance::LeastConnections, ..Default::default() };
+        pools.reload(&[upstream("users", &[&up, &flaky], settings)]);
+        assert_eq!(healthy(), [true, false]);
+        assert_eq!(pools.stats()[0].balance, Balance::LeastConnections);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate_name("users-v2.internal").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("a/b").is_err());
+
+        let check: HealthCheck = serde_json::from_str(r#"{"path": "/health"}"#).unwrap();
+        assert_eq!((check.interval_secs, check.healthy_threshold, check.unhealthy_threshold), (10, 2, 3));
+        let mut settings = UpstreamSettings { health_check: Some(check), ..Default::default() };
+        assert!(settings.validate().is_ok());
+        settings.health_check.as_mut().unwrap().path = "health".to_string();
+        assert!(settings.validate().is_err());
+
+        let settings = UpstreamSettings { hash_header: Some("bad header".to_string()), ..Default::default() };
+        assert!(settings.validate().is_err());
+    }
+}
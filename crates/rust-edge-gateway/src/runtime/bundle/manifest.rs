@@ -7,6 +7,11 @@ use std::collections::HashMap;
 use std::path::Path;
 use anyhow::{Context, Result};
 
+use crate::router::middleware::{self, Middleware, MiddlewareKind};
+use crate::router::proxy::ProxySettings;
+use crate::router::rules::Rule;
+use crate::router::site::SiteSettings;
+
 /// The bundle manifest (bundle.yaml)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BundleManifest {
@@ -21,6 +26,32 @@ pub struct BundleManifest {
     #[serde(default)]
     pub tls: Option<TlsConfig>,
 
+    /// Static site served on the bundle's domains. Files under the bundle's
+    /// `site/` directory are installed as `<sites dir>/<bundle name>`.
+    #[serde(default)]
+    pub site: Option<SiteSettings>,
+
+    /// Redirect and rewrite rules for the bundle's domains. When present they
+    /// replace each domain's rules, in order.
+    #[serde(default)]
+    pub rules: Option<Vec<Rule>>,
+
+    /// Named middleware definitions, referred to by name from
+    /// `domain_middleware`, `collection_middleware` and each route's
+    /// `middleware`
+    #[serde(default)]
+    pub middleware: HashMap<String, Middleware>,
+
+    /// Middleware of the bundle's domains. When present it replaces each
+    /// domain's middleware.
+    #[serde(default)]
+    pub domain_middleware: Option<Vec<String>>,
+
+    /// Middleware of the collection the bundle is imported into. When
+    /// present it replaces the collection's middleware.
+    #[serde(default)]
+    pub collection_middleware: Option<Vec<String>>,
+
     /// Service configurations
     #[serde(default)]
     pub services: HashMap<String, ServiceConfig>,
@@ -55,7 +86,10 @@ pub struct BundleInfo {
 }
 
 /// TLS configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Applied to the bundle's domains on import, and stored as their `tls`
+/// domain setting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TlsConfig {
     /// TLS provider: letsencrypt, manual, none
     #[serde(default = "default_tls_provider")]
@@ -70,6 +104,16 @@ pub struct TlsConfig {
     pub http_challenge: bool,
 }
 
+impl TlsConfig {
+    /// Check that the provider is one the gateway supports
+    pub fn validate(&self) -> Result<(), String> {
+        match self.provider.as_str() {
+            "letsencrypt" | "manual" | "none" => Ok(()),
+            other => Err(format!("Unknown TLS provider: {} (expected letsencrypt, manual or none)", other)),
+        }
+    }
+}
+
 fn default_tls_provider() -> String { "none".to_string() }
 fn default_true() -> bool { true }
 
@@ -109,16 +153,31 @@ pub struct RouteConfig {
     /// URL path pattern (supports {param} syntax)
     pub path: String,
     
-    /// Handler name (matches .so/.dll filename without extension)
+    /// Handler name (matches .so/.dll filename without extension); optional
+    /// for proxy routes
+    #[serde(default)]
     pub handler: String,
+
+    /// Forward the route to an upstream instead of a handler
+    #[serde(default)]
+    pub proxy: Option<ProxySettings>,
     
-    /// Optional middleware
+    /// Names of the route's middleware, defined in the manifest's
+    /// `middleware` section
     #[serde(default)]
     pub middleware: Vec<String>,
+
+    /// Kinds of domain and collection middleware the route does without
+    #[serde(default)]
+    pub skip_middleware: Vec<MiddlewareKind>,
     
     /// Optional timeout override (seconds)
     #[serde(default)]
     pub timeout_secs: Option<u64>,
+    
+    /// Optional request body size limit override (bytes)
+    #[serde(default)]
+    pub max_body_size: Option<usize>,
 }
 
 impl BundleManifest {
@@ -127,8 +186,9 @@ impl BundleManifest {
         // First, substitute environment variables
         let expanded = expand_env_vars(yaml);
         
-        // Then parse
-        serde_yaml::from_str(&expanded)
+        // Then parse, reading enums (rules, middleware) from single-key maps
+        // such as `https: { status: 308 }` as well as from YAML tags
+        serde_yaml::with::singleton_map_recursive::deserialize(serde_yaml::Deserializer::from_str(&expanded))
             .context("Failed to parse bundle manifest")
     }
     
@@ -157,8 +217,40 @@ impl BundleManifest {
             if route.path.is_empty() {
                 anyhow::bail!("Route path is required");
             }
-            if route.handler.is_empty() {
-                anyhow::bail!("Route handler is required");
+            match &route.proxy {
+                Some(proxy) => {
+                    if let Err(e) = proxy.validate() {
+                        anyhow::bail!("Route {} {}: {}", route.method, route.path, e);
+                    }
+                }
+                None if route.handler.is_empty() => anyhow::bail!("Route handler is required"),
+                None => {}
+            }
+            if route.max_body_size == Some(0) {
+                anyhow::bail!("Route max_body_size must be greater than 0");
+            }
+            if route.timeout_secs == Some(0) {
+                anyhow::bail!("Route timeout_secs must be greater than 0");
+            }
+            if let Err(e) = self.resolve_middleware(&route.middleware) {
+                anyhow::bail!("Route {} {}: {}", route.method, route.path, e);
+            }
+        }
+
+        // Validate middleware
+        for (name, definition) in &self.middleware {
+            if let Err(e) = definition.validate() {
+                anyhow::bail!("Middleware '{}': {}", name, e);
+            }
+        }
+        if let Some(names) = &self.domain_middleware {
+            if let Err(e) = self.resolve_middleware(names) {
+                anyhow::bail!("domain_middleware: {}", e);
+            }
+        }
+        if let Some(names) = &self.collection_middleware {
+            if let Err(e) = self.resolve_middleware(names) {
+                anyhow::bail!("collection_middleware: {}", e);
             }
         }
         
@@ -172,6 +264,35 @@ impl BundleManifest {
         Ok(())
     }
     
+    /// Find the route entry for an endpoint
+    ///
+    /// Routes match on method and path, or else on handler name (compared
+    /// after normalization, so `getUser` matches `get_user`) when exactly one
+    /// route uses that handler.
+    pub fn find_route(&self, method: &str, path: &str, handler: &str) -> Option<&RouteConfig> {
+        if let Some(route) = self.routes.iter().find(|r| r.method.eq_ignore_ascii_case(method) && r.path == path) {
+            return Some(route);
+        }
+
+        let handler = crate::bundle::normalize_handler_name(handler);
+        let mut by_handler = self.routes.iter().filter(|r| {
+            !r.handler.is_empty() && crate::bundle::normalize_handler_name(&r.handler) == handler
+        });
+        match (by_handler.next(), by_handler.next()) {
+            (Some(route), None) => Some(route),
+            _ => None,
+        }
+    }
+    
+    /// Look up the middleware definitions a list of names refers to
+    pub fn resolve_middleware(&self, names: &[String]) -> Result<Vec<Middleware>, String> {
+        let list = names.iter()
+            .map(|name| self.middleware.get(name).cloned().ok_or_else(|| format!("Unknown middleware '{}'", name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        middleware::validate_list(&list)?;
+        Ok(list)
+    }
+
     /// Get the connection string for a service
     pub fn get_service_connection(&self, name: &str) -> Option<String> {
         self.services.get(name).and_then(|s| {
@@ -255,6 +376,10 @@ services:
     kind: redis
     url: redis://localhost:6379
 
+rules:
+  - https: { status: 308 }
+  - redirect: { prefix: /v1, to: /v2 }
+
 routes:
   - method: GET
     path: /users/{id}
@@ -264,6 +389,7 @@ routes:
   - method: POST
     path: /users
     handler: create_user
+    max_body_size: 1048576
 "#;
         
         let manifest = BundleManifest::parse(yaml).unwrap();
@@ -272,8 +398,123 @@ routes:
         assert_eq!(manifest.services.len(), 2);
         assert_eq!(manifest.routes.len(), 2);
         assert!(manifest.tls.is_some());
+        assert_eq!(manifest.rules.as_ref().map(Vec::len), Some(2));
+        assert_eq!(manifest.routes[0].max_body_size, None);
+        assert_eq!(manifest.routes[1].max_body_size, Some(1048576));
     }
     
+    #[test]
+    fn test_find_route() {
+        let yaml = r#"
+bundle:
+  name: users-api
+  version: 1.0.0
+
+routes:
+  - method: GET
+    path: /users/{id}
+    handler: getUser
+  - method: POST
+    path: /users
+    handler: save_user
+  - method: PUT
+    path: /users/{id}
+    handler: save_user
+"#;
+        
+        let manifest = BundleManifest::parse(yaml).unwrap();
+        assert_eq!(manifest.find_route("get", "/users/{id}", "other").unwrap().handler, "getUser");
+        assert_eq!(manifest.find_route("POST", "/users", "saveUser").unwrap().path, "/users");
+        assert_eq!(manifest.find_route("PUT", "/users/{id}", "save_user").unwrap().method, "PUT");
+        assert!(manifest.find_route("DELETE", "/users", "delete_user").is_none());
+
+        // The handler name alone picks a route only if no other route uses it
+        assert_eq!(manifest.find_route("GET", "/v2/users/{id}", "get_user").unwrap().handler, "getUser");
+        assert!(manifest.find_route("PATCH", "/v2/users/{id}", "save_user").is_none());
+    }
+    
+    #[test]
+    fn test_parse_proxy_route() {
+        let yaml = r#"
+bundle:
+  name: edge
+  version: 1.0.0
+
+routes:
+  - method: GET
+    path: /users/{*rest}
+    proxy:
+      upstream: http://users.internal:8080
+      strip_prefix: /users
+      request_headers:
+        add:
+          x-api-version: "2"
+        remove:
+          - cookie
+      retries: 2
+    timeout_secs: 5
+  - method: POST
+    path: /orders
+"#;
+
+        let manifest = BundleManifest::parse(yaml).unwrap();
+        let proxy = manifest.routes[0].proxy.as_ref().unwrap();
+        assert_eq!(proxy.upstream, "http://users.internal:8080");
+        assert_eq!(proxy.retries, 2);
+        assert_eq!(proxy.request_headers.remove, vec!["cookie"]);
+        assert!(manifest.routes[0].handler.is_empty());
+
+        // The second route has neither a handler nor a proxy
+        assert!(manifest.validate().is_err());
+        let mut manifest = manifest;
+        manifest.routes.pop();
+        manifest.validate().unwrap();
+    }
+
+    #[test]
+    fn test_parse_middleware() {
+        let yaml = r#"
+bundle:
+  name: edge
+  version: 1.0.0
+
+middleware:
+  auth:
+    jwt:
+      algorithm: HS256
+      secret: ${MISSING_JWT_SECRET:-s3cret}
+  limit:
+    rate-limit:
+      requests: 100
+      window_secs: 60
+
+domain_middleware: [limit]
+
+routes:
+  - method: GET
+    path: /users
+    handler: list_users
+    middleware: [auth]
+  - method: GET
+    path: /health
+    handler: health
+    skip_middleware: [rate-limit]
+"#;
+
+        let manifest = BundleManifest::parse(yaml).unwrap();
+        manifest.validate().unwrap();
+        let route = manifest.resolve_middleware(&manifest.routes[0].middleware).unwrap();
+        assert_eq!(route[0].kind(), MiddlewareKind::Jwt);
+        assert_eq!(manifest.routes[1].skip_middleware, vec![MiddlewareKind::RateLimit]);
+
+        // Names must be defined, and a list may use each kind once
+        assert!(manifest.resolve_middleware(&["missing".to_string()]).is_err());
+        assert!(manifest.resolve_middleware(&["auth".to_string(), "auth".to_string()]).is_err());
+        let mut manifest = manifest;
+        manifest.routes[0].middleware.push("missing".to_string());
+        assert!(manifest.validate().is_err());
+    }
+
     #[test]
     fn test_env_var_expansion() {
         std::env::set_var("TEST_VAR", "hello");
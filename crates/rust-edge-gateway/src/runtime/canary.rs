@@ -0,0 +1,245 @@
+//! Canary releases
+//!
+//! A candidate version of an endpoint's handler can be loaded next to the
+//! running (primary) version. Each request is routed to the candidate with
+//! probability `weight` percent. With a `sticky` key the choice is made by
+//! hashing the key, so one user keeps seeing the same version; without one
+//! the request id is hashed and requests are spread independently.
+//!
+//! Both versions count requests, errors and latency from the moment the
+//! candidate is loaded, so they can be compared before the candidate is
+//! promoted or aborted.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+/// Suffix of the build id candidate versions are compiled under
+const CANDIDATE_SUFFIX: &str = "-canary";
+
+/// How traffic is split between the primary and the candidate version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CanarySettings {
+    /// Percentage of requests routed to the candidate (0-100)
+    pub weight: u8,
+
+    /// What keeps a client on one version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sticky: Option<Sticky>,
+}
+
+/// Request attribute hashed to pick a version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sticky {
+    /// Value of a request header
+    Header(String),
+    /// Value of a cookie
+    Cookie(String),
+    /// The resolved client IP
+    ClientIp,
+}
+
+/// The parts of a request used to pick a version
+pub struct SplitRequest<'a> {
+    pub headers: &'a HeaderMap,
+    pub client_ip: Option<&'a str>,
+    pub request_id: &'a str,
+}
+
+impl CanarySettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        if self.weight > 100 {
+            return Err(format!("Weight must be between 0 and 100, got {}", self.weight));
+        }
+        match &self.sticky {
+            Some(Sticky::Header(name)) => {
+                axum::http::HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| format!("Invalid header name: {}", name))?;
+            }
+            Some(Sticky::Cookie(name)) if name.is_empty() || name.contains([';', '=', ' ']) => {
+                return Err(format!("Invalid cookie name: {}", name));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Whether a request goes to the candidate
+    ///
+    /// Requests without the sticky attribute are split by request id.
+    pub fn routes_to_candidate(&self, request: &SplitRequest<'_>) -> bool {
+        let key = match &self.sticky {
+            Some(Sticky::Header(name)) => request.headers.get(name.as_str()).and_then(|v| v.to_str().ok()),
+            Some(Sticky::Cookie(name)) => cookie(request.headers, name),
+            Some(Sticky::ClientIp) => request.client_ip,
+            None => None,
+        };
+        bucket(key.unwrap_or(request.request_id)) < self.weight
+    }
+}
+
+/// Value of the cookie `name` in the request's `Cookie` headers
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get_all(axum::http::header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Bucket 0-99 of a key, stable across restarts
+fn bucket(key: &str) -> u8 {
+    (crate::router::upstream::hash(key) % 100) as u8
+}
+
+/// Build id a candidate is compiled under, kept apart from the primary's
+pub fn candidate_build_id(endpoint_id: &str) -> String {
+    format!("{}{}", endpoint_id, CANDIDATE_SUFFIX)
+}
+
+/// Request counters of one loaded handler version
+#[derive(Debug, Default)]
+pub struct VersionCounters {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    latency_micros: AtomicU64,
+    max_latency_micros: AtomicU64,
+}
+
+impl VersionCounters {
+    /// Record a finished call; `failed` is a 5xx response, panic or timeout
+    pub fn record(&self, failed: bool, elapsed: Duration) {
+        let micros = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_latency_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    /// Start counting from zero
+    pub fn reset(&self) {
+        self.requests.store(0, Ordering::Relaxed);
+        self.errors.store(0, Ordering::Relaxed);
+        self.latency_micros.store(0, Ordering::Relaxed);
+        self.max_latency_micros.store(0, Ordering::Relaxed);
+    }
+
+    /// Current values, for a version with `generation` and `active` requests
+    pub fn snapshot(&self, generation: u64, active: u64) -> VersionStats {
+        let requests = self.requests.load(Ordering::Relaxed);
+        let errors = self.errors.load(Ordering::Relaxed);
+        let latency = self.latency_micros.load(Ordering::Relaxed);
+        let ratio = |n: u64| if requests == 0 { 0.0 } else { n as f64 / requests as f64 };
+        VersionStats {
+            generation,
+            requests,
+            errors,
+            error_rate: ratio(errors),
+            mean_latency_ms: ratio(latency) / 1000.0,
+            max_latency_ms: self.max_latency_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+            active_requests: active,
+        }
+    }
+}
+
+/// Counters of one handler version, as reported by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionStats {
+    pub generation: u64,
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    pub mean_latency_ms: f64,
+    pub max_latency_ms: f64,
+    pub active_requests: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn request<'a>(headers: &'a HeaderMap, client_ip: Option<&'a str>, request_id: &'a str) -> SplitRequest<'a> {
+        SplitRequest { headers, client_ip, request_id }
+    }
+
+    #[test]
+    fn test_weight_bounds() {
+        let headers = HeaderMap::new();
+        let none = CanarySettings { weight: 0, sticky: None };
+        let all = CanarySettings { weight: 100, sticky: None };
+        for i in 0..200 {
+            let id = format!("req-{}", i);
+            assert!(!none.routes_to_candidate(&request(&headers, None, &id)));
+            assert!(all.routes_to_candidate(&request(&headers, None, &id)));
+        }
+        assert!(CanarySettings { weight: 101, sticky: None }.validate().is_err());
+    }
+
+    #[test]
+    fn test_split_is_close_to_weight() {
+        let headers = HeaderMap::new();
+        let settings = CanarySettings { weight: 20, sticky: None };
+        let candidate = (0..10_000)
+            .filter(|i| settings.routes_to_candidate(&request(&headers, None, &format!("req-{}", i))))
+            .count();
+        assert!((1_700..2_300).contains(&candidate), "{} of 10000 went to the candidate", candidate);
+    }
+
+    #[test]
+    fn test_sticky_keys() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-user", HeaderValue::from_static("alice"));
+        headers.insert("cookie", HeaderValue::from_static("theme=dark; session=abc123"));
+
+        // The same key picks the same version whatever the request id
+        let by_header = CanarySettings { weight: 50, sticky: Some(Sticky::Header("x-user".to_string())) };
+        let first = by_header.routes_to_candidate(&request(&headers, None, "a"));
+        assert!((0..50).all(|i| by_header.routes_to_candidate(&request(&headers, None, &i.to_string())) == first));
+
+        let by_cookie = CanarySettings { weight: 50, sticky: Some(Sticky::Cookie("session".to_string())) };
+        assert_eq!(cookie(&headers, "session"), Some("abc123"));
+        let first = by_cookie.routes_to_candidate(&request(&headers, None, "a"));
+        assert!((0..50).all(|i| by_cookie.routes_to_candidate(&request(&headers, None, &i.to_string())) == first));
+
+        let by_ip = CanarySettings { weight: 50, sticky: Some(Sticky::ClientIp) };
+        let first = by_ip.routes_to_candidate(&request(&headers, Some("203.0.113.7"), "a"));
+        assert!((0..50).all(|i| by_ip.routes_to_candidate(&request(&headers, Some("203.0.113.7"), &i.to_string())) == first));
+
+        assert!(CanarySettings { weight: 10, sticky: Some(Sticky::Cookie("a b".to_string())) }.validate().is_err());
+        assert!(CanarySettings { weight: 10, sticky: Some(Sticky::Header("bad header".to_string())) }.validate().is_err());
+    }
+
+    #[test]
+    fn test_counters() {
+        let counters = VersionCounters::default();
+        counters.record(false, Duration::from_millis(10));
+        counters.record(true, Duration::from_millis(30));
+
+        let stats = counters.snapshot(7, 1);
+        assert_eq!((stats.requests, stats.errors), (2, 1));
+        assert_eq!(stats.error_rate, 0.5);
+        assert_eq!(stats.mean_latency_ms, 20.0);
+        assert_eq!(stats.max_latency_ms, 30.0);
+
+        counters.reset();
+        assert_eq!(counters.snapshot(7, 0).requests, 0);
+    }
+
+    #[test]
+    fn test_settings_format() {
+        let settings: CanarySettings = serde_json::from_str(r#"{"weight": 10, "sticky": {"cookie": "session"}}"#).unwrap();
+        assert_eq!(settings.sticky, Some(Sticky::Cookie("session".to_string())));
+        let settings: CanarySettings = serde_json::from_str(r#"{"weight": 10, "sticky": "client_ip"}"#).unwrap();
+        assert_eq!(settings.sticky, Some(Sticky::ClientIp));
+        assert_eq!(candidate_build_id("ep-1"), "ep-1-canary");
+    }
+}
@@ -2,7 +2,9 @@
 //!
 //! Loads handler functions from dynamic libraries (.so/.dll) at runtime.
 //! Supports hot-swapping handlers without gateway restart.
-//! Provides graceful draining for zero-downtime deployments.
+//! Provides graceful draining for zero-downtime deployments, canary
+//! releases that run a candidate version next to the current one, and
+//! shadow versions that receive a copy of live traffic.
 //!
 //! # Architecture
 //!
@@ -25,11 +27,47 @@ use std::pin::Pin;
 use std::future::Future;
 
 use libloading::{Library, Symbol};
-use tokio::sync::RwLock;
+use tokio::sync::{Notify, RwLock};
 use anyhow::{anyhow, Result};
 
-use rust_edge_gateway_sdk::{Request, Response, Context as SdkContext};
+use rust_edge_gateway_sdk::{Request, Response, Context as SdkContext, ABI_VERSION};
+use rust_edge_gateway_sdk::socket::{SocketEvent, WebSocket};
 use super::context::Context as RuntimeContext;
+use super::canary::{candidate_build_id, CanarySettings, SplitRequest, VersionCounters, VersionStats};
+use super::mirror::{shadow_build_id, MirrorCounters, MirrorSettings, Shadow};
+
+/// Errors from executing a handler
+#[derive(Debug, thiserror::Error)]
+pub enum ExecuteError {
+    #[error("Handler not loaded: {0}")]
+    NotLoaded(String),
+
+    #[error("Handler is draining, cannot accept new requests")]
+    Draining,
+
+    #[error("Handler task panicked: {0}")]
+    Panicked(String),
+
+    #[error("Handler execution timed out after {0:?}")]
+    TimedOut(Duration),
+
+    #[error("Handler does not accept WebSocket connections: {0}")]
+    NotSocket(String),
+}
+
+/// A handler library built against a different SDK interface version
+///
+/// Its entry points cannot be called safely; the endpoint must be compiled
+/// again against the gateway's SDK.
+#[derive(Debug, thiserror::Error)]
+#[error("Handler library {path:?} was built for SDK ABI version {}, the gateway uses version {}; compile it again", .found.map_or("(none)".to_string(), |v| v.to_string()), ABI_VERSION)]
+pub struct AbiMismatch {
+    pub path: PathBuf,
+    pub found: Option<u32>,
+}
+
+/// Exported as `handler_abi_version` by every handler library
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
 
 /// Type alias for the handler entry point function
 ///
@@ -47,6 +85,23 @@ use super::context::Context as RuntimeContext;
 /// with bridge implementations that communicate with service actors.
 pub type HandlerFn = unsafe extern "C" fn(&SdkContext, Request) -> Response;
 
+/// Type alias for the WebSocket entry point function
+///
+/// Exported as `socket_entry` by handlers compiled for WebSocket endpoints
+/// and called once per connection event:
+/// ```ignore
+/// #[no_mangle]
+/// pub extern "C" fn socket_entry(ctx: &Context, socket: &WebSocket, event: SocketEvent)
+/// ```
+///
+/// As with [`HandlerFn`], both sides are Rust built against the same SDK, so
+/// the event is passed by value even though it is not a C type.
+#[allow(improper_ctypes_definitions)]
+pub type SocketFn = unsafe extern "C" fn(&SdkContext, &WebSocket, SocketEvent);
+
+/// Source of [`LoadedHandler::generation`] numbers
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
 /// A loaded handler with its library
 pub struct LoadedHandler {
     /// The loaded library (must stay alive while handler is in use)
@@ -55,12 +110,18 @@ pub struct LoadedHandler {
     /// The handler entry point (pub(crate) for use in execute_with_timeout)
     pub(crate) entry: HandlerFn,
 
+    /// The WebSocket entry point, if the library exports one
+    pub(crate) socket_entry: Option<SocketFn>,
+
     /// Path the library was loaded from
     pub path: PathBuf,
 
     /// When the handler was loaded
     pub loaded_at: Instant,
 
+    /// Unique number of this load, used to tell versions of an endpoint apart
+    pub generation: u64,
+
     /// Handler metadata
     pub metadata: HandlerMetadata,
 
@@ -69,6 +130,18 @@ pub struct LoadedHandler {
 
     /// Whether this handler is draining (not accepting new requests)
     draining: AtomicBool,
+
+    /// Response streams still open, counted apart from active requests
+    open_streams: AtomicU64,
+
+    /// Whether open streams should end, set on draining or shutdown
+    ending_streams: AtomicBool,
+
+    /// Wakes open streams once `ending_streams` is set
+    streams_ended: Notify,
+
+    /// Requests, errors and latency of this version
+    pub counters: VersionCounters,
 }
 
 /// Metadata about a handler
@@ -89,22 +162,83 @@ pub struct RequestGuard {
     handler: Arc<LoadedHandler>,
 }
 
+impl RequestGuard {
+    /// Whether the handler has started draining since the guard was taken
+    pub fn is_draining(&self) -> bool {
+        self.handler.is_draining()
+    }
+
+    /// Generation of the handler the guard was taken on
+    pub fn generation(&self) -> u64 {
+        self.handler.generation
+    }
+
+    /// Turn the request into an open response stream
+    ///
+    /// The request stops counting as active, so draining the handler does
+    /// not wait for a long-lived stream; the stream is ended instead.
+    pub fn into_stream(self) -> StreamGuard {
+        self.handler.open_streams.fetch_add(1, Ordering::SeqCst);
+        StreamGuard { handler: Arc::clone(&self.handler) }
+    }
+}
+
 impl Drop for RequestGuard {
     fn drop(&mut self) {
         self.handler.active_requests.fetch_sub(1, Ordering::SeqCst);
     }
 }
 
+/// Guard that keeps a handler's library loaded while one of its response
+/// streams is open, and decrements the open stream count when dropped
+pub struct StreamGuard {
+    handler: Arc<LoadedHandler>,
+}
+
+impl StreamGuard {
+    /// Wait until the handler's open streams should end
+    pub async fn ended(&self) {
+        loop {
+            // Registered before checking, so a concurrent end is not missed
+            let notified = self.handler.streams_ended.notified();
+            if self.handler.ending_streams.load(Ordering::SeqCst) {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.handler.open_streams.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Check that a library was built against the gateway's SDK ABI version
+///
+/// # Safety
+/// Calls the library's `handler_abi_version` export, if it has one.
+unsafe fn check_abi_version(library: &Library, path: &Path) -> Result<()> {
+    let found = library.get::<AbiVersionFn>(b"handler_abi_version").ok().map(|f| f());
+    if found != Some(ABI_VERSION) {
+        return Err(AbiMismatch { path: path.to_path_buf(), found }.into());
+    }
+    Ok(())
+}
+
 impl LoadedHandler {
     /// Load a handler from a dynamic library
     ///
     /// # Safety
     /// This function loads and executes code from a dynamic library.
     /// The library must export a `handler_entry` function with the correct signature.
+    /// Libraries built against another SDK ABI version fail with [`AbiMismatch`].
     pub unsafe fn load(path: &Path, name: &str) -> Result<Self> {
         // Load the library
         let library = Library::new(path)
             .map_err(|e| anyhow!("Failed to load library {:?}: {}", path, e))?;
+        check_abi_version(&library, path)?;
 
         // Get the entry point symbol
         let entry: Symbol<HandlerFn> = library.get(b"handler_entry")
@@ -113,11 +247,16 @@ impl LoadedHandler {
         // Convert to raw function pointer (safe because library stays alive)
         let entry_fn: HandlerFn = *entry;
 
+        // WebSocket handlers also export socket_entry
+        let socket_entry = library.get::<SocketFn>(b"socket_entry").ok().map(|s| *s);
+
         Ok(Self {
             _library: library,
             entry: entry_fn,
+            socket_entry,
             path: path.to_path_buf(),
             loaded_at: Instant::now(),
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
             metadata: HandlerMetadata {
                 name: name.to_string(),
                 version: None,
@@ -125,6 +264,10 @@ impl LoadedHandler {
             },
             active_requests: AtomicU64::new(0),
             draining: AtomicBool::new(false),
+            open_streams: AtomicU64::new(0),
+            ending_streams: AtomicBool::new(false),
+            streams_ended: Notify::new(),
+            counters: VersionCounters::default(),
         })
     }
 
@@ -152,16 +295,30 @@ impl LoadedHandler {
         self.active_requests.load(Ordering::SeqCst)
     }
 
-    /// Mark this handler as draining (no new requests accepted)
+    /// Get the number of open response streams
+    pub fn open_stream_count(&self) -> u64 {
+        self.open_streams.load(Ordering::SeqCst)
+    }
+
+    /// Mark this handler as draining (no new requests accepted) and end
+    /// its open streams
     pub fn start_draining(&self) {
         self.draining.store(true, Ordering::SeqCst);
+        self.end_streams();
         tracing::info!(
             handler = %self.metadata.name,
             active = self.active_request_count(),
+            streams = self.open_stream_count(),
             "Handler started draining"
         );
     }
 
+    /// End this handler's open response streams
+    pub fn end_streams(&self) {
+        self.ending_streams.store(true, Ordering::SeqCst);
+        self.streams_ended.notify_waiters();
+    }
+
     /// Check if handler is draining
     pub fn is_draining(&self) -> bool {
         self.draining.load(Ordering::SeqCst)
@@ -176,6 +333,51 @@ impl LoadedHandler {
     pub fn age(&self) -> Duration {
         self.loaded_at.elapsed()
     }
+
+    /// Counters of this version
+    pub fn stats(&self) -> VersionStats {
+        self.counters.snapshot(self.generation, self.active_request_count())
+    }
+
+    /// Execute with timeout and request tracking
+    ///
+    /// The call is recorded in the version's counters; a 5xx response, a
+    /// panic or a timeout counts as an error.
+    pub async fn execute_with_timeout(
+        self: &Arc<Self>,
+        ctx: &SdkContext,
+        req: Request,
+        timeout: Duration,
+    ) -> Result<(Response, RequestGuard), ExecuteError> {
+        // Acquire request guard for tracking
+        let guard = self.acquire_request()
+            .ok_or(ExecuteError::Draining)?;
+
+        // Get the entry function pointer (Copy/Send safe)
+        let entry = self.entry;
+
+        // Clone context for spawn_blocking (SDK Context is Clone + Send)
+        let ctx = ctx.clone();
+
+        // Wrap sync execution in spawn_blocking for timeout support. The
+        // guard moves into the call so a call that outlives its timeout still
+        // counts as active.
+        let started = Instant::now();
+        let future = tokio::task::spawn_blocking(move || {
+            // Safety: entry is from a loaded library that remains alive
+            let response = unsafe { entry(&ctx, req) };
+            (response, guard)
+        });
+
+        let result = match tokio::time::timeout(timeout, future).await {
+            Ok(Ok((response, guard))) => Ok((response, guard)),
+            Ok(Err(e)) => Err(ExecuteError::Panicked(e.to_string())),
+            Err(_) => Err(ExecuteError::TimedOut(timeout)),
+        };
+        let failed = result.as_ref().map_or(true, |(response, _)| response.status >= 500);
+        self.counters.record(failed, started.elapsed());
+        result
+    }
 }
 
 // Safety: The handler function pointer is safe to send between threads
@@ -194,6 +396,12 @@ pub struct HandlerRegistry {
     /// Handlers that are draining (previous versions waiting for requests to complete)
     draining_handlers: RwLock<Vec<Arc<LoadedHandler>>>,
 
+    /// Candidate versions receiving a share of their endpoint's traffic
+    candidates: RwLock<HashMap<String, Candidate>>,
+
+    /// Shadow versions receiving a copy of their endpoint's traffic
+    shadows: RwLock<HashMap<String, Shadow>>,
+
     /// Directory where handler libraries are stored
     handlers_dir: PathBuf,
 }
@@ -204,6 +412,8 @@ impl HandlerRegistry {
         Self {
             handlers: RwLock::new(HashMap::new()),
             draining_handlers: RwLock::new(Vec::new()),
+            candidates: RwLock::new(HashMap::new()),
+            shadows: RwLock::new(HashMap::new()),
             handlers_dir,
         }
     }
@@ -250,9 +460,18 @@ impl HandlerRegistry {
     pub async fn unload(&self, endpoint_id: &str) -> Result<()> {
         let mut handlers = self.handlers.write().await;
 
-        if handlers.remove(endpoint_id).is_some() {
+        if let Some(handler) = handlers.remove(endpoint_id) {
+            handler.end_streams();
             tracing::info!("Unloaded handler: {}", endpoint_id);
         }
+        if let Some(candidate) = self.candidates.write().await.remove(endpoint_id) {
+            candidate.handler.end_streams();
+            tracing::info!("Unloaded candidate handler: {}", endpoint_id);
+        }
+        if let Some(shadow) = self.shadows.write().await.remove(endpoint_id) {
+            shadow.handler.end_streams();
+            tracing::info!("Unloaded shadow handler: {}", endpoint_id);
+        }
 
         Ok(())
     }
@@ -273,8 +492,11 @@ impl HandlerRegistry {
 
         tracing::info!("Hot-swapped handler: {} (old handler dropped)", endpoint_id);
 
-        // Old handler is dropped here, which unloads the library
-        drop(old);
+        // Old handler is dropped here, which unloads the library once its
+        // open streams have ended
+        if let Some(old) = old {
+            old.end_streams();
+        }
 
         Ok(())
     }
@@ -305,17 +527,37 @@ impl HandlerRegistry {
         // Get the old handler and start draining
         let old_handler = {
             let mut handlers = self.handlers.write().await;
-            let old = handlers.insert(endpoint_id.to_string(), Arc::clone(&new_handler));
-            old
+            handlers.insert(endpoint_id.to_string(), Arc::clone(&new_handler))
         };
+        let drain_result = self.retire(endpoint_id, old_handler, drain_timeout).await;
+
+        tracing::info!(
+            "Graceful hot-swap: {} (draining: {}, pending: {})",
+            endpoint_id,
+            drain_result.draining,
+            drain_result.old_requests_pending
+        );
+
+        Ok(drain_result)
+    }
 
-        let drain_result = if let Some(old_handler) = old_handler {
+    /// Stop routing to `old_handler` and let it finish its in-flight requests
+    ///
+    /// A handler with active requests is kept on the draining list until
+    /// they complete or `drain_timeout` passes; an idle one is dropped.
+    async fn retire(
+        &self,
+        endpoint_id: &str,
+        old_handler: Option<Arc<LoadedHandler>>,
+        drain_timeout: Duration,
+    ) -> DrainResult {
+        if let Some(old_handler) = old_handler {
             let old_active = old_handler.active_request_count();
 
-            if old_active > 0 {
-                // Start draining the old handler
-                old_handler.start_draining();
+            // Refuse new requests and end open streams, even when idle
+            old_handler.start_draining();
 
+            if old_active > 0 {
                 // Add to draining list
                 {
                     let mut draining = self.draining_handlers.write().await;
@@ -355,7 +597,7 @@ impl HandlerRegistry {
                 }
             } else {
                 // No active requests, drop immediately
-                tracing::info!("Graceful swap: {} (no active requests)", endpoint_id);
+                tracing::info!("Retired handler: {} (no active requests)", endpoint_id);
                 DrainResult {
                     swapped: true,
                     old_requests_pending: 0,
@@ -369,16 +611,7 @@ impl HandlerRegistry {
                 old_requests_pending: 0,
                 draining: false,
             }
-        };
-
-        tracing::info!(
-            "Graceful hot-swap: {} (draining: {}, pending: {})",
-            endpoint_id,
-            drain_result.draining,
-            drain_result.old_requests_pending
-        );
-
-        Ok(drain_result)
+        }
     }
 
     /// Clean up fully drained handlers
@@ -407,6 +640,14 @@ impl HandlerRegistry {
         handlers.get(endpoint_id).cloned()
     }
 
+    /// Generation of the handler currently loaded for an endpoint
+    ///
+    /// Changes every time the endpoint's handler is loaded or swapped.
+    pub async fn generation(&self, endpoint_id: &str) -> Option<u64> {
+        let handlers = self.handlers.read().await;
+        handlers.get(endpoint_id).map(|h| h.generation)
+    }
+
     /// Check if a handler is loaded
     pub async fn is_loaded(&self, endpoint_id: &str) -> bool {
         let handlers = self.handlers.read().await;
@@ -442,53 +683,208 @@ impl HandlerRegistry {
         Ok(handler.execute(ctx, req))
     }
 
-    /// Execute a handler with timeout and request tracking
+    /// Execute the current handler of an endpoint with timeout and request tracking
     pub async fn execute_with_timeout(
         &self,
         endpoint_id: &str,
         ctx: &SdkContext,
         req: Request,
         timeout: Duration,
-    ) -> Result<Response> {
+    ) -> Result<(Response, RequestGuard), ExecuteError> {
         let handler = self.get(endpoint_id).await
+            .ok_or_else(|| ExecuteError::NotLoaded(endpoint_id.to_string()))?;
+        handler.execute_with_timeout(ctx, req, timeout).await
+    }
+
+    /// The version of an endpoint's handler that serves a request: its
+    /// candidate when the request falls in the canary's share, otherwise
+    /// the current handler
+    pub async fn select(&self, endpoint_id: &str, request: &SplitRequest<'_>) -> Option<Arc<LoadedHandler>> {
+        {
+            let candidates = self.candidates.read().await;
+            if let Some(candidate) = candidates.get(endpoint_id) {
+                if candidate.settings.routes_to_candidate(request) {
+                    return Some(Arc::clone(&candidate.handler));
+                }
+            }
+        }
+        self.get(endpoint_id).await
+    }
+
+    /// Load the compiled candidate version of an endpoint next to its current
+    /// handler and start routing `settings.weight` percent of requests to it
+    ///
+    /// A previous candidate is drained. The current handler's counters are
+    /// reset so that both versions are compared over the same period.
+    pub async fn load_candidate(&self, endpoint_id: &str, settings: CanarySettings, drain_timeout: Duration) -> Result<()> {
+        let primary = self.get(endpoint_id).await
             .ok_or_else(|| anyhow!("Handler not loaded: {}", endpoint_id))?;
+        let handler = self.load_build(endpoint_id, &candidate_build_id(endpoint_id))?;
 
-        // Acquire request guard for tracking
-        let _guard = handler.acquire_request()
-            .ok_or_else(|| anyhow!("Handler is draining, cannot accept new requests"))?;
+        primary.counters.reset();
+        let previous = self.candidates.write().await
+            .insert(endpoint_id.to_string(), Candidate { handler: Arc::clone(&handler), settings });
+        self.retire(endpoint_id, previous.map(|c| c.handler), drain_timeout).await;
 
-        // Get the entry function pointer (Copy/Send safe)
-        let entry = handler.entry;
+        tracing::info!("Loaded candidate handler: {} (generation {})", endpoint_id, handler.generation);
+        Ok(())
+    }
 
-        // Clone context for spawn_blocking (SDK Context is Clone + Send)
-        let ctx = ctx.clone();
+    /// Change how much traffic an endpoint's candidate receives
+    ///
+    /// Returns false if the endpoint has no candidate.
+    pub async fn set_canary_settings(&self, endpoint_id: &str, settings: CanarySettings) -> bool {
+        let mut candidates = self.candidates.write().await;
+        match candidates.get_mut(endpoint_id) {
+            Some(candidate) => {
+                candidate.settings = settings;
+                true
+            }
+            None => false,
+        }
+    }
 
-        // Wrap sync execution in spawn_blocking for timeout support
-        let future = tokio::task::spawn_blocking(move || {
-            // Safety: entry is from a loaded library that remains alive
-            unsafe { entry(&ctx, req) }
-        });
+    /// Make an endpoint's candidate its current handler
+    ///
+    /// The candidate's library replaces the endpoint's library on disk, so it
+    /// is the version loaded after a restart. The previous handler drains.
+    pub async fn promote(&self, endpoint_id: &str, drain_timeout: Duration) -> Result<DrainResult> {
+        let mut candidates = self.candidates.write().await;
+        let candidate = candidates.remove(endpoint_id)
+            .ok_or_else(|| anyhow!("No candidate loaded for {}", endpoint_id))?;
+
+        // A rename leaves the file mapped by the running version intact
+        if let Err(e) = std::fs::rename(&candidate.handler.path, self.library_path(endpoint_id)) {
+            candidates.insert(endpoint_id.to_string(), candidate);
+            return Err(anyhow!("Failed to move candidate library into place: {}", e));
+        }
+        drop(candidates);
+
+        let old_handler = self.handlers.write().await
+            .insert(endpoint_id.to_string(), candidate.handler);
+        let drain_result = self.retire(endpoint_id, old_handler, drain_timeout).await;
+
+        tracing::info!("Promoted candidate handler: {}", endpoint_id);
+        Ok(drain_result)
+    }
+
+    /// Stop routing to an endpoint's candidate and drain it
+    ///
+    /// Returns None if the endpoint has no candidate.
+    pub async fn abort_candidate(&self, endpoint_id: &str, drain_timeout: Duration) -> Option<DrainResult> {
+        let candidate = self.candidates.write().await.remove(endpoint_id)?;
+        if let Err(e) = std::fs::remove_file(&candidate.handler.path) {
+            tracing::warn!("Failed to remove candidate library {:?}: {}", candidate.handler.path, e);
+        }
+        let drain_result = self.retire(endpoint_id, Some(candidate.handler), drain_timeout).await;
+
+        tracing::info!("Aborted candidate handler: {}", endpoint_id);
+        Some(drain_result)
+    }
+
+    /// Load the compiled shadow version of an endpoint and start mirroring
+    /// its requests to it, replacing any previous shadow
+    pub async fn load_shadow(&self, endpoint_id: &str, settings: MirrorSettings, drain_timeout: Duration) -> Result<()> {
+        if !self.is_loaded(endpoint_id).await {
+            return Err(anyhow!("Handler not loaded: {}", endpoint_id));
+        }
+        let handler = self.load_build(endpoint_id, &shadow_build_id(endpoint_id))?;
+
+        let shadow = Shadow { handler: Arc::clone(&handler), settings, counters: Arc::new(MirrorCounters::default()) };
+        let previous = self.shadows.write().await.insert(endpoint_id.to_string(), shadow);
+        self.retire(endpoint_id, previous.map(|s| s.handler), drain_timeout).await;
+
+        tracing::info!("Loaded shadow handler: {} (generation {})", endpoint_id, handler.generation);
+        Ok(())
+    }
+
+    /// Change which requests are mirrored to an endpoint's shadow
+    ///
+    /// Returns false if the endpoint has no shadow.
+    pub async fn set_mirror_settings(&self, endpoint_id: &str, settings: MirrorSettings) -> bool {
+        let mut shadows = self.shadows.write().await;
+        match shadows.get_mut(endpoint_id) {
+            Some(shadow) => {
+                shadow.settings = settings;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop mirroring to an endpoint's shadow and drain it
+    ///
+    /// Returns None if the endpoint has no shadow.
+    pub async fn remove_shadow(&self, endpoint_id: &str, drain_timeout: Duration) -> Option<DrainResult> {
+        let shadow = self.shadows.write().await.remove(endpoint_id)?;
+        let drain_result = self.retire(endpoint_id, Some(shadow.handler), drain_timeout).await;
+
+        tracing::info!("Removed shadow handler: {}", endpoint_id);
+        Some(drain_result)
+    }
+
+    /// The shadow of an endpoint, if its requests are mirrored
+    pub async fn shadow(&self, endpoint_id: &str) -> Option<Shadow> {
+        self.shadows.read().await.get(endpoint_id).cloned()
+    }
 
-        match tokio::time::timeout(timeout, future).await {
-            Ok(Ok(response)) => Ok(response),
-            Ok(Err(e)) => Err(anyhow!("Handler task panicked: {}", e)),
-            Err(_) => Err(anyhow!("Handler execution timed out")),
+    /// Load the library compiled under `build_id` as a version of `endpoint_id`
+    fn load_build(&self, endpoint_id: &str, build_id: &str) -> Result<Arc<LoadedHandler>> {
+        let lib_path = self.library_path(build_id);
+        if !lib_path.exists() {
+            return Err(anyhow!("Handler library not found: {:?}", lib_path));
         }
+        let handler = unsafe { LoadedHandler::load(&lib_path, endpoint_id)? };
+        Ok(Arc::new(handler))
+    }
+
+    /// Counters of an endpoint's current handler and of its candidate
+    pub async fn version_stats(&self, endpoint_id: &str) -> (Option<VersionStats>, Option<VersionStats>) {
+        let primary = self.get(endpoint_id).await.map(|h| h.stats());
+        let candidate = self.candidates.read().await.get(endpoint_id).map(|c| c.handler.stats());
+        (primary, candidate)
+    }
+
+    /// Reserve a handler for a WebSocket connection
+    ///
+    /// The connection is served by the version [`select`](Self::select)
+    /// picks. The returned guard counts it as an active request until it is
+    /// dropped, so graceful swaps wait for open connections to close.
+    pub async fn acquire_socket(&self, endpoint_id: &str, request: &SplitRequest<'_>) -> Result<(SocketFn, RequestGuard), ExecuteError> {
+        let handler = self.select(endpoint_id, request).await
+            .ok_or_else(|| ExecuteError::NotLoaded(endpoint_id.to_string()))?;
+        let entry = handler.socket_entry
+            .ok_or_else(|| ExecuteError::NotSocket(endpoint_id.to_string()))?;
+        let guard = handler.acquire_request()
+            .ok_or(ExecuteError::Draining)?;
+        Ok((entry, guard))
     }
 
     /// Get handler stats
     pub async fn stats(&self) -> HandlerStats {
         let handlers = self.handlers.read().await;
         let draining = self.draining_handlers.read().await;
+        let candidates = self.candidates.read().await;
 
         let mut total_active = 0u64;
+        let mut open_streams = 0u64;
         for handler in handlers.values() {
             total_active += handler.active_request_count();
+            open_streams += handler.open_stream_count();
+        }
+        for candidate in candidates.values() {
+            total_active += candidate.handler.active_request_count();
+            open_streams += candidate.handler.open_stream_count();
+        }
+        for shadow in self.shadows.read().await.values() {
+            total_active += shadow.handler.active_request_count();
+            open_streams += shadow.handler.open_stream_count();
         }
 
         let mut draining_active = 0u64;
         for handler in draining.iter() {
             draining_active += handler.active_request_count();
+            open_streams += handler.open_stream_count();
         }
 
         HandlerStats {
@@ -496,6 +892,42 @@ impl HandlerRegistry {
             draining_count: draining.len(),
             active_requests: total_active,
             draining_requests: draining_active,
+            open_streams,
+        }
+    }
+
+    /// End the open response streams of every handler, for shutdown
+    ///
+    /// Handlers keep serving requests; only their streams are ended, so
+    /// that connections holding a stream can close.
+    pub async fn end_streams(&self) {
+        for handler in self.handlers.read().await.values() {
+            handler.end_streams();
+        }
+        for candidate in self.candidates.read().await.values() {
+            candidate.handler.end_streams();
+        }
+        for shadow in self.shadows.read().await.values() {
+            shadow.handler.end_streams();
+        }
+        for handler in self.draining_handlers.read().await.iter() {
+            handler.end_streams();
+        }
+    }
+
+    /// Wait until no handler has active requests
+    ///
+    /// Returns `false` if requests are still active at `deadline`.
+    pub async fn wait_idle(&self, deadline: tokio::time::Instant) -> bool {
+        loop {
+            let stats = self.stats().await;
+            if stats.active_requests + stats.draining_requests == 0 {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
         }
     }
 
@@ -506,6 +938,12 @@ impl HandlerRegistry {
     }
 }
 
+/// A candidate version of an endpoint's handler and its share of traffic
+struct Candidate {
+    handler: Arc<LoadedHandler>,
+    settings: CanarySettings,
+}
+
 /// Result of a graceful drain operation
 #[derive(Debug, Clone)]
 pub struct DrainResult {
@@ -528,6 +966,8 @@ pub struct HandlerStats {
     pub active_requests: u64,
     /// Active requests on draining handlers
     pub draining_requests: u64,
+    /// Response streams still open, not counted as active requests
+    pub open_streams: u64,
 }
 
 /// Format the library filename for the current platform
@@ -597,9 +1037,48 @@ impl Default for FallbackHandler {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::runtime::services::Services;
-    use crate::runtime::context::{ContextBuilder, RuntimeConfig};
+
+    #[allow(improper_ctypes_definitions)]
+    unsafe extern "C" fn ok_entry(_ctx: &SdkContext, _req: Request) -> Response {
+        Response::text(200, "ok")
+    }
+
+    /// A handler backed by the test binary itself, for counting requests
+    fn test_handler(name: &str) -> Arc<LoadedHandler> {
+        #[cfg(unix)]
+        let library = Library::from(libloading::os::unix::Library::this());
+        #[cfg(windows)]
+        let library = Library::from(libloading::os::windows::Library::this().unwrap());
+        Arc::new(LoadedHandler {
+            _library: library,
+            entry: ok_entry,
+            socket_entry: None,
+            path: PathBuf::new(),
+            loaded_at: Instant::now(),
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+            metadata: HandlerMetadata { name: name.to_string(), version: None, description: None },
+            active_requests: AtomicU64::new(0),
+            draining: AtomicBool::new(false),
+            open_streams: AtomicU64::new(0),
+            ending_streams: AtomicBool::new(false),
+            streams_ended: Notify::new(),
+            counters: VersionCounters::default(),
+        })
+    }
     
+    #[test]
+    fn test_abi_version_required() {
+        // The test binary exports no handler_abi_version
+        #[cfg(unix)]
+        let library = Library::from(libloading::os::unix::Library::this());
+        #[cfg(windows)]
+        let library = Library::from(libloading::os::windows::Library::this().unwrap());
+        let err = unsafe { check_abi_version(&library, Path::new("old.so")) }.unwrap_err();
+        let mismatch = err.downcast_ref::<AbiMismatch>().unwrap();
+        assert_eq!(mismatch.found, None);
+        assert!(err.to_string().contains("compile it again"));
+    }
+
     #[test]
     fn test_library_name_format() {
         let name = format_library_name("my-handler");
@@ -625,8 +1104,7 @@ mod tests {
             }))
         }).await;
         
-        let services = Services::new();
-        let ctx = ContextBuilder::new(services).build();
+        let ctx = SdkContext::new("test".to_string());
         let req = Request::default();
         
         let response = fallback.execute("test", &ctx, req).await.unwrap();
@@ -640,5 +1118,80 @@ mod tests {
         assert!(!registry.is_loaded("nonexistent").await);
         assert_eq!(registry.count().await, 0);
         assert!(registry.list().await.is_empty());
+
+        let ctx = SdkContext::new("test".to_string());
+        let result = registry.execute_with_timeout("nonexistent", &ctx, Request::default(), Duration::from_secs(1)).await;
+        assert!(matches!(result, Err(ExecuteError::NotLoaded(id)) if id == "nonexistent"));
+    }
+
+    #[tokio::test]
+    async fn test_wait_idle() {
+        let registry = HandlerRegistry::new(PathBuf::from("/tmp/handlers"));
+        assert!(registry.wait_idle(tokio::time::Instant::now()).await);
+
+        let handler = test_handler("ep");
+        registry.handlers.write().await.insert("ep".to_string(), Arc::clone(&handler));
+        let guard = handler.acquire_request().unwrap();
+
+        // A call in progress holds the wait until the deadline
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(250);
+        assert!(!registry.wait_idle(deadline).await);
+        assert!(tokio::time::Instant::now() >= deadline);
+
+        // Finishing the call ends the wait before the deadline
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            drop(guard);
+        });
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        assert!(registry.wait_idle(deadline).await);
+        assert!(tokio::time::Instant::now() < deadline);
+        release.await.unwrap();
+
+        // Calls on a draining version count too
+        let guard = handler.acquire_request().unwrap();
+        registry.handlers.write().await.clear();
+        registry.draining_handlers.write().await.push(handler);
+        assert!(!registry.wait_idle(tokio::time::Instant::now()).await);
+        drop(guard);
+        assert!(registry.wait_idle(tokio::time::Instant::now()).await);
+    }
+
+    #[tokio::test]
+    async fn test_streams_end_on_drain() {
+        use rust_edge_gateway_sdk::BodyStream;
+
+        let registry = HandlerRegistry::new(PathBuf::from("/tmp/handlers"));
+        let handler = test_handler("ep");
+        registry.handlers.write().await.insert("ep".to_string(), Arc::clone(&handler));
+
+        // An open stream is not an active request
+        let (writer, stream) = BodyStream::channel(1);
+        let guard = handler.acquire_request().unwrap().into_stream();
+        let body = crate::router::stream::into_body(stream.take().unwrap(), Some(guard));
+        assert_eq!(handler.active_request_count(), 0);
+        assert_eq!(registry.stats().await.open_streams, 1);
+        assert!(registry.wait_idle(tokio::time::Instant::now()).await);
+
+        writer.write("first;").unwrap();
+        let read = tokio::spawn(axum::body::to_bytes(body, usize::MAX));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!read.is_finished());
+
+        // Draining ends the body even though the writer is still open
+        handler.start_draining();
+        let bytes = tokio::time::timeout(Duration::from_secs(5), read).await.unwrap().unwrap().unwrap();
+        assert_eq!(&bytes[..], b"first;");
+
+        // The pump stops at the handler's next write
+        let closed = tokio::task::spawn_blocking(move || {
+            (0..3).any(|_| writer.write("more").is_err())
+        });
+        assert!(closed.await.unwrap());
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while handler.open_stream_count() > 0 {
+                tokio::task::yield_now().await;
+            }
+        }).await.unwrap();
     }
 }
\ No newline at end of file
@@ -0,0 +1,592 @@
+//! Traffic mirroring to a shadow handler version
+//!
+//! A shadow version of an endpoint's handler receives a copy of live
+//! requests after the current version has answered them. The shadow's
+//! responses are never sent to clients; they are compared with the
+//! response the client got, and differences in status, latency and body are
+//! counted and sampled.
+//!
+//! Shadow calls run in the background and are skipped, not queued, while
+//! `max_in_flight` of them are running, so a slow shadow cannot hold up live
+//! traffic. The shadow gets a [`Context`](SdkContext) whose write operations
+//! succeed without doing anything; reads go to the live services.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use rust_edge_gateway_sdk::services::{MinioClient, ObjectInfo, ServiceFuture, SqliteClient};
+use rust_edge_gateway_sdk::{Context as SdkContext, Request, Response};
+use serde::{Deserialize, Serialize};
+
+use super::handler::LoadedHandler;
+
+/// Suffix of the build id shadow versions are compiled under
+const SHADOW_SUFFIX: &str = "-shadow";
+
+/// Mismatches kept for inspection
+const RECENT_MISMATCHES: usize = 20;
+
+/// Request attribute that tells a handler it is running as a shadow
+pub const SHADOW_ATTRIBUTE: &str = "shadow";
+
+/// Which requests are mirrored and how responses are compared
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MirrorSettings {
+    /// Percentage of requests mirrored (0-100)
+    #[serde(default = "default_percent")]
+    pub percent: u8,
+
+    /// Compare response bodies as well as statuses
+    #[serde(default = "default_compare_body")]
+    pub compare_body: bool,
+
+    /// How much slower than the current version the shadow may answer
+    /// before the request counts as a latency mismatch
+    #[serde(default = "default_latency_tolerance_ms")]
+    pub latency_tolerance_ms: u64,
+
+    /// Shadow calls allowed at once; requests beyond are not mirrored
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: u64,
+}
+
+fn default_percent() -> u8 {
+    100
+}
+
+fn default_compare_body() -> bool {
+    true
+}
+
+fn default_latency_tolerance_ms() -> u64 {
+    100
+}
+
+fn default_max_in_flight() -> u64 {
+    16
+}
+
+impl Default for MirrorSettings {
+    fn default() -> Self {
+        Self {
+            percent: default_percent(),
+            compare_body: default_compare_body(),
+            latency_tolerance_ms: default_latency_tolerance_ms(),
+            max_in_flight: default_max_in_flight(),
+        }
+    }
+}
+
+impl MirrorSettings {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), String> {
+        if self.percent > 100 {
+            return Err(format!("Percent must be between 0 and 100, got {}", self.percent));
+        }
+        if self.max_in_flight == 0 {
+            return Err("max_in_flight must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether the request with `request_id` is mirrored
+    ///
+    /// The id is salted so the sample is independent of a canary split,
+    /// which hashes the same id.
+    pub fn samples(&self, request_id: &str) -> bool {
+        let key = format!("mirror:{}", request_id);
+        ((crate::router::upstream::hash(&key) % 100) as u8) < self.percent
+    }
+}
+
+/// Build id a shadow is compiled under, kept apart from the primary's
+pub fn shadow_build_id(endpoint_id: &str) -> String {
+    format!("{}{}", endpoint_id, SHADOW_SUFFIX)
+}
+
+/// A shadow version of an endpoint's handler
+#[derive(Clone)]
+pub struct Shadow {
+    pub handler: Arc<LoadedHandler>,
+    pub settings: MirrorSettings,
+    pub counters: Arc<MirrorCounters>,
+}
+
+/// What the client was answered, for comparison with the shadow
+#[derive(Debug, Clone)]
+pub struct Observed {
+    pub status: u16,
+    /// Buffered body; None for a streamed response, whose body is not compared
+    pub body: Option<Bytes>,
+    pub elapsed: Duration,
+}
+
+impl Observed {
+    /// Record a handler response that took `elapsed`
+    pub fn of(response: &Response, elapsed: Duration) -> Self {
+        let body = match &response.stream {
+            Some(_) => None,
+            None => Some(response.body.clone().map(|b| b.into_bytes()).unwrap_or_default()),
+        };
+        Self { status: response.status, body, elapsed }
+    }
+}
+
+/// How a shadow response differed from the client's response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MismatchKind {
+    /// The shadow failed, panicked or timed out
+    Error,
+    Status,
+    Body,
+    Latency,
+}
+
+/// One sampled mismatch
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub request_id: String,
+    pub method: String,
+    pub path: String,
+    pub kind: MismatchKind,
+    pub primary_status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow_status: Option<u16>,
+    pub primary_ms: f64,
+    pub shadow_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Comparison counters of a shadow
+#[derive(Debug, Default)]
+pub struct MirrorCounters {
+    mirrored: AtomicU64,
+    skipped: AtomicU64,
+    errors: AtomicU64,
+    status_mismatches: AtomicU64,
+    body_mismatches: AtomicU64,
+    latency_mismatches: AtomicU64,
+    suppressed_writes: AtomicU64,
+    recent: Mutex<VecDeque<Mismatch>>,
+}
+
+impl MirrorCounters {
+    /// Count a request that was not mirrored because too many shadow calls
+    /// were running
+    pub fn skip(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn suppress_write(&self) {
+        self.suppressed_writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record(&self, mismatch: Mismatch) {
+        let counter = match mismatch.kind {
+            MismatchKind::Error => &self.errors,
+            MismatchKind::Status => &self.status_mismatches,
+            MismatchKind::Body => &self.body_mismatches,
+            MismatchKind::Latency => &self.latency_mismatches,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_MISMATCHES {
+            recent.pop_front();
+        }
+        recent.push_back(mismatch);
+    }
+
+    /// Current values, with the most recent mismatches first
+    pub fn snapshot(&self) -> MirrorStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MirrorStats {
+            mirrored: load(&self.mirrored),
+            skipped: load(&self.skipped),
+            errors: load(&self.errors),
+            status_mismatches: load(&self.status_mismatches),
+            body_mismatches: load(&self.body_mismatches),
+            latency_mismatches: load(&self.latency_mismatches),
+            suppressed_writes: load(&self.suppressed_writes),
+            recent_mismatches: self.recent.lock().unwrap().iter().rev().cloned().collect(),
+        }
+    }
+}
+
+/// Comparison counters of a shadow, as reported by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct MirrorStats {
+    pub mirrored: u64,
+    pub skipped: u64,
+    pub errors: u64,
+    pub status_mismatches: u64,
+    pub body_mismatches: u64,
+    pub latency_mismatches: u64,
+    pub suppressed_writes: u64,
+    pub recent_mismatches: Vec<Mismatch>,
+}
+
+/// Run a copy of a request on the shadow and compare its response with the
+/// one the client got
+///
+/// Meant to be spawned after the client's response is ready.
+pub async fn replay(shadow: Shadow, ctx: SdkContext, mut request: Request, primary: Observed, timeout: Duration) {
+    let method = request.method.clone();
+    let path = request.path.clone();
+    let request_id = request.request_id.clone();
+    request.attributes.insert(SHADOW_ATTRIBUTE.to_string(), "true".to_string());
+    let ctx = shadow_context(&ctx, &shadow.counters);
+
+    let started = Instant::now();
+    let result = shadow.handler.execute_with_timeout(&ctx, request, timeout).await;
+    let elapsed = started.elapsed();
+    shadow.counters.mirrored.fetch_add(1, Ordering::Relaxed);
+
+    // Streamed shadow bodies are dropped unread, which closes the stream
+    let (status, outcome) = match result {
+        Ok((response, _guard)) => (Some(response.status), compare(&primary, &response, elapsed, &shadow.settings)),
+        Err(e) => (None, Some((MismatchKind::Error, Some(e.to_string())))),
+    };
+    if let Some((kind, detail)) = outcome {
+        tracing::debug!(request_id = %request_id, kind = ?kind, "Shadow response differs");
+        shadow.counters.record(Mismatch {
+            request_id,
+            method,
+            path,
+            kind,
+            primary_status: primary.status,
+            shadow_status: status,
+            primary_ms: primary.elapsed.as_secs_f64() * 1000.0,
+            shadow_ms: elapsed.as_secs_f64() * 1000.0,
+            detail,
+        });
+    }
+}
+
+/// The first way a shadow response differs from the client's, if any
+fn compare(
+    primary: &Observed,
+    shadow: &Response,
+    shadow_elapsed: Duration,
+    settings: &MirrorSettings,
+) -> Option<(MismatchKind, Option<String>)> {
+    if shadow.status >= 500 && primary.status < 500 {
+        return Some((MismatchKind::Error, None));
+    }
+    if shadow.status != primary.status {
+        return Some((MismatchKind::Status, None));
+    }
+    if settings.compare_body && shadow.stream.is_none() {
+        if let Some(primary_body) = &primary.body {
+            let shadow_body = shadow.body.as_ref().map(|b| b.as_bytes()).unwrap_or_default();
+            if let Some(detail) = body_diff(primary_body, shadow_body) {
+                return Some((MismatchKind::Body, Some(detail)));
+            }
+        }
+    }
+    let tolerance = Duration::from_millis(settings.latency_tolerance_ms);
+    if shadow_elapsed > primary.elapsed + tolerance {
+        return Some((MismatchKind::Latency, None));
+    }
+    None
+}
+
+/// Where two bodies differ, or None if they are equal
+///
+/// JSON bodies are compared as values, so key order and whitespace do not
+/// count, and the difference is reported as a JSON pointer. Other bodies are
+/// compared byte by byte.
+fn body_diff(primary: &[u8], shadow: &[u8]) -> Option<String> {
+    if primary == shadow {
+        return None;
+    }
+    if let (Ok(a), Ok(b)) = (
+        serde_json::from_slice::<serde_json::Value>(primary),
+        serde_json::from_slice::<serde_json::Value>(shadow),
+    ) {
+        return json_diff(&a, &b, String::new()).map(|pointer| {
+            format!("JSON differs at '{}'", pointer)
+        });
+    }
+    let offset = primary.iter().zip(shadow).position(|(a, b)| a != b)
+        .unwrap_or(primary.len().min(shadow.len()));
+    Some(format!("Bodies differ at byte {} (lengths {} and {})", offset, primary.len(), shadow.len()))
+}
+
+/// JSON pointer of the first difference between two values
+fn json_diff(a: &serde_json::Value, b: &serde_json::Value, pointer: String) -> Option<String> {
+    use serde_json::Value;
+
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter().find_map(|key| {
+                let child = format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => json_diff(a, b, child),
+                    _ => Some(child),
+                }
+            })
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            let diff = a.iter().zip(b).enumerate()
+                .find_map(|(i, (a, b))| json_diff(a, b, format!("{}/{}", pointer, i)));
+            match diff {
+                Some(diff) => Some(diff),
+                None if a.len() != b.len() => Some(format!("{}/{}", pointer, a.len().min(b.len()))),
+                None => None,
+            }
+        }
+        _ if a == b => None,
+        _ => Some(pointer),
+    }
+}
+
+/// A copy of `ctx` whose write operations succeed without effect
+pub fn shadow_context(ctx: &SdkContext, counters: &Arc<MirrorCounters>) -> SdkContext {
+    let mut shadow = SdkContext::new(ctx.request_id.clone());
+    shadow.minio = ctx.minio.clone().map(|inner| {
+        Arc::new(ShadowMinio { inner, counters: Arc::clone(counters) }) as Arc<dyn MinioClient>
+    });
+    shadow.sqlite = ctx.sqlite.clone().map(|inner| {
+        Arc::new(ShadowSqlite { inner, counters: Arc::clone(counters) }) as Arc<dyn SqliteClient>
+    });
+    shadow
+}
+
+/// Object storage for shadows: reads go through, writes are dropped
+struct ShadowMinio {
+    inner: Arc<dyn MinioClient>,
+    counters: Arc<MirrorCounters>,
+}
+
+impl MinioClient for ShadowMinio {
+    fn get_object<'a>(&'a self, bucket: &'a str, key: &'a str) -> ServiceFuture<'a, Vec<u8>> {
+        self.inner.get_object(bucket, key)
+    }
+
+    fn put_object<'a>(&'a self, _bucket: &'a str, _key: &'a str, _data: Vec<u8>, _content_type: Option<&'a str>) -> ServiceFuture<'a, ()> {
+        self.counters.suppress_write();
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete_object<'a>(&'a self, _bucket: &'a str, _key: &'a str) -> ServiceFuture<'a, ()> {
+        self.counters.suppress_write();
+        Box::pin(async { Ok(()) })
+    }
+
+    fn list_objects<'a>(&'a self, bucket: &'a str, prefix: &'a str) -> ServiceFuture<'a, Vec<ObjectInfo>> {
+        self.inner.list_objects(bucket, prefix)
+    }
+
+    fn default_bucket(&self) -> &str {
+        self.inner.default_bucket()
+    }
+}
+
+/// Database for shadows: read-only queries go through, anything that could
+/// write is dropped
+struct ShadowSqlite {
+    inner: Arc<dyn SqliteClient>,
+    counters: Arc<MirrorCounters>,
+}
+
+impl SqliteClient for ShadowSqlite {
+    fn query<'a>(&'a self, sql: &'a str, params: Vec<String>) -> ServiceFuture<'a, Vec<std::collections::HashMap<String, serde_json::Value>>> {
+        // A query can write too, e.g. `UPDATE ... RETURNING`
+        Box::pin(async move {
+            match self.inner.query_read_only(sql, params).await? {
+                Some(rows) => Ok(rows),
+                None => {
+                    self.counters.suppress_write();
+                    Ok(Vec::new())
+                }
+            }
+        })
+    }
+
+    fn query_read_only<'a>(&'a self, sql: &'a str, params: Vec<String>) -> ServiceFuture<'a, Option<Vec<std::collections::HashMap<String, serde_json::Value>>>> {
+        self.inner.query_read_only(sql, params)
+    }
+
+    fn execute<'a>(&'a self, _sql: &'a str, _params: Vec<String>) -> ServiceFuture<'a, u64> {
+        self.counters.suppress_write();
+        Box::pin(async { Ok(0) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::services::database::{Database, DatabaseConfig, Row};
+    use rust_edge_gateway_sdk::services::ServiceError;
+    use std::collections::HashMap;
+
+    fn observed(status: u16, body: &str, millis: u64) -> Observed {
+        Observed { status, body: Some(Bytes::from(body.to_string())), elapsed: Duration::from_millis(millis) }
+    }
+
+    fn response(status: u16, body: &str) -> Response {
+        Response::text(status, body)
+    }
+
+    #[test]
+    fn test_compare() {
+        let settings = MirrorSettings::default();
+        let fast = Duration::from_millis(10);
+
+        assert_eq!(compare(&observed(200, "ok", 10), &response(200, "ok"), fast, &settings), None);
+        assert_eq!(compare(&observed(200, "ok", 10), &response(404, "ok"), fast, &settings), Some((MismatchKind::Status, None)));
+        assert_eq!(compare(&observed(200, "ok", 10), &response(500, "ok"), fast, &settings), Some((MismatchKind::Error, None)));
+        assert!(matches!(compare(&observed(200, "ok", 10), &response(200, "no"), fast, &settings), Some((MismatchKind::Body, Some(_)))));
+        assert_eq!(
+            compare(&observed(200, "ok", 10), &response(200, "ok"), Duration::from_millis(500), &settings),
+            Some((MismatchKind::Latency, None)),
+        );
+
+        // Bodies are ignored when disabled, and when the client's was streamed
+        let no_body = MirrorSettings { compare_body: false, ..Default::default() };
+        assert_eq!(compare(&observed(200, "ok", 10), &response(200, "no"), fast, &no_body), None);
+        let streamed = Observed { body: None, ..observed(200, "", 10) };
+        assert_eq!(compare(&streamed, &response(200, "no"), fast, &settings), None);
+    }
+
+    #[test]
+    fn test_body_diff() {
+        assert_eq!(body_diff(b"same", b"same"), None);
+        assert_eq!(body_diff(br#"{"a": 1, "b": [1, 2]}"#, br#"{"b":[1,2],"a":1}"#), None);
+        assert_eq!(body_diff(br#"{"a": {"b": [1, 2]}}"#, br#"{"a": {"b": [1, 3]}}"#).as_deref(), Some("JSON differs at '/a/b/1'"));
+        assert_eq!(body_diff(br#"{"a": 1}"#, br#"{"a": 1, "c/d": 2}"#).as_deref(), Some("JSON differs at '/c~1d'"));
+        assert_eq!(body_diff(br#"[1]"#, br#"[1, 2]"#).as_deref(), Some("JSON differs at '/1'"));
+        assert_eq!(body_diff(b"hello", b"help").as_deref(), Some("Bodies differ at byte 3 (lengths 5 and 4)"));
+    }
+
+    #[test]
+    fn test_recent_mismatches_are_bounded() {
+        let counters = MirrorCounters::default();
+        for i in 0..RECENT_MISMATCHES + 5 {
+            counters.record(Mismatch {
+                request_id: i.to_string(),
+                method: "GET".to_string(),
+                path: "/".to_string(),
+                kind: MismatchKind::Status,
+                primary_status: 200,
+                shadow_status: Some(404),
+                primary_ms: 1.0,
+                shadow_ms: 1.0,
+                detail: None,
+            });
+        }
+        let stats = counters.snapshot();
+        assert_eq!(stats.status_mismatches, (RECENT_MISMATCHES + 5) as u64);
+        assert_eq!(stats.recent_mismatches.len(), RECENT_MISMATCHES);
+        assert_eq!(stats.recent_mismatches[0].request_id, (RECENT_MISMATCHES + 4).to_string());
+    }
+
+    /// A database client over the gateway's database actor
+    struct ActorSqlite(Database);
+
+    fn rows(rows: Vec<Row>) -> Vec<HashMap<String, serde_json::Value>> {
+        rows.into_iter().map(|row| row.values).collect()
+    }
+
+    fn values(params: Vec<String>) -> Vec<serde_json::Value> {
+        params.into_iter().map(serde_json::Value::String).collect()
+    }
+
+    impl SqliteClient for ActorSqlite {
+        fn query<'a>(&'a self, sql: &'a str, params: Vec<String>) -> ServiceFuture<'a, Vec<HashMap<String, serde_json::Value>>> {
+            Box::pin(async move {
+                self.0.query(sql, &values(params)).await
+                    .map(rows)
+                    .map_err(|e| ServiceError::OperationFailed(e.to_string()))
+            })
+        }
+
+        fn execute<'a>(&'a self, sql: &'a str, params: Vec<String>) -> ServiceFuture<'a, u64> {
+            Box::pin(async move {
+                self.0.execute(sql, &values(params)).await
+                    .map_err(|e| ServiceError::OperationFailed(e.to_string()))
+            })
+        }
+
+        fn query_read_only<'a>(&'a self, sql: &'a str, params: Vec<String>) -> ServiceFuture<'a, Option<Vec<HashMap<String, serde_json::Value>>>> {
+            Box::pin(async move {
+                self.0.query_read_only(sql, &values(params)).await
+                    .map(|r| r.map(rows))
+                    .map_err(|e| ServiceError::OperationFailed(e.to_string()))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shadow_database_writes_are_dropped() {
+        let db = Database::start(DatabaseConfig {
+            db_type: "sqlite".to_string(),
+            url: ":memory:".to_string(),
+            pool_size: 1,
+            timeout_secs: 30,
+        }).await.unwrap();
+        db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)", &[]).await.unwrap();
+        db.execute("INSERT INTO users (id, name) VALUES (1, 'alice')", &[]).await.unwrap();
+
+        let mut ctx = SdkContext::new("req-1".to_string());
+        ctx.sqlite = Some(Arc::new(ActorSqlite(db.clone())));
+        let counters = Arc::new(MirrorCounters::default());
+        let shadow = shadow_context(&ctx, &counters);
+        let sqlite = shadow.sqlite.as_ref().unwrap();
+
+        // Reads go through
+        let read = sqlite.query("SELECT name FROM users WHERE id = ?", vec!["1".to_string()]).await.unwrap();
+        assert_eq!(read[0]["name"], "alice");
+
+        // Writes, including queries that write, leave the row unchanged
+        let returned = sqlite.query("UPDATE users SET name = 'bob' WHERE id = 1 RETURNING name", Vec::new()).await.unwrap();
+        assert!(returned.is_empty());
+        assert_eq!(sqlite.execute("DELETE FROM users", Vec::new()).await.unwrap(), 0);
+        let rows = db.query("SELECT name FROM users WHERE id = 1", &[]).await.unwrap();
+        assert_eq!(rows[0].get("name"), Some(&serde_json::json!("alice")));
+        assert_eq!(counters.snapshot().suppressed_writes, 2);
+    }
+
+    #[test]
+    fn test_settings() {
+        let settings: MirrorSettings = serde_json::from_str(r#"{"percent": 25}"#).unwrap();
+        assert_eq!(settings, MirrorSettings { percent: 25, ..Default::default() });
+        assert!(MirrorSettings { percent: 101, ..Default::default() }.validate().is_err());
+        assert!(MirrorSettings { max_in_flight: 0, ..Default::default() }.validate().is_err());
+
+        let none = MirrorSettings { percent: 0, ..Default::default() };
+        assert!((0..100).all(|i| !none.samples(&i.to_string())));
+        assert!((0..100).all(|i| MirrorSettings::default().samples(&i.to_string())));
+        assert_eq!(shadow_build_id("ep-1"), "ep-1-shadow");
+    }
+
+    #[test]
+    fn test_sample_is_independent_of_canary() {
+        use crate::runtime::canary::{CanarySettings, SplitRequest};
+
+        let headers = axum::http::HeaderMap::new();
+        let canary = CanarySettings { weight: 50, sticky: None };
+        let mirror = MirrorSettings { percent: 50, ..Default::default() };
+        let (mut mirrored, mut both) = (0, 0);
+        for i in 0..10_000 {
+            let id = format!("req-{}", i);
+            let candidate = canary.routes_to_candidate(&SplitRequest { headers: &headers, client_ip: None, request_id: &id });
+            if mirror.samples(&id) {
+                mirrored += 1;
+                both += usize::from(candidate);
+            }
+        }
+        // Half of the mirrored requests went to each version
+        assert!((4_500..5_500).contains(&mirrored), "{} of 10000 mirrored", mirrored);
+        assert!((2_200..2_800).contains(&both), "{} of 10000 mirrored and sent to the candidate", both);
+    }
+}
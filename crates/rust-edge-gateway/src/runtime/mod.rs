@@ -5,16 +5,20 @@
 //! - Actor-based services (database, cache, storage)
 //! - Dynamic library handler loading with hot-swap
 //! - Graceful handler draining for zero-downtime deployments
+//! - Canary releases that split traffic between two handler versions
+//! - Traffic mirroring to shadow handler versions
 //! - Service lifecycle management
 //! - Bundle deployment system
 
 pub mod context;
 pub mod services;
 pub mod handler;
+pub mod canary;
+pub mod mirror;
 pub mod actor;
 pub mod bundle;
 
 pub use context::Context;
 pub use services::Services;
-pub use handler::HandlerRegistry;
+pub use handler::{AbiMismatch, HandlerRegistry};
 pub use bundle::BundleManifest;
\ No newline at end of file
@@ -117,6 +117,16 @@ impl Cache {
         
         Ok(Self { handle, config })
     }
+
+    /// Ask the actor to stop once it has processed the commands already queued
+    pub async fn shutdown(&self) {
+        let _ = self.handle.send(CacheCommand::Shutdown).await;
+    }
+
+    /// Whether the actor is still running
+    pub fn is_alive(&self) -> bool {
+        self.handle.is_alive()
+    }
     
     /// Get a value by key
     pub async fn get(&self, key: &str) -> Result<Option<String>, ServiceError> {
@@ -396,4 +406,22 @@ mod tests {
         let loaded: User = cache.get_json("user:1").await.unwrap().unwrap();
         assert_eq!(loaded, user);
     }
+
+    #[tokio::test]
+    async fn test_services_shutdown_stops_actor() {
+        let config = CacheConfig {
+            cache_type: "memory".to_string(),
+            url: None,
+            default_ttl_secs: 3600,
+            max_entries: 1000,
+        };
+
+        let cache = Cache::start(config).await.unwrap();
+        cache.set("key", "value", None).await.unwrap();
+        let services = crate::runtime::Services::new().with_cache(cache.clone());
+
+        assert!(services.shutdown(std::time::Duration::from_secs(1)).await);
+        assert!(!cache.is_alive());
+        assert!(cache.get("key").await.is_err());
+    }
 }
\ No newline at end of file
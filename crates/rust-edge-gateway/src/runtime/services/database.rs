@@ -82,6 +82,14 @@ pub enum DatabaseCommand {
         reply: oneshot::Sender<Result<Option<Row>, ServiceError>>,
     },
     
+    /// Execute a query that cannot change the database, and return all
+    /// rows; `None` if the statement could write
+    QueryReadOnly {
+        sql: String,
+        params: Vec<serde_json::Value>,
+        reply: oneshot::Sender<Result<Option<Vec<Row>>, ServiceError>>,
+    },
+    
     /// Execute a statement (INSERT, UPDATE, DELETE) and return affected rows
     Execute {
         sql: String,
@@ -118,6 +126,16 @@ impl Database {
         
         Ok(Self { handle, config })
     }
+
+    /// Ask the actor to stop once it has processed the commands already queued
+    pub async fn shutdown(&self) {
+        let _ = self.handle.send(DatabaseCommand::Shutdown).await;
+    }
+
+    /// Whether the actor is still running
+    pub fn is_alive(&self) -> bool {
+        self.handle.is_alive()
+    }
     
     /// Execute a query and return all matching rows
     pub async fn query(&self, sql: &str, params: &[serde_json::Value]) -> Result<Vec<Row>, ServiceError> {
@@ -145,6 +163,22 @@ impl Database {
         rx.await.map_err(|_| ServiceError::Unavailable("no response from database actor".into()))?
     }
     
+    /// Execute a query only if it cannot change the database
+    ///
+    /// Returns `None`, without running the statement, if it could write,
+    /// such as an `UPDATE ... RETURNING`.
+    pub async fn query_read_only(&self, sql: &str, params: &[serde_json::Value]) -> Result<Option<Vec<Row>>, ServiceError> {
+        let (tx, rx) = oneshot::channel();
+        
+        self.handle.send(DatabaseCommand::QueryReadOnly {
+            sql: sql.to_string(),
+            params: params.to_vec(),
+            reply: tx,
+        }).await.map_err(|_| ServiceError::Unavailable("database actor closed".into()))?;
+        
+        rx.await.map_err(|_| ServiceError::Unavailable("no response from database actor".into()))?
+    }
+    
     /// Execute a statement and return the number of affected rows
     pub async fn execute(&self, sql: &str, params: &[serde_json::Value]) -> Result<u64, ServiceError> {
         let (tx, rx) = oneshot::channel();
@@ -228,6 +262,15 @@ async fn database_actor(config: DatabaseConfig, mut rx: mpsc::Receiver<DatabaseC
                 let _ = reply.send(result);
             }
             
+            DatabaseCommand::QueryReadOnly { sql, params, reply } => {
+                let result = if let Some(ref conn) = conn {
+                    execute_sqlite_read_only(conn, &sql, &params)
+                } else {
+                    Ok(Some(vec![]))
+                };
+                let _ = reply.send(result);
+            }
+            
             DatabaseCommand::Execute { sql, params, reply } => {
                 let result = if let Some(ref conn) = conn {
                     execute_sqlite_statement(conn, &sql, &params)
@@ -258,9 +301,32 @@ fn execute_sqlite_query(
     sql: &str,
     params: &[serde_json::Value],
 ) -> Result<Vec<Row>, ServiceError> {
+    let mut stmt = conn.prepare(sql)
+        .map_err(|e| ServiceError::QueryFailed(e.to_string()))?;
+    query_rows(&mut stmt, params)
+}
+
+/// Execute a SQLite query and return rows, unless it could write
+fn execute_sqlite_read_only(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    params: &[serde_json::Value],
+) -> Result<Option<Vec<Row>>, ServiceError> {
     let mut stmt = conn.prepare(sql)
         .map_err(|e| ServiceError::QueryFailed(e.to_string()))?;
     
+    // Checked before the statement is stepped, which is when it writes
+    if !stmt.readonly() {
+        return Ok(None);
+    }
+    query_rows(&mut stmt, params).map(Some)
+}
+
+/// Run a prepared statement and collect its rows
+fn query_rows(
+    stmt: &mut rusqlite::Statement<'_>,
+    params: &[serde_json::Value],
+) -> Result<Vec<Row>, ServiceError> {
     let column_names: Vec<String> = stmt.column_names()
         .iter()
         .map(|s| s.to_string())
@@ -402,6 +468,14 @@ mod tests {
         assert_eq!(rows.len(), 1);
         assert_eq!(rows[0].get("name"), Some(&serde_json::json!("Alice")));
         
+        // Read-only queries refuse statements that write
+        let rows = db.query_read_only("SELECT name FROM test WHERE id = ?", &[serde_json::json!(1)]).await.unwrap();
+        assert_eq!(rows.map(|r| r.len()), Some(1));
+        let refused = db.query_read_only("UPDATE test SET name = 'Bob' RETURNING name", &[]).await.unwrap();
+        assert!(refused.is_none());
+        let rows = db.query("SELECT name FROM test", &[]).await.unwrap();
+        assert_eq!(rows[0].get("name"), Some(&serde_json::json!("Alice")));
+        
         // Health check
         assert!(db.health().await.unwrap());
     }
@@ -46,6 +46,20 @@ pub enum MinioOp {
         key: String,
         reply: oneshot::Sender<Result<Bytes, ActorError>>,
     },
+    /// Bytes `start..=end` of an object (to the end if `end` is None)
+    GetObjectRange {
+        bucket: String,
+        key: String,
+        start: u64,
+        end: Option<u64>,
+        reply: oneshot::Sender<Result<Bytes, ActorError>>,
+    },
+    /// Object metadata, None if there is no such object
+    HeadObject {
+        bucket: String,
+        key: String,
+        reply: oneshot::Sender<Result<Option<ObjectInfo>, ActorError>>,
+    },
     PutObject {
         bucket: String,
         key: String,
@@ -93,6 +107,28 @@ impl MinioHandle {
         rx.await?
     }
 
+    pub async fn get_object_range(&self, bucket: &str, key: &str, start: u64, end: Option<u64>) -> Result<Bytes, ActorError> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(MinioOp::GetObjectRange {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            start,
+            end,
+            reply: tx,
+        }).await?;
+        rx.await?
+    }
+
+    pub async fn head_object(&self, bucket: &str, key: &str) -> Result<Option<ObjectInfo>, ActorError> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(MinioOp::HeadObject {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            reply: tx,
+        }).await?;
+        rx.await?
+    }
+
     pub async fn put_object(&self, bucket: &str, key: &str, data: Bytes, content_type: Option<&str>) -> Result<(), ActorError> {
         let (tx, rx) = oneshot::channel();
         self.sender.send(MinioOp::PutObject {
@@ -131,6 +167,11 @@ impl MinioHandle {
         rx.await?
     }
 
+    /// Ask the actor to stop once it has processed the operations already queued
+    pub async fn shutdown(&self) {
+        let _ = self.sender.send(MinioOp::Shutdown).await;
+    }
+
     pub fn is_alive(&self) -> bool {
         !self.sender.is_closed()
     }
@@ -204,6 +245,14 @@ impl MinioServiceActor {
                     let result = self.get_object_impl(&bucket, &key).await;
                     let _ = reply.send(result);
                 }
+                MinioOp::GetObjectRange { bucket, key, start, end, reply } => {
+                    let result = self.get_object_range_impl(&bucket, &key, start, end).await;
+                    let _ = reply.send(result);
+                }
+                MinioOp::HeadObject { bucket, key, reply } => {
+                    let result = self.head_object_impl(&bucket, &key).await;
+                    let _ = reply.send(result);
+                }
                 MinioOp::PutObject { bucket, key, data, content_type, reply } => {
                     let result = self.put_object_impl(&bucket, &key, data, content_type).await;
                     let _ = reply.send(result);
@@ -235,6 +284,39 @@ impl MinioServiceActor {
         Ok(Bytes::from(response.to_vec()))
     }
 
+    async fn get_object_range_impl(&self, _bucket: &str, key: &str, start: u64, end: Option<u64>) -> Result<Bytes, ActorError> {
+        // rust-s3 rejects a range whose end equals its start, so ask for one
+        // byte more and cut it off
+        let single = end == Some(start);
+        let end = if single { Some(start + 1) } else { end };
+        let response = self.bucket.get_object_range(key, start, end).await
+            .map_err(|e| ActorError::OperationFailed(format!("GetObjectRange failed: {}", e)))?;
+        if !matches!(response.status_code(), 200 | 206) {
+            return Err(ActorError::OperationFailed(format!("GetObjectRange failed: status {}", response.status_code())));
+        }
+        let mut bytes = Bytes::from(response.to_vec());
+        if single {
+            bytes.truncate(1);
+        }
+        Ok(bytes)
+    }
+
+    async fn head_object_impl(&self, _bucket: &str, key: &str) -> Result<Option<ObjectInfo>, ActorError> {
+        let (head, status) = self.bucket.head_object(key).await
+            .map_err(|e| ActorError::OperationFailed(format!("HeadObject failed: {}", e)))?;
+        match status {
+            200 => Ok(Some(ObjectInfo {
+                key: key.to_string(),
+                size: head.content_length.unwrap_or(0).max(0) as u64,
+                last_modified: head.last_modified.unwrap_or_default(),
+                etag: head.e_tag,
+                content_type: head.content_type,
+            })),
+            404 => Ok(None),
+            status => Err(ActorError::OperationFailed(format!("HeadObject failed: status {}", status))),
+        }
+    }
+
     async fn put_object_impl(&self, _bucket: &str, key: &str, data: Bytes, content_type: Option<String>) -> Result<(), ActorError> {
         let ct = content_type.as_deref().unwrap_or("application/octet-stream");
         self.bucket.put_object_with_content_type(key, &data, ct).await
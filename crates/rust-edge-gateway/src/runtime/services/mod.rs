@@ -87,6 +87,30 @@ impl Services {
         self
     }
 
+    /// Stop every service actor
+    ///
+    /// Each actor gets its `Shutdown` command after the work already queued.
+    /// Returns `false` if some were still running after `timeout`.
+    pub async fn shutdown(&self, timeout: std::time::Duration) -> bool {
+        if let Some(db) = &self.db { db.shutdown().await; }
+        if let Some(cache) = &self.cache { cache.shutdown().await; }
+        if let Some(storage) = &self.storage { storage.shutdown().await; }
+        if let Some(minio) = &self.minio { minio.shutdown().await; }
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.db.as_ref().is_some_and(Database::is_alive)
+            || self.cache.as_ref().is_some_and(Cache::is_alive)
+            || self.storage.as_ref().is_some_and(ObjectStore::is_alive)
+            || self.minio.as_ref().is_some_and(MinioHandle::is_alive)
+        {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        true
+    }
+
     /// Get minio or return error
     pub fn require_minio(&self) -> Result<&MinioHandle, ServiceError> {
         self.minio.as_ref()
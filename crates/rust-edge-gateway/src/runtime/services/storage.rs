@@ -136,6 +136,16 @@ impl ObjectStore {
         
         Ok(Self { handle, config })
     }
+
+    /// Ask the actor to stop once it has processed the commands already queued
+    pub async fn shutdown(&self) {
+        let _ = self.handle.send(StorageCommand::Shutdown).await;
+    }
+
+    /// Whether the actor is still running
+    pub fn is_alive(&self) -> bool {
+        self.handle.is_alive()
+    }
     
     /// Upload an object
     pub async fn put(
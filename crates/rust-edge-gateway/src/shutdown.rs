@@ -0,0 +1,135 @@
+//! Graceful shutdown
+//!
+//! On SIGTERM or Ctrl-C the servers stop accepting connections and finish
+//! the requests already in flight. [`drain`] then waits, up to the shutdown
+//! deadline, for handler calls to return (a call can outlive its connection
+//! when the client disconnects or the call times out), stops the service
+//! actors and flushes the log output.
+
+use std::io::Write;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::AppState;
+
+/// How long service actors get to process their `Shutdown` command
+const SERVICE_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tells the servers to stop accepting connections
+#[derive(Debug)]
+pub struct Shutdown {
+    tx: watch::Sender<bool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self { tx: watch::Sender::new(false) }
+    }
+
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    /// Future that completes once [`Shutdown::trigger`] is called, for
+    /// `with_graceful_shutdown`
+    pub fn wait(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut rx = self.tx.subscribe();
+        async move {
+            let _ = rx.wait_for(|&stop| stop).await;
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wait for SIGTERM or Ctrl-C
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received Ctrl-C"),
+        _ = terminate => tracing::info!("Received SIGTERM"),
+    }
+}
+
+/// Drain the gateway after [`Shutdown::trigger`]
+///
+/// Ends open response streams, waits for the servers to finish their open
+/// connections and for active handler calls to complete, both within
+/// `timeout`, then stops the service actors. Whatever is still running at
+/// the deadline is abandoned.
+pub async fn drain(state: &AppState, servers: Vec<JoinHandle<std::io::Result<()>>>, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+
+    // Streamed responses would otherwise hold their connections open
+    state.handler_registry.end_streams().await;
+
+    if tokio::time::timeout_at(deadline, futures::future::join_all(servers)).await.is_err() {
+        tracing::warn!("Connections still open after {:?}, closing them", timeout);
+    }
+
+    if !state.handler_registry.wait_idle(deadline).await {
+        let stats = state.handler_registry.stats().await;
+        tracing::warn!(
+            active = stats.active_requests + stats.draining_requests,
+            "Handler calls still running after {:?}, abandoning them", timeout
+        );
+    }
+
+    let services = state.runtime_services.read().await.clone();
+    if !services.shutdown(SERVICE_STOP_TIMEOUT).await {
+        tracing::warn!("Service actors did not stop within {:?}", SERVICE_STOP_TIMEOUT);
+    }
+
+    tracing::info!("Shutdown complete");
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_trigger_signals_every_subscriber() {
+        let shutdown = Shutdown::new();
+        let waiters: Vec<_> = (0..3).map(|_| tokio::spawn(shutdown.wait())).collect();
+        tokio::task::yield_now().await;
+        assert!(waiters.iter().all(|w| !w.is_finished()));
+
+        shutdown.trigger();
+        for waiter in waiters {
+            tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+        }
+
+        // Waiting after the trigger completes at once
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait()).await.unwrap();
+    }
+}
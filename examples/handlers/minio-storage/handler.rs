@@ -109,7 +109,7 @@ pub fn handle_put(ctx: &Context, req: Request) -> Response {
     };
     
     let bucket = bucket.as_deref().unwrap_or(minio.default_bucket());
-    let data = req.body_bytes();
+    let data = req.body_bytes().to_vec();
     let size = data.len();
     
     let rt = tokio::runtime::Handle::current();